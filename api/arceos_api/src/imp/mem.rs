@@ -2,6 +2,8 @@ cfg_alloc! {
     use core::alloc::Layout;
     use core::ptr::NonNull;
 
+    pub use axalloc::CacheStats as AxAllocCacheStats;
+
     pub fn ax_alloc(layout: Layout) -> Option<NonNull<u8>> {
         axalloc::global_allocator().alloc(layout).ok()
     }
@@ -9,4 +11,21 @@ cfg_alloc! {
     pub fn ax_dealloc(ptr: NonNull<u8>, layout: Layout) {
         axalloc::global_allocator().dealloc(ptr, layout)
     }
+
+    pub fn ax_alloc_cache_stats() -> AxAllocCacheStats {
+        axalloc::global_allocator().cache_stats()
+    }
+
+    #[cfg(feature = "alloc-trace")]
+    pub use axalloc::trace::Snapshot as AxAllocSnapshot;
+
+    #[cfg(feature = "alloc-trace")]
+    pub fn ax_alloc_snapshot() -> AxAllocSnapshot {
+        axalloc::trace::snapshot()
+    }
+
+    #[cfg(feature = "alloc-debug")]
+    pub fn ax_alloc_check_heap() {
+        axalloc::debug::check_heap()
+    }
 }
@@ -24,8 +24,11 @@ pub fn ax_exit(_exit_code: i32) -> ! {
 }
 
 cfg_task! {
+    use axtask::SpawnError;
     use core::time::Duration;
 
+    pub use axtask::{AxCpuMask, TaskStat as AxTaskStat};
+
     /// A handle to a task.
     pub struct AxTaskHandle {
         inner: axtask::AxTaskRef,
@@ -56,21 +59,55 @@ cfg_task! {
         axtask::current().id().as_u64()
     }
 
-    pub fn ax_spawn<F>(f: F, name: alloc::string::String, stack_size: usize) -> AxTaskHandle
+    pub fn ax_spawn<F>(
+        f: F,
+        name: alloc::string::String,
+        stack_size: usize,
+        affinity: Option<AxCpuMask>,
+    ) -> crate::AxResult<AxTaskHandle>
     where
         F: FnOnce() + Send + 'static,
     {
-        let inner = axtask::spawn_raw(f, name, stack_size);
-        AxTaskHandle {
+        let res = match affinity {
+            Some(cpumask) => axtask::spawn_raw_with_affinity(f, name, stack_size, cpumask),
+            None => axtask::try_spawn_raw(f, name, stack_size),
+        };
+        let inner = res.map_err(|e| match e {
+            SpawnError::EmptyCpuMask => {
+                axerrno::ax_err_type!(InvalidInput, "ax_spawn: the CPU mask is empty")
+            }
+            SpawnError::TooManyTasks => axerrno::ax_err_type!(WouldBlock, "ax_spawn: too many tasks"),
+        })?;
+        Ok(AxTaskHandle {
             id: inner.id().as_u64(),
             inner,
-        }
+        })
     }
 
     pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32> {
         task.inner.join()
     }
 
+    pub fn ax_cancel_task(task: &AxTaskHandle) -> bool {
+        axtask::cancel(&task.inner)
+    }
+
+    pub fn ax_test_cancel() {
+        axtask::test_cancel()
+    }
+
+    pub fn ax_set_cancel_enabled(enabled: bool) -> bool {
+        axtask::set_cancel_enabled(enabled)
+    }
+
+    pub fn ax_sleeping_lock_acquired() {
+        axtask::sleeping_lock_acquired()
+    }
+
+    pub fn ax_sleeping_lock_released() {
+        axtask::sleeping_lock_released()
+    }
+
     pub fn ax_set_current_priority(prio: isize) -> crate::AxResult {
         if axtask::set_priority(prio) {
             Ok(())
@@ -82,6 +119,29 @@ cfg_task! {
         }
     }
 
+    pub fn ax_set_current_affinity(cpumask: AxCpuMask) -> crate::AxResult {
+        if axtask::set_affinity(axtask::current().as_task_ref(), cpumask) {
+            Ok(())
+        } else {
+            axerrno::ax_err!(
+                InvalidInput,
+                "ax_set_current_affinity: the CPU mask is empty"
+            )
+        }
+    }
+
+    pub fn ax_task_stat(task: &AxTaskHandle) -> AxTaskStat {
+        task.inner.stat()
+    }
+
+    pub fn ax_current_task_stat() -> AxTaskStat {
+        axtask::current().stat()
+    }
+
+    pub fn ax_total_task_stat() -> AxTaskStat {
+        axtask::total_stat()
+    }
+
     pub fn ax_wait_queue_wait(
         wq: &AxWaitQueueHandle,
         until_condition: impl Fn() -> bool,
@@ -99,6 +159,50 @@ cfg_task! {
         false
     }
 
+    /// A handle to a high-resolution timer.
+    #[cfg(feature = "irq")]
+    pub struct AxHrTimerHandle(axtask::HrTimer);
+
+    #[cfg(feature = "irq")]
+    pub fn ax_hrtimer_new(
+        callback: impl Fn() + Send + Sync + 'static,
+        in_irq: bool,
+    ) -> AxHrTimerHandle {
+        let mode = if in_irq {
+            axtask::HrTimerMode::Irq
+        } else {
+            axtask::HrTimerMode::Task
+        };
+        AxHrTimerHandle(axtask::HrTimer::new(mode, callback))
+    }
+
+    #[cfg(feature = "irq")]
+    pub fn ax_hrtimer_start(
+        timer: &AxHrTimerHandle,
+        deadline: crate::time::AxTimeValue,
+        period: Option<Duration>,
+    ) {
+        timer.0.start(deadline, period)
+    }
+
+    #[cfg(feature = "irq")]
+    pub fn ax_hrtimer_cancel(timer: &AxHrTimerHandle) -> bool {
+        timer.0.cancel()
+    }
+
+    #[cfg(feature = "irq")]
+    pub fn ax_hrtimer_get(
+        timer: &AxHrTimerHandle,
+    ) -> Option<(crate::time::AxTimeValue, Option<Duration>)> {
+        let expires = timer.0.expires()?;
+        Some((expires, timer.0.period()))
+    }
+
+    #[cfg(feature = "irq")]
+    pub fn ax_hrtimer_overrun(timer: &AxHrTimerHandle) -> usize {
+        timer.0.overrun()
+    }
+
     pub fn ax_wait_queue_wake(wq: &AxWaitQueueHandle, count: u32) {
         if count == u32::MAX {
             wq.0.notify_all(true);
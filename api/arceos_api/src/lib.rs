@@ -52,6 +52,11 @@ pub mod time {
 pub mod mem {
     use core::{alloc::Layout, ptr::NonNull};
 
+    define_api_type! {
+        @cfg "alloc";
+        pub type AxAllocCacheStats;
+    }
+
     define_api! {
         @cfg "alloc";
         /// Allocate a continuous memory blocks with the given `layout` in
@@ -62,6 +67,30 @@ pub mod mem {
         /// Deallocate the memory block at the given `ptr` pointer with the given
         /// `layout`, which should be allocated by [`ax_alloc`].
         pub fn ax_dealloc(ptr: NonNull<u8>, layout: Layout);
+        /// Returns the statistics of the per-CPU allocation caches, which
+        /// are enabled with the `smp` feature.
+        pub fn ax_alloc_cache_stats() -> AxAllocCacheStats;
+    }
+
+    define_api_type! {
+        @cfg "alloc-trace";
+        pub type AxAllocSnapshot;
+    }
+
+    define_api! {
+        @cfg "alloc-trace";
+        /// Takes a snapshot of the live allocations of the global allocator,
+        /// which can be grouped by call sites or compared with an earlier
+        /// snapshot to find memory leaks.
+        pub fn ax_alloc_snapshot() -> AxAllocSnapshot;
+    }
+
+    define_api! {
+        @cfg "alloc-debug";
+        /// Checks the red zones of all live blocks and the poison of all
+        /// freed blocks in the quarantine, and panics if any of them is
+        /// overwritten.
+        pub fn ax_alloc_check_heap();
     }
 }
 
@@ -84,6 +113,8 @@ pub mod task {
         @cfg "multitask";
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
+        pub type AxCpuMask;
+        pub type AxTaskStat;
     }
 
     define_api! {
@@ -108,16 +139,42 @@ pub mod task {
         /// Returns the current task's ID.
         pub fn ax_current_task_id() -> u64;
         /// Spawns a new task with the given entry point and other arguments.
+        /// If `affinity` is given, the task only runs on the CPUs in it.
         pub fn ax_spawn(
             f: impl FnOnce() + Send + 'static,
             name: alloc::string::String,
-            stack_size: usize
-        ) -> AxTaskHandle;
+            stack_size: usize,
+            affinity: Option<AxCpuMask>
+        ) -> crate::AxResult<AxTaskHandle>;
         /// Waits for the given task to exit, and returns its exit code (the
         /// argument of [`ax_exit`]).
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
+        /// Requests the given task to exit, which takes effect when it waits
+        /// or sleeps without holding sleeping locks. Returns `false` if it
+        /// has already been canceled.
+        pub fn ax_cancel_task(task: &AxTaskHandle) -> bool;
+        /// Exits the current task if it has been canceled.
+        pub fn ax_test_cancel();
+        /// Enables or disables the cancellation of the current task, and
+        /// returns whether it was enabled.
+        pub fn ax_set_cancel_enabled(enabled: bool) -> bool;
+        /// Records that the current task has acquired a sleeping lock, it's
+        /// not canceled until it releases all of them.
+        pub fn ax_sleeping_lock_acquired();
+        /// Records that the current task has released a sleeping lock.
+        pub fn ax_sleeping_lock_released();
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
+        /// Sets the CPU affinity mask of the current task. The current task
+        /// is migrated immediately if the current CPU is not in the mask.
+        pub fn ax_set_current_affinity(cpumask: AxCpuMask) -> crate::AxResult;
+        /// Returns the CPU usage statistics of the given task.
+        pub fn ax_task_stat(task: &AxTaskHandle) -> AxTaskStat;
+        /// Returns the CPU usage statistics of the current task.
+        pub fn ax_current_task_stat() -> AxTaskStat;
+        /// Returns the accumulated CPU usage statistics of all tasks,
+        /// including the exited ones.
+        pub fn ax_total_task_stat() -> AxTaskStat;
 
         /// Blocks the current task and put it into the wait queue, until the
         /// given condition becomes true, or the the given duration has elapsed
@@ -133,6 +190,40 @@ pub mod task {
         /// `count` is `u32::MAX`, it will wake up all tasks in the wait queue.
         pub fn ax_wait_queue_wake(wq: &AxWaitQueueHandle, count: u32);
     }
+
+    define_api_type! {
+        @cfg "multitask", "irq";
+        pub type AxHrTimerHandle;
+    }
+
+    define_api! {
+        @cfg "multitask", "irq";
+
+        /// Creates a high-resolution timer that is not started. `callback`
+        /// runs in the timer interrupt handler if `in_irq` is true, otherwise
+        /// in a dedicated task, each time the timer expires.
+        pub fn ax_hrtimer_new(
+            callback: impl Fn() + Send + Sync + 'static,
+            in_irq: bool
+        ) -> AxHrTimerHandle;
+        /// Starts the timer to expire at `deadline`, then every `period` after
+        /// that if it's specified. A started timer is restarted.
+        pub fn ax_hrtimer_start(
+            timer: &AxHrTimerHandle,
+            deadline: crate::time::AxTimeValue,
+            period: Option<core::time::Duration>
+        );
+        /// Cancels the timer. Returns `false` if it's not active.
+        pub fn ax_hrtimer_cancel(timer: &AxHrTimerHandle) -> bool;
+        /// Returns the time of the next expiration and the period of the
+        /// timer, or [`None`] if it's not active.
+        pub fn ax_hrtimer_get(
+            timer: &AxHrTimerHandle
+        ) -> Option<(crate::time::AxTimeValue, Option<core::time::Duration>)>;
+        /// Returns the number of expirations missed before the last time the
+        /// callback runs.
+        pub fn ax_hrtimer_overrun(timer: &AxHrTimerHandle) -> usize;
+    }
 }
 
 /// Filesystem manipulation operations.
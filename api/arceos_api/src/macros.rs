@@ -6,13 +6,16 @@ macro_rules! define_api_type {
             $vis use $crate::imp::$name;
         )+
     };
-    ( @cfg $feature:literal; $( $(#[$attr:meta])* $vis:vis type $name:ident; )+ ) => {
+    ( @cfg $($feature:literal),+; $($items:tt)+ ) => {
+        define_api_type! { @cfg_meta all($(feature = $feature),+); $($items)+ }
+    };
+    ( @cfg_meta $cfg:meta; $( $(#[$attr:meta])* $vis:vis type $name:ident; )+ ) => {
         $(
-            #[cfg(feature = $feature)]
+            #[cfg($cfg)]
             $(#[$attr])*
             $vis use $crate::imp::$name;
 
-            #[cfg(all(feature = "dummy-if-not-enabled", not(feature = $feature)))]
+            #[cfg(all(feature = "dummy-if-not-enabled", not($cfg)))]
             $(#[$attr])*
             $vis struct $name;
         )+
@@ -28,19 +31,22 @@ macro_rules! define_api {
             }
         )+
     };
+    ( @cfg $($feature:literal),+; $($items:tt)+ ) => {
+        define_api! { @cfg_meta all($(feature = $feature),+); $($items)+ }
+    };
     (
-        @cfg $feature:literal;
+        @cfg_meta $cfg:meta;
         $( $(#[$attr:meta])* $vis:vis fn $name:ident( $($arg:ident : $type:ty),* $(,)? ) $( -> $ret:ty )? ; )+
     ) => {
         $(
-            #[cfg(feature = $feature)]
+            #[cfg($cfg)]
             $(#[$attr])*
             $vis fn $name( $($arg : $type),* ) $( -> $ret )? {
                 $crate::imp::$name( $($arg),* )
             }
 
             #[allow(unused_variables)]
-            #[cfg(all(feature = "dummy-if-not-enabled", not(feature = $feature)))]
+            #[cfg(all(feature = "dummy-if-not-enabled", not($cfg)))]
             $(#[$attr])*
             $vis fn $name( $($arg : $type),* ) $( -> $ret )? {
                 unimplemented!(stringify!($name))
@@ -1,17 +1,47 @@
 fn main() {
     use std::io::Write;
 
-    fn gen_pthread_mutex(out_file: &str) -> std::io::Result<()> {
-        // TODO: generate size and initial content automatically.
+    fn gen_sync_types(out_file: &str) -> std::io::Result<()> {
+        // TODO: generate sizes and initial contents automatically.
+        // The initial contents are `core::mem::transmute::<_, [usize; N]>(x)`, where `x` is
+        // `axsync::Mutex::new(())`, `axsync::Condvar::new()` or `axsync::RwLock::new(())`.
         let (mutex_size, mutex_init) = if cfg!(feature = "multitask") {
             if cfg!(feature = "smp") {
-                (6, "{0, 0, 8, 0, 0, 0}") // core::mem::transmute::<_, [usize; 6]>(axsync::Mutex::new(()))
+                (10, "{0, 0, 8, 0, 0, 0, 0, 0, 0, 0}")
             } else {
-                (5, "{0, 8, 0, 0, 0}") // core::mem::transmute::<_, [usize; 5]>(axsync::Mutex::new(()))
+                (8, "{0, 8, 0, 0, 0, 0, 0, 0}")
             }
         } else {
             (1, "{0}")
         };
+        let (cond_size, cond_init) = if cfg!(feature = "multitask") {
+            if cfg!(feature = "smp") {
+                (6, "{0, 0, 8, 0, 0, 0}")
+            } else {
+                (5, "{0, 8, 0, 0, 0}")
+            }
+        } else {
+            (1, "{0}")
+        };
+        let (rwlock_size, rwlock_init) = if cfg!(feature = "multitask") {
+            if cfg!(feature = "smp") {
+                (12, "{0, 0, 0, 0, 8, 0, 0, 0, 0, 8, 0, 0}")
+            } else {
+                (10, "{0, 8, 0, 0, 0, 8, 0, 0, 0, 0}")
+            }
+        } else {
+            (1, "{0}")
+        };
+        // `axsync::Barrier` and `axsync::Semaphore`, which have no static initializers.
+        let (barrier_size, sem_size) = if cfg!(feature = "multitask") {
+            if cfg!(feature = "smp") {
+                (18, 6)
+            } else {
+                (15, 5)
+            }
+        } else {
+            (1, 1)
+        };
 
         let mut output = Vec::new();
         writeln!(
@@ -21,11 +51,36 @@ fn main() {
         writeln!(
             output,
             r#"
+#ifndef _AX_SYNC_TYPES_H
+#define _AX_SYNC_TYPES_H
+
 typedef struct {{
     long __l[{mutex_size}];
 }} pthread_mutex_t;
 
 #define PTHREAD_MUTEX_INITIALIZER {{ .__l = {mutex_init}}}
+
+typedef struct {{
+    long __l[{cond_size}];
+}} pthread_cond_t;
+
+#define PTHREAD_COND_INITIALIZER {{ .__l = {cond_init}}}
+
+typedef struct {{
+    long __l[{rwlock_size}];
+}} pthread_rwlock_t;
+
+#define PTHREAD_RWLOCK_INITIALIZER {{ .__l = {rwlock_init}}}
+
+typedef struct {{
+    long __l[{barrier_size}];
+}} pthread_barrier_t;
+
+typedef struct {{
+    long __l[{sem_size}];
+}} sem_t;
+
+#endif // _AX_SYNC_TYPES_H
 "#
         )?;
         std::fs::write(out_file, output)?;
@@ -48,10 +103,24 @@ typedef struct {{
             "pthread_attr_t",
             "pthread_mutex_t",
             "pthread_mutexattr_t",
+            "pthread_cond_t",
+            "pthread_condattr_t",
+            "pthread_rwlock_t",
+            "pthread_rwlockattr_t",
+            "pthread_barrier_t",
+            "pthread_barrierattr_t",
+            "sem_t",
+            "cpu_set_t",
+            "sched_param",
             "epoll_event",
             "iovec",
             "clockid_t",
             "rlimit",
+            "rusage",
+            "tms",
+            "timer_t",
+            "itimerspec",
+            "sigevent",
             "aibuf",
         ];
         let allow_vars = [
@@ -65,6 +134,13 @@ typedef struct {{
             "EPOLL_CTL_.*",
             "EPOLL.*",
             "RLIMIT_.*",
+            "RUSAGE_.*",
+            "CLOCK_.*",
+            "TIMER_ABSTIME",
+            "SIGEV_.*",
+            "SCHED_.*",
+            "PTHREAD_CANCEL_.*",
+            "PTHREAD_BARRIER_SERIAL_THREAD",
             "EAI_.*",
             "MAXADDRS",
         ];
@@ -74,7 +150,7 @@ typedef struct {{
 
         impl bindgen::callbacks::ParseCallbacks for MyCallbacks {
             fn include_file(&self, fname: &str) {
-                if !fname.contains("ax_pthread_mutex.h") {
+                if !fname.contains("ax_sync_types.h") {
                     println!("cargo:rerun-if-changed={}", fname);
                 }
             }
@@ -101,6 +177,6 @@ typedef struct {{
             .expect("Couldn't write bindings!");
     }
 
-    gen_pthread_mutex("../../ulib/axlibc/include/ax_pthread_mutex.h").unwrap();
+    gen_sync_types("../../ulib/axlibc/include/ax_sync_types.h").unwrap();
     gen_c_to_rust_bindings("ctypes.h", "src/ctypes_gen.rs");
 }
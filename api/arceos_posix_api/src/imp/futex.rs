@@ -0,0 +1,71 @@
+use crate::ctypes;
+
+use axerrno::LinuxError;
+use axtask::FutexError;
+
+use core::ffi::c_int;
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
+
+const FUTEX_WAIT: c_int = 0;
+const FUTEX_WAKE: c_int = 1;
+const FUTEX_REQUEUE: c_int = 3;
+
+/// All futexes are private to the address space, this flag is ignored.
+const FUTEX_PRIVATE_FLAG: c_int = 128;
+
+fn futex_ref<'a>(uaddr: *mut u32) -> axerrno::LinuxResult<&'a AtomicU32> {
+    if uaddr.is_null() || uaddr as usize % core::mem::align_of::<AtomicU32>() != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(unsafe { &*uaddr.cast::<AtomicU32>() })
+}
+
+/// Wait or wake on the futex word at `uaddr`.
+///
+/// Supported operations are `FUTEX_WAIT`, `FUTEX_WAKE` and `FUTEX_REQUEUE`.
+/// For `FUTEX_WAIT`, `timeout` is relative and may be null. For
+/// `FUTEX_REQUEUE`, the maximum number of waiters to requeue is passed in
+/// `timeout` as an integer, as Linux does.
+pub unsafe fn sys_futex(
+    uaddr: *mut u32,
+    futex_op: c_int,
+    val: u32,
+    timeout: *const ctypes::timespec,
+    uaddr2: *mut u32,
+    val3: u32,
+) -> c_int {
+    debug!(
+        "sys_futex <= {:#x} {} {} {:#x} {:#x} {}",
+        uaddr as usize, futex_op, val, timeout as usize, uaddr2 as usize, val3
+    );
+    syscall_body!(sys_futex, {
+        let futex = futex_ref(uaddr)?;
+        match futex_op & !FUTEX_PRIVATE_FLAG {
+            FUTEX_WAIT => {
+                let timeout = if timeout.is_null() {
+                    None
+                } else {
+                    let ts = unsafe { *timeout };
+                    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+                        return Err(LinuxError::EINVAL);
+                    }
+                    Some(Duration::from(ts))
+                };
+                match axtask::futex_wait(futex, val, timeout) {
+                    Ok(()) => Ok(0),
+                    Err(FutexError::WouldBlock) => Err(LinuxError::EAGAIN),
+                    Err(FutexError::TimedOut) => Err(LinuxError::ETIMEDOUT),
+                }
+            }
+            FUTEX_WAKE => Ok(axtask::futex_wake(futex, val as usize) as c_int),
+            FUTEX_REQUEUE => {
+                let target = futex_ref(uaddr2)?;
+                let (woken, _) =
+                    axtask::futex_requeue(futex, val as usize, target, timeout as usize);
+                Ok(woken as c_int)
+            }
+            _ => Err(LinuxError::ENOSYS),
+        }
+    })
+}
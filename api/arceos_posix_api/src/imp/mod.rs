@@ -10,6 +10,8 @@ pub mod time;
 pub mod fd_ops;
 #[cfg(feature = "fs")]
 pub mod fs;
+#[cfg(feature = "multitask")]
+pub mod futex;
 #[cfg(any(feature = "select", feature = "epoll"))]
 pub mod io_mpx;
 #[cfg(feature = "net")]
@@ -18,3 +20,7 @@ pub mod net;
 pub mod pipe;
 #[cfg(feature = "multitask")]
 pub mod pthread;
+#[cfg(feature = "multitask")]
+pub mod semaphore;
+#[cfg(all(feature = "multitask", feature = "irq"))]
+pub mod timer;
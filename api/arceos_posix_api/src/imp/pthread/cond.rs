@@ -0,0 +1,138 @@
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+use axerrno::LinuxError;
+use axsync::Condvar;
+
+use core::ffi::c_int;
+use core::mem::{size_of, ManuallyDrop};
+use core::time::Duration;
+
+use super::mutex::PthreadMutex;
+use crate::imp::time::timeout_from_abstime;
+
+static_assertions::const_assert_eq!(
+    size_of::<ctypes::pthread_cond_t>(),
+    size_of::<PthreadCond>()
+);
+
+#[repr(C)]
+pub struct PthreadCond(Condvar);
+
+impl PthreadCond {
+    const fn new() -> Self {
+        Self(Condvar::new())
+    }
+
+    /// Waits on the condition variable, with the mutex locked by the current
+    /// task. Returns `true` if it timed out.
+    fn wait(&self, mutex: &PthreadMutex, timeout: Option<Duration>) -> bool {
+        // The guard of the mutex was forgotten when it's locked.
+        let guard = unsafe { mutex.inner().make_guard_unchecked() };
+        let (guard, timed_out) = match timeout {
+            Some(dur) => {
+                let (guard, res) = self.0.wait_timeout(guard, dur);
+                (guard, res.timed_out())
+            }
+            None => (self.0.wait(guard), false),
+        };
+        let _guard = ManuallyDrop::new(guard);
+        timed_out
+    }
+}
+
+/// Initialize a condition variable.
+pub fn sys_pthread_cond_init(
+    cond: *mut ctypes::pthread_cond_t,
+    attr: *const ctypes::pthread_condattr_t,
+) -> c_int {
+    debug!(
+        "sys_pthread_cond_init <= {:#x} {:#x}",
+        cond as usize, attr as usize
+    );
+    syscall_body!(sys_pthread_cond_init, {
+        check_null_mut_ptr(cond)?;
+        unsafe {
+            cond.cast::<PthreadCond>().write(PthreadCond::new());
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy a condition variable.
+pub fn sys_pthread_cond_destroy(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_destroy <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_destroy, {
+        check_null_mut_ptr(cond)?;
+        unsafe { cond.cast::<PthreadCond>().drop_in_place() };
+        Ok(0)
+    })
+}
+
+/// Wait on the condition variable, the mutex must be locked by the current
+/// thread.
+pub fn sys_pthread_cond_wait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+) -> c_int {
+    debug!(
+        "sys_pthread_cond_wait <= {:#x} {:#x}",
+        cond as usize, mutex as usize
+    );
+    syscall_body!(sys_pthread_cond_wait, {
+        check_null_mut_ptr(cond)?;
+        check_null_mut_ptr(mutex)?;
+        unsafe {
+            (*cond.cast::<PthreadCond>()).wait(&*mutex.cast::<PthreadMutex>(), None);
+        }
+        Ok(0)
+    })
+}
+
+/// Wait on the condition variable until the absolute time `abstime`.
+///
+/// The time is measured by the clock of [`sys_clock_gettime`], regardless of
+/// the clock in the attribute.
+///
+/// [`sys_clock_gettime`]: crate::sys_clock_gettime
+pub fn sys_pthread_cond_timedwait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+    abstime: *const ctypes::timespec,
+) -> c_int {
+    debug!(
+        "sys_pthread_cond_timedwait <= {:#x} {:#x} {:#x}",
+        cond as usize, mutex as usize, abstime as usize
+    );
+    syscall_body!(sys_pthread_cond_timedwait, {
+        check_null_mut_ptr(cond)?;
+        check_null_mut_ptr(mutex)?;
+        let timeout = timeout_from_abstime(abstime)?;
+        let timed_out = unsafe {
+            (*cond.cast::<PthreadCond>()).wait(&*mutex.cast::<PthreadMutex>(), Some(timeout))
+        };
+        if timed_out {
+            return Err(LinuxError::ETIMEDOUT);
+        }
+        Ok(0)
+    })
+}
+
+/// Wake up one thread waiting on the condition variable.
+pub fn sys_pthread_cond_signal(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_signal <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_signal, {
+        check_null_mut_ptr(cond)?;
+        unsafe { (*cond.cast::<PthreadCond>()).0.notify_one() };
+        Ok(0)
+    })
+}
+
+/// Wake up all threads waiting on the condition variable.
+pub fn sys_pthread_cond_broadcast(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_broadcast <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_broadcast, {
+        check_null_mut_ptr(cond)?;
+        unsafe { (*cond.cast::<PthreadCond>()).0.notify_all() };
+        Ok(0)
+    })
+}
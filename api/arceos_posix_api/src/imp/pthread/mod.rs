@@ -8,9 +8,20 @@ use spin::RwLock;
 
 use crate::ctypes;
 
+pub mod barrier;
+pub mod cond;
 pub mod mutex;
+pub mod rwlock;
+
+/// The return value of the threads that are canceled.
+const PTHREAD_CANCELED: *mut c_void = usize::MAX as *mut c_void;
 
 lazy_static::lazy_static! {
+    /// The threads that are not joined yet, by their task IDs.
+    ///
+    /// An entry is removed before its [`Pthread`] is dropped at join. The
+    /// `Pthread` holds a reference to its task, so the task ID is not freed
+    /// and reused by another thread while it's still in the map.
     static ref TID_TO_PTHREAD: RwLock<BTreeMap<u64, ForceSendSync<ctypes::pthread_t>>> = {
         let mut map = BTreeMap::new();
         let main_task = axtask::current();
@@ -59,7 +70,7 @@ impl Pthread {
             drop(their_packet);
         };
 
-        let task_inner = axtask::spawn(main);
+        let task_inner = axtask::try_spawn(main).map_err(|_| LinuxError::EAGAIN)?;
         let tid = task_inner.id().as_u64();
         let thread = Pthread {
             inner: task_inner,
@@ -88,21 +99,48 @@ impl Pthread {
         axtask::exit(0);
     }
 
+    fn task(ptr: ctypes::pthread_t) -> LinuxResult<&'static AxTaskRef> {
+        let thread = unsafe { (ptr as *const Pthread).as_ref() };
+        thread.map(|t| &t.inner).ok_or(LinuxError::ESRCH)
+    }
+
     fn join(ptr: ctypes::pthread_t) -> LinuxResult<*mut c_void> {
         if core::ptr::eq(ptr, Self::current_ptr() as _) {
             return Err(LinuxError::EDEADLK);
         }
 
         let thread = unsafe { Box::from_raw(ptr as *mut Pthread) };
-        thread.inner.join();
+        let exit_code = thread.inner.join();
         let tid = thread.inner.id().as_u64();
-        let retval = unsafe { *thread.retval.result.get() };
+        let retval = if exit_code == Some(axtask::EXIT_CANCELED) {
+            PTHREAD_CANCELED
+        } else {
+            unsafe { *thread.retval.result.get() }
+        };
+        // Remove it first, the ID may be reused once the thread is dropped.
         TID_TO_PTHREAD.write().remove(&tid);
         drop(thread);
         Ok(retval)
     }
 }
 
+/// Returns the task of the thread with the given ID, or the current task if
+/// `tid` is zero.
+///
+/// Returns `ESRCH` if the thread has exited, even if it's not joined yet.
+pub(crate) fn task_by_tid(tid: c_int) -> LinuxResult<AxTaskRef> {
+    let curr = axtask::current();
+    if tid == 0 || tid as u64 == curr.id().as_u64() {
+        return Ok(curr.as_task_ref().clone());
+    }
+    let map = TID_TO_PTHREAD.read();
+    let task = Pthread::task(map.get(&(tid as u64)).ok_or(LinuxError::ESRCH)?.0)?;
+    if task.is_exited() {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(task.clone())
+}
+
 /// Returns the `pthread` struct of current thread.
 pub fn sys_pthread_self() -> ctypes::pthread_t {
     Pthread::current().expect("fail to get current thread") as *const Pthread as _
@@ -147,8 +185,76 @@ pub unsafe fn sys_pthread_join(thread: ctypes::pthread_t, retval: *mut *mut c_vo
     })
 }
 
+/// Requests the given thread to be canceled.
+///
+/// The thread exits when it reaches a cancellation point, e.g., when it waits
+/// on a condition variable, sleeps, or calls `pthread_testcancel`, but not
+/// while it holds any mutex or read-write lock. Its joiner gets
+/// `PTHREAD_CANCELED` as the return value.
+pub fn sys_pthread_cancel(thread: ctypes::pthread_t) -> c_int {
+    debug!("sys_pthread_cancel <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_cancel, {
+        let task = Pthread::task(thread)?;
+        axtask::cancel(task);
+        Ok(0)
+    })
+}
+
+/// Exits the current thread if it has been canceled and the cancellation is
+/// enabled.
+pub fn sys_pthread_testcancel() {
+    debug!("sys_pthread_testcancel");
+    axtask::test_cancel();
+}
+
+/// Enables or disables the cancellation of the current thread, and stores the
+/// old state in `oldstate` if it's not null.
+pub unsafe fn sys_pthread_setcancelstate(state: c_int, oldstate: *mut c_int) -> c_int {
+    debug!("sys_pthread_setcancelstate <= {}", state);
+    syscall_body!(sys_pthread_setcancelstate, {
+        let enabled = match state as u32 {
+            ctypes::PTHREAD_CANCEL_ENABLE => true,
+            ctypes::PTHREAD_CANCEL_DISABLE => false,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let old_enabled = axtask::set_cancel_enabled(enabled);
+        if !oldstate.is_null() {
+            let old = if old_enabled {
+                ctypes::PTHREAD_CANCEL_ENABLE
+            } else {
+                ctypes::PTHREAD_CANCEL_DISABLE
+            };
+            unsafe { *oldstate = old as c_int };
+        }
+        Ok(0)
+    })
+}
+
 #[derive(Clone, Copy)]
-struct ForceSendSync<T>(T);
+pub(crate) struct ForceSendSync<T>(pub(crate) T);
 
 unsafe impl<T> Send for ForceSendSync<T> {}
 unsafe impl<T> Sync for ForceSendSync<T> {}
+
+/// Set the CPU affinity mask of the given thread.
+///
+/// Returns `EINVAL` if the mask is empty or not allowed for the thread, see
+/// [`axtask::set_affinity`].
+pub unsafe fn sys_pthread_setaffinity_np(
+    thread: ctypes::pthread_t,
+    cpusetsize: usize,
+    cpuset: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "sys_pthread_setaffinity_np <= {:#x} {} {:#x}",
+        thread as usize, cpusetsize, cpuset as usize
+    );
+    syscall_body!(sys_pthread_setaffinity_np, {
+        let task = Pthread::task(thread)?;
+        let cpumask = unsafe { super::task::cpu_set_to_mask(cpusetsize, cpuset)? };
+        if !axtask::set_affinity(task, cpumask) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(0)
+    })
+}
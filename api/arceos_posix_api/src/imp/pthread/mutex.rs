@@ -28,9 +28,18 @@ impl PthreadMutex {
         unsafe { self.0.force_unlock() };
         Ok(())
     }
+
+    /// The inner [`Mutex`], which must be locked by the current task to wait
+    /// on condition variables.
+    pub(super) fn inner(&self) -> &Mutex<()> {
+        &self.0
+    }
 }
 
 /// Initialize a mutex.
+///
+/// The attributes are ignored. All mutexes are of the `PTHREAD_PRIO_INHERIT`
+/// protocol, as [`Mutex`] always supports priority inheritance.
 pub fn sys_pthread_mutex_init(
     mutex: *mut ctypes::pthread_mutex_t,
     _attr: *const ctypes::pthread_mutexattr_t,
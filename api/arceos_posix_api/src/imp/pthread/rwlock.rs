@@ -0,0 +1,128 @@
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::RwLock;
+
+use core::ffi::c_int;
+use core::mem::{size_of, ManuallyDrop};
+
+static_assertions::const_assert_eq!(
+    size_of::<ctypes::pthread_rwlock_t>(),
+    size_of::<PthreadRwLock>()
+);
+
+#[repr(C)]
+pub struct PthreadRwLock(RwLock<()>);
+
+impl PthreadRwLock {
+    const fn new() -> Self {
+        Self(RwLock::new(()))
+    }
+
+    fn rdlock(&self) -> LinuxResult {
+        let _guard = ManuallyDrop::new(self.0.read());
+        Ok(())
+    }
+
+    fn tryrdlock(&self) -> LinuxResult {
+        let _guard = ManuallyDrop::new(self.0.try_read().ok_or(LinuxError::EBUSY)?);
+        Ok(())
+    }
+
+    fn wrlock(&self) -> LinuxResult {
+        let _guard = ManuallyDrop::new(self.0.write());
+        Ok(())
+    }
+
+    fn trywrlock(&self) -> LinuxResult {
+        let _guard = ManuallyDrop::new(self.0.try_write().ok_or(LinuxError::EBUSY)?);
+        Ok(())
+    }
+
+    fn unlock(&self) -> LinuxResult {
+        if self.0.is_locked_exclusive() {
+            unsafe { self.0.force_unlock_write() };
+        } else {
+            unsafe { self.0.force_unlock_read() };
+        }
+        Ok(())
+    }
+}
+
+/// Initialize a reader-writer lock.
+pub fn sys_pthread_rwlock_init(
+    rwlock: *mut ctypes::pthread_rwlock_t,
+    attr: *const ctypes::pthread_rwlockattr_t,
+) -> c_int {
+    debug!(
+        "sys_pthread_rwlock_init <= {:#x} {:#x}",
+        rwlock as usize, attr as usize
+    );
+    syscall_body!(sys_pthread_rwlock_init, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe {
+            rwlock.cast::<PthreadRwLock>().write(PthreadRwLock::new());
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy a reader-writer lock.
+pub fn sys_pthread_rwlock_destroy(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_destroy <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_destroy, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe { rwlock.cast::<PthreadRwLock>().drop_in_place() };
+        Ok(0)
+    })
+}
+
+/// Lock the given reader-writer lock for reading.
+pub fn sys_pthread_rwlock_rdlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_rdlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_rdlock, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe { (*rwlock.cast::<PthreadRwLock>()).rdlock()? };
+        Ok(0)
+    })
+}
+
+/// Try to lock the given reader-writer lock for reading without blocking.
+pub fn sys_pthread_rwlock_tryrdlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_tryrdlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_tryrdlock, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe { (*rwlock.cast::<PthreadRwLock>()).tryrdlock()? };
+        Ok(0)
+    })
+}
+
+/// Lock the given reader-writer lock for writing.
+pub fn sys_pthread_rwlock_wrlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_wrlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_wrlock, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe { (*rwlock.cast::<PthreadRwLock>()).wrlock()? };
+        Ok(0)
+    })
+}
+
+/// Try to lock the given reader-writer lock for writing without blocking.
+pub fn sys_pthread_rwlock_trywrlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_trywrlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_trywrlock, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe { (*rwlock.cast::<PthreadRwLock>()).trywrlock()? };
+        Ok(0)
+    })
+}
+
+/// Unlock the given reader-writer lock, held for either reading or writing.
+pub fn sys_pthread_rwlock_unlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_unlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_unlock, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe { (*rwlock.cast::<PthreadRwLock>()).unlock()? };
+        Ok(0)
+    })
+}
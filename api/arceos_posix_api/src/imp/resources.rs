@@ -2,6 +2,10 @@ use crate::ctypes;
 use axerrno::LinuxError;
 use core::ffi::c_int;
 
+const RUSAGE_SELF: c_int = ctypes::RUSAGE_SELF as _;
+const RUSAGE_CHILDREN: c_int = ctypes::RUSAGE_CHILDREN as _;
+const RUSAGE_THREAD: c_int = ctypes::RUSAGE_THREAD as _;
+
 /// Get resource limitations
 ///
 /// TODO: support more resource types
@@ -49,3 +53,36 @@ pub unsafe fn sys_setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit
         Ok(0)
     })
 }
+
+/// Get resource usage of the calling process or thread
+///
+/// Only the CPU time and context switches are supported. All threads are
+/// counted as one process, and there are no child processes.
+pub unsafe fn sys_getrusage(who: c_int, usage: *mut ctypes::rusage) -> c_int {
+    debug!("sys_getrusage <= {} {:#x}", who, usage as usize);
+    syscall_body!(sys_getrusage, {
+        crate::utils::check_null_mut_ptr(usage)?;
+        let mut ru = ctypes::rusage::default();
+        match who {
+            RUSAGE_SELF | RUSAGE_THREAD => {
+                let (utime, stime) = super::time::cpu_times(who == RUSAGE_THREAD);
+                ru.ru_utime = utime.into();
+                ru.ru_stime = stime.into();
+                #[cfg(feature = "multitask")]
+                {
+                    let stat = if who == RUSAGE_THREAD {
+                        axtask::current().stat()
+                    } else {
+                        axtask::total_stat()
+                    };
+                    ru.ru_nvcsw = stat.voluntary_switches as _;
+                    ru.ru_nivcsw = stat.involuntary_switches as _;
+                }
+            }
+            RUSAGE_CHILDREN => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        unsafe { *usage = ru };
+        Ok(0)
+    })
+}
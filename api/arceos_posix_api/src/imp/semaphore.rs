@@ -0,0 +1,108 @@
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+use axerrno::LinuxError;
+use axsync::Semaphore;
+
+use core::ffi::{c_int, c_uint};
+use core::mem::size_of;
+
+use super::time::timeout_from_abstime;
+
+static_assertions::const_assert_eq!(size_of::<ctypes::sem_t>(), size_of::<Semaphore>());
+
+/// Initialize an unnamed semaphore with the given value.
+///
+/// Semaphores shared between processes are not supported, `pshared` is
+/// ignored.
+pub fn sys_sem_init(sem: *mut ctypes::sem_t, pshared: c_int, value: c_uint) -> c_int {
+    debug!("sys_sem_init <= {:#x} {} {}", sem as usize, pshared, value);
+    syscall_body!(sys_sem_init, {
+        check_null_mut_ptr(sem)?;
+        if value > c_int::MAX as c_uint {
+            return Err(LinuxError::EINVAL);
+        }
+        unsafe {
+            sem.cast::<Semaphore>()
+                .write(Semaphore::new(value as usize));
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy an unnamed semaphore.
+pub fn sys_sem_destroy(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_destroy <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_destroy, {
+        check_null_mut_ptr(sem)?;
+        unsafe { sem.cast::<Semaphore>().drop_in_place() };
+        Ok(0)
+    })
+}
+
+/// Decrement the semaphore, blocking until its value is positive.
+pub fn sys_sem_wait(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_wait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_wait, {
+        check_null_mut_ptr(sem)?;
+        unsafe { (*sem.cast::<Semaphore>()).acquire() };
+        Ok(0)
+    })
+}
+
+/// Decrement the semaphore if its value is positive, otherwise fail with
+/// `EAGAIN`.
+pub fn sys_sem_trywait(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_trywait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_trywait, {
+        check_null_mut_ptr(sem)?;
+        if !unsafe { (*sem.cast::<Semaphore>()).try_acquire() } {
+            return Err(LinuxError::EAGAIN);
+        }
+        Ok(0)
+    })
+}
+
+/// Decrement the semaphore, blocking until its value is positive or the
+/// absolute time `abstime` has passed.
+pub fn sys_sem_timedwait(sem: *mut ctypes::sem_t, abstime: *const ctypes::timespec) -> c_int {
+    debug!(
+        "sys_sem_timedwait <= {:#x} {:#x}",
+        sem as usize, abstime as usize
+    );
+    syscall_body!(sys_sem_timedwait, {
+        check_null_mut_ptr(sem)?;
+        let sem = unsafe { &*sem.cast::<Semaphore>() };
+        if sem.try_acquire() {
+            return Ok(0);
+        }
+        let timeout = timeout_from_abstime(abstime)?;
+        if !sem.acquire_timeout(timeout) {
+            return Err(LinuxError::ETIMEDOUT);
+        }
+        Ok(0)
+    })
+}
+
+/// Increment the semaphore, and wake up a thread waiting on it.
+pub fn sys_sem_post(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_post <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_post, {
+        check_null_mut_ptr(sem)?;
+        unsafe { (*sem.cast::<Semaphore>()).release() };
+        Ok(0)
+    })
+}
+
+/// Get the value of the semaphore.
+pub unsafe fn sys_sem_getvalue(sem: *mut ctypes::sem_t, sval: *mut c_int) -> c_int {
+    debug!(
+        "sys_sem_getvalue <= {:#x} {:#x}",
+        sem as usize, sval as usize
+    );
+    syscall_body!(sys_sem_getvalue, {
+        check_null_mut_ptr(sem)?;
+        check_null_mut_ptr(sval)?;
+        unsafe { *sval = (*sem.cast::<Semaphore>()).count() as c_int };
+        Ok(0)
+    })
+}
@@ -1,5 +1,12 @@
 use core::ffi::c_int;
 
+#[cfg(feature = "multitask")]
+use {
+    crate::{ctypes, utils::check_null_ptr},
+    axerrno::{LinuxError, LinuxResult},
+    axtask::{AxCpuMask, SchedPolicy},
+};
+
 /// Relinquish the CPU, and switches to another task.
 ///
 /// For single-threaded configuration (`multitask` feature is disabled), we just
@@ -38,3 +45,182 @@ pub fn sys_exit(exit_code: c_int) -> ! {
     #[cfg(not(feature = "multitask"))]
     axhal::misc::terminate();
 }
+
+/// Converts a `cpu_set_t` of `cpusetsize` bytes to [`AxCpuMask`], CPUs that
+/// do not exist are ignored.
+///
+/// The set is accessed byte by byte, which is the same as accessing it as
+/// an array of `unsigned long` on little-endian architectures.
+#[cfg(feature = "multitask")]
+pub(crate) unsafe fn cpu_set_to_mask(
+    cpusetsize: usize,
+    cpuset: *const ctypes::cpu_set_t,
+) -> LinuxResult<AxCpuMask> {
+    check_null_ptr(cpuset)?;
+    let bytes = unsafe { core::slice::from_raw_parts(cpuset as *const u8, cpusetsize) };
+    let mut cpumask = AxCpuMask::new();
+    for cpu_id in 0..axconfig::SMP.min(cpusetsize * 8) {
+        if bytes[cpu_id / 8] & (1 << (cpu_id % 8)) != 0 {
+            cpumask.set(cpu_id, true);
+        }
+    }
+    if cpumask.is_empty() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(cpumask)
+}
+
+/// Stores the [`AxCpuMask`] into a `cpu_set_t` of `cpusetsize` bytes.
+#[cfg(feature = "multitask")]
+pub(crate) unsafe fn mask_to_cpu_set(
+    cpumask: AxCpuMask,
+    cpusetsize: usize,
+    cpuset: *mut ctypes::cpu_set_t,
+) -> LinuxResult {
+    check_null_ptr(cpuset)?;
+    if cpusetsize * 8 < axconfig::SMP {
+        return Err(LinuxError::EINVAL);
+    }
+    let bytes = unsafe { core::slice::from_raw_parts_mut(cpuset as *mut u8, cpusetsize) };
+    bytes.fill(0);
+    for cpu_id in &cpumask {
+        bytes[cpu_id / 8] |= 1 << (cpu_id % 8);
+    }
+    Ok(())
+}
+
+/// Set the CPU affinity mask of the thread whose ID is `pid`.
+///
+/// If `pid` is zero, the calling thread is used. Returns `EINVAL` if the mask
+/// is empty or not allowed for the thread, see [`axtask::set_affinity`].
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    cpuset: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "sys_sched_setaffinity <= {} {} {:#x}",
+        pid, cpusetsize, cpuset as usize
+    );
+    syscall_body!(sys_sched_setaffinity, {
+        let task = super::pthread::task_by_tid(pid)?;
+        let cpumask = unsafe { cpu_set_to_mask(cpusetsize, cpuset)? };
+        if !axtask::set_affinity(&task, cpumask) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(0)
+    })
+}
+
+/// Get the CPU affinity mask of the thread whose ID is `pid`.
+///
+/// If `pid` is zero, the calling thread is used.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    cpuset: *mut ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "sys_sched_getaffinity <= {} {} {:#x}",
+        pid, cpusetsize, cpuset as usize
+    );
+    syscall_body!(sys_sched_getaffinity, {
+        let task = super::pthread::task_by_tid(pid)?;
+        unsafe { mask_to_cpu_set(task.cpumask(), cpusetsize, cpuset)? };
+        Ok(0)
+    })
+}
+
+/// Converts a POSIX scheduling policy to [`SchedPolicy`].
+#[cfg(feature = "multitask")]
+fn sched_policy_from(policy: c_int) -> LinuxResult<SchedPolicy> {
+    // The values of `SchedPolicy` are the same as the `SCHED_*` constants.
+    u8::try_from(policy)
+        .ok()
+        .and_then(|policy| SchedPolicy::try_from(policy).ok())
+        .ok_or(LinuxError::EINVAL)
+}
+
+/// Set the scheduling policy and priority of the thread whose ID is `pid`.
+///
+/// If `pid` is zero, the calling thread is used.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setscheduler(
+    pid: c_int,
+    policy: c_int,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    debug!(
+        "sys_sched_setscheduler <= {} {} {:#x}",
+        pid, policy, param as usize
+    );
+    syscall_body!(sys_sched_setscheduler, {
+        check_null_ptr(param)?;
+        let policy = sched_policy_from(policy)?;
+        let prio = unsafe { (*param).sched_priority };
+        if prio < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let task = super::pthread::task_by_tid(pid)?;
+        if !axtask::set_sched_policy(&task, policy, prio as usize) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(0)
+    })
+}
+
+/// Get the scheduling policy of the thread whose ID is `pid`.
+///
+/// If `pid` is zero, the calling thread is used.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_getscheduler(pid: c_int) -> c_int {
+    debug!("sys_sched_getscheduler <= {}", pid);
+    syscall_body!(sys_sched_getscheduler, {
+        let task = super::pthread::task_by_tid(pid)?;
+        Ok(task.sched_policy() as c_int)
+    })
+}
+
+/// Get the scheduling priority of the thread whose ID is `pid`.
+///
+/// If `pid` is zero, the calling thread is used.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getparam(pid: c_int, param: *mut ctypes::sched_param) -> c_int {
+    debug!("sys_sched_getparam <= {} {:#x}", pid, param as usize);
+    syscall_body!(sys_sched_getparam, {
+        check_null_ptr(param)?;
+        let task = super::pthread::task_by_tid(pid)?;
+        unsafe { (*param).sched_priority = task.sched_priority() as c_int };
+        Ok(0)
+    })
+}
+
+/// Get the maximum priority value of the scheduling policy.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_get_priority_max(policy: c_int) -> c_int {
+    debug!("sys_sched_get_priority_max <= {}", policy);
+    syscall_body!(sys_sched_get_priority_max, {
+        let policy = sched_policy_from(policy)?;
+        Ok(if policy.is_realtime() {
+            axtask::RT_PRIO_MAX as c_int
+        } else {
+            0
+        })
+    })
+}
+
+/// Get the minimum priority value of the scheduling policy.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_get_priority_min(policy: c_int) -> c_int {
+    debug!("sys_sched_get_priority_min <= {}", policy);
+    syscall_body!(sys_sched_get_priority_min, {
+        let policy = sched_policy_from(policy)?;
+        Ok(if policy.is_realtime() {
+            axtask::RT_PRIO_MIN as c_int
+        } else {
+            0
+        })
+    })
+}
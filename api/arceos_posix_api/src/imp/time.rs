@@ -34,19 +34,84 @@ impl From<Duration> for ctypes::timeval {
     }
 }
 
-/// Get clock time since booting
-pub unsafe fn sys_clock_gettime(_clk: ctypes::clockid_t, ts: *mut ctypes::timespec) -> c_int {
+/// Converts the absolute time of [`sys_clock_gettime`] to the duration from
+/// now, which is zero if the time has passed.
+#[cfg(feature = "multitask")]
+pub(crate) fn timeout_from_abstime(
+    abstime: *const ctypes::timespec,
+) -> axerrno::LinuxResult<Duration> {
+    crate::utils::check_null_ptr(abstime)?;
+    let ts = unsafe { *abstime };
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::from(ts).saturating_sub(axhal::time::current_time()))
+}
+
+/// Returns the user and system CPU time of the calling thread, or of all
+/// threads if `thread` is `false`.
+pub(crate) fn cpu_times(thread: bool) -> (Duration, Duration) {
+    #[cfg(feature = "multitask")]
+    {
+        let stat = if thread {
+            axtask::current().stat()
+        } else {
+            axtask::total_stat()
+        };
+        (stat.user_time, stat.system_time)
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        let _ = thread;
+        (axhal::time::current_time(), Duration::ZERO)
+    }
+}
+
+fn duration_to_ticks(d: Duration) -> ctypes::clock_t {
+    (d.as_nanos() * axconfig::TICKS_PER_SEC as u128 / axhal::time::NANOS_PER_SEC as u128) as _
+}
+
+/// Get clock time since booting, or the CPU time of the process or thread
+pub unsafe fn sys_clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespec) -> c_int {
     syscall_body!(sys_clock_gettime, {
         if ts.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        let now = axhal::time::current_time().into();
+        let now = match clk as u32 {
+            ctypes::CLOCK_PROCESS_CPUTIME_ID | ctypes::CLOCK_THREAD_CPUTIME_ID => {
+                let (utime, stime) = cpu_times(clk as u32 == ctypes::CLOCK_THREAD_CPUTIME_ID);
+                utime + stime
+            }
+            _ => axhal::time::current_time(),
+        }
+        .into();
         unsafe { *ts = now };
         debug!("sys_clock_gettime: {}.{:09}s", now.tv_sec, now.tv_nsec);
         Ok(0)
     })
 }
 
+/// Get process times, returns the elapsed clock ticks since booting
+///
+/// All threads are counted as one process, and there are no child processes.
+pub unsafe fn sys_times(buf: *mut ctypes::tms) -> ctypes::clock_t {
+    debug!("sys_times <= {:#x}", buf as usize);
+    syscall_body!(sys_times, {
+        if !buf.is_null() {
+            let (utime, stime) = cpu_times(false);
+            unsafe {
+                *buf = ctypes::tms {
+                    tms_utime: duration_to_ticks(utime),
+                    tms_stime: duration_to_ticks(stime),
+                    tms_cutime: 0,
+                    tms_cstime: 0,
+                }
+            };
+        }
+        Ok(duration_to_ticks(axhal::time::current_time()))
+    })
+}
+
 /// Sleep some nanoseconds
 ///
 /// TODO: should be woken by signals, and set errno
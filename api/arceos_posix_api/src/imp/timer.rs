@@ -0,0 +1,167 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::ffi::{c_int, c_void};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{HrTimer, HrTimerMode};
+use spin::Mutex;
+
+use super::pthread::ForceSendSync;
+use crate::ctypes;
+use crate::utils::{check_null_mut_ptr, check_null_ptr};
+
+/// The maximum value returned by [`sys_timer_getoverrun`].
+const DELAYTIMER_MAX: usize = c_int::MAX as usize;
+
+static TIMERS: Mutex<BTreeMap<usize, Arc<HrTimer>>> = Mutex::new(BTreeMap::new());
+
+fn find_timer(timerid: ctypes::timer_t) -> LinuxResult<Arc<HrTimer>> {
+    TIMERS
+        .lock()
+        .get(&(timerid as usize))
+        .cloned()
+        .ok_or(LinuxError::EINVAL)
+}
+
+fn duration_from_timespec(ts: ctypes::timespec) -> LinuxResult<Duration> {
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::from(ts))
+}
+
+fn timer_get(timer: &HrTimer) -> ctypes::itimerspec {
+    match timer.expires() {
+        Some(expires) => ctypes::itimerspec {
+            it_interval: timer.period().unwrap_or_default().into(),
+            it_value: expires.saturating_sub(axhal::time::current_time()).into(),
+        },
+        None => ctypes::itimerspec::default(),
+    }
+}
+
+/// Create a per-process timer
+///
+/// Only `CLOCK_REALTIME` and `CLOCK_MONOTONIC` are supported, which are the
+/// same clock. Signals are not supported, so the notification method must
+/// be `SIGEV_NONE` or `SIGEV_THREAD`. For `SIGEV_THREAD`, the notification
+/// functions of all timers are called in a shared thread, and
+/// `sigev_notify_attributes` is ignored.
+pub unsafe fn sys_timer_create(
+    clockid: ctypes::clockid_t,
+    sevp: *mut ctypes::sigevent,
+    timerid: *mut ctypes::timer_t,
+) -> c_int {
+    debug!(
+        "sys_timer_create <= {} {:#x} {:#x}",
+        clockid, sevp as usize, timerid as usize
+    );
+    syscall_body!(sys_timer_create, {
+        check_null_mut_ptr(timerid)?;
+        if !matches!(
+            clockid as u32,
+            ctypes::CLOCK_REALTIME | ctypes::CLOCK_MONOTONIC
+        ) {
+            return Err(LinuxError::EINVAL);
+        }
+        // A null `sevp` means `SIGEV_SIGNAL` with `SIGALRM`.
+        check_null_mut_ptr(sevp).map_err(|_| LinuxError::EINVAL)?;
+        let sev = unsafe { *sevp };
+        let timer = match sev.sigev_notify as u32 {
+            ctypes::SIGEV_NONE => HrTimer::new(HrTimerMode::Irq, || {}),
+            ctypes::SIGEV_THREAD => {
+                let notify = unsafe { sev.__sev_fields.__sev_thread.sigev_notify_function }
+                    .ok_or(LinuxError::EINVAL)?;
+                let value = ForceSendSync(sev.sigev_value);
+                HrTimer::new(HrTimerMode::Task, move || {
+                    // Capture the whole wrapper, not the field that is not `Send`.
+                    let value = &value;
+                    unsafe { notify(value.0) }
+                })
+            }
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let timer = Arc::new(timer);
+        let id = Arc::as_ptr(&timer) as usize;
+        TIMERS.lock().insert(id, timer);
+        unsafe { *timerid = id as *mut c_void };
+        Ok(0)
+    })
+}
+
+/// Delete a per-process timer
+pub fn sys_timer_delete(timerid: ctypes::timer_t) -> c_int {
+    debug!("sys_timer_delete <= {:#x}", timerid as usize);
+    syscall_body!(sys_timer_delete, {
+        // The timer is canceled when it's dropped.
+        TIMERS
+            .lock()
+            .remove(&(timerid as usize))
+            .ok_or(LinuxError::EINVAL)?;
+        Ok(0)
+    })
+}
+
+/// Arm or disarm a per-process timer
+///
+/// The timer is disarmed if `new_value->it_value` is zero. The previous
+/// setting is returned in `old_value` if it's not null.
+pub unsafe fn sys_timer_settime(
+    timerid: ctypes::timer_t,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    debug!(
+        "sys_timer_settime <= {:#x} {} {:#x} {:#x}",
+        timerid as usize, flags, new_value as usize, old_value as usize
+    );
+    syscall_body!(sys_timer_settime, {
+        check_null_ptr(new_value)?;
+        let timer = find_timer(timerid)?;
+        let new_value = unsafe { *new_value };
+        let value = duration_from_timespec(new_value.it_value)?;
+        let interval = duration_from_timespec(new_value.it_interval)?;
+        if !old_value.is_null() {
+            unsafe { *old_value = timer_get(&timer) };
+        }
+        if value.is_zero() {
+            timer.cancel();
+        } else {
+            let deadline = if flags as u32 & ctypes::TIMER_ABSTIME != 0 {
+                value
+            } else {
+                axhal::time::current_time() + value
+            };
+            timer.start(deadline, Some(interval));
+        }
+        Ok(0)
+    })
+}
+
+/// Get the remaining time and the interval of a per-process timer
+pub unsafe fn sys_timer_gettime(
+    timerid: ctypes::timer_t,
+    curr_value: *mut ctypes::itimerspec,
+) -> c_int {
+    debug!(
+        "sys_timer_gettime <= {:#x} {:#x}",
+        timerid as usize, curr_value as usize
+    );
+    syscall_body!(sys_timer_gettime, {
+        check_null_mut_ptr(curr_value)?;
+        let timer = find_timer(timerid)?;
+        unsafe { *curr_value = timer_get(&timer) };
+        Ok(0)
+    })
+}
+
+/// Get the overrun count of a per-process timer, which is the number of
+/// expirations missed before the last notification
+pub fn sys_timer_getoverrun(timerid: ctypes::timer_t) -> c_int {
+    debug!("sys_timer_getoverrun <= {:#x}", timerid as usize);
+    syscall_body!(sys_timer_getoverrun, {
+        let timer = find_timer(timerid)?;
+        Ok(timer.overrun().min(DELAYTIMER_MAX))
+    })
+}
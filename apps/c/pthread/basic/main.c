@@ -1,7 +1,11 @@
 #include <assert.h>
+#include <errno.h>
 #include <pthread.h>
+#include <sched.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <string.h>
+#include <time.h>
 #include <unistd.h>
 
 void *ThreadFunc1(void *arg)
@@ -41,6 +45,93 @@ void *ThreadFunc3(void *arg)
     return 0;
 }
 
+static pthread_mutex_t pi_lock;
+static volatile int pi_locked = 0;
+
+void *ThreadFunc4(void *arg)
+{
+    struct sched_param param;
+
+    pthread_mutex_lock(&pi_lock);
+    pi_locked = 1;
+
+    // wait for the main thread to block on the lock
+    do {
+        usleep(1000);
+        assert(sched_getparam(0, &param) == 0);
+    } while (param.sched_priority == 0);
+    *(int *)arg = param.sched_priority;
+
+    pthread_mutex_unlock(&pi_lock);
+    assert(sched_getparam(0, &param) == 0);
+    assert(param.sched_priority == 0);
+    return 0;
+}
+
+static pthread_cond_t cond = PTHREAD_COND_INITIALIZER;
+static volatile int cond_locked = 0;
+
+void *ThreadFunc5(void *arg)
+{
+    pthread_mutex_lock(&lock);
+    cond_locked = 1;
+    pthread_mutex_unlock(&lock);
+    return 0;
+}
+
+static pthread_rwlock_t rwlock;
+
+void *ThreadFunc6(void *arg)
+{
+    pthread_rwlock_wrlock(&rwlock);
+
+    int value = *(int *)arg;
+
+    // long operation
+    for (int i = 0; i < 10000; i++) getpid();
+
+    *(int *)arg = value + 1;
+
+    pthread_rwlock_unlock(&rwlock);
+
+    pthread_rwlock_rdlock(&rwlock);
+    assert(*(int *)arg > 0);
+    pthread_rwlock_unlock(&rwlock);
+    return 0;
+}
+
+#define BARRIER_THREADS 4
+#define BARRIER_ROUNDS  10
+
+static pthread_barrier_t barrier;
+
+void *ThreadFunc7(void *arg)
+{
+    for (int i = 0; i < BARRIER_ROUNDS; i++) {
+        int res = pthread_barrier_wait(&barrier);
+        if (res == PTHREAD_BARRIER_SERIAL_THREAD) {
+            __atomic_fetch_add((int *)arg, 1, __ATOMIC_RELAXED);
+        } else {
+            assert(res == 0);
+        }
+    }
+    return 0;
+}
+
+void *ThreadFunc8(void *arg)
+{
+    *(volatile int *)arg = getpid();
+    return 0;
+}
+
+void *ThreadFunc9(void *arg)
+{
+    *(volatile int *)arg = getpid();
+    // wait to be canceled
+    while (1) usleep(1000);
+    return 0;
+}
+
 void test_create_join()
 {
     int res;
@@ -117,14 +208,179 @@ void test_mutex()
     assert(data == NUM_THREADS);
 }
 
+void test_mutex_prio_inherit()
+{
+    int protocol;
+    int boosted = 0;
+    pthread_t t;
+    pthread_mutexattr_t attr;
+    struct sched_param param = {.sched_priority = 10};
+
+    assert(pthread_mutexattr_init(&attr) == 0);
+    assert(pthread_mutexattr_setprotocol(&attr, PTHREAD_PRIO_PROTECT) == ENOTSUP);
+    assert(pthread_mutexattr_setprotocol(&attr, PTHREAD_PRIO_INHERIT) == 0);
+    assert(pthread_mutexattr_getprotocol(&attr, &protocol) == 0);
+    assert(protocol == PTHREAD_PRIO_INHERIT);
+    assert(pthread_mutex_init(&pi_lock, &attr) == 0);
+    pthread_mutexattr_destroy(&attr);
+
+    assert(pthread_create(&t, NULL, ThreadFunc4, &boosted) == 0);
+    while (!pi_locked) usleep(1000);
+
+    // the owner inherits the priority of the main thread
+    assert(sched_setscheduler(0, SCHED_FIFO, &param) == 0);
+    pthread_mutex_lock(&pi_lock);
+    pthread_mutex_unlock(&pi_lock);
+    param.sched_priority = 0;
+    assert(sched_setscheduler(0, SCHED_OTHER, &param) == 0);
+
+    assert(pthread_join(t, NULL) == 0);
+    printf("test_mutex_prio_inherit: priority = %d\n", boosted);
+    assert(boosted == 10);
+}
+
+void test_cond_timedwait()
+{
+    pthread_t t;
+    struct timespec abstime;
+
+    pthread_mutex_lock(&lock);
+    assert(clock_gettime(CLOCK_REALTIME, &abstime) == 0);
+    abstime.tv_nsec += 10000000;
+    if (abstime.tv_nsec >= 1000000000) {
+        abstime.tv_sec += 1;
+        abstime.tv_nsec -= 1000000000;
+    }
+    assert(pthread_cond_timedwait(&cond, &lock, &abstime) == -1);
+    assert(errno == ETIMEDOUT);
+
+    // the mutex is locked again after the timeout
+    assert(pthread_create(&t, NULL, ThreadFunc5, NULL) == 0);
+    usleep(10000);
+    assert(!cond_locked);
+    pthread_mutex_unlock(&lock);
+
+    assert(pthread_join(t, NULL) == 0);
+    assert(cond_locked);
+    puts("test_cond_timedwait: timed out with the mutex locked");
+}
+
+void test_rwlock()
+{
+    const int NUM_THREADS = 100;
+    int data = 0;
+    pthread_t t[NUM_THREADS];
+
+    assert(pthread_rwlock_init(&rwlock, NULL) == 0);
+
+    // readers share the lock, and exclude writers
+    assert(pthread_rwlock_rdlock(&rwlock) == 0);
+    assert(pthread_rwlock_tryrdlock(&rwlock) == 0);
+    assert(pthread_rwlock_trywrlock(&rwlock) == -1 && errno == EBUSY);
+    assert(pthread_rwlock_unlock(&rwlock) == 0);
+    assert(pthread_rwlock_trywrlock(&rwlock) == -1 && errno == EBUSY);
+    assert(pthread_rwlock_unlock(&rwlock) == 0);
+
+    // a writer excludes both readers and writers
+    assert(pthread_rwlock_trywrlock(&rwlock) == 0);
+    assert(pthread_rwlock_tryrdlock(&rwlock) == -1 && errno == EBUSY);
+    assert(pthread_rwlock_trywrlock(&rwlock) == -1 && errno == EBUSY);
+    assert(pthread_rwlock_unlock(&rwlock) == 0);
+    assert(pthread_rwlock_tryrdlock(&rwlock) == 0);
+    assert(pthread_rwlock_unlock(&rwlock) == 0);
+
+    for (int i = 0; i < NUM_THREADS; i++) {
+        int res = pthread_create(&t[i], NULL, ThreadFunc6, &data);
+        if (res != 0) {
+            puts("pthread create fail");
+            return;
+        }
+    }
+
+    for (int i = 0; i < NUM_THREADS; i++) {
+        int res = pthread_join(t[i], NULL);
+        if (res != 0) {
+            puts("pthread join fail");
+        }
+    }
+
+    assert(pthread_rwlock_destroy(&rwlock) == 0);
+    printf("test_rwlock: data = %d\n", data);
+    assert(data == NUM_THREADS);
+}
+
+void test_barrier()
+{
+    int serial = 0;
+    pthread_t t[BARRIER_THREADS];
+
+    assert(pthread_barrier_init(&barrier, NULL, 0) == -1 && errno == EINVAL);
+    assert(pthread_barrier_init(&barrier, NULL, BARRIER_THREADS) == 0);
+
+    for (int i = 0; i < BARRIER_THREADS; i++) {
+        assert(pthread_create(&t[i], NULL, ThreadFunc7, &serial) == 0);
+    }
+    for (int i = 0; i < BARRIER_THREADS; i++) {
+        assert(pthread_join(t[i], NULL) == 0);
+    }
+
+    assert(pthread_barrier_destroy(&barrier) == 0);
+    printf("test_barrier: serial threads = %d\n", serial);
+    assert(serial == BARRIER_ROUNDS);
+}
+
+void test_tid_reuse()
+{
+    volatile int tid0 = 0, tid1 = 0, tid2 = 0;
+    void *thread_result;
+    cpu_set_t set;
+    pthread_t t;
+
+    assert(pthread_create(&t, NULL, ThreadFunc8, (void *)&tid1) == 0);
+    while (!tid1) usleep(1000);
+    // an exited thread can not be found by its ID, even if it's not joined
+    while (sched_getaffinity(tid1, sizeof(set), &set) == 0) usleep(1000);
+    assert(errno == ESRCH);
+    assert(pthread_join(t, NULL) == 0);
+    assert(sched_getaffinity(tid1, sizeof(set), &set) == -1 && errno == ESRCH);
+
+    // the joined threads are freed after another thread exits, then the ID
+    // is reused as the lowest free one
+    assert(pthread_create(&t, NULL, ThreadFunc8, (void *)&tid0) == 0);
+    assert(pthread_join(t, NULL) == 0);
+    usleep(10000);
+    assert(pthread_create(&t, NULL, ThreadFunc9, (void *)&tid2) == 0);
+    while (!tid2) usleep(1000);
+    assert(tid2 == tid1);
+
+    // the reused ID refers to the new thread
+    CPU_ZERO(&set);
+    CPU_SET(1, &set);
+    assert(sched_setaffinity(tid2, sizeof(set), &set) == 0);
+    CPU_ZERO(&set);
+    assert(sched_getaffinity(tid2, sizeof(set), &set) == 0);
+    assert(CPU_ISSET(1, &set) && !CPU_ISSET(0, &set));
+    assert(pthread_cancel(t) == 0);
+    assert(pthread_join(t, &thread_result) == 0);
+    assert(thread_result == PTHREAD_CANCELED);
+    assert(sched_getaffinity(tid2, sizeof(set), &set) == -1 && errno == ESRCH);
+    puts("test_tid_reuse: the reused ID refers to the new thread");
+}
+
 int main()
 {
     pthread_t main_thread = pthread_self();
     assert(main_thread != 0);
 
+    // before any other thread exits
+    test_tid_reuse();
     test_create_join();
     test_create_exit();
     test_mutex();
+    test_mutex_prio_inherit();
+    test_cond_timedwait();
+    test_rwlock();
+    test_barrier();
     puts("(C)Pthread basic tests run OK!");
 
     return 0;
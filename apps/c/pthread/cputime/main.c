@@ -0,0 +1,130 @@
+#include <assert.h>
+#include <errno.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/times.h>
+#include <time.h>
+#include <unistd.h>
+
+static long long ts_to_ns(struct timespec *ts)
+{
+    return (long long)ts->tv_sec * 1000000000 + ts->tv_nsec;
+}
+
+static long long tv_to_ns(struct timeval *tv)
+{
+    return (long long)tv->tv_sec * 1000000000 + (long long)tv->tv_usec * 1000;
+}
+
+static long long clock_ns(clockid_t clk)
+{
+    struct timespec ts;
+    assert(clock_gettime(clk, &ts) == 0);
+    return ts_to_ns(&ts);
+}
+
+static void spin_ms(int ms)
+{
+    long long end = clock_ns(CLOCK_MONOTONIC) + (long long)ms * 1000000;
+    while (clock_ns(CLOCK_MONOTONIC) < end) {
+    }
+}
+
+void *spin_thread(void *arg)
+{
+    long long start = clock_ns(CLOCK_THREAD_CPUTIME_ID);
+    spin_ms(200);
+    long long used = clock_ns(CLOCK_THREAD_CPUTIME_ID) - start;
+    assert(used >= 150000000 && used <= 300000000);
+
+    struct rusage ru;
+    assert(getrusage(RUSAGE_THREAD, &ru) == 0);
+    long long ru_used = tv_to_ns(&ru.ru_utime) + tv_to_ns(&ru.ru_stime);
+    assert(ru_used >= 150000000);
+    assert(ru_used <= clock_ns(CLOCK_THREAD_CPUTIME_ID));
+    return NULL;
+}
+
+void *sleep_thread(void *arg)
+{
+    long long start = clock_ns(CLOCK_THREAD_CPUTIME_ID);
+    usleep(200000);
+    long long used = clock_ns(CLOCK_THREAD_CPUTIME_ID) - start;
+    assert(used < 50000000);
+
+    struct rusage ru;
+    assert(getrusage(RUSAGE_THREAD, &ru) == 0);
+    assert(ru.ru_nvcsw >= 1);
+    return NULL;
+}
+
+void test_thread_cputime()
+{
+    pthread_t t1, t2;
+    pthread_create(&t1, NULL, spin_thread, NULL);
+    pthread_create(&t2, NULL, sleep_thread, NULL);
+    pthread_join(t1, NULL);
+    pthread_join(t2, NULL);
+    puts("test_thread_cputime OK");
+}
+
+void test_process_cputime()
+{
+    const int NUM_THREADS = 3;
+    pthread_t t[NUM_THREADS];
+
+    long long start = clock_ns(CLOCK_PROCESS_CPUTIME_ID);
+    for (int i = 0; i < NUM_THREADS; i++) {
+        pthread_create(&t[i], NULL, spin_thread, NULL);
+    }
+    for (int i = 0; i < NUM_THREADS; i++) {
+        pthread_join(t[i], NULL);
+    }
+    // the time of the exited threads is still counted
+    long long used = clock_ns(CLOCK_PROCESS_CPUTIME_ID) - start;
+    assert(used >= NUM_THREADS * 150000000LL);
+
+    struct rusage ru;
+    assert(getrusage(RUSAGE_SELF, &ru) == 0);
+    assert(tv_to_ns(&ru.ru_utime) + tv_to_ns(&ru.ru_stime) >= used);
+    puts("test_process_cputime OK");
+}
+
+void test_getrusage_args()
+{
+    struct rusage ru;
+    assert(getrusage(RUSAGE_CHILDREN, &ru) == 0);
+    assert(ru.ru_utime.tv_sec == 0 && ru.ru_utime.tv_usec == 0);
+    assert(ru.ru_stime.tv_sec == 0 && ru.ru_stime.tv_usec == 0);
+    assert(getrusage(12345, &ru) == -1 && errno == EINVAL);
+    puts("test_getrusage_args OK");
+}
+
+void test_times()
+{
+    long ticks_per_sec = sysconf(_SC_CLK_TCK);
+    assert(ticks_per_sec > 0);
+
+    struct tms before, after;
+    clock_t start = times(&before);
+    spin_ms(200);
+    clock_t end = times(&after);
+    assert(end - start >= ticks_per_sec / 10);
+
+    clock_t used = (after.tms_utime + after.tms_stime) - (before.tms_utime + before.tms_stime);
+    assert(used >= ticks_per_sec / 10);
+    assert(after.tms_cutime == 0 && after.tms_cstime == 0);
+    assert(times(NULL) >= end);
+    puts("test_times OK");
+}
+
+int main()
+{
+    test_thread_cputime();
+    test_process_cputime();
+    test_getrusage_args();
+    test_times();
+    puts("(C)Pthread CPU time tests run OK!");
+    return 0;
+}
@@ -38,6 +38,49 @@ fn test_btree_map(rng: &mut impl RngCore) {
     println!("test_btree_map() OK!");
 }
 
+#[cfg(all(feature = "axstd", feature = "alloc-trace"))]
+fn test_leak_report() {
+    use std::boxed::Box;
+    use std::os::arceos::api::mem::ax_alloc_snapshot;
+    const N: usize = 100;
+
+    let before = ax_alloc_snapshot();
+    let mut leaked = Vec::with_capacity(N);
+    for i in 0..N {
+        leaked.push(Box::leak(Box::new([i as u64; 8])) as *const _);
+    }
+    drop(leaked);
+    let after = ax_alloc_snapshot();
+
+    let diff = after.diff(&before);
+    println!("{}", diff);
+    let site = &diff.sites()[0];
+    assert_eq!(site.allocated, N);
+    assert_eq!(site.allocated_bytes, N * 64);
+    assert_eq!(site.freed, 0);
+    println!("test_leak_report() OK!");
+}
+
+#[cfg(all(feature = "axstd", feature = "alloc-debug"))]
+fn test_heap_check(rng: &mut impl RngCore) {
+    use std::os::arceos::api::mem::ax_alloc_check_heap;
+
+    // Fill the quarantine with freed blocks of various sizes.
+    let mut blocks = Vec::new();
+    for _ in 0..10_000 {
+        let len = (rng.next_u32() % 512) as usize;
+        blocks.push(vec![0xffu8; len]);
+        if rng.next_u32() % 2 == 0 {
+            let idx = rng.next_u32() as usize % blocks.len();
+            blocks.swap_remove(idx);
+        }
+    }
+    ax_alloc_check_heap();
+    drop(blocks);
+    ax_alloc_check_heap();
+    println!("test_heap_check() OK!");
+}
+
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
     println!("Running memory tests...");
@@ -45,6 +88,10 @@ fn main() {
     let mut rng = SmallRng::seed_from_u64(0xdead_beef);
     test_vec(&mut rng);
     test_btree_map(&mut rng);
+    #[cfg(all(feature = "axstd", feature = "alloc-trace"))]
+    test_leak_report();
+    #[cfg(all(feature = "axstd", feature = "alloc-debug"))]
+    test_heap_check(&mut rng);
 
     println!("Memory tests run OK!");
 }
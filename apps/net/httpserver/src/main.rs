@@ -5,6 +5,9 @@
 //! ```
 //! ab -n 5000 -c 20 http://X.X.X.X:5555/
 //! ```
+//!
+//! On ArceOS, each connection is served by an asynchronous task instead of a
+//! thread, so that the concurrent connections do not need their own stacks.
 
 #![cfg_attr(feature = "axstd", no_std)]
 #![cfg_attr(feature = "axstd", no_main)]
@@ -13,8 +16,18 @@
 #[cfg(feature = "axstd")]
 extern crate axstd as std;
 
-use std::io::{self, prelude::*};
+use std::io;
+
+#[cfg(feature = "axstd")]
+use std::executor;
+#[cfg(feature = "axstd")]
+use std::net::{AsyncTcpListener, AsyncTcpStream};
+
+#[cfg(not(feature = "axstd"))]
+use std::io::prelude::*;
+#[cfg(not(feature = "axstd"))]
 use std::net::{TcpListener, TcpStream};
+#[cfg(not(feature = "axstd"))]
 use std::thread;
 
 const LOCAL_IP: &str = "0.0.0.0";
@@ -59,6 +72,43 @@ macro_rules! info {
     };
 }
 
+#[cfg(feature = "axstd")]
+async fn http_server(mut stream: AsyncTcpStream) -> io::Result<()> {
+    let mut buf = [0u8; 4096];
+    let _len = stream.read(&mut buf).await?;
+
+    let response = format!(header!(), CONTENT.len(), CONTENT);
+    stream.write_all(response.as_bytes()).await?;
+
+    Ok(())
+}
+
+#[cfg(feature = "axstd")]
+fn accept_loop() -> io::Result<()> {
+    let listener = AsyncTcpListener::bind((LOCAL_IP, LOCAL_PORT))?;
+    println!("listen on: http://{}/", listener.local_addr().unwrap());
+
+    executor::block_on(async move {
+        let mut i = 0;
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    info!("new client {}: {}", i, addr);
+                    executor::spawn(async move {
+                        match http_server(stream).await {
+                            Err(e) => info!("client connection error: {:?}", e),
+                            Ok(()) => info!("client {} closed successfully", i),
+                        }
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+            i += 1;
+        }
+    })
+}
+
+#[cfg(not(feature = "axstd"))]
 fn http_server(mut stream: TcpStream) -> io::Result<()> {
     let mut buf = [0u8; 4096];
     let _len = stream.read(&mut buf)?;
@@ -69,6 +119,7 @@ fn http_server(mut stream: TcpStream) -> io::Result<()> {
     Ok(())
 }
 
+#[cfg(not(feature = "axstd"))]
 fn accept_loop() -> io::Result<()> {
     let listener = TcpListener::bind((LOCAL_IP, LOCAL_PORT))?;
     println!("listen on: http://{}/", listener.local_addr().unwrap());
@@ -0,0 +1,108 @@
+//! Spin lock contention benchmark.
+//!
+//! One task is pinned on each CPU, and all of them increase a counter
+//! protected by a `SpinNoIrq` lock as fast as possible. The throughput, the
+//! acquisitions of each CPU and the longest wait time are reported for each
+//! lock algorithm, to compare their fairness.
+
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axstd as std;
+
+use std::os::arceos::api::{config::SMP, task::AxCpuMask};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use spinlock::{McsLock, RawSpinLock, SpinNoIrq, TasLock, TicketLock};
+
+const DURATION: Duration = Duration::from_secs(2);
+/// The work in the critical section, in loop iterations.
+const CS_WORK: usize = 50;
+/// The work out of the critical section, in loop iterations.
+const NCS_WORK: usize = 100;
+
+struct TaskResult {
+    count: u64,
+    max_wait: Duration,
+}
+
+fn busy(iters: usize) {
+    for i in 0..iters {
+        core::hint::black_box(i);
+    }
+}
+
+fn bench<L: RawSpinLock + Send + Sync + 'static>(name: &str) {
+    let lock = Arc::new(SpinNoIrq::<u64, L>::new(0));
+    let ready = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let tasks: Vec<_> = (0..SMP)
+        .map(|cpu| {
+            let (lock, ready, stop) = (lock.clone(), ready.clone(), stop.clone());
+            let mut cpumask = AxCpuMask::new();
+            cpumask.set(cpu, true);
+            thread::Builder::new()
+                .affinity(cpumask)
+                .spawn(move || {
+                    // Start together, and let others run on this CPU until then.
+                    ready.fetch_add(1, Ordering::AcqRel);
+                    while ready.load(Ordering::Acquire) < SMP {
+                        thread::yield_now();
+                    }
+                    let start = Instant::now();
+                    let mut res = TaskResult {
+                        count: 0,
+                        max_wait: Duration::ZERO,
+                    };
+                    while !stop.load(Ordering::Relaxed) {
+                        let wait_start = Instant::now();
+                        let mut guard = lock.lock();
+                        res.max_wait = res.max_wait.max(wait_start.elapsed());
+                        *guard += 1;
+                        busy(CS_WORK);
+                        drop(guard);
+
+                        res.count += 1;
+                        busy(NCS_WORK);
+                        if start.elapsed() >= DURATION {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    }
+                    res
+                })
+                .unwrap()
+        })
+        .collect();
+
+    let results: Vec<TaskResult> = tasks.into_iter().map(|t| t.join().unwrap()).collect();
+    let total: u64 = results.iter().map(|r| r.count).sum();
+    assert_eq!(*lock.lock(), total);
+
+    let min = results.iter().map(|r| r.count).min().unwrap();
+    let max = results.iter().map(|r| r.count).max().unwrap();
+    let max_wait = results.iter().map(|r| r.max_wait).max().unwrap();
+    println!(
+        "{:<8} {:>10} ops/s, per CPU min/max = {}/{} ({}%), max wait = {:?}",
+        name,
+        total / DURATION.as_secs(),
+        min,
+        max,
+        min * 100 / max.max(1),
+        max_wait,
+    );
+}
+
+#[no_mangle]
+fn main() {
+    println!("Spin lock contention benchmark on {} CPUs...", SMP);
+    bench::<TasLock>("tas");
+    bench::<TicketLock>("ticket");
+    bench::<McsLock>("mcs");
+    println!("Spin lock benchmark run OK!");
+}
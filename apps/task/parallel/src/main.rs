@@ -94,5 +94,11 @@ fn main() {
     println!("sum = {}", actual);
     assert_eq!(expect, actual);
 
+    #[cfg(feature = "axstd")]
+    println!(
+        "allocator cache: {}",
+        std::os::arceos::api::mem::ax_alloc_cache_stats()
+    );
+
     println!("Parallel summation tests run OK!");
 }
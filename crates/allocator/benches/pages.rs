@@ -0,0 +1,73 @@
+mod utils;
+
+use allocator::{BaseAllocator, BitmapPageAllocator, BuddyPageAllocator, PageAllocator};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+
+use self::utils::MemoryPool;
+
+const PAGE_SIZE: usize = 0x1000;
+const POOL_SIZE: usize = 1024 * 1024 * 512;
+
+/// Allocates `n` blocks of `num_pages` pages, and frees them in random order.
+fn pages_rand_free(alloc: &mut impl PageAllocator, n: usize, num_pages: usize) {
+    let mut rng = SmallRng::seed_from_u64(0xdead_beef);
+    let mut blocks: Vec<_> = (0..n)
+        .map(|_| alloc.alloc_pages(num_pages, PAGE_SIZE).unwrap())
+        .collect();
+    blocks.shuffle(&mut rng);
+    for addr in blocks {
+        alloc.dealloc_pages(addr, num_pages);
+    }
+}
+
+/// Allocates and frees blocks of random sizes up to 256 pages, keeping at
+/// most `n` blocks allocated.
+fn pages_mixed(alloc: &mut impl PageAllocator, n: usize, rounds: usize) {
+    let mut rng = SmallRng::seed_from_u64(0xdead_beef);
+    let mut blocks = Vec::with_capacity(n);
+    for _ in 0..rounds {
+        if blocks.len() == n || (!blocks.is_empty() && rng.gen_ratio(1, 2)) {
+            let idx = rng.gen_range(0..blocks.len());
+            let (addr, num_pages) = blocks.swap_remove(idx);
+            alloc.dealloc_pages(addr, num_pages);
+        } else {
+            let num_pages = rng.gen_range(1..=256);
+            let addr = alloc.alloc_pages(num_pages, PAGE_SIZE).unwrap();
+            blocks.push((addr, num_pages));
+        }
+    }
+    for (addr, num_pages) in blocks {
+        alloc.dealloc_pages(addr, num_pages);
+    }
+}
+
+fn bench<A: PageAllocator>(c: &mut Criterion, alloc_name: &str, alloc: &mut A) {
+    let mut g = c.benchmark_group(alloc_name);
+    g.bench_function("pages_rand_free_64K_1", |b| {
+        b.iter(|| pages_rand_free(alloc, black_box(65_536), 1));
+    });
+    g.sample_size(10);
+    g.bench_function("pages_rand_free_1K_64", |b| {
+        b.iter(|| pages_rand_free(alloc, black_box(1_024), 64));
+    });
+    g.bench_function("pages_mixed_256_100K", |b| {
+        b.iter(|| pages_mixed(alloc, black_box(256), black_box(100_000)));
+    });
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut pool = MemoryPool::new(POOL_SIZE);
+    let start = pool.as_slice().as_ptr() as usize;
+
+    let mut bitmap = BitmapPageAllocator::<PAGE_SIZE>::new();
+    bitmap.init(start, POOL_SIZE);
+    bench(c, "bitmap", &mut bitmap);
+
+    let mut buddy = BuddyPageAllocator::<PAGE_SIZE>::new();
+    buddy.init(start, POOL_SIZE);
+    bench(c, "buddy_page", &mut buddy);
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,224 @@
+//! Bitmap allocation of IDs.
+
+use crate::{AllocError, AllocResult, BaseAllocator, IdAllocator};
+
+const BITS: usize = u64::BITS as usize;
+/// The maximum number of words, limited by the size of the summary.
+const MAX_WORDS: usize = BITS * BITS;
+
+/// Splits `[start, end)` into the indices of words and the masks of the IDs
+/// in them. The range must not be empty.
+fn masks(start: usize, end: usize) -> impl Iterator<Item = (usize, u64)> {
+    (start / BITS..end.div_ceil(BITS)).map(move |w| {
+        let lo = start.max(w * BITS) - w * BITS;
+        let hi = end.min((w + 1) * BITS) - w * BITS;
+        (w, (u64::MAX >> (BITS - (hi - lo))) << lo)
+    })
+}
+
+/// An ID allocator based on a two-level bitmap.
+///
+/// It manages the IDs in `[0, 64 * WORDS)`, of which the usable ones are
+/// added by [`BaseAllocator::init`] and [`BaseAllocator::add_memory`]. Each
+/// ID has a bit indicating whether it's free, and each word of the bitmap has
+/// a bit in the summary indicating whether it has any free ID. So the lowest
+/// free ID is found in `O(WORDS / 64)` time at most, which keeps the
+/// allocated IDs small, and freed IDs are reused first.
+///
+/// Contiguous IDs are allocated by scanning the free ones from the lowest,
+/// skipping the ranges that contain allocated IDs.
+///
+/// `WORDS` must be no more than 4096, i.e., at most 256K IDs.
+pub struct BitmapIdAllocator<const WORDS: usize> {
+    /// Whether each ID is free, one bit per ID.
+    free: [u64; WORDS],
+    /// Whether each ID is added to the allocator.
+    usable: [u64; WORDS],
+    /// Whether each word of `free` is not zero.
+    summary: [u64; BITS],
+    total: usize,
+    used: usize,
+}
+
+impl<const WORDS: usize> BitmapIdAllocator<WORDS> {
+    /// The number of IDs that the bitmap can hold.
+    const CAPACITY: usize = WORDS * BITS;
+
+    /// Creates a new empty `BitmapIdAllocator`.
+    pub const fn new() -> Self {
+        assert!(WORDS <= MAX_WORDS);
+        Self {
+            free: [0; WORDS],
+            usable: [0; WORDS],
+            summary: [0; BITS],
+            total: 0,
+            used: 0,
+        }
+    }
+
+    fn is_free(&self, id: usize) -> bool {
+        self.free[id / BITS] & (1 << (id % BITS)) != 0
+    }
+
+    fn is_usable(&self, id: usize) -> bool {
+        self.usable[id / BITS] & (1 << (id % BITS)) != 0
+    }
+
+    /// Returns the first ID in `[start, end)` whose bit in `word(w)` is set,
+    /// where `w` is the index of its word.
+    fn find_in_range(
+        &self,
+        start: usize,
+        end: usize,
+        word: impl Fn(usize) -> u64,
+    ) -> Option<usize> {
+        masks(start, end).find_map(|(w, mask)| {
+            let bits = word(w) & mask;
+            (bits != 0).then(|| w * BITS + bits.trailing_zeros() as usize)
+        })
+    }
+
+    /// Marks the IDs in `[start, end)` as free or allocated.
+    fn set_free(&mut self, start: usize, end: usize, free: bool) {
+        for (w, mask) in masks(start, end) {
+            if free {
+                self.free[w] |= mask;
+            } else {
+                self.free[w] &= !mask;
+            }
+            let bit = 1 << (w % BITS);
+            if self.free[w] != 0 {
+                self.summary[w / BITS] |= bit;
+            } else {
+                self.summary[w / BITS] &= !bit;
+            }
+        }
+    }
+
+    /// Returns the lowest free ID that is no less than `from`.
+    fn next_free(&self, from: usize) -> Option<usize> {
+        if from >= Self::CAPACITY {
+            return None;
+        }
+        let w = from / BITS;
+        let bits = self.free[w] & (u64::MAX << (from % BITS));
+        if bits != 0 {
+            return Some(w * BITS + bits.trailing_zeros() as usize);
+        }
+        // Find the next word with free IDs from the summary.
+        let w = w + 1;
+        let next = (w / BITS..BITS).find_map(|s| {
+            let mask = if s == w / BITS {
+                u64::MAX << (w % BITS)
+            } else {
+                u64::MAX
+            };
+            let bits = self.summary[s] & mask;
+            (bits != 0).then(|| s * BITS + bits.trailing_zeros() as usize)
+        })?;
+        Some(next * BITS + self.free[next].trailing_zeros() as usize)
+    }
+}
+
+impl<const WORDS: usize> BaseAllocator for BitmapIdAllocator<WORDS> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.add_memory(start, size).unwrap();
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        let end = match start.checked_add(size) {
+            Some(end) if size > 0 && end <= Self::CAPACITY => end,
+            _ => return Err(AllocError::InvalidParam),
+        };
+        if self.find_in_range(start, end, |w| self.usable[w]).is_some() {
+            return Err(AllocError::MemoryOverlap);
+        }
+        for (w, mask) in masks(start, end) {
+            self.usable[w] |= mask;
+        }
+        self.set_free(start, end, true);
+        self.total += size;
+        Ok(())
+    }
+}
+
+impl<const WORDS: usize> IdAllocator for BitmapIdAllocator<WORDS> {
+    fn alloc_id(&mut self, count: usize, align_pow2: usize) -> AllocResult<usize> {
+        if count == 0 || !align_pow2.is_power_of_two() {
+            return Err(AllocError::InvalidParam);
+        }
+        if count > self.available() {
+            return Err(AllocError::NoMemory);
+        }
+        let mut from = 0;
+        while let Some(id) = self.next_free(from) {
+            let Some(start) = id.checked_next_multiple_of(align_pow2) else {
+                break;
+            };
+            let end = start.saturating_add(count);
+            if end > Self::CAPACITY {
+                break;
+            }
+            match self.find_in_range(start, end, |w| !self.free[w]) {
+                // Retry from the next ID of the allocated one.
+                Some(allocated) => from = allocated + 1,
+                None => {
+                    self.set_free(start, end, false);
+                    self.used += count;
+                    return Ok(start);
+                }
+            }
+        }
+        Err(AllocError::NoMemory)
+    }
+
+    fn dealloc_id(&mut self, start_id: usize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        let end = start_id.saturating_add(count);
+        assert!(
+            end <= Self::CAPACITY
+                && self
+                    .find_in_range(start_id, end, |w| !self.usable[w] | self.free[w])
+                    .is_none(),
+            "deallocate unallocated IDs: [{}, {})",
+            start_id,
+            end
+        );
+        self.set_free(start_id, end, true);
+        self.used -= count;
+    }
+
+    fn is_allocated(&self, id: usize) -> bool {
+        id < Self::CAPACITY && self.is_usable(id) && !self.is_free(id)
+    }
+
+    /// Marks the given `id` as allocated.
+    ///
+    /// Returns [`AllocError::InvalidParam`] if it's not added to the
+    /// allocator, or [`AllocError::MemoryOverlap`] if it's already allocated.
+    fn alloc_fixed_id(&mut self, id: usize) -> AllocResult {
+        if id >= Self::CAPACITY || !self.is_usable(id) {
+            return Err(AllocError::InvalidParam);
+        }
+        if !self.is_free(id) {
+            return Err(AllocError::MemoryOverlap);
+        }
+        self.set_free(id, id + 1, false);
+        self.used += 1;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.total
+    }
+
+    fn used(&self) -> usize {
+        self.used
+    }
+
+    fn available(&self) -> usize {
+        self.total - self.used
+    }
+}
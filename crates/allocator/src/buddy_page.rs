@@ -0,0 +1,292 @@
+//! Buddy allocation in page-granularity.
+
+use crate::{AllocError, AllocResult, BaseAllocator, PageAllocator};
+
+/// The maximum order of free blocks, i.e., a block has at most
+/// `2^MAX_ORDER` pages.
+const MAX_ORDER: usize = 28;
+/// The maximum number of memory regions.
+const MAX_REGIONS: usize = 16;
+
+/// The tag of the first page of a free block, with the order of the block in
+/// the low bits. Other pages are tagged with zero.
+const FREE_BLOCK: u8 = 0x80;
+
+/// The link of a free block, stored in its first page.
+struct FreeLink {
+    prev: usize,
+    next: usize,
+}
+
+/// A memory region managed by the allocator.
+#[derive(Clone, Copy)]
+struct Region {
+    /// The first usable page.
+    start: usize,
+    /// The end of the usable pages.
+    end: usize,
+    /// The pages in `[wild, end)` have never been touched. They are added to
+    /// the free lists on demand, and only the tags of the pages before it
+    /// are valid.
+    wild: usize,
+    /// The tags of the pages, one byte per page, at the beginning of the
+    /// region.
+    tags: usize,
+}
+
+/// A page-granularity memory allocator based on the buddy system.
+///
+/// Free blocks have `2^order` pages, and are aligned to their sizes. Each
+/// order has a doubly-linked list of free blocks, whose links are stored in
+/// the free blocks themselves. So the allocator writes to the managed memory,
+/// which must be accessible when it's allocated.
+///
+/// The metadata is built lazily: a newly added region is not touched, and
+/// its pages are added to the free lists from the low end only when the free
+/// lists can not satisfy an allocation. So a large region can be added
+/// before all of it is mapped, as long as the low pages are accessible.
+///
+/// A block is allocated by splitting a larger block if needed, and a freed
+/// block is merged with its buddy (the other half of the larger block)
+/// repeatedly as long as the buddy is free, both in `O(log n)` time. For the
+/// page counts that are not powers of two, the unused tail of the block is
+/// freed immediately.
+///
+/// Whether a block is free is recorded in a byte per page, which is taken
+/// from the beginning of each memory region, i.e., about `1 / PAGE_SIZE` of
+/// the memory, and is also initialized on demand. More regions can be added
+/// by [`BaseAllocator::add_memory`].
+///
+/// The `PAGE_SIZE` must be a power of two.
+pub struct BuddyPageAllocator<const PAGE_SIZE: usize> {
+    free_lists: [usize; MAX_ORDER + 1],
+    regions: [Region; MAX_REGIONS],
+    num_regions: usize,
+    total_pages: usize,
+    used_pages: usize,
+}
+
+impl<const PAGE_SIZE: usize> BuddyPageAllocator<PAGE_SIZE> {
+    /// Creates a new empty `BuddyPageAllocator`.
+    pub const fn new() -> Self {
+        Self {
+            free_lists: [0; MAX_ORDER + 1],
+            regions: [Region {
+                start: 0,
+                end: 0,
+                wild: 0,
+                tags: 0,
+            }; MAX_REGIONS],
+            num_regions: 0,
+            total_pages: 0,
+            used_pages: 0,
+        }
+    }
+
+    fn find_region(&self, addr: usize) -> Option<&Region> {
+        self.regions[..self.num_regions]
+            .iter()
+            .find(|r| (r.start..r.end).contains(&addr))
+    }
+
+    fn tag(region: &Region, addr: usize) -> *mut u8 {
+        (region.tags + (addr - region.start) / PAGE_SIZE) as *mut u8
+    }
+
+    fn push_free(&mut self, region: &Region, addr: usize, order: usize) {
+        let head = self.free_lists[order];
+        unsafe {
+            (addr as *mut FreeLink).write(FreeLink {
+                prev: 0,
+                next: head,
+            });
+            if head != 0 {
+                (*(head as *mut FreeLink)).prev = addr;
+            }
+            Self::tag(region, addr).write(FREE_BLOCK | order as u8);
+        }
+        self.free_lists[order] = addr;
+    }
+
+    fn remove_free(&mut self, region: &Region, addr: usize, order: usize) {
+        unsafe {
+            let FreeLink { prev, next } = (addr as *const FreeLink).read();
+            if prev != 0 {
+                (*(prev as *mut FreeLink)).next = next;
+            } else {
+                self.free_lists[order] = next;
+            }
+            if next != 0 {
+                (*(next as *mut FreeLink)).prev = prev;
+            }
+            Self::tag(region, addr).write(0);
+        }
+    }
+
+    /// Frees a block, and merges it with its buddies.
+    fn free_block(&mut self, region: &Region, mut addr: usize, mut order: usize) {
+        while order < MAX_ORDER {
+            let size = PAGE_SIZE << order;
+            let buddy = addr ^ size;
+            if buddy < region.start
+                || buddy + size > region.wild
+                || unsafe { Self::tag(region, buddy).read() } != FREE_BLOCK | order as u8
+            {
+                break;
+            }
+            self.remove_free(region, buddy, order);
+            addr = addr.min(buddy);
+            order += 1;
+        }
+        self.push_free(region, addr, order);
+    }
+
+    /// Frees the pages in `[start, end)` as the largest aligned blocks.
+    fn free_range(&mut self, region: &Region, start: usize, end: usize) {
+        let mut addr = start;
+        while addr < end {
+            let align_order = (addr / PAGE_SIZE).trailing_zeros() as usize;
+            let size_order = ((end - addr) / PAGE_SIZE).ilog2() as usize;
+            let order = align_order.min(size_order).min(MAX_ORDER);
+            self.free_block(region, addr, order);
+            addr += PAGE_SIZE << order;
+        }
+    }
+
+    /// Adds the lowest untouched pages of the regions to the free lists, as a
+    /// block of at most `2^order` pages. Returns `false` if all pages have
+    /// been added.
+    fn grow(&mut self, order: usize) -> bool {
+        let Some(idx) = self.regions[..self.num_regions]
+            .iter()
+            .position(|r| r.wild < r.end)
+        else {
+            return false;
+        };
+        let addr = self.regions[idx].wild;
+        let align_order = (addr / PAGE_SIZE).trailing_zeros() as usize;
+        let size_order = ((self.regions[idx].end - addr) / PAGE_SIZE).ilog2() as usize;
+        let order = align_order.min(size_order).min(order);
+        let num_pages = 1 << order;
+        self.regions[idx].wild += num_pages * PAGE_SIZE;
+        let region = self.regions[idx];
+        unsafe { core::ptr::write_bytes(Self::tag(&region, addr), 0, num_pages) };
+        self.free_block(&region, addr, order);
+        true
+    }
+
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let mut cur = loop {
+            if let Some(o) = (order..=MAX_ORDER).find(|&o| self.free_lists[o] != 0) {
+                break o;
+            }
+            if !self.grow(order) {
+                return None;
+            }
+        };
+        let addr = self.free_lists[cur];
+        let region = *self.find_region(addr).unwrap();
+        self.remove_free(&region, addr, cur);
+        // Split the block, and free the upper halves.
+        while cur > order {
+            cur -= 1;
+            self.push_free(&region, addr + (PAGE_SIZE << cur), cur);
+        }
+        Some(addr)
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        assert!(PAGE_SIZE.is_power_of_two());
+        self.add_memory(start, size).unwrap();
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        if self.num_regions == MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        let end = super::align_down(start + size, PAGE_SIZE);
+        let start = super::align_up(start, PAGE_SIZE);
+        if start == 0 || start >= end {
+            return Err(AllocError::InvalidParam);
+        }
+        if self.regions[..self.num_regions]
+            .iter()
+            .any(|r| r.tags < end && start < r.end)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+
+        // Each page needs a byte of tag besides itself.
+        let num_pages = (end - start) / (PAGE_SIZE + 1);
+        let first_page = start + super::align_up(num_pages, PAGE_SIZE);
+        let num_pages = num_pages.min((end - first_page) / PAGE_SIZE);
+        if num_pages == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        // The pages are added to the free lists on demand.
+        self.regions[self.num_regions] = Region {
+            start: first_page,
+            end: first_page + num_pages * PAGE_SIZE,
+            wild: first_page,
+            tags: start,
+        };
+        self.num_regions += 1;
+        self.total_pages += num_pages;
+        Ok(())
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if align_pow2 % PAGE_SIZE != 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        let align_pow2 = align_pow2 / PAGE_SIZE;
+        if !align_pow2.is_power_of_two() || num_pages == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        let order = (num_pages.next_power_of_two().trailing_zeros() as usize)
+            .max(align_pow2.trailing_zeros() as usize);
+        if order > MAX_ORDER {
+            return Err(AllocError::NoMemory);
+        }
+        let addr = self.alloc_block(order).ok_or(AllocError::NoMemory)?;
+        let end = addr + (PAGE_SIZE << order);
+        let tail = addr + num_pages * PAGE_SIZE;
+        if tail < end {
+            let region = *self.find_region(addr).unwrap();
+            self.free_range(&region, tail, end);
+        }
+        self.used_pages += num_pages;
+        Ok(addr)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        let region = *self
+            .find_region(pos)
+            .expect("dealloc pages out of the managed memory");
+        let end = pos + num_pages * PAGE_SIZE;
+        assert!(
+            end <= region.wild,
+            "dealloc pages out of the managed memory"
+        );
+        self.free_range(&region, pos, end);
+        self.used_pages -= num_pages;
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used_pages
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total_pages - self.used_pages
+    }
+}
@@ -5,8 +5,9 @@
 //! - [`ByteAllocator`]: Byte-granularity memory allocator. (e.g.,
 //!   [`BuddyByteAllocator`], [`SlabByteAllocator`])
 //! - [`PageAllocator`]: Page-granularity memory allocator. (e.g.,
-//!   [`BitmapPageAllocator`])
-//! - [`IdAllocator`]: Used to allocate unique IDs.
+//!   [`BitmapPageAllocator`], [`BuddyPageAllocator`])
+//! - [`IdAllocator`]: Used to allocate unique IDs. (e.g.,
+//!   [`BitmapIdAllocator`])
 
 #![no_std]
 #![cfg_attr(feature = "allocator_api", feature(allocator_api))]
@@ -16,6 +17,16 @@ mod bitmap;
 #[cfg(feature = "bitmap")]
 pub use bitmap::BitmapPageAllocator;
 
+#[cfg(feature = "bitmap_id")]
+mod bitmap_id;
+#[cfg(feature = "bitmap_id")]
+pub use bitmap_id::BitmapIdAllocator;
+
+#[cfg(feature = "buddy_page")]
+mod buddy_page;
+#[cfg(feature = "buddy_page")]
+pub use buddy_page::BuddyPageAllocator;
+
 #[cfg(feature = "buddy")]
 mod buddy;
 #[cfg(feature = "buddy")]
@@ -122,11 +133,13 @@ pub trait IdAllocator: BaseAllocator {
     fn available(&self) -> usize;
 }
 
+#[cfg(any(feature = "bitmap", feature = "buddy_page"))]
 #[inline]
 const fn align_down(pos: usize, align: usize) -> usize {
     pos & !(align - 1)
 }
 
+#[cfg(any(feature = "bitmap", feature = "buddy_page"))]
 #[inline]
 const fn align_up(pos: usize, align: usize) -> usize {
     (pos + align - 1) & !(align - 1)
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use allocator::{AllocError, BaseAllocator, BitmapIdAllocator, IdAllocator};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+/// Allocates and frees ranges of random sizes and alignments, and checks
+/// them against the allocated IDs recorded in a map.
+fn test_random<A: IdAllocator>(alloc: &mut A, n: usize) {
+    let mut rng = SmallRng::seed_from_u64(0xdead_beef);
+    let total = alloc.size();
+    let mut ranges = BTreeMap::new();
+    for _ in 0..n {
+        if rng.gen_ratio(2, 3) || ranges.is_empty() {
+            let max_count = 1 << rng.gen_range(0..7);
+            let count = rng.gen_range(1..=max_count);
+            let align_pow2 = 1 << rng.gen_range(0..5);
+            let Ok(start) = alloc.alloc_id(count, align_pow2) else {
+                continue;
+            };
+            assert_eq!(start % align_pow2, 0);
+            if let Some((&prev, &prev_count)) = ranges.range(..start + count).next_back() {
+                assert!(prev + prev_count <= start);
+            }
+            ranges.insert(start, count);
+            assert!((start..start + count).all(|id| alloc.is_allocated(id)));
+        } else {
+            let idx = rng.gen_range(0..ranges.len());
+            let start = *ranges.keys().nth(idx).unwrap();
+            let count = ranges.remove(&start).unwrap();
+            alloc.dealloc_id(start, count);
+            assert!(!(start..start + count).any(|id| alloc.is_allocated(id)));
+        }
+        let used: usize = ranges.values().sum();
+        assert_eq!(alloc.used(), used);
+        assert_eq!(alloc.available(), total - used);
+    }
+    for (start, count) in ranges {
+        alloc.dealloc_id(start, count);
+    }
+    assert_eq!(alloc.used(), 0);
+}
+
+#[test]
+fn bitmap_id_alloc() {
+    let mut alloc = BitmapIdAllocator::<64>::new();
+    alloc.init(1, 4095);
+    assert_eq!(alloc.size(), 4095);
+
+    // The lowest free IDs are allocated first, and freed IDs are reused.
+    let ids: Vec<_> = (0..100).map(|_| alloc.alloc_id(1, 1).unwrap()).collect();
+    assert_eq!(ids, (1..=100).collect::<Vec<_>>());
+    alloc.dealloc_id(42, 1);
+    alloc.dealloc_id(7, 1);
+    assert!(!alloc.is_allocated(7));
+    assert_eq!(alloc.alloc_id(1, 1).unwrap(), 7);
+    assert_eq!(alloc.alloc_id(1, 1).unwrap(), 42);
+    assert_eq!(alloc.alloc_id(1, 1).unwrap(), 101);
+
+    // Contiguous and aligned ranges skip the allocated IDs.
+    assert_eq!(alloc.alloc_id(30, 64).unwrap(), 128);
+    alloc.dealloc_id(10, 22);
+    assert_eq!(alloc.alloc_id(16, 16).unwrap(), 16);
+    assert_eq!(alloc.alloc_id(6, 1).unwrap(), 10);
+    assert_eq!(alloc.alloc_id(100, 1).unwrap(), 158);
+
+    alloc.alloc_fixed_id(1000).unwrap();
+    assert!(alloc.is_allocated(1000));
+    assert_eq!(alloc.used(), 101 - 22 + 30 + 16 + 6 + 100 + 1);
+    alloc.dealloc_id(1000, 1);
+    for (start, count) in [(1, 9), (10, 6), (16, 16), (32, 70), (128, 30), (158, 100)] {
+        alloc.dealloc_id(start, count);
+    }
+    assert_eq!(alloc.used(), 0);
+
+    test_random(&mut alloc, 50_000);
+
+    // All IDs can be allocated at once after the random test.
+    assert_eq!(alloc.alloc_id(4095, 1).unwrap(), 1);
+    assert_eq!(alloc.available(), 0);
+    alloc.dealloc_id(1, 4095);
+}
+
+#[test]
+fn bitmap_id_alloc_errors() {
+    let mut alloc = BitmapIdAllocator::<4>::new();
+    alloc.init(0, 100);
+    assert!(matches!(
+        alloc.alloc_id(0, 1),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.alloc_id(1, 3),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(alloc.alloc_id(101, 1), Err(AllocError::NoMemory)));
+    assert!(matches!(
+        alloc.alloc_fixed_id(100),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.alloc_fixed_id(usize::MAX),
+        Err(AllocError::InvalidParam)
+    ));
+    alloc.alloc_fixed_id(0).unwrap();
+    assert!(matches!(alloc.alloc_id(40, 128), Err(AllocError::NoMemory)));
+    assert!(matches!(
+        alloc.alloc_fixed_id(0),
+        Err(AllocError::MemoryOverlap)
+    ));
+    assert!(!alloc.is_allocated(100));
+    assert!(!alloc.is_allocated(usize::MAX));
+
+    assert!(matches!(
+        alloc.add_memory(50, 100),
+        Err(AllocError::MemoryOverlap)
+    ));
+    assert!(matches!(
+        alloc.add_memory(200, 100),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.add_memory(200, 0),
+        Err(AllocError::InvalidParam)
+    ));
+
+    let ids: Vec<_> = (1..100).map(|_| alloc.alloc_id(1, 1).unwrap()).collect();
+    assert_eq!(ids, (1..100).collect::<Vec<_>>());
+    assert!(matches!(alloc.alloc_id(1, 1), Err(AllocError::NoMemory)));
+    alloc.dealloc_id(0, 100);
+    assert_eq!(alloc.available(), 100);
+}
+
+#[test]
+#[should_panic(expected = "deallocate unallocated IDs")]
+fn bitmap_id_double_free() {
+    let mut alloc = BitmapIdAllocator::<4>::new();
+    alloc.init(0, 100);
+    let id = alloc.alloc_id(4, 4).unwrap();
+    alloc.dealloc_id(id, 4);
+    alloc.dealloc_id(id + 2, 1);
+}
+
+#[test]
+fn bitmap_id_add_memory() {
+    let mut alloc = BitmapIdAllocator::<64>::new();
+    alloc.init(3000, 1000);
+    // Unaligned and separated regions.
+    alloc.add_memory(5, 995).unwrap();
+    alloc.add_memory(1999, 2).unwrap();
+    assert_eq!(alloc.size(), 1997);
+
+    assert_eq!(alloc.alloc_id(1, 1).unwrap(), 5);
+    // Allocated IDs never go out of the regions.
+    assert_eq!(alloc.alloc_id(995, 1).unwrap(), 3000);
+    assert_eq!(alloc.alloc_id(2, 2).unwrap(), 6);
+    alloc.alloc_fixed_id(2000).unwrap();
+    assert!(matches!(
+        alloc.alloc_fixed_id(2001),
+        Err(AllocError::InvalidParam)
+    ));
+    alloc.dealloc_id(3000, 995);
+    alloc.dealloc_id(2000, 1);
+    alloc.dealloc_id(6, 2);
+    alloc.dealloc_id(5, 1);
+
+    test_random(&mut alloc, 20_000);
+    assert_eq!(alloc.available(), 1997);
+}
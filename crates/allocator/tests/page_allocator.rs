@@ -0,0 +1,179 @@
+use std::alloc::Layout;
+use std::collections::BTreeMap;
+
+use allocator::{AllocError, BaseAllocator, BuddyPageAllocator, PageAllocator};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+const PAGE_SIZE: usize = 4096;
+const POOL_SIZE: usize = 1024 * 1024 * 64;
+
+fn run_test(f: impl FnOnce(usize)) {
+    let layout = Layout::from_size_align(POOL_SIZE, PAGE_SIZE).unwrap();
+    let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+    f(ptr as usize);
+    unsafe { std::alloc::dealloc(ptr, layout) };
+}
+
+/// Allocates and frees blocks of random sizes and alignments, and checks that
+/// the allocated blocks do not overlap.
+fn test_random<A: PageAllocator>(alloc: &mut A, n: usize) {
+    let mut rng = SmallRng::seed_from_u64(0xdead_beef);
+    let total = alloc.total_pages();
+    let mut blocks = BTreeMap::new();
+    for _ in 0..n {
+        if rng.gen_ratio(2, 3) || blocks.is_empty() {
+            let max_pages = 1 << rng.gen_range(0..10);
+            let num_pages = rng.gen_range(1..=max_pages);
+            let align_pow2 = A::PAGE_SIZE << rng.gen_range(0..6);
+            let Ok(addr) = alloc.alloc_pages(num_pages, align_pow2) else {
+                continue;
+            };
+            assert_eq!(addr % align_pow2, 0);
+            let end = addr + num_pages * A::PAGE_SIZE;
+            if let Some((&prev, &prev_pages)) = blocks.range(..end).next_back() {
+                assert!(prev + prev_pages * A::PAGE_SIZE <= addr);
+            }
+            blocks.insert(addr, num_pages);
+        } else {
+            let idx = rng.gen_range(0..blocks.len());
+            let addr = *blocks.keys().nth(idx).unwrap();
+            let num_pages = blocks.remove(&addr).unwrap();
+            alloc.dealloc_pages(addr, num_pages);
+        }
+        let used: usize = blocks.values().sum();
+        assert_eq!(alloc.used_pages(), used);
+        assert_eq!(alloc.available_pages(), total - used);
+    }
+    for (addr, num_pages) in blocks {
+        alloc.dealloc_pages(addr, num_pages);
+    }
+    assert_eq!(alloc.used_pages(), 0);
+}
+
+#[test]
+fn buddy_page_alloc() {
+    run_test(|start| {
+        let mut alloc = BuddyPageAllocator::<PAGE_SIZE>::new();
+        alloc.init(start, POOL_SIZE);
+        // A byte of each page is used as its tag.
+        let total = alloc.total_pages();
+        assert!(total > POOL_SIZE / PAGE_SIZE - POOL_SIZE / PAGE_SIZE / PAGE_SIZE - 2);
+        assert_eq!(alloc.used_pages(), 0);
+
+        let a = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+        let b = alloc.alloc_pages(3, PAGE_SIZE).unwrap();
+        let c = alloc.alloc_pages(16, PAGE_SIZE * 16).unwrap();
+        assert_eq!(c % (PAGE_SIZE * 16), 0);
+        assert_eq!(alloc.used_pages(), 20);
+        alloc.dealloc_pages(b, 3);
+        alloc.dealloc_pages(a, 1);
+        alloc.dealloc_pages(c, 16);
+        assert_eq!(alloc.used_pages(), 0);
+        assert_eq!(alloc.available_pages(), total);
+
+        test_random(&mut alloc, 50_000);
+
+        // All blocks are merged back, so the largest one can be allocated.
+        let big = (total + 1).next_power_of_two() / 4;
+        let addr = alloc.alloc_pages(big, PAGE_SIZE).unwrap();
+        alloc.dealloc_pages(addr, big);
+        assert_eq!(alloc.available_pages(), total);
+    })
+}
+
+#[test]
+fn buddy_page_alloc_errors() {
+    run_test(|start| {
+        let mut alloc = BuddyPageAllocator::<PAGE_SIZE>::new();
+        alloc.init(start, PAGE_SIZE * 64);
+        assert!(matches!(
+            alloc.alloc_pages(0, PAGE_SIZE),
+            Err(AllocError::InvalidParam)
+        ));
+        assert!(matches!(
+            alloc.alloc_pages(1, PAGE_SIZE / 2),
+            Err(AllocError::InvalidParam)
+        ));
+        assert!(matches!(
+            alloc.alloc_pages(1, PAGE_SIZE * 3),
+            Err(AllocError::InvalidParam)
+        ));
+        assert!(matches!(
+            alloc.alloc_pages(64, PAGE_SIZE),
+            Err(AllocError::NoMemory)
+        ));
+        assert!(matches!(
+            alloc.add_memory(start + PAGE_SIZE * 32, PAGE_SIZE * 64),
+            Err(AllocError::MemoryOverlap)
+        ));
+
+        let total = alloc.total_pages();
+        let pages: Vec<_> = (0..total)
+            .map(|_| alloc.alloc_pages(1, PAGE_SIZE).unwrap())
+            .collect();
+        assert!(matches!(
+            alloc.alloc_pages(1, PAGE_SIZE),
+            Err(AllocError::NoMemory)
+        ));
+        for addr in pages {
+            alloc.dealloc_pages(addr, 1);
+        }
+        assert_eq!(alloc.available_pages(), total);
+    })
+}
+
+#[test]
+fn buddy_page_add_memory() {
+    run_test(|start| {
+        let mut alloc = BuddyPageAllocator::<PAGE_SIZE>::new();
+        let half = POOL_SIZE / 2;
+        alloc.init(start + half, half);
+        let total = alloc.total_pages();
+        // Unaligned bounds are shrunk to pages.
+        alloc.add_memory(start + 100, half - 200).unwrap();
+        assert!(alloc.total_pages() > total * 2 - 4);
+
+        // Large blocks are allocated from both regions.
+        let blocks: Vec<_> =
+            std::iter::from_fn(|| alloc.alloc_pages(total / 8, PAGE_SIZE).ok()).collect();
+        assert!(blocks.len() > 8);
+        for &addr in &blocks {
+            alloc.dealloc_pages(addr, total / 8);
+        }
+        test_random(&mut alloc, 10_000);
+        assert_eq!(alloc.used_pages(), 0);
+    })
+}
+
+#[test]
+fn buddy_page_lazy_metadata() {
+    run_test(|start| {
+        const POISON: u8 = 0xa5;
+        let pool = unsafe { std::slice::from_raw_parts_mut(start as *mut u8, POOL_SIZE) };
+        pool.fill(POISON);
+
+        let mut alloc = BuddyPageAllocator::<PAGE_SIZE>::new();
+        alloc.init(start, POOL_SIZE);
+        let total = alloc.total_pages();
+        // Adding the region does not touch it.
+        assert!(pool.iter().all(|&b| b == POISON));
+
+        // Small allocations only touch the low pages and their tags.
+        let pages: Vec<_> = (0..8)
+            .map(|_| alloc.alloc_pages(1, PAGE_SIZE).unwrap())
+            .collect();
+        let low = POOL_SIZE / 64;
+        assert!(pages.iter().all(|&addr| addr + PAGE_SIZE <= start + low));
+        assert!(pool[low..].iter().all(|&b| b == POISON));
+        for addr in pages {
+            alloc.dealloc_pages(addr, 1);
+        }
+        assert!(pool[low..].iter().all(|&b| b == POISON));
+
+        // The untouched pages are still available for large blocks.
+        let big = (total + 1).next_power_of_two() / 4;
+        let addr = alloc.alloc_pages(big, PAGE_SIZE).unwrap();
+        alloc.dealloc_pages(addr, big);
+        assert_eq!(alloc.available_pages(), total);
+    })
+}
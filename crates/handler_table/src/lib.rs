@@ -32,6 +32,22 @@ impl<const N: usize> HandlerTable<N> {
             .is_ok()
     }
 
+    /// Unregisters the handler for the given index, and returns it.
+    ///
+    /// The table is read without locking, so the handler may be still
+    /// running on other CPUs when it returns. In the kernel, the handlers
+    /// run in RCU read-side critical sections (e.g., IRQ handlers), and the
+    /// caller should wait for a grace period before releasing the resources
+    /// used by the handler.
+    pub fn unregister_handler(&self, idx: usize) -> Option<Handler> {
+        let handler = self.handlers[idx].swap(0, Ordering::AcqRel);
+        if handler != 0 {
+            Some(unsafe { core::mem::transmute::<usize, Handler>(handler) })
+        } else {
+            None
+        }
+    }
+
     /// Handles the event with the given index.
     ///
     /// Returns `true` if the event is handled, `false` if no handler is
@@ -0,0 +1,223 @@
+//! Lock classes and the dependency graph between them.
+
+use core::cell::UnsafeCell;
+use core::panic::Location;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+
+/// The maximum number of lock classes.
+pub const MAX_CLASSES: usize = 1024;
+
+/// The maximum number of dependencies between lock classes.
+pub const MAX_DEPS: usize = 4096;
+
+/// The maximum length of the dependency chains in the reports.
+pub const MAX_CHAIN_LEN: usize = 16;
+
+/// A location in the source code, where a lock is created or acquired.
+pub type Site = &'static Location<'static>;
+
+/// A dependency that a lock of class `to` was acquired while holding a lock
+/// of class `from`.
+#[derive(Clone, Copy)]
+pub struct Dep {
+    pub from: u16,
+    pub to: u16,
+    /// Where the lock of `from` was acquired.
+    pub from_site: Site,
+    /// Where the lock of `to` was acquired.
+    pub to_site: Site,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_KEY: AtomicPtr<Location<'static>> = AtomicPtr::new(null_mut());
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_DEPS: AtomicU64 = AtomicU64::new(0);
+
+/// The keys of the registered classes, indexed by the class IDs.
+static CLASS_KEYS: [AtomicPtr<Location<'static>>; MAX_CLASSES] = [NO_KEY; MAX_CLASSES];
+
+/// The adjacency matrix of the dependencies, for the lock-free lookups.
+static DEP_BITMAP: [AtomicU64; MAX_CLASSES * MAX_CLASSES / 64] =
+    [NO_DEPS; MAX_CLASSES * MAX_CLASSES / 64];
+
+static GRAPH: Graph = Graph {
+    locked: AtomicBool::new(false),
+    inner: UnsafeCell::new(GraphInner {
+        deps: [None; MAX_DEPS],
+        num_deps: 0,
+        parent: [0; MAX_CLASSES],
+        queue: [0; MAX_CLASSES],
+    }),
+};
+
+struct GraphInner {
+    deps: [Option<Dep>; MAX_DEPS],
+    num_deps: usize,
+    /// Scratch space of the breadth-first search, which is too large for
+    /// small stacks.
+    parent: [u16; MAX_CLASSES],
+    queue: [u16; MAX_CLASSES],
+}
+
+/// The dependency details, locked by a raw spin lock that is not tracked.
+struct Graph {
+    locked: AtomicBool,
+    inner: UnsafeCell<GraphInner>,
+}
+
+unsafe impl Sync for Graph {}
+
+/// The result of adding a dependency.
+#[allow(clippy::large_enum_variant)] // it's never stored
+pub enum AddDep {
+    /// The dependency is added, or it already exists.
+    Ok,
+    /// The dependency would close a cycle. The existing chain in the reverse
+    /// direction is returned, in the order from `to` to `from`.
+    Cycle([Option<Dep>; MAX_CHAIN_LEN]),
+    /// There are too many dependencies.
+    Full,
+}
+
+fn same_key(a: Site, b: Site) -> bool {
+    a.line() == b.line() && a.column() == b.column() && a.file() == b.file()
+}
+
+fn hash_key(key: Site) -> usize {
+    let mut h = (key.file().as_ptr() as usize) ^ (key.file().len() << 16);
+    h ^= (key.line() as usize).wrapping_mul(0x9e37_79b9) ^ (key.column() as usize) << 8;
+    h ^ (h >> 13)
+}
+
+/// Looks up the class ID of the key, or registers a new one.
+///
+/// Returns [`None`] if the class table is full.
+pub fn class_id(key: Site) -> Option<u16> {
+    let ptr = key as *const _ as *mut Location<'static>;
+    let start = hash_key(key) % MAX_CLASSES;
+    for i in 0..MAX_CLASSES {
+        let id = (start + i) % MAX_CLASSES;
+        let slot = &CLASS_KEYS[id];
+        let mut cur = slot.load(Ordering::Acquire);
+        if cur.is_null() {
+            match slot.compare_exchange(null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Some(id as u16),
+                Err(winner) => cur = winner,
+            }
+        }
+        if same_key(unsafe { &*cur }, key) {
+            return Some(id as u16);
+        }
+    }
+    None
+}
+
+/// Returns the key of the class.
+pub fn class_key(id: u16) -> Site {
+    unsafe { &*CLASS_KEYS[id as usize].load(Ordering::Acquire) }
+}
+
+fn bit(from: u16, to: u16) -> (usize, u64) {
+    let idx = from as usize * MAX_CLASSES + to as usize;
+    (idx / 64, 1 << (idx % 64))
+}
+
+/// Whether the dependency `from -> to` has been recorded.
+pub fn has_dep(from: u16, to: u16) -> bool {
+    let (word, mask) = bit(from, to);
+    DEP_BITMAP[word].load(Ordering::Acquire) & mask != 0
+}
+
+/// Records the dependency, unless it closes a cycle.
+pub fn add_dep(dep: Dep) -> AddDep {
+    while GRAPH
+        .locked
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+    let graph = unsafe { &mut *GRAPH.inner.get() };
+    let res = if has_dep(dep.from, dep.to) {
+        AddDep::Ok // added by another CPU just now
+    } else if let Some(chain) = graph.find_chain(dep.to, dep.from) {
+        AddDep::Cycle(chain)
+    } else if graph.num_deps == MAX_DEPS {
+        AddDep::Full
+    } else {
+        graph.deps[graph.num_deps] = Some(dep);
+        graph.num_deps += 1;
+        let (word, mask) = bit(dep.from, dep.to);
+        DEP_BITMAP[word].fetch_or(mask, Ordering::Release);
+        AddDep::Ok
+    };
+    GRAPH.locked.store(false, Ordering::Release);
+    res
+}
+
+impl GraphInner {
+    /// Finds the shortest dependency chain from `src` to `dst`.
+    fn find_chain(&mut self, src: u16, dst: u16) -> Option<[Option<Dep>; MAX_CHAIN_LEN]> {
+        const UNVISITED: u16 = u16::MAX;
+        self.parent.fill(UNVISITED);
+        self.parent[src as usize] = src;
+        self.queue[0] = src;
+        let (mut head, mut tail) = (0, 1);
+        let mut found = false;
+        'bfs: while head < tail {
+            let from = self.queue[head];
+            head += 1;
+            let row = from as usize * MAX_CLASSES / 64;
+            for (i, word) in DEP_BITMAP[row..row + MAX_CLASSES / 64].iter().enumerate() {
+                let mut bits = word.load(Ordering::Acquire);
+                while bits != 0 {
+                    let to = (i * 64 + bits.trailing_zeros() as usize) as u16;
+                    bits &= bits - 1;
+                    if self.parent[to as usize] == UNVISITED {
+                        self.parent[to as usize] = from;
+                        self.queue[tail] = to;
+                        tail += 1;
+                        if to == dst {
+                            found = true;
+                            break 'bfs;
+                        }
+                    }
+                }
+            }
+        }
+        if !found {
+            return None;
+        }
+
+        // Walk back from `dst` to collect the path, reusing the queue, then
+        // keep the dependencies nearest to `src`.
+        let mut len = 0;
+        let mut node = dst;
+        while node != src {
+            self.queue[len] = node;
+            len += 1;
+            node = self.parent[node as usize];
+        }
+        let mut chain = [None; MAX_CHAIN_LEN];
+        let mut from = src;
+        for (i, &to) in self.queue[..len]
+            .iter()
+            .rev()
+            .take(MAX_CHAIN_LEN)
+            .enumerate()
+        {
+            chain[i] = self.dep(from, to);
+            from = to;
+        }
+        Some(chain)
+    }
+
+    fn dep(&self, from: u16, to: u16) -> Option<Dep> {
+        self.deps[..self.num_deps]
+            .iter()
+            .flatten()
+            .find(|dep| dep.from == from && dep.to == to)
+            .copied()
+    }
+}
@@ -0,0 +1,399 @@
+//! A runtime lock dependency validator, to detect potential deadlocks before
+//! they happen.
+//!
+//! Locks are grouped into classes by where they are created, e.g., all locks
+//! created by the same `SpinNoIrq::new` call share a class. Each time a lock
+//! is acquired while other locks are held, the dependencies from the classes
+//! of the held locks to its class are recorded. A report is printed when:
+//!
+//! - A new dependency closes a cycle, i.e., two classes of locks are acquired
+//!   in different orders (ABBA), which may deadlock if two tasks run them at
+//!   the same time, even if it has never happened.
+//! - A sleeping lock is acquired with IRQs disabled or spin locks held.
+//! - A lock is acquired again by the task that holds it.
+//!
+//! The report contains where the locks were acquired for both the new
+//! dependency and the existing ones. The validator turns itself off after the
+//! first report, like the Linux one, since the following reports are usually
+//! noises caused by the first one.
+//!
+//! The crate user must implement the [`LockdepIf`] trait using
+//! [`crate_interface::impl_interface`], to provide the held locks of the
+//! current task. The lock implementations call [`LockClass::acquire`],
+//! [`LockClass::acquired`] and [`release`] to report the lock operations.
+//!
+//! The classes and dependencies are stored in fixed-size tables, so that no
+//! memory is allocated while locking.
+
+#![cfg_attr(not(test), no_std)]
+#![feature(const_caller_location)]
+
+#[macro_use]
+extern crate log;
+
+mod graph;
+
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+use kernel_guard::IrqSave;
+
+use self::graph::{AddDep, Dep, MAX_CHAIN_LEN};
+pub use self::graph::{Site, MAX_CLASSES, MAX_DEPS};
+
+/// The maximum number of locks held by a task at the same time.
+pub const MAX_HELD_LOCKS: usize = 32;
+
+/// Whether the validator is working. It's turned off after the first report.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Low-level interfaces that must be implemented by the crate user.
+#[crate_interface::def_interface]
+pub trait LockdepIf {
+    /// Returns the locks held by the current task, or a null pointer if the
+    /// current task is not available, such as in early boot stages.
+    fn current_held_locks() -> *mut HeldLocks;
+
+    /// Whether local IRQs are enabled on the current CPU.
+    fn irqs_enabled() -> bool;
+}
+
+/// The kind of a lock.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LockKind {
+    /// A spin lock, which is held with preemption disabled.
+    Spin,
+    /// A sleeping lock, the waiters are blocked.
+    Sleep,
+}
+
+#[derive(Clone, Copy)]
+struct HeldLock {
+    addr: usize,
+    class: u16,
+    kind: LockKind,
+    site: Site,
+}
+
+/// The stack of the locks held by a task.
+pub struct HeldLocks {
+    locks: [Option<HeldLock>; MAX_HELD_LOCKS],
+    len: usize,
+}
+
+impl HeldLocks {
+    /// Creates an empty stack.
+    pub const fn new() -> Self {
+        Self {
+            locks: [None; MAX_HELD_LOCKS],
+            len: 0,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &HeldLock> {
+        self.locks[..self.len].iter().flatten()
+    }
+
+    fn push(&mut self, lock: HeldLock) -> bool {
+        if self.len == MAX_HELD_LOCKS {
+            return false;
+        }
+        self.locks[self.len] = Some(lock);
+        self.len += 1;
+        true
+    }
+
+    /// Removes the lock at `addr`, which may not be the last acquired one.
+    fn remove(&mut self, addr: usize) -> Option<HeldLock> {
+        let idx = (0..self.len)
+            .rev()
+            .find(|&i| matches!(self.locks[i], Some(lock) if lock.addr == addr))?;
+        let lock = self.locks[idx].take();
+        self.locks.copy_within(idx + 1..self.len, idx);
+        self.len -= 1;
+        self.locks[self.len] = None;
+        lock
+    }
+
+    /// Moves the lock at `addr` to the held locks of another task.
+    ///
+    /// It's used for the locks that are acquired by a task but released by
+    /// another, such as the run queue lock held across context switches.
+    pub fn transfer(&mut self, addr: *const (), to: &mut HeldLocks) {
+        let _guard = IrqSave::new();
+        if let Some(lock) = self.remove(addr as usize) {
+            to.push(lock);
+        }
+    }
+}
+
+impl Default for HeldLocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The class of a lock, identified by where the lock is created.
+pub struct LockClass {
+    key: Site,
+    /// The class ID plus 1, or 0 if it has not been looked up.
+    id: AtomicU16,
+}
+
+impl LockClass {
+    /// Creates the class of a lock created by the caller.
+    ///
+    /// The lock constructors should be marked with `#[track_caller]`, so that
+    /// the locks created at different places belong to different classes.
+    #[track_caller]
+    pub const fn new() -> Self {
+        Self {
+            key: Location::caller(),
+            id: AtomicU16::new(0),
+        }
+    }
+
+    /// Returns where the locks of this class are created.
+    pub const fn key(&self) -> Site {
+        self.key
+    }
+
+    fn id(&self) -> Option<u16> {
+        match self.id.load(Ordering::Relaxed) {
+            0 => {
+                let id = graph::class_id(self.key)?;
+                self.id.store(id + 1, Ordering::Relaxed);
+                Some(id)
+            }
+            id => Some(id - 1),
+        }
+    }
+
+    /// Checks the dependencies before the lock at `addr` is acquired at
+    /// `site`, it's called before the task may spin or block on the lock.
+    ///
+    /// For spin locks, it also records the lock as held, since the task can
+    /// not do anything else before the lock is acquired.
+    pub fn acquire(&self, addr: *const (), kind: LockKind, site: Site) {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+        let irqs_enabled = crate_interface::call_interface!(LockdepIf::irqs_enabled);
+        let _guard = IrqSave::new();
+        let Some(held) = (unsafe { current_held_locks() }) else {
+            return;
+        };
+        let Some(class) = self.id() else {
+            return turn_off("too many lock classes");
+        };
+        let new = HeldLock {
+            addr: addr as usize,
+            class,
+            kind,
+            site,
+        };
+        if !check_acquire(held, &new, irqs_enabled) {
+            return;
+        }
+        if kind == LockKind::Spin && !held.push(new) {
+            turn_off("too many locks held");
+        }
+    }
+
+    /// Records the lock at `addr` as held, after it's acquired at `site`.
+    ///
+    /// It's called after the sleeping locks are acquired, or after the locks
+    /// are acquired by `try_lock`, without checking the dependencies since
+    /// `try_lock` never waits.
+    pub fn acquired(&self, addr: *const (), kind: LockKind, site: Site) {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+        let _guard = IrqSave::new();
+        let Some(held) = (unsafe { current_held_locks() }) else {
+            return;
+        };
+        let Some(class) = self.id() else {
+            return turn_off("too many lock classes");
+        };
+        let new = HeldLock {
+            addr: addr as usize,
+            class,
+            kind,
+            site,
+        };
+        if !held.push(new) {
+            turn_off("too many locks held");
+        }
+    }
+}
+
+impl Default for LockClass {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records that the lock at `addr` is released by the current task.
+///
+/// Nothing happens if the lock is not recorded as held, e.g., it's acquired
+/// before the validator is ready.
+pub fn release(addr: *const ()) {
+    let _guard = IrqSave::new();
+    if let Some(held) = unsafe { current_held_locks() } {
+        held.remove(addr as usize);
+    }
+}
+
+/// Whether the validator is still working, i.e., nothing has been reported.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// # Safety
+///
+/// IRQs must be disabled while the result is used.
+unsafe fn current_held_locks() -> Option<&'static mut HeldLocks> {
+    let ptr = crate_interface::call_interface!(LockdepIf::current_held_locks);
+    unsafe { ptr.as_mut() }
+}
+
+/// Checks the new lock against the held locks, and records the new
+/// dependencies. Returns `false` if it's reported.
+fn check_acquire(held: &HeldLocks, new: &HeldLock, irqs_enabled: bool) -> bool {
+    if let Some(prev) = held.iter().find(|lock| lock.addr == new.addr) {
+        report_recursive(held, prev, new);
+        return false;
+    }
+    if new.kind == LockKind::Sleep
+        && (!irqs_enabled || held.iter().any(|lock| lock.kind == LockKind::Spin))
+    {
+        report_sleep_in_atomic(held, new, irqs_enabled);
+        return false;
+    }
+    for prev in held.iter() {
+        // The locks of the same class can not be ordered by their classes.
+        if prev.class == new.class || graph::has_dep(prev.class, new.class) {
+            continue;
+        }
+        let dep = Dep {
+            from: prev.class,
+            to: new.class,
+            from_site: prev.site,
+            to_site: new.site,
+        };
+        match graph::add_dep(dep) {
+            AddDep::Ok => {}
+            AddDep::Cycle(chain) => {
+                report_cycle(held, prev, new, &chain);
+                return false;
+            }
+            AddDep::Full => {
+                turn_off("too many lock dependencies");
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn turn_off(reason: &str) {
+    if ENABLED.swap(false, Ordering::Relaxed) {
+        warn!("lockdep: {}, turning off the lock validator", reason);
+    }
+}
+
+/// Turns off the validator before printing the report, so that the locks
+/// acquired by the logger are not checked.
+fn begin_report(title: &str) -> bool {
+    if !ENABLED.swap(false, Ordering::Relaxed) {
+        return false; // reported by another CPU
+    }
+    error!("================================================================");
+    error!("lockdep: {}", title);
+    true
+}
+
+fn end_report(held: &HeldLocks) {
+    error!("locks held by the current task:");
+    for (i, lock) in held.iter().enumerate() {
+        print_lock(i, lock);
+    }
+    error!("turning off the lock validator");
+    error!("================================================================");
+}
+
+fn print_lock(i: usize, lock: &HeldLock) {
+    error!(
+        "  #{}: {:?} lock {:#x} of class {}, acquired at {}",
+        i,
+        lock.kind,
+        lock.addr,
+        graph::class_key(lock.class),
+        lock.site
+    );
+}
+
+fn report_recursive(held: &HeldLocks, prev: &HeldLock, new: &HeldLock) {
+    if begin_report("recursive locking detected") {
+        error!(
+            "lock {:#x} of class {} acquired at {} is acquired again at {}",
+            new.addr,
+            graph::class_key(new.class),
+            prev.site,
+            new.site
+        );
+        end_report(held);
+    }
+}
+
+fn report_sleep_in_atomic(held: &HeldLocks, new: &HeldLock, irqs_enabled: bool) {
+    if begin_report("sleeping lock acquired in atomic context") {
+        error!(
+            "lock {:#x} of class {} is acquired at {}, with IRQs {}",
+            new.addr,
+            graph::class_key(new.class),
+            new.site,
+            if irqs_enabled { "enabled" } else { "disabled" }
+        );
+        end_report(held);
+    }
+}
+
+fn report_cycle(
+    held: &HeldLocks,
+    prev: &HeldLock,
+    new: &HeldLock,
+    chain: &[Option<Dep>; MAX_CHAIN_LEN],
+) {
+    if begin_report("possible circular locking dependency detected") {
+        error!(
+            "acquiring lock of class {} at {}",
+            graph::class_key(new.class),
+            new.site
+        );
+        error!(
+            "while holding lock of class {} acquired at {},",
+            graph::class_key(prev.class),
+            prev.site
+        );
+        error!("but the reverse dependency chain already exists:");
+        for dep in chain.iter().flatten() {
+            error!(
+                "  class {} acquired at {}",
+                graph::class_key(dep.from),
+                dep.from_site
+            );
+            error!(
+                "  -> class {} acquired at {}",
+                graph::class_key(dep.to),
+                dep.to_site
+            );
+        }
+        end_report(held);
+    }
+}
+
+#[cfg(test)]
+mod tests;
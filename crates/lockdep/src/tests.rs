@@ -0,0 +1,198 @@
+use core::cell::{Cell, UnsafeCell};
+use core::panic::Location;
+use core::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use crate::{release, HeldLocks, LockClass, LockKind, LockdepIf, ENABLED};
+
+thread_local! {
+    static HELD_LOCKS: UnsafeCell<HeldLocks> = const { UnsafeCell::new(HeldLocks::new()) };
+    static IRQS_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+struct LockdepIfImpl;
+
+#[crate_interface::impl_interface]
+impl LockdepIf for LockdepIfImpl {
+    fn current_held_locks() -> *mut HeldLocks {
+        HELD_LOCKS.with(|held| held.get())
+    }
+
+    fn irqs_enabled() -> bool {
+        IRQS_ENABLED.with(|enabled| enabled.get())
+    }
+}
+
+/// Serializes the tests, as they share the global switch.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+struct TestLock {
+    class: LockClass,
+    kind: LockKind,
+}
+
+impl TestLock {
+    #[track_caller]
+    const fn new(kind: LockKind) -> Self {
+        Self {
+            class: LockClass::new(),
+            kind,
+        }
+    }
+
+    fn addr(&self) -> *const () {
+        self as *const _ as *const ()
+    }
+
+    #[track_caller]
+    fn lock(&self) {
+        let site = Location::caller();
+        self.class.acquire(self.addr(), self.kind, site);
+        if self.kind == LockKind::Sleep {
+            self.class.acquired(self.addr(), self.kind, site);
+        }
+    }
+
+    fn unlock(&self) {
+        release(self.addr());
+    }
+}
+
+fn run_test<F: FnOnce()>(f: F) -> bool {
+    let _guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    ENABLED.store(true, Ordering::Relaxed);
+    IRQS_ENABLED.with(|enabled| enabled.set(true));
+    f();
+    HELD_LOCKS.with(|held| unsafe { *held.get() = HeldLocks::new() });
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[test]
+fn test_same_order() {
+    let a = TestLock::new(LockKind::Spin);
+    let b = TestLock::new(LockKind::Sleep);
+    let ok = run_test(|| {
+        for _ in 0..3 {
+            b.lock();
+            a.lock();
+            a.unlock();
+            b.unlock();
+        }
+    });
+    assert!(ok);
+}
+
+#[test]
+fn test_abba() {
+    let a = TestLock::new(LockKind::Spin);
+    let b = TestLock::new(LockKind::Spin);
+    let ok = run_test(|| {
+        a.lock();
+        b.lock();
+        b.unlock();
+        a.unlock();
+
+        b.lock();
+        a.lock();
+    });
+    assert!(!ok);
+}
+
+#[test]
+fn test_indirect_cycle() {
+    let a = TestLock::new(LockKind::Spin);
+    let b = TestLock::new(LockKind::Spin);
+    let c = TestLock::new(LockKind::Spin);
+    let ok = run_test(|| {
+        a.lock();
+        b.lock();
+        b.unlock();
+        a.unlock();
+
+        // In another task.
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                b.lock();
+                c.lock();
+                c.unlock();
+                b.unlock();
+            });
+        });
+
+        c.lock();
+        a.lock();
+    });
+    assert!(!ok);
+}
+
+#[test]
+fn test_same_class() {
+    let locks = [TestLock::new(LockKind::Spin), TestLock::new(LockKind::Spin)];
+    let ok = run_test(|| {
+        // Created at different places, so the classes are different.
+        let (a, b) = (&locks[0], &locks[1]);
+        a.lock();
+        b.lock();
+        b.unlock();
+        a.unlock();
+
+        // Created at the same place, the order can not be checked.
+        let same: Vec<_> = (0..2).map(|_| TestLock::new(LockKind::Spin)).collect();
+        same[0].lock();
+        same[1].lock();
+        same[1].unlock();
+        same[0].unlock();
+        same[1].lock();
+        same[0].lock();
+    });
+    assert!(ok);
+}
+
+#[test]
+fn test_recursive() {
+    let a = TestLock::new(LockKind::Spin);
+    let ok = run_test(|| {
+        a.lock();
+        a.lock();
+    });
+    assert!(!ok);
+}
+
+#[test]
+fn test_sleep_with_irqs_disabled() {
+    let m = TestLock::new(LockKind::Sleep);
+    let ok = run_test(|| {
+        IRQS_ENABLED.with(|enabled| enabled.set(false));
+        m.lock();
+    });
+    assert!(!ok);
+}
+
+#[test]
+fn test_sleep_under_spin() {
+    let s = TestLock::new(LockKind::Spin);
+    let m = TestLock::new(LockKind::Sleep);
+    let ok = run_test(|| {
+        s.lock();
+        m.lock();
+    });
+    assert!(!ok);
+}
+
+#[test]
+fn test_transfer() {
+    let a = TestLock::new(LockKind::Spin);
+    let b = TestLock::new(LockKind::Spin);
+    let ok = run_test(|| {
+        let mut other = HeldLocks::new();
+        a.lock();
+        HELD_LOCKS.with(|held| unsafe { (*held.get()).transfer(a.addr(), &mut other) });
+        // `a` is no longer held by the current task.
+        b.lock();
+        b.unlock();
+        a.lock();
+        a.unlock();
+        assert_eq!(other.iter().count(), 1);
+    });
+    assert!(ok);
+}
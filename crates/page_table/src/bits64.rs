@@ -131,6 +131,35 @@ impl<M: PagingMetaData, PTE: GenericPTE, IF: PagingIf> PageTable64<M, PTE, IF> {
         Ok(size)
     }
 
+    /// Splits the huge page that contains `vaddr` into pages of the next
+    /// smaller size, which have the same targets and mapping flags.
+    ///
+    /// Returns the page size of the mapping after splitting. It does nothing
+    /// if the mapping is already a 4K page.
+    ///
+    /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
+    /// mapping is not present.
+    pub fn split_huge_page(&mut self, vaddr: VirtAddr) -> PagingResult<PageSize> {
+        let (entry, size) = self.get_entry_mut(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let small_size = match size {
+            PageSize::Size1G => PageSize::Size2M,
+            PageSize::Size2M => PageSize::Size4K,
+            PageSize::Size4K => return Ok(size),
+        };
+        let (paddr, flags) = (entry.paddr(), entry.flags());
+        let table_paddr = Self::alloc_table()?;
+        for (i, small_entry) in self.table_of_mut(table_paddr).iter_mut().enumerate() {
+            *small_entry =
+                GenericPTE::new_page(paddr + i * small_size as usize, flags, small_size.is_huge());
+        }
+        *entry = GenericPTE::new_table(table_paddr);
+        self.intrm_tables.push(table_paddr);
+        Ok(small_size)
+    }
+
     /// Map a contiguous virtual memory region to a contiguous physical memory
     /// region with the given mapping `flags`.
     ///
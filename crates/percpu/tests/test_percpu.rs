@@ -99,4 +99,20 @@ fn test_percpu() {
         assert_eq!(s.foo, 0x2333);
         assert_eq!(s.bar, 100);
     });
+
+    // the remote pointer of the current CPU is the same as the current pointer.
+    unsafe {
+        assert_eq!(U8.remote_ptr(0), U8.current_ptr());
+        assert_eq!(STRUCT.remote_ref_raw(0).foo, 0x2333);
+    }
+
+    #[cfg(not(feature = "sp-naive"))]
+    unsafe {
+        let base1 = percpu_area_base(1);
+        assert_eq!(base1 + U64.offset(), U64.remote_ptr(1) as usize);
+        assert_eq!(base1 + STRUCT.offset(), STRUCT.remote_ptr(1) as usize);
+        // the other CPU is untouched.
+        assert_eq!(*U64.remote_ref_raw(1), 0);
+        assert_eq!(STRUCT.remote_ref_raw(1).foo, 0);
+    }
 }
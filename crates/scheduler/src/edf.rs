@@ -0,0 +1,425 @@
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicIsize, AtomicU64, AtomicUsize, Ordering};
+
+use crate::BaseScheduler;
+
+/// Fixed-point shift of the bandwidth values (`runtime / period`).
+const BW_SHIFT: u32 = 20;
+const BW_UNIT: u64 = 1 << BW_SHIFT;
+
+/// At most 95% of each CPU can be reserved by real-time tasks, the rest is
+/// left to best-effort tasks (same as the default `sched_rt_runtime_us` in
+/// Linux).
+const MAX_BW_PER_CPU: u64 = BW_UNIT * 95 / 100;
+
+/// Time slice (in ticks) of the best-effort tasks.
+const BEST_EFFORT_TIME_SLICE: isize = 5;
+
+/// Real-time parameters of a task in the [`EDFScheduler`].
+///
+/// All values are in scheduler ticks. The task is guaranteed to receive
+/// `runtime` ticks of CPU time in every `period`, and each of these
+/// reservations should be finished within `deadline` ticks after it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EDFParams {
+    /// The CPU time reserved in each period.
+    pub runtime: u64,
+    /// The relative deadline of each activation.
+    pub deadline: u64,
+    /// The minimum interval between two activations.
+    pub period: u64,
+}
+
+impl EDFParams {
+    /// Creates a new set of real-time parameters.
+    pub const fn new(runtime: u64, deadline: u64, period: u64) -> Self {
+        Self {
+            runtime,
+            deadline,
+            period,
+        }
+    }
+
+    /// Whether the parameters satisfy `0 < runtime <= deadline <= period`.
+    pub const fn is_valid(&self) -> bool {
+        self.runtime > 0 && self.runtime <= self.deadline && self.deadline <= self.period
+    }
+
+    /// The CPU bandwidth (`runtime / period`) in fixed point.
+    const fn bandwidth(&self) -> u64 {
+        ((self.runtime as u128 * BW_UNIT as u128) / self.period as u128) as u64
+    }
+}
+
+/// The admission control of the [`EDFScheduler`].
+///
+/// It tracks the total bandwidth reserved by the real-time tasks on some CPUs,
+/// and rejects new reservations that would overload them. For partitioned
+/// scheduling, each CPU has its own instance, and the real-time tasks must
+/// stay on the CPU that their bandwidth is reserved on.
+pub struct EDFBandwidth {
+    used: AtomicU64,
+    limit: u64,
+}
+
+impl EDFBandwidth {
+    /// Creates a new bandwidth pool for `num_cpus` CPUs.
+    pub const fn new(num_cpus: usize) -> Self {
+        Self {
+            used: AtomicU64::new(0),
+            limit: MAX_BW_PER_CPU * num_cpus as u64,
+        }
+    }
+
+    /// Returns the total utilization reserved in the pool, in per mille of
+    /// one CPU.
+    pub fn utilization(&self) -> u64 {
+        self.used.load(Ordering::Acquire) * 1000 / BW_UNIT
+    }
+
+    /// Replaces the reservation of `old` with `new` atomically. Returns
+    /// `false` and keeps the pool unchanged if `new` is invalid or the new
+    /// total exceeds the limit.
+    ///
+    /// It should be called before [`EDFScheduler::set_params`].
+    pub fn reserve(&self, old: Option<&EDFParams>, new: Option<&EDFParams>) -> bool {
+        if new.is_some_and(|p| !p.is_valid()) {
+            return false;
+        }
+        let old_bw = old.map_or(0, EDFParams::bandwidth);
+        let new_bw = new.map_or(0, EDFParams::bandwidth);
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                let total = used - old_bw + new_bw;
+                (new_bw <= old_bw || total <= self.limit).then_some(total)
+            })
+            .is_ok()
+    }
+}
+
+/// A task wrapper for the [`EDFScheduler`].
+///
+/// A task without real-time parameters is a best-effort task, which is only
+/// scheduled when no real-time task with remaining budget is ready.
+pub struct EDFTask<T> {
+    inner: T,
+    runtime: AtomicU64,
+    deadline: AtomicU64,
+    period: AtomicU64,
+    abs_deadline: AtomicU64,
+    replenish_at: AtomicU64,
+    budget: AtomicU64,
+    time_slice: AtomicIsize,
+    id: AtomicUsize,
+}
+
+impl<T> EDFTask<T> {
+    /// Creates a new best-effort [`EDFTask`] from the inner task struct.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            runtime: AtomicU64::new(0),
+            deadline: AtomicU64::new(0),
+            period: AtomicU64::new(0),
+            abs_deadline: AtomicU64::new(0),
+            replenish_at: AtomicU64::new(0),
+            budget: AtomicU64::new(0),
+            time_slice: AtomicIsize::new(BEST_EFFORT_TIME_SLICE),
+            id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the real-time parameters of the task, or [`None`] if it is a
+    /// best-effort task.
+    pub fn params(&self) -> Option<EDFParams> {
+        let runtime = self.runtime.load(Ordering::Acquire);
+        if runtime == 0 {
+            return None;
+        }
+        Some(EDFParams::new(
+            runtime,
+            self.deadline.load(Ordering::Acquire),
+            self.period.load(Ordering::Acquire),
+        ))
+    }
+
+    /// Returns the absolute deadline of the current activation, or [`None`]
+    /// if it is a best-effort task.
+    pub fn abs_deadline(&self) -> Option<u64> {
+        self.is_realtime()
+            .then(|| self.abs_deadline.load(Ordering::Acquire))
+    }
+
+    fn set_params(&self, params: Option<EDFParams>) {
+        let params = params.unwrap_or(EDFParams::new(0, 0, 0));
+        self.runtime.store(params.runtime, Ordering::Release);
+        self.deadline.store(params.deadline, Ordering::Release);
+        self.period.store(params.period, Ordering::Release);
+    }
+
+    fn is_realtime(&self) -> bool {
+        self.runtime.load(Ordering::Acquire) > 0
+    }
+
+    fn is_throttled(&self) -> bool {
+        self.budget.load(Ordering::Acquire) == 0
+    }
+
+    fn id(&self) -> usize {
+        self.id.load(Ordering::Acquire)
+    }
+
+    /// Starts a new activation at `now` with full budget.
+    fn replenish(&self, now: u64) {
+        self.budget
+            .store(self.runtime.load(Ordering::Acquire), Ordering::Release);
+        self.abs_deadline.store(
+            now + self.deadline.load(Ordering::Acquire),
+            Ordering::Release,
+        );
+        self.replenish_at
+            .store(now + self.period.load(Ordering::Acquire), Ordering::Release);
+    }
+
+    /// Checks whether the task can keep its current budget and deadline when
+    /// it wakes up at `now`, i.e., whether the remaining budget does not
+    /// exceed the reserved bandwidth before the deadline. Otherwise a new
+    /// activation is started.
+    fn update_on_wakeup(&self, now: u64) {
+        let budget = self.budget.load(Ordering::Acquire);
+        if budget == 0 {
+            // Exhausted tasks must wait for the replenishment.
+            if self.replenish_at.load(Ordering::Acquire) <= now {
+                self.replenish(now);
+            }
+            return;
+        }
+        let abs_deadline = self.abs_deadline.load(Ordering::Acquire);
+        let runtime = self.runtime.load(Ordering::Acquire) as u128;
+        let period = self.period.load(Ordering::Acquire) as u128;
+        if abs_deadline <= now || budget as u128 * period > (abs_deadline - now) as u128 * runtime {
+            self.replenish(now);
+        }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Deref for EDFTask<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// An [Earliest Deadline First][1] (EDF) real-time scheduler with sporadic
+/// servers.
+///
+/// Each real-time task is served by a sporadic server with the parameters
+/// (runtime, deadline, period) set by [`EDFScheduler::set_params`]. Ready
+/// real-time tasks are always picked in the order of their absolute deadlines.
+/// The running task consumes its budget at each timer tick. When the budget is
+/// exhausted, the task is throttled until the next replenishment, so a task
+/// that overruns can never steal the bandwidth reserved by others.
+///
+/// Tasks without real-time parameters (and throttled tasks) are not starved
+/// completely: they are scheduled in round-robin order when there is no ready
+/// real-time task, best-effort tasks before throttled ones.
+///
+/// The scheduler measures time in ticks, the clock advances on each
+/// [`task_tick`] and [`clock_tick`].
+///
+/// [1]: https://en.wikipedia.org/wiki/Earliest_deadline_first_scheduling
+/// [`task_tick`]: BaseScheduler::task_tick
+/// [`clock_tick`]: EDFScheduler::clock_tick
+pub struct EDFScheduler<T> {
+    ready_queue: BTreeMap<(u64, usize), Arc<EDFTask<T>>>, // (abs_deadline, id)
+    throttled_queue: BTreeMap<(u64, usize), Arc<EDFTask<T>>>, // (replenish_at, id)
+    best_effort_queue: VecDeque<Arc<EDFTask<T>>>,
+    clock: u64,
+    id_pool: usize,
+}
+
+impl<T> EDFScheduler<T> {
+    /// Creates a new empty [`EDFScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            throttled_queue: BTreeMap::new(),
+            best_effort_queue: VecDeque::new(),
+            clock: 0,
+            id_pool: 0,
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Earliest Deadline First"
+    }
+
+    /// Returns the current time of the scheduler clock, in ticks.
+    pub const fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// Advances the scheduler clock by one tick without charging any task.
+    ///
+    /// It should be called on timer ticks where no task in this scheduler is
+    /// running (e.g., the CPU is idle). Returns `true` if some task becomes
+    /// ready by replenishment.
+    pub fn clock_tick(&mut self) -> bool {
+        self.clock += 1;
+        self.replenish()
+    }
+
+    /// Sets the real-time parameters of a task, or turns it back into a
+    /// best-effort task if `params` is [`None`].
+    ///
+    /// The new reservation must have been admitted by
+    /// [`EDFBandwidth::reserve`]. Returns `false` if the parameters are
+    /// invalid, in which case the task keeps its old parameters.
+    ///
+    /// The task must not be in the ready queue (e.g., it is running).
+    pub fn set_params(&mut self, task: &Arc<EDFTask<T>>, params: Option<EDFParams>) -> bool {
+        if params.is_some_and(|p| !p.is_valid()) {
+            return false;
+        }
+        task.set_params(params);
+        task.replenish(self.clock);
+        true
+    }
+
+    fn next_id(&mut self) -> usize {
+        self.id_pool += 1;
+        self.id_pool
+    }
+
+    fn enqueue(&mut self, task: Arc<EDFTask<T>>) {
+        if !task.is_realtime() {
+            self.best_effort_queue.push_back(task);
+            return;
+        }
+        let id = self.next_id();
+        task.id.store(id, Ordering::Release);
+        if task.is_throttled() {
+            let replenish_at = task.replenish_at.load(Ordering::Acquire);
+            self.throttled_queue.insert((replenish_at, id), task);
+        } else {
+            let abs_deadline = task.abs_deadline.load(Ordering::Acquire);
+            self.ready_queue.insert((abs_deadline, id), task);
+        }
+    }
+
+    /// Moves throttled tasks whose replenishment time has come to the ready
+    /// queue. Returns `true` if any task is moved.
+    fn replenish(&mut self) -> bool {
+        let mut replenished = false;
+        while let Some(entry) = self.throttled_queue.first_entry() {
+            if entry.key().0 > self.clock {
+                break;
+            }
+            let task = entry.remove();
+            task.replenish(self.clock);
+            self.enqueue(task);
+            replenished = true;
+        }
+        replenished
+    }
+
+    /// Whether a task in the ready queue has an earlier deadline than
+    /// `abs_deadline`.
+    fn has_earlier_deadline(&self, abs_deadline: u64) -> bool {
+        self.ready_queue
+            .first_key_value()
+            .is_some_and(|((d, _), _)| *d < abs_deadline)
+    }
+}
+
+impl<T> BaseScheduler for EDFScheduler<T> {
+    type SchedItem = Arc<EDFTask<T>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        if task.is_realtime() {
+            task.update_on_wakeup(self.clock);
+        }
+        self.enqueue(task);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        if !task.is_realtime() {
+            // TODO: more efficient
+            return self
+                .best_effort_queue
+                .iter()
+                .position(|t| Arc::ptr_eq(t, task))
+                .and_then(|idx| self.best_effort_queue.remove(idx));
+        }
+        if task.is_throttled() {
+            let key = (task.replenish_at.load(Ordering::Acquire), task.id());
+            self.throttled_queue.remove(&key)
+        } else {
+            let key = (task.abs_deadline.load(Ordering::Acquire), task.id());
+            self.ready_queue.remove(&key)
+        }
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        self.replenish();
+        if let Some((_, task)) = self.ready_queue.pop_first() {
+            return Some(task);
+        }
+        if let Some(task) = self.best_effort_queue.pop_front() {
+            return Some(task);
+        }
+        self.throttled_queue.pop_first().map(|(_, task)| task)
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        if prev.is_realtime() {
+            self.enqueue(prev);
+        } else if prev.time_slice.load(Ordering::Acquire) > 0 && preempt {
+            self.best_effort_queue.push_front(prev);
+        } else {
+            prev.time_slice
+                .store(BEST_EFFORT_TIME_SLICE, Ordering::Release);
+            self.best_effort_queue.push_back(prev);
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        self.clock += 1;
+        self.replenish();
+
+        if !current.is_realtime() {
+            let old_slice = current.time_slice.fetch_sub(1, Ordering::Release);
+            return old_slice <= 1 || !self.ready_queue.is_empty();
+        }
+
+        if current.is_throttled() {
+            // Running in the background, catch up the replenishment.
+            if current.replenish_at.load(Ordering::Acquire) <= self.clock {
+                current.replenish(self.clock);
+            } else {
+                return !self.ready_queue.is_empty() || !self.best_effort_queue.is_empty();
+            }
+        } else if current.budget.fetch_sub(1, Ordering::AcqRel) <= 1 {
+            // Budget exhausted, throttle it until the next period.
+            return true;
+        }
+        self.has_earlier_deadline(current.abs_deadline.load(Ordering::Acquire))
+    }
+
+    fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
+        false
+    }
+}
@@ -0,0 +1,73 @@
+use alloc::{collections::VecDeque, sync::Arc};
+
+use crate::BaseScheduler;
+
+/// A scheduler for the idle class tasks (`SCHED_IDLE`), which only run when
+/// there is nothing else to do.
+///
+/// The tasks are scheduled in round-robin order with a time slice of
+/// `TIME_SLICE` ticks. Unlike the [`RRScheduler`], it does not need a task
+/// wrapper, so it can share the task type with other schedulers.
+///
+/// [`RRScheduler`]: crate::RRScheduler
+pub struct IdleScheduler<T, const TIME_SLICE: usize> {
+    ready_queue: VecDeque<Arc<T>>,
+    /// The remaining time slice of the running task.
+    time_slice: isize,
+}
+
+impl<T, const S: usize> IdleScheduler<T, S> {
+    /// Creates a new empty [`IdleScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+            time_slice: S as isize,
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Idle"
+    }
+}
+
+impl<T, const S: usize> BaseScheduler for IdleScheduler<T, S> {
+    type SchedItem = Arc<T>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        self.ready_queue.push_back(task);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        // TODO: more efficient
+        self.ready_queue
+            .iter()
+            .position(|t| Arc::ptr_eq(t, task))
+            .and_then(|idx| self.ready_queue.remove(idx))
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        let task = self.ready_queue.pop_front()?;
+        self.time_slice = S as isize;
+        Some(task)
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        if preempt && self.time_slice > 0 {
+            self.ready_queue.push_front(prev)
+        } else {
+            self.ready_queue.push_back(prev)
+        }
+    }
+
+    fn task_tick(&mut self, _current: &Self::SchedItem) -> bool {
+        self.time_slice -= 1;
+        self.time_slice <= 0
+    }
+
+    fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
+        false
+    }
+}
@@ -5,13 +5,27 @@
 //! - [`FifoScheduler`]: FIFO (First-In-First-Out) scheduler (cooperative).
 //! - [`RRScheduler`]: Round-robin scheduler (preemptive).
 //! - [`CFScheduler`]: Completely Fair Scheduler (preemptive).
+//! - [`MLFQScheduler`]: Multi-level feedback queue scheduler (preemptive).
+//! - [`EDFScheduler`]: Earliest Deadline First real-time scheduler with
+//!   admission control (preemptive).
+//! - [`RTScheduler`]: Fixed-priority real-time scheduler with `SCHED_FIFO` and
+//!   `SCHED_RR` semantics (preemptive).
+//! - [`IdleScheduler`]: Round-robin scheduler for the idle class tasks
+//!   (preemptive).
+//!
+//! The last two schedulers do not need their own task wrappers, so they can be
+//! stacked with other schedulers as scheduling classes.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(const_mut_refs)]
 
 mod cfs;
+mod edf;
 mod fifo;
+mod idle;
+mod mlfq;
 mod round_robin;
+mod rt;
 
 #[cfg(test)]
 mod tests;
@@ -19,8 +33,12 @@ mod tests;
 extern crate alloc;
 
 pub use cfs::{CFSTask, CFScheduler};
+pub use edf::{EDFBandwidth, EDFParams, EDFScheduler, EDFTask};
 pub use fifo::{FifoScheduler, FifoTask};
+pub use idle::IdleScheduler;
+pub use mlfq::{MLFQScheduler, MLFQTask};
 pub use round_robin::{RRScheduler, RRTask};
+pub use rt::{RTSchedInfo, RTScheduler, RT_PRIO_MAX, RT_PRIO_MIN};
 
 /// The base scheduler trait that all schedulers should implement.
 ///
@@ -0,0 +1,215 @@
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+
+use crate::BaseScheduler;
+
+/// A task wrapper for the [`MLFQScheduler`].
+///
+/// It records the queue level of the task and its remaining time allotment
+/// at that level.
+pub struct MLFQTask<T> {
+    inner: T,
+    /// The current queue level, 0 is the highest priority.
+    level: AtomicUsize,
+    /// The highest level that the task can be boosted to, set by
+    /// [`set_priority`](MLFQScheduler::set_priority).
+    base_level: AtomicUsize,
+    time_slice: AtomicIsize,
+    /// Whether the task is woken up after blocking, set by
+    /// [`set_woken`](MLFQTask::set_woken) and cleared when it's added.
+    woken: AtomicBool,
+}
+
+impl<T> MLFQTask<T> {
+    /// Creates a new [`MLFQTask`] from the inner task struct.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            level: AtomicUsize::new(0),
+            base_level: AtomicUsize::new(0),
+            time_slice: AtomicIsize::new(0),
+            woken: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the current queue level of the task.
+    pub fn level(&self) -> usize {
+        self.level.load(Ordering::Acquire)
+    }
+
+    fn base_level(&self) -> usize {
+        self.base_level.load(Ordering::Acquire)
+    }
+
+    fn time_slice(&self) -> isize {
+        self.time_slice.load(Ordering::Acquire)
+    }
+
+    /// Moves the task to the given level, with a full time allotment of that
+    /// level.
+    fn set_level(&self, level: usize, base_slice: usize) {
+        self.level.store(level, Ordering::Release);
+        self.time_slice
+            .store((base_slice << level) as isize, Ordering::Release);
+    }
+
+    /// Marks the task as woken up after blocking, so that it's promoted when
+    /// it's added to the scheduler next time.
+    pub fn set_woken(&self) {
+        self.woken.store(true, Ordering::Release);
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Deref for MLFQTask<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A [Multi-Level Feedback Queue][1] (MLFQ) preemptive scheduler.
+///
+/// Tasks are kept in `LEVELS` round-robin ready queues, and the tasks in the
+/// highest non-empty queue (level 0) always run first. The time allotment of
+/// a task at level `i` is `BASE_SLICE << i` timer ticks, and it's not reset
+/// when the task yields, so a task can't keep its level by yielding before
+/// the allotment runs out. The priorities are adjusted by the behavior of
+/// tasks:
+///
+/// - A task that uses up its allotment is CPU-bound, and it's demoted to the
+///   next lower level.
+/// - A task that is woken up after blocking is I/O-bound, and it's promoted
+///   to the next higher level when it's added back (the waker marks it with
+///   [`MLFQTask::set_woken`]). Tasks added back for other reasons, e.g.,
+///   migrated from other CPUs, keep their levels. A running task is
+///   preempted when a task of a higher level is ready.
+/// - Every `BOOST_PERIOD` ticks, all tasks are moved back to their base levels,
+///   so that CPU-bound tasks are not starved by a stream of I/O-bound tasks.
+///
+/// The base level of a task is 0 by default, it can be changed by
+/// [`set_priority`](BaseScheduler::set_priority) with a value in
+/// `0..LEVELS`. A task is never promoted above its base level.
+///
+/// Like [`RRScheduler`], it uses [`VecDeque`] as the ready queues. So it may
+/// take O(n) time to remove a task from the ready queue.
+///
+/// [1]: https://en.wikipedia.org/wiki/Multilevel_feedback_queue
+/// [`RRScheduler`]: crate::RRScheduler
+pub struct MLFQScheduler<T, const LEVELS: usize, const BASE_SLICE: usize, const BOOST_PERIOD: usize>
+{
+    ready_queues: [VecDeque<Arc<MLFQTask<T>>>; LEVELS],
+    /// Timer ticks since the last priority boost.
+    ticks: usize,
+}
+
+impl<T, const L: usize, const S: usize, const B: usize> MLFQScheduler<T, L, S, B> {
+    const EMPTY_QUEUE: VecDeque<Arc<MLFQTask<T>>> = VecDeque::new();
+
+    /// Creates a new empty [`MLFQScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            ready_queues: [Self::EMPTY_QUEUE; L],
+            ticks: 0,
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Multi-level feedback queue"
+    }
+
+    fn remove_queued(&mut self, task: &Arc<MLFQTask<T>>) -> Option<Arc<MLFQTask<T>>> {
+        let queue = &mut self.ready_queues[task.level()];
+        queue
+            .iter()
+            .position(|t| Arc::ptr_eq(t, task))
+            .and_then(|idx| queue.remove(idx))
+    }
+
+    /// Moves all tasks back to their base levels, keeping their order.
+    fn boost(&mut self, current: &Arc<MLFQTask<T>>) {
+        let tasks: Vec<_> = self
+            .ready_queues
+            .iter_mut()
+            .flat_map(|queue| queue.drain(..))
+            .collect();
+        for task in tasks {
+            let level = task.base_level();
+            task.set_level(level, S);
+            self.ready_queues[level].push_back(task);
+        }
+        current.set_level(current.base_level(), S);
+    }
+}
+
+impl<T, const L: usize, const S: usize, const B: usize> BaseScheduler
+    for MLFQScheduler<T, L, S, B>
+{
+    type SchedItem = Arc<MLFQTask<T>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        if task.woken.swap(false, Ordering::AcqRel) {
+            let level = task.level().saturating_sub(1).max(task.base_level());
+            task.set_level(level, S);
+        }
+        self.ready_queues[task.level()].push_back(task);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        self.remove_queued(task)
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        self.ready_queues
+            .iter_mut()
+            .find_map(|queue| queue.pop_front())
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        let level = prev.level();
+        if prev.time_slice() <= 0 {
+            let level = (level + 1).min(L - 1);
+            prev.set_level(level, S);
+            self.ready_queues[level].push_back(prev);
+        } else if preempt {
+            self.ready_queues[level].push_front(prev);
+        } else {
+            self.ready_queues[level].push_back(prev);
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        self.ticks += 1;
+        if self.ticks >= B {
+            self.ticks = 0;
+            self.boost(current);
+        }
+        let old_slice = current.time_slice.fetch_sub(1, Ordering::Release);
+        old_slice <= 1
+            || self.ready_queues[..current.level()]
+                .iter()
+                .any(|queue| !queue.is_empty())
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        if !(0..L as isize).contains(&prio) {
+            return false;
+        }
+        let queued = self.remove_queued(task);
+        task.base_level.store(prio as usize, Ordering::Release);
+        task.set_level(prio as usize, S);
+        if let Some(task) = queued {
+            self.ready_queues[prio as usize].push_back(task);
+        }
+        true
+    }
+}
@@ -0,0 +1,169 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::ops::Deref;
+
+use crate::BaseScheduler;
+
+/// The lowest real-time priority.
+pub const RT_PRIO_MIN: usize = 1;
+/// The highest real-time priority.
+pub const RT_PRIO_MAX: usize = 99;
+
+/// The real-time attributes of a task that are used by the [`RTScheduler`].
+///
+/// They are usually stored in the inner task struct, so that the task can be
+/// switched between different schedulers.
+pub trait RTSchedInfo {
+    /// Returns the real-time priority, ranging from [`RT_PRIO_MIN`] to
+    /// [`RT_PRIO_MAX`]. Higher values mean higher priority.
+    fn rt_priority(&self) -> usize;
+
+    /// Sets the real-time priority.
+    fn set_rt_priority(&self, prio: usize);
+
+    /// Whether the task is scheduled in round-robin order (`SCHED_RR`) with
+    /// tasks of the same priority, otherwise in first-in-first-out order
+    /// (`SCHED_FIFO`).
+    fn is_round_robin(&self) -> bool;
+}
+
+/// A fixed-priority real-time scheduler, with the same semantics as the
+/// `SCHED_FIFO` and `SCHED_RR` policies of POSIX.
+///
+/// The ready task with the highest priority is always picked. A `SCHED_FIFO`
+/// task runs until it blocks, yields or is preempted by a higher priority
+/// task. A `SCHED_RR` task additionally gives up the CPU to tasks of the same
+/// priority when its time slice of `RR_TIME_SLICE` ticks runs out.
+///
+/// The task type `T` is a wrapper of the inner task struct, which provides
+/// [`RTSchedInfo`] via [`Deref`].
+pub struct RTScheduler<T, const RR_TIME_SLICE: usize> {
+    ready_queues: [VecDeque<Arc<T>>; RT_PRIO_MAX + 1],
+    /// Bit `i` is set if `ready_queues[i]` is not empty.
+    ready_bitmap: u128,
+    /// The remaining time slice of the running task.
+    time_slice: isize,
+}
+
+impl<T, const S: usize> RTScheduler<T, S> {
+    const EMPTY_QUEUE: VecDeque<Arc<T>> = VecDeque::new();
+
+    /// Creates a new empty [`RTScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            ready_queues: [Self::EMPTY_QUEUE; RT_PRIO_MAX + 1],
+            ready_bitmap: 0,
+            time_slice: S as isize,
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Real-time"
+    }
+
+    /// Returns the highest priority of the ready tasks, or [`None`] if there
+    /// is no ready task.
+    pub fn highest_priority(&self) -> Option<usize> {
+        if self.ready_bitmap == 0 {
+            None
+        } else {
+            Some(127 - self.ready_bitmap.leading_zeros() as usize)
+        }
+    }
+
+    fn pop_from(&mut self, prio: usize) -> Option<Arc<T>> {
+        let task = self.ready_queues[prio].pop_front();
+        if self.ready_queues[prio].is_empty() {
+            self.ready_bitmap &= !(1 << prio);
+        }
+        task
+    }
+}
+
+impl<T, const S: usize> RTScheduler<T, S>
+where
+    T: Deref,
+    T::Target: RTSchedInfo,
+{
+    fn push(&mut self, task: Arc<T>, front: bool) {
+        let prio = task.rt_priority().clamp(RT_PRIO_MIN, RT_PRIO_MAX);
+        if front {
+            self.ready_queues[prio].push_front(task);
+        } else {
+            self.ready_queues[prio].push_back(task);
+        }
+        self.ready_bitmap |= 1 << prio;
+    }
+}
+
+impl<T, const S: usize> BaseScheduler for RTScheduler<T, S>
+where
+    T: Deref,
+    T::Target: RTSchedInfo,
+{
+    type SchedItem = Arc<T>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        self.push(task, false);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        let prio = task.rt_priority().clamp(RT_PRIO_MIN, RT_PRIO_MAX);
+        let queue = &mut self.ready_queues[prio];
+        // TODO: more efficient
+        let task = queue
+            .iter()
+            .position(|t| Arc::ptr_eq(t, task))
+            .and_then(|idx| queue.remove(idx));
+        if queue.is_empty() {
+            self.ready_bitmap &= !(1 << prio);
+        }
+        task
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        let task = self.pop_from(self.highest_priority()?);
+        self.time_slice = S as isize;
+        task
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        // A preempted task keeps its position at the head of the queue, unless
+        // it's a round-robin task that has used up its time slice.
+        let front = preempt && (!prev.is_round_robin() || self.time_slice > 0);
+        self.push(prev, front);
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        let prio = current.rt_priority();
+        if self.highest_priority().is_some_and(|p| p > prio) {
+            return true;
+        }
+        if current.is_round_robin() {
+            self.time_slice -= 1;
+            if self.time_slice <= 0 {
+                if !self.ready_queues[prio].is_empty() {
+                    return true;
+                }
+                // Keep running if there is no other task of the same priority.
+                self.time_slice = S as isize;
+            }
+        }
+        false
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        if !(RT_PRIO_MIN as isize..=RT_PRIO_MAX as isize).contains(&prio) {
+            return false;
+        }
+        if let Some(task) = self.remove_task(task) {
+            task.set_rt_priority(prio as usize);
+            self.push(task, false);
+        } else {
+            task.set_rt_priority(prio as usize);
+        }
+        true
+    }
+}
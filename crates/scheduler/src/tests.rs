@@ -82,3 +82,376 @@ macro_rules! def_test_sched {
 def_test_sched!(fifo, FifoScheduler::<usize>, FifoTask::<usize>);
 def_test_sched!(rr, RRScheduler::<usize, 5>, RRTask::<usize, 5>);
 def_test_sched!(cfs, CFScheduler::<usize>, CFSTask::<usize>);
+def_test_sched!(edf, EDFScheduler::<usize>, EDFTask::<usize>);
+def_test_sched!(mlfq, MLFQScheduler::<usize, 4, 2, 100>, MLFQTask::<usize>);
+
+mod mlfq_feedback {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    type Scheduler = MLFQScheduler<usize, 4, 2, 100>;
+
+    /// Runs the task for `ticks` timer ticks, or until it needs rescheduling.
+    fn run(scheduler: &mut Scheduler, ticks: usize) -> Arc<MLFQTask<usize>> {
+        let curr = scheduler.pick_next_task().unwrap();
+        for _ in 0..ticks {
+            if scheduler.task_tick(&curr) {
+                break;
+            }
+        }
+        curr
+    }
+
+    #[test]
+    fn test_demote_and_promote() {
+        let mut scheduler = Scheduler::new();
+        let batch = Arc::new(MLFQTask::new(0));
+        let interactive = Arc::new(MLFQTask::new(1));
+        scheduler.add_task(batch.clone());
+        scheduler.add_task(interactive.clone());
+
+        // the CPU-bound task uses up its allotments and sinks to the bottom
+        scheduler.remove_task(&interactive).unwrap();
+        for level in 0..3 {
+            let curr = run(&mut scheduler, usize::MAX);
+            assert_eq!(curr.level(), level);
+            scheduler.put_prev_task(curr, false);
+        }
+        assert_eq!(batch.level(), 3);
+        let curr = run(&mut scheduler, usize::MAX);
+        scheduler.put_prev_task(curr, false);
+        assert_eq!(batch.level(), 3);
+
+        // yielding does not reset the allotment
+        let curr = run(&mut scheduler, 15);
+        scheduler.put_prev_task(curr, false);
+        let curr = scheduler.pick_next_task().unwrap();
+        assert!(scheduler.task_tick(&curr));
+        scheduler.put_prev_task(curr, false);
+        assert_eq!(batch.level(), 3);
+
+        // the woken up task preempts the CPU-bound task
+        let curr = scheduler.pick_next_task().unwrap();
+        interactive.set_woken();
+        scheduler.add_task(interactive.clone());
+        assert!(scheduler.task_tick(&curr));
+        scheduler.put_prev_task(curr, true);
+
+        // the I/O-bound task is promoted when it's woken up
+        let curr = run(&mut scheduler, usize::MAX);
+        assert_eq!(*curr.inner(), 1);
+        scheduler.put_prev_task(curr, false);
+        assert_eq!(interactive.level(), 1);
+        let curr = scheduler.pick_next_task().unwrap();
+        assert_eq!(*curr.inner(), 1);
+        curr.set_woken();
+        scheduler.add_task(curr);
+        assert_eq!(interactive.level(), 0);
+    }
+
+    #[test]
+    fn test_readd_without_wakeup() {
+        let mut scheduler = Scheduler::new();
+        let task = Arc::new(MLFQTask::new(0));
+        scheduler.add_task(task.clone());
+        let curr = run(&mut scheduler, usize::MAX);
+        scheduler.put_prev_task(curr, false);
+        assert_eq!(task.level(), 1);
+
+        // e.g., migrated to another CPU, or its parameters are changed
+        let task = scheduler.remove_task(&task).unwrap();
+        scheduler.add_task(task.clone());
+        assert_eq!(task.level(), 1);
+
+        // only promoted once for a wakeup
+        let task = scheduler.pick_next_task().unwrap();
+        task.set_woken();
+        scheduler.add_task(task.clone());
+        assert_eq!(task.level(), 0);
+        let curr = run(&mut scheduler, 1);
+        scheduler.put_prev_task(curr, false);
+        assert_eq!(task.level(), 0);
+    }
+
+    #[test]
+    fn test_priority_boost() {
+        let mut scheduler = Scheduler::new();
+        let batch = Arc::new(MLFQTask::new(0));
+        scheduler.add_task(batch.clone());
+        let mut ticks = 0;
+        while batch.level() < 3 {
+            let curr = scheduler.pick_next_task().unwrap();
+            loop {
+                ticks += 1;
+                if scheduler.task_tick(&curr) {
+                    break;
+                }
+            }
+            scheduler.put_prev_task(curr, false);
+        }
+
+        // a stream of interactive tasks can not starve the batch task
+        let curr = scheduler.pick_next_task().unwrap();
+        for i in 1..100 - ticks {
+            scheduler.add_task(Arc::new(MLFQTask::new(i)));
+            scheduler.task_tick(&curr);
+        }
+        assert_eq!(batch.level(), 3);
+        scheduler.task_tick(&curr);
+        assert_eq!(batch.level(), 0);
+    }
+
+    #[test]
+    fn test_base_level() {
+        let mut scheduler = Scheduler::new();
+        let t0 = Arc::new(MLFQTask::new(0));
+        let t1 = Arc::new(MLFQTask::new(1));
+        scheduler.add_task(t0.clone());
+        scheduler.add_task(t1.clone());
+        assert!(!scheduler.set_priority(&t0, 4));
+        assert!(!scheduler.set_priority(&t0, -1));
+        assert!(scheduler.set_priority(&t0, 2));
+        assert_eq!(t0.level(), 2);
+
+        // never promoted above the base level
+        scheduler.remove_task(&t0).unwrap();
+        scheduler.add_task(t0.clone());
+        assert_eq!(t0.level(), 2);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 1);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+    }
+}
+
+mod edf_realtime {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    /// Admits the reservation by `bw` and sets the parameters of `task`.
+    fn set_params(
+        scheduler: &mut EDFScheduler<usize>,
+        task: &Arc<EDFTask<usize>>,
+        params: Option<EDFParams>,
+        bw: &EDFBandwidth,
+    ) -> bool {
+        bw.reserve(task.params().as_ref(), params.as_ref()) && scheduler.set_params(task, params)
+    }
+
+    #[test]
+    fn test_admission() {
+        let bw = EDFBandwidth::new(1);
+        let mut scheduler = EDFScheduler::<usize>::new();
+        let t0 = Arc::new(EDFTask::new(0));
+        let t1 = Arc::new(EDFTask::new(1));
+
+        assert!(!set_params(
+            &mut scheduler,
+            &t0,
+            Some(EDFParams::new(0, 10, 10)),
+            &bw
+        ));
+        assert!(!set_params(
+            &mut scheduler,
+            &t0,
+            Some(EDFParams::new(5, 10, 8)),
+            &bw
+        ));
+        assert!(set_params(
+            &mut scheduler,
+            &t0,
+            Some(EDFParams::new(5, 10, 10)),
+            &bw
+        ));
+        assert_eq!(bw.utilization(), 500);
+        // 50% + 50% exceeds the 95% limit
+        assert!(!set_params(
+            &mut scheduler,
+            &t1,
+            Some(EDFParams::new(5, 10, 10)),
+            &bw
+        ));
+        assert_eq!(t1.params(), None);
+        assert!(set_params(
+            &mut scheduler,
+            &t1,
+            Some(EDFParams::new(4, 10, 10)),
+            &bw
+        ));
+        // shrinking a reservation is always allowed
+        assert!(set_params(
+            &mut scheduler,
+            &t0,
+            Some(EDFParams::new(2, 10, 10)),
+            &bw
+        ));
+        assert!(set_params(&mut scheduler, &t0, None, &bw));
+        assert!(set_params(&mut scheduler, &t1, None, &bw));
+        assert_eq!(bw.utilization(), 0);
+    }
+
+    #[test]
+    fn test_partitioned_admission() {
+        let bw = [EDFBandwidth::new(1), EDFBandwidth::new(1)];
+        let params = EDFParams::new(5, 10, 10);
+        assert!(bw[0].reserve(None, Some(&params)));
+        // 50% + 50% exceeds the limit, but fits on another CPU
+        assert!(!bw[0].reserve(None, Some(&params)));
+        assert!(bw[1].reserve(None, Some(&params)));
+        assert!(!bw[1].reserve(None, Some(&EDFParams::new(2, 1, 10))));
+        assert_eq!(bw[0].utilization(), 500);
+        assert_eq!(bw[1].utilization(), 500);
+        // moving a reservation releases it on the old CPU
+        assert!(bw[0].reserve(Some(&params), None));
+        assert_eq!(bw[0].utilization(), 0);
+    }
+
+    #[test]
+    fn test_deadline_order() {
+        let bw = EDFBandwidth::new(1);
+        let mut scheduler = EDFScheduler::<usize>::new();
+        let best_effort = Arc::new(EDFTask::new(0));
+        let late = Arc::new(EDFTask::new(1));
+        let early = Arc::new(EDFTask::new(2));
+        assert!(set_params(
+            &mut scheduler,
+            &late,
+            Some(EDFParams::new(2, 20, 20)),
+            &bw
+        ));
+        assert!(set_params(
+            &mut scheduler,
+            &early,
+            Some(EDFParams::new(2, 5, 10)),
+            &bw
+        ));
+
+        scheduler.add_task(best_effort.clone());
+        scheduler.add_task(late.clone());
+        scheduler.add_task(early.clone());
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 2);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 1);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+        assert!(scheduler.pick_next_task().is_none());
+
+        // an earlier deadline preempts the running real-time task
+        scheduler.add_task(early.clone());
+        assert!(scheduler.task_tick(&late));
+        // best-effort tasks are always preempted by real-time tasks
+        assert!(scheduler.task_tick(&best_effort));
+    }
+
+    #[test]
+    fn test_budget_throttling() {
+        let bw = EDFBandwidth::new(1);
+        let mut scheduler = EDFScheduler::<usize>::new();
+        let rt = Arc::new(EDFTask::new(0));
+        let best_effort = Arc::new(EDFTask::new(1));
+        assert!(set_params(
+            &mut scheduler,
+            &rt,
+            Some(EDFParams::new(3, 10, 10)),
+            &bw
+        ));
+        scheduler.add_task(rt.clone());
+        scheduler.add_task(best_effort);
+
+        let mut ran = 0;
+        for _ in 0..30 {
+            let next = scheduler.pick_next_task().unwrap();
+            if *next.inner() == 0 {
+                ran += 1;
+            }
+            scheduler.task_tick(&next);
+            scheduler.put_prev_task(next, false);
+        }
+        // 3 ticks in each of the 3 periods
+        assert_eq!(ran, 9);
+    }
+}
+def_test_sched!(idle, IdleScheduler::<FifoTask<usize>, 5>, FifoTask::<usize>);
+
+mod rt_priority {
+    use crate::*;
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Task {
+        id: usize,
+        prio: AtomicUsize,
+        round_robin: bool,
+    }
+
+    impl RTSchedInfo for Task {
+        fn rt_priority(&self) -> usize {
+            self.prio.load(Ordering::Acquire)
+        }
+        fn set_rt_priority(&self, prio: usize) {
+            self.prio.store(prio, Ordering::Release)
+        }
+        fn is_round_robin(&self) -> bool {
+            self.round_robin
+        }
+    }
+
+    fn new_task(id: usize, prio: usize, round_robin: bool) -> Arc<FifoTask<Task>> {
+        Arc::new(FifoTask::new(Task {
+            id,
+            prio: AtomicUsize::new(prio),
+            round_robin,
+        }))
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let mut scheduler = RTScheduler::<FifoTask<Task>, 5>::new();
+        scheduler.add_task(new_task(0, 10, false));
+        scheduler.add_task(new_task(1, 20, false));
+        scheduler.add_task(new_task(2, 10, false));
+        let t3 = new_task(3, 5, false);
+        scheduler.add_task(t3.clone());
+        assert_eq!(scheduler.highest_priority(), Some(20));
+
+        assert!(scheduler.set_priority(&t3, 30));
+        assert!(!scheduler.set_priority(&t3, 100));
+        for id in [3, 1, 0, 2] {
+            assert_eq!(scheduler.pick_next_task().unwrap().id, id);
+        }
+        assert!(scheduler.pick_next_task().is_none());
+        assert_eq!(scheduler.highest_priority(), None);
+    }
+
+    #[test]
+    fn test_preempt() {
+        let mut scheduler = RTScheduler::<FifoTask<Task>, 5>::new();
+        scheduler.add_task(new_task(0, 10, false));
+        scheduler.add_task(new_task(1, 10, false));
+        let curr = scheduler.pick_next_task().unwrap();
+        for _ in 0..100 {
+            assert!(!scheduler.task_tick(&curr));
+        }
+
+        // preempted by a higher priority task, and stays at the queue head
+        scheduler.add_task(new_task(2, 20, false));
+        assert!(scheduler.task_tick(&curr));
+        scheduler.put_prev_task(curr, true);
+        assert_eq!(scheduler.pick_next_task().unwrap().id, 2);
+        assert_eq!(scheduler.pick_next_task().unwrap().id, 0);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        const NUM_TASKS: usize = 3;
+        let mut scheduler = RTScheduler::<FifoTask<Task>, 5>::new();
+        for i in 0..NUM_TASKS {
+            scheduler.add_task(new_task(i, 10, true));
+        }
+        for i in 0..NUM_TASKS * 10 {
+            let curr = scheduler.pick_next_task().unwrap();
+            assert_eq!(curr.id, i % NUM_TASKS);
+            for _ in 0..4 {
+                assert!(!scheduler.task_tick(&curr));
+            }
+            assert!(scheduler.task_tick(&curr));
+            scheduler.put_prev_task(curr, true);
+        }
+    }
+}
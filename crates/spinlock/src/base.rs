@@ -1,7 +1,9 @@
-//! A naïve spinning mutex.
+//! A spinning mutex with a configurable lock algorithm.
 //!
-//! Waiting threads hammer an atomic variable until it becomes available. Best-case latency is low, but worst-case
-//! latency is theoretically infinite.
+//! Waiting threads spin until the lock becomes available, in the way of the
+//! raw lock [`RawSpinLock`]. With the default [`TasLock`], they hammer an
+//! atomic variable. Best-case latency is low, but worst-case latency is
+//! theoretically infinite.
 //!
 //! Based on [`spin::Mutex`](https://docs.rs/spin/latest/src/spin/mutex/spin.rs.html).
 
@@ -10,51 +12,64 @@ use core::fmt;
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
 
-#[cfg(feature = "smp")]
-use core::sync::atomic::{AtomicBool, Ordering};
-
 use kernel_guard::BaseGuard;
+#[cfg(feature = "lockdep")]
+use {core::panic::Location, lockdep::LockClass, lockdep::LockKind};
+
+use crate::raw::{RawSpinLock, TasLock};
 
 /// A [spin lock](https://en.m.wikipedia.org/wiki/Spinlock) providing mutually
 /// exclusive access to data.
 ///
 /// This is a base struct, the specific behavior depends on the generic
 /// parameter `G` that implements [`BaseGuard`], such as whether to disable
-/// local IRQs or kernel preemption before acquiring the lock.
+/// local IRQs or kernel preemption before acquiring the lock. The generic
+/// parameter `L` is the lock algorithm that implements [`RawSpinLock`], which
+/// is the unfair [`TasLock`] by default.
 ///
 /// For single-core environment (without the "smp" feature), we remove the lock
 /// state, CPU can always get the lock if we follow the proper guard in use.
-pub struct BaseSpinLock<G: BaseGuard, T: ?Sized> {
-    _phantom: PhantomData<G>,
+///
+/// With the "lockdep" feature, the locks created at different places belong
+/// to different lock classes, whose acquisition order is checked at runtime.
+pub struct BaseSpinLock<G: BaseGuard, T: ?Sized, L: RawSpinLock = TasLock> {
+    _phantom: PhantomData<(G, L)>,
     #[cfg(feature = "smp")]
-    lock: AtomicBool,
+    lock: L,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
     data: UnsafeCell<T>,
 }
 
 /// A guard that provides mutable data access.
 ///
 /// When the guard falls out of scope it will release the lock.
-pub struct BaseSpinLockGuard<'a, G: BaseGuard, T: ?Sized + 'a> {
-    _phantom: &'a PhantomData<G>,
+pub struct BaseSpinLockGuard<'a, G: BaseGuard, T: ?Sized + 'a, L: RawSpinLock = TasLock> {
+    _phantom: &'a PhantomData<(G, L)>,
     irq_state: G::State,
     data: *mut T,
     #[cfg(feature = "smp")]
-    lock: &'a AtomicBool,
+    lock: &'a L,
+    #[cfg(feature = "lockdep")]
+    lock_addr: *const (),
 }
 
 // Same unsafe impls as `std::sync::Mutex`
-unsafe impl<G: BaseGuard, T: ?Sized + Send> Sync for BaseSpinLock<G, T> {}
-unsafe impl<G: BaseGuard, T: ?Sized + Send> Send for BaseSpinLock<G, T> {}
+unsafe impl<G: BaseGuard, T: ?Sized + Send, L: RawSpinLock> Sync for BaseSpinLock<G, T, L> {}
+unsafe impl<G: BaseGuard, T: ?Sized + Send, L: RawSpinLock> Send for BaseSpinLock<G, T, L> {}
 
-impl<G: BaseGuard, T> BaseSpinLock<G, T> {
+impl<G: BaseGuard, T, L: RawSpinLock> BaseSpinLock<G, T, L> {
     /// Creates a new [`BaseSpinLock`] wrapping the supplied data.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(data: T) -> Self {
         Self {
             _phantom: PhantomData,
             data: UnsafeCell::new(data),
             #[cfg(feature = "smp")]
-            lock: AtomicBool::new(false),
+            lock: L::INIT,
+            #[cfg(feature = "lockdep")]
+            class: LockClass::new(),
         }
     }
 
@@ -68,35 +83,28 @@ impl<G: BaseGuard, T> BaseSpinLock<G, T> {
     }
 }
 
-impl<G: BaseGuard, T: ?Sized> BaseSpinLock<G, T> {
+impl<G: BaseGuard, T: ?Sized, L: RawSpinLock> BaseSpinLock<G, T, L> {
     /// Locks the [`BaseSpinLock`] and returns a guard that permits access to the inner data.
     ///
     /// The returned value may be dereferenced for data access
     /// and the lock will be dropped when the guard falls out of scope.
     #[inline(always)]
-    pub fn lock(&self) -> BaseSpinLockGuard<G, T> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn lock(&self) -> BaseSpinLockGuard<G, T, L> {
         let irq_state = G::acquire();
+        #[cfg(feature = "lockdep")]
+        self.class
+            .acquire(self.lock_addr(), LockKind::Spin, Location::caller());
         #[cfg(feature = "smp")]
-        {
-            // Can fail to lock even if the spinlock is not locked. May be more efficient than `try_lock`
-            // when called in a loop.
-            while self
-                .lock
-                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
-                .is_err()
-            {
-                // Wait until the lock looks unlocked before retrying
-                while self.is_locked() {
-                    core::hint::spin_loop();
-                }
-            }
-        }
+        self.lock.lock();
         BaseSpinLockGuard {
             _phantom: &PhantomData,
             irq_state,
             data: unsafe { &mut *self.data.get() },
             #[cfg(feature = "smp")]
             lock: &self.lock,
+            #[cfg(feature = "lockdep")]
+            lock_addr: self.lock_addr(),
         }
     }
 
@@ -110,7 +118,7 @@ impl<G: BaseGuard, T: ?Sized> BaseSpinLock<G, T> {
     pub fn is_locked(&self) -> bool {
         cfg_if::cfg_if! {
             if #[cfg(feature = "smp")] {
-                self.lock.load(Ordering::Relaxed)
+                self.lock.is_locked()
             } else {
                 false
             }
@@ -119,29 +127,30 @@ impl<G: BaseGuard, T: ?Sized> BaseSpinLock<G, T> {
 
     /// Try to lock this [`BaseSpinLock`], returning a lock guard if successful.
     #[inline(always)]
-    pub fn try_lock(&self) -> Option<BaseSpinLockGuard<G, T>> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn try_lock(&self) -> Option<BaseSpinLockGuard<G, T, L>> {
         let irq_state = G::acquire();
 
         cfg_if::cfg_if! {
             if #[cfg(feature = "smp")] {
-                // The reason for using a strong compare_exchange is explained here:
-                // https://github.com/Amanieu/parking_lot/pull/207#issuecomment-575869107
-                let is_unlocked = self
-                .lock
-                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-                .is_ok();
+                let is_unlocked = self.lock.try_lock();
             } else {
                 let is_unlocked = true;
             }
         }
 
         if is_unlocked {
+            #[cfg(feature = "lockdep")]
+            self.class
+                .acquired(self.lock_addr(), LockKind::Spin, Location::caller());
             Some(BaseSpinLockGuard {
                 _phantom: &PhantomData,
                 irq_state,
                 data: unsafe { &mut *self.data.get() },
                 #[cfg(feature = "smp")]
                 lock: &self.lock,
+                #[cfg(feature = "lockdep")]
+                lock_addr: self.lock_addr(),
             })
         } else {
             None
@@ -157,8 +166,19 @@ impl<G: BaseGuard, T: ?Sized> BaseSpinLock<G, T> {
     /// lock to FFI that doesn't know how to deal with RAII.
     #[inline(always)]
     pub unsafe fn force_unlock(&self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock_addr());
         #[cfg(feature = "smp")]
-        self.lock.store(false, Ordering::Release);
+        self.lock.unlock();
+    }
+
+    /// Returns the address that identifies this lock in [`lockdep`].
+    ///
+    /// The data address is not used, as it's not unique for zero-sized data.
+    #[cfg(feature = "lockdep")]
+    #[inline(always)]
+    pub fn lock_addr(&self) -> *const () {
+        self as *const Self as *const ()
     }
 
     /// Returns a mutable reference to the underlying data.
@@ -174,14 +194,15 @@ impl<G: BaseGuard, T: ?Sized> BaseSpinLock<G, T> {
     }
 }
 
-impl<G: BaseGuard, T: ?Sized + Default> Default for BaseSpinLock<G, T> {
+impl<G: BaseGuard, T: ?Sized + Default, L: RawSpinLock> Default for BaseSpinLock<G, T, L> {
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn default() -> Self {
         Self::new(Default::default())
     }
 }
 
-impl<G: BaseGuard, T: ?Sized + fmt::Debug> fmt::Debug for BaseSpinLock<G, T> {
+impl<G: BaseGuard, T: ?Sized + fmt::Debug, L: RawSpinLock> fmt::Debug for BaseSpinLock<G, T, L> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.try_lock() {
             Some(guard) => write!(f, "SpinLock {{ data: ")
@@ -192,7 +213,7 @@ impl<G: BaseGuard, T: ?Sized + fmt::Debug> fmt::Debug for BaseSpinLock<G, T> {
     }
 }
 
-impl<'a, G: BaseGuard, T: ?Sized> Deref for BaseSpinLockGuard<'a, G, T> {
+impl<'a, G: BaseGuard, T: ?Sized, L: RawSpinLock> Deref for BaseSpinLockGuard<'a, G, T, L> {
     type Target = T;
     #[inline(always)]
     fn deref(&self) -> &T {
@@ -201,7 +222,7 @@ impl<'a, G: BaseGuard, T: ?Sized> Deref for BaseSpinLockGuard<'a, G, T> {
     }
 }
 
-impl<'a, G: BaseGuard, T: ?Sized> DerefMut for BaseSpinLockGuard<'a, G, T> {
+impl<'a, G: BaseGuard, T: ?Sized, L: RawSpinLock> DerefMut for BaseSpinLockGuard<'a, G, T, L> {
     #[inline(always)]
     fn deref_mut(&mut self) -> &mut T {
         // We know statically that only we are referencing data
@@ -209,19 +230,25 @@ impl<'a, G: BaseGuard, T: ?Sized> DerefMut for BaseSpinLockGuard<'a, G, T> {
     }
 }
 
-impl<'a, G: BaseGuard, T: ?Sized + fmt::Debug> fmt::Debug for BaseSpinLockGuard<'a, G, T> {
+impl<'a, G: BaseGuard, T: ?Sized + fmt::Debug, L: RawSpinLock> fmt::Debug
+    for BaseSpinLockGuard<'a, G, T, L>
+{
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(&**self, f)
     }
 }
 
-impl<'a, G: BaseGuard, T: ?Sized> Drop for BaseSpinLockGuard<'a, G, T> {
+impl<'a, G: BaseGuard, T: ?Sized, L: RawSpinLock> Drop for BaseSpinLockGuard<'a, G, T, L> {
     /// The dropping of the [`BaseSpinLockGuard`] will release the lock it was
     /// created from.
     #[inline(always)]
     fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock_addr);
         #[cfg(feature = "smp")]
-        self.lock.store(false, Ordering::Release);
+        unsafe {
+            self.lock.unlock()
+        };
         G::release(self.irq_state);
     }
 }
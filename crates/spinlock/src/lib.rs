@@ -1,45 +1,68 @@
 //! `no_std` spin lock implementation that can disable kernel local IRQs or
 //! preemption while locking.
 //!
+//! The lock algorithm is chosen by the last generic parameter of the locks,
+//! which implements [`RawSpinLock`]:
+//!
+//! - [`TasLock`] (default): A test-and-set lock. It's the fastest without
+//!   contention, but it's unfair and one CPU may starve under contention.
+//! - [`TicketLock`]: CPUs get the lock in the order they ask for it.
+//! - [`McsLock`]: A queued lock like the Linux `qspinlock`, which is also fair,
+//!   and scales better under heavy contention since each waiter spins on its
+//!   own cache line.
+//!
+//! ```
+//! use spinlock::{SpinNoIrq, TicketLock};
+//!
+//! static FAIR: SpinNoIrq<usize, TicketLock> = SpinNoIrq::new(0);
+//! *FAIR.lock() += 1;
+//! ```
+//!
 //! # Cargo Features
 //!
 //! - `smp`: Use in the **multi-core** environment. For **single-core**
 //!   environment (without this feature), the lock state is unnecessary and
 //!   optimized out. CPU can always get the lock if we follow the proper guard
 //!   in use. By default, this feature is disabled.
+//! - `lockdep`: Check the lock acquisition order with the [`lockdep`] crate,
+//!   to detect potential deadlocks. The locks created at different places are
+//!   in different classes. The crate user must implement the
+//!   [`lockdep::LockdepIf`] trait. By default, this feature is disabled.
 
 #![cfg_attr(not(test), no_std)]
 
 mod base;
+mod raw;
 
 use kernel_guard::{NoOp, NoPreempt, NoPreemptIrqSave};
 
 pub use self::base::{BaseSpinLock, BaseSpinLockGuard};
+pub use self::raw::{McsLock, RawSpinLock, TasLock, TicketLock};
 
 /// A spin lock that disables kernel preemption while trying to lock, and
 /// re-enables it after unlocking.
 ///
 /// It must be used in the local IRQ-disabled context, or never be used in
 /// interrupt handlers.
-pub type SpinNoPreempt<T> = BaseSpinLock<NoPreempt, T>;
+pub type SpinNoPreempt<T, L = TasLock> = BaseSpinLock<NoPreempt, T, L>;
 
 /// A guard that provides mutable data access for [`SpinNoPreempt`].
-pub type SpinNoPreemptGuard<'a, T> = BaseSpinLockGuard<'a, NoPreempt, T>;
+pub type SpinNoPreemptGuard<'a, T, L = TasLock> = BaseSpinLockGuard<'a, NoPreempt, T, L>;
 
 /// A spin lock that disables kernel preemption and local IRQs while trying to
 /// lock, and re-enables it after unlocking.
 ///
 /// It can be used in the IRQ-enabled context.
-pub type SpinNoIrq<T> = BaseSpinLock<NoPreemptIrqSave, T>;
+pub type SpinNoIrq<T, L = TasLock> = BaseSpinLock<NoPreemptIrqSave, T, L>;
 
 /// A guard that provides mutable data access for [`SpinNoIrq`].
-pub type SpinNoIrqGuard<'a, T> = BaseSpinLockGuard<'a, NoPreemptIrqSave, T>;
+pub type SpinNoIrqGuard<'a, T, L = TasLock> = BaseSpinLockGuard<'a, NoPreemptIrqSave, T, L>;
 
 /// A raw spin lock that does nothing while trying to lock.
 ///
 /// It must be used in the preemption-disabled and local IRQ-disabled context,
 /// or never be used in interrupt handlers.
-pub type SpinRaw<T> = BaseSpinLock<NoOp, T>;
+pub type SpinRaw<T, L = TasLock> = BaseSpinLock<NoOp, T, L>;
 
 /// A guard that provides mutable data access for [`SpinRaw`].
-pub type SpinRawGuard<'a, T> = BaseSpinLockGuard<'a, NoOp, T>;
+pub type SpinRawGuard<'a, T, L = TasLock> = BaseSpinLockGuard<'a, NoOp, T, L>;
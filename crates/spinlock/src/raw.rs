@@ -0,0 +1,349 @@
+//! Raw spin lock algorithms, which only provide mutual exclusion.
+//!
+//! They are used as the generic parameter `L` of [`BaseSpinLock`], to choose
+//! between throughput and fairness:
+//!
+//! - [`TasLock`]: A test-and-set lock. It's the fastest without contention,
+//!   but it's unfair, and all waiters hammer the same cache line.
+//! - [`TicketLock`]: CPUs get the lock in the order they ask for it. All
+//!   waiters still spin on the same cache line.
+//! - [`McsLock`]: A queued lock like the Linux `qspinlock`. CPUs get the lock
+//!   in order, and each waiter spins on its own queue node, so the cache line
+//!   of the lock is not bounced between waiters.
+//!
+//! [`BaseSpinLock`]: crate::BaseSpinLock
+
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+
+/// Waits a moment while spinning.
+///
+/// The host tests may run more threads than CPUs, then the waiters yield the
+/// CPU to the holder, otherwise the fair locks make no progress.
+#[inline(always)]
+fn relax() {
+    #[cfg(test)]
+    std::thread::yield_now();
+    #[cfg(not(test))]
+    core::hint::spin_loop();
+}
+
+/// A raw spin lock algorithm.
+///
+/// # Safety
+///
+/// Implementations must guarantee that the lock is held by at most one CPU
+/// at a time, and that releasing the lock happens before the next
+/// acquisition.
+pub unsafe trait RawSpinLock {
+    /// The initial, unlocked state.
+    const INIT: Self;
+
+    /// Acquires the lock, spinning until it's available.
+    fn lock(&self);
+
+    /// Tries to acquire the lock once, returns `true` on success.
+    fn try_lock(&self) -> bool;
+
+    /// Releases the lock.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held by the current CPU.
+    unsafe fn unlock(&self);
+
+    /// Returns `true` if the lock is currently held.
+    fn is_locked(&self) -> bool;
+}
+
+/// A test-and-set spin lock.
+pub struct TasLock {
+    locked: AtomicBool,
+}
+
+unsafe impl RawSpinLock for TasLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        locked: AtomicBool::new(false),
+    };
+
+    #[inline(always)]
+    fn lock(&self) {
+        // Can fail to lock even if the spinlock is not locked. May be more efficient than `try_lock`
+        // when called in a loop.
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Wait until the lock looks unlocked before retrying
+            while self.is_locked() {
+                relax();
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock(&self) -> bool {
+        // The reason for using a strong compare_exchange is explained here:
+        // https://github.com/Amanieu/parking_lot/pull/207#issuecomment-575869107
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+/// A ticket spin lock, which is acquired in the FIFO order.
+pub struct TicketLock {
+    /// The next ticket to hand out.
+    next: AtomicU32,
+    /// The ticket of the current holder.
+    owner: AtomicU32,
+}
+
+unsafe impl RawSpinLock for TicketLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        next: AtomicU32::new(0),
+        owner: AtomicU32::new(0),
+    };
+
+    #[inline(always)]
+    fn lock(&self) {
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+        while self.owner.load(Ordering::Acquire) != ticket {
+            relax();
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock(&self) -> bool {
+        let owner = self.owner.load(Ordering::Acquire);
+        self.next
+            .compare_exchange(
+                owner,
+                owner.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self) {
+        // Only the holder updates the owner.
+        let owner = self.owner.load(Ordering::Relaxed);
+        self.owner.store(owner.wrapping_add(1), Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn is_locked(&self) -> bool {
+        self.next.load(Ordering::Relaxed) != self.owner.load(Ordering::Relaxed)
+    }
+}
+
+/// A queue node of [`McsLock`], on the stack of a waiter.
+///
+/// It's word-aligned, so the lowest bit of its address is always zero.
+struct McsNode {
+    next: AtomicPtr<McsNode>,
+    wait: AtomicBool,
+}
+
+/// A queued spin lock based on the MCS lock.
+///
+/// The state is the address of the last waiter in the queue, with the lowest
+/// bit set if the lock is held. Without contention, it works like
+/// [`TasLock`]. Otherwise the waiters line up in a queue of nodes on their
+/// stacks, and only the head of the queue spins on the lock state.
+///
+/// Unlike the classic MCS lock, the holder does not own a queue node: the
+/// head of the queue passes the headship to its successor right after it
+/// gets the lock. So the nodes are released when [`RawSpinLock::lock`]
+/// returns, and the lock fits in the guard scheme of [`BaseSpinLock`].
+///
+/// [`BaseSpinLock`]: crate::BaseSpinLock
+pub struct McsLock {
+    state: AtomicUsize,
+}
+
+const MCS_LOCKED: usize = 1;
+
+impl McsLock {
+    #[cold]
+    fn lock_slow(&self) {
+        let node = McsNode {
+            next: AtomicPtr::new(null_mut()),
+            wait: AtomicBool::new(true),
+        };
+        let node_ptr = &node as *const McsNode as usize;
+
+        // Append the node to the queue, keeping the locked bit.
+        let mut state = self.state.load(Ordering::Relaxed);
+        while let Err(s) = self.state.compare_exchange_weak(
+            state,
+            node_ptr | (state & MCS_LOCKED),
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        ) {
+            state = s;
+        }
+        let prev = (state & !MCS_LOCKED) as *const McsNode;
+        if !prev.is_null() {
+            // The previous waiter is still in `lock_slow`, until it sees us.
+            unsafe { &*prev }
+                .next
+                .store(&node as *const _ as *mut _, Ordering::Release);
+            while node.wait.load(Ordering::Acquire) {
+                relax();
+            }
+        }
+
+        // Now we are the head of the queue, wait for the holder.
+        loop {
+            let state = self.state.load(Ordering::Acquire);
+            if state & MCS_LOCKED != 0 {
+                relax();
+            } else if state != node_ptr {
+                break;
+            } else if self
+                .state
+                .compare_exchange(node_ptr, MCS_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                // We were the last waiter, the queue is empty now.
+                return;
+            }
+        }
+
+        // There are waiters behind us, and nobody else can take the lock
+        // while the queue is not empty.
+        self.state.fetch_or(MCS_LOCKED, Ordering::Acquire);
+        let next = loop {
+            let next = node.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                break next;
+            }
+            relax();
+        };
+        unsafe { &*next }.wait.store(false, Ordering::Release);
+    }
+}
+
+unsafe impl RawSpinLock for McsLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        state: AtomicUsize::new(0),
+    };
+
+    #[inline(always)]
+    fn lock(&self) {
+        if !self.try_lock() {
+            self.lock_slow();
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(0, MCS_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self) {
+        self.state.fetch_and(!MCS_LOCKED, Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & MCS_LOCKED != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::UnsafeCell;
+    use std::thread;
+
+    struct Counter<L> {
+        lock: L,
+        value: UnsafeCell<usize>,
+    }
+
+    unsafe impl<L: RawSpinLock> Sync for Counter<L> {}
+
+    fn contend<L: RawSpinLock + Send>() {
+        const THREADS: usize = 8;
+        const ITERS: usize = 10_000;
+        let counter = Counter {
+            lock: L::INIT,
+            value: UnsafeCell::new(0),
+        };
+        thread::scope(|s| {
+            for i in 0..THREADS {
+                let counter = &counter;
+                s.spawn(move || {
+                    for _ in 0..ITERS {
+                        if i % 2 == 0 {
+                            counter.lock.lock();
+                        } else {
+                            while !counter.lock.try_lock() {
+                                relax();
+                            }
+                        }
+                        unsafe {
+                            *counter.value.get() += 1;
+                            counter.lock.unlock();
+                        }
+                    }
+                });
+            }
+        });
+        assert!(!counter.lock.is_locked());
+        assert_eq!(counter.value.into_inner(), THREADS * ITERS);
+    }
+
+    fn try_lock<L: RawSpinLock>() {
+        let lock = L::INIT;
+        assert!(!lock.is_locked());
+        assert!(lock.try_lock());
+        assert!(lock.is_locked());
+        assert!(!lock.try_lock());
+        unsafe { lock.unlock() };
+        assert!(!lock.is_locked());
+        lock.lock();
+        assert!(!lock.try_lock());
+        unsafe { lock.unlock() };
+        assert!(lock.try_lock());
+    }
+
+    #[test]
+    fn test_tas_lock() {
+        try_lock::<TasLock>();
+        contend::<TasLock>();
+    }
+
+    #[test]
+    fn test_ticket_lock() {
+        try_lock::<TicketLock>();
+        contend::<TicketLock>();
+    }
+
+    #[test]
+    fn test_mcs_lock() {
+        try_lock::<McsLock>();
+        contend::<McsLock>();
+    }
+}
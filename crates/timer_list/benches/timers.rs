@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use timer_list::{TimeValue, TimerEvent, TimerHandle, TimerList, TimerWheel};
+
+struct NopEvent(usize);
+
+impl TimerEvent for NopEvent {
+    fn callback(self, _now: TimeValue) {}
+}
+
+/// The common interface of the timer implementations.
+trait Timers: Default {
+    fn set(&mut self, deadline: TimeValue, event: NopEvent) -> TimerHandle;
+    fn cancel_handle(&mut self, handle: TimerHandle) -> Option<NopEvent>;
+    fn expire_one(&mut self, now: TimeValue) -> Option<(TimeValue, NopEvent)>;
+}
+
+macro_rules! impl_timers {
+    ($ty: ty) => {
+        impl Timers for $ty {
+            fn set(&mut self, deadline: TimeValue, event: NopEvent) -> TimerHandle {
+                self.set(deadline, event)
+            }
+            fn cancel_handle(&mut self, handle: TimerHandle) -> Option<NopEvent> {
+                self.cancel_handle(handle)
+            }
+            fn expire_one(&mut self, now: TimeValue) -> Option<(TimeValue, NopEvent)> {
+                self.expire_one(now)
+            }
+        }
+    };
+}
+
+impl_timers!(TimerList<NopEvent>);
+impl_timers!(TimerWheel<NopEvent>);
+
+/// Random deadlines within `max` from the start.
+fn deadlines(n: usize, max: Duration) -> Vec<Duration> {
+    let mut rng = SmallRng::seed_from_u64(0xdead_beef);
+    (0..n)
+        .map(|_| Duration::from_nanos(rng.gen_range(0..max.as_nanos() as u64)))
+        .collect()
+}
+
+/// Sets `n` timeouts and cancels all of them in random order, like socket
+/// timeouts which rarely fire.
+fn set_cancel<T: Timers>(deadlines: &[Duration]) {
+    let mut timers = T::default();
+    let mut handles: Vec<_> = deadlines
+        .iter()
+        .enumerate()
+        .map(|(i, &ddl)| timers.set(ddl, NopEvent(i)))
+        .collect();
+    handles.shuffle(&mut SmallRng::seed_from_u64(0xdead_beef));
+    for handle in handles {
+        black_box(timers.cancel_handle(handle));
+    }
+}
+
+/// Sets `n` timeouts and expires all of them with 1ms ticks.
+fn set_expire<T: Timers>(deadlines: &[Duration]) {
+    let mut timers = T::default();
+    for (i, &ddl) in deadlines.iter().enumerate() {
+        timers.set(ddl, NopEvent(i));
+    }
+    let mut now = Duration::ZERO;
+    let mut expired = 0;
+    while expired < deadlines.len() {
+        while let Some((_, event)) = timers.expire_one(now) {
+            black_box(event.0);
+            expired += 1;
+        }
+        now += Duration::from_millis(1);
+    }
+}
+
+/// Keeps `n` pending timeouts, and re-arms one of them in each round.
+fn rearm<T: Timers>(deadlines: &[Duration], rounds: usize) {
+    let mut timers = T::default();
+    let mut handles: Vec<_> = deadlines
+        .iter()
+        .enumerate()
+        .map(|(i, &ddl)| timers.set(ddl, NopEvent(i)))
+        .collect();
+    let mut rng = SmallRng::seed_from_u64(0xdead_beef);
+    for i in 0..rounds {
+        let idx = rng.gen_range(0..handles.len());
+        black_box(timers.cancel_handle(handles[idx]));
+        handles[idx] = timers.set(deadlines[i % deadlines.len()], NopEvent(i));
+    }
+}
+
+fn bench<T: Timers>(c: &mut Criterion, name: &str) {
+    let socket_timeouts = deadlines(10_000, Duration::from_secs(30));
+    let sleeps = deadlines(10_000, Duration::from_secs(1));
+    let mut g = c.benchmark_group(name);
+    g.bench_function("set_cancel_10K", |b| {
+        b.iter(|| set_cancel::<T>(black_box(&socket_timeouts)));
+    });
+    g.sample_size(10);
+    g.bench_function("set_expire_10K", |b| {
+        b.iter(|| set_expire::<T>(black_box(&sleeps)));
+    });
+    g.bench_function("rearm_10K_x10K", |b| {
+        b.iter(|| rearm::<T>(black_box(&socket_timeouts), 10_000));
+    });
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    bench::<TimerList<NopEvent>>(c, "heap");
+    bench::<TimerWheel<NopEvent>>(c, "wheel");
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
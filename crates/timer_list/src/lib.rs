@@ -1,6 +1,15 @@
 //! A list of timed events that will be triggered sequentially when the timer
 //! expires.
 //!
+//! Two implementations are provided with the same interface:
+//!
+//! - [`TimerList`]: a binary heap of events, cancelled events are removed
+//!   lazily.
+//! - [`TimerWheel`]: a hierarchical timing wheel, setting and cancelling an
+//!   event take O(1) time.
+//!
+//! Events can be cancelled by the [`TimerHandle`] returned when they are set.
+//!
 //! # Examples
 //!
 //! ```
@@ -31,10 +40,14 @@
 
 extern crate alloc;
 
-use alloc::{boxed::Box, collections::BinaryHeap};
+mod wheel;
+
+use alloc::{boxed::Box, collections::BinaryHeap, vec::Vec};
 use core::cmp::{Ord, Ordering, PartialOrd};
 use core::time::Duration;
 
+pub use self::wheel::TimerWheel;
+
 /// The type of the time value.
 ///
 /// Currently it is just an alias of [`core::time::Duration`].
@@ -46,56 +59,111 @@ pub trait TimerEvent {
     fn callback(self, now: TimeValue);
 }
 
-struct TimerEventWrapper<E> {
+/// A handle to a timed event, returned when the event is set.
+///
+/// It can be used to cancel the event later. A handle of an event that has
+/// expired or been cancelled is never reused, so cancelling it does nothing.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TimerHandle(u64);
+
+struct TimerEventWrapper {
     deadline: TimeValue,
-    event: E,
+    handle: TimerHandle,
+}
+
+struct EventSlot<E> {
+    /// `None` if the slot is free.
+    event: Option<E>,
+    /// Incremented when the slot is freed, so that the stale handles do not
+    /// match the reused slot.
+    generation: u32,
 }
 
 /// A list of timed events.
 ///
 /// It internally uses a min-heap to store the events by deadline, make it
 /// possible to trigger these events sequentially.
+///
+/// The events themselves are kept in a slab indexed by the handles. A
+/// cancelled event is taken out of the slab at once, but its entry stays in
+/// the heap as a tombstone until it reaches the top, or until the tombstones
+/// outnumber the pending events and the heap is rebuilt.
 pub struct TimerList<E: TimerEvent> {
-    events: BinaryHeap<TimerEventWrapper<E>>,
+    events: BinaryHeap<TimerEventWrapper>,
+    slots: Vec<EventSlot<E>>,
+    free_slots: Vec<u32>,
+    len: usize,
 }
 
-impl<E> PartialOrd for TimerEventWrapper<E> {
+impl PartialOrd for TimerEventWrapper {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other)) // reverse ordering for Min-heap
     }
 }
 
-impl<E> Ord for TimerEventWrapper<E> {
+impl Ord for TimerEventWrapper {
     fn cmp(&self, other: &Self) -> Ordering {
         other.deadline.cmp(&self.deadline) // reverse ordering for Min-heap
     }
 }
 
-impl<E> PartialEq for TimerEventWrapper<E> {
+impl PartialEq for TimerEventWrapper {
     fn eq(&self, other: &Self) -> bool {
         self.deadline.eq(&other.deadline)
     }
 }
 
-impl<E> Eq for TimerEventWrapper<E> {}
+impl Eq for TimerEventWrapper {}
 
 impl<E: TimerEvent> TimerList<E> {
     /// Creates a new empty timer list.
     pub fn new() -> Self {
         Self {
             events: BinaryHeap::new(),
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+            len: 0,
         }
     }
 
     /// Whether there is no timed event.
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.events.is_empty()
+        self.len == 0
     }
 
     /// Set a timed event that will be triggered at `deadline`.
-    pub fn set(&mut self, deadline: TimeValue, event: E) {
-        self.events.push(TimerEventWrapper { deadline, event });
+    ///
+    /// Returns a handle to cancel the event.
+    pub fn set(&mut self, deadline: TimeValue, event: E) -> TimerHandle {
+        let idx = if let Some(idx) = self.free_slots.pop() {
+            self.slots[idx as usize].event = Some(event);
+            idx
+        } else {
+            assert!(
+                self.slots.len() < u32::MAX as usize,
+                "too many timer events"
+            );
+            self.slots.push(EventSlot {
+                event: Some(event),
+                generation: 0,
+            });
+            (self.slots.len() - 1) as u32
+        };
+        self.len += 1;
+        let generation = self.slots[idx as usize].generation;
+        let handle = TimerHandle(((generation as u64) << 32) | idx as u64);
+        self.events.push(TimerEventWrapper { deadline, handle });
+        handle
+    }
+
+    /// Cancel the event of the given handle.
+    ///
+    /// Returns the event if it has not expired or been cancelled.
+    pub fn cancel_handle(&mut self, handle: TimerHandle) -> Option<E> {
+        let event = self.take(handle)?;
+        self.purge();
+        Some(event)
     }
 
     /// Cancel all events that meet the condition.
@@ -103,8 +171,13 @@ impl<E: TimerEvent> TimerList<E> {
     where
         F: Fn(&E) -> bool,
     {
-        // TODO: performance optimization
-        self.events.retain(|e| !condition(&e.event));
+        for idx in 0..self.slots.len() as u32 {
+            let slot = &self.slots[idx as usize];
+            if slot.event.as_ref().is_some_and(&condition) {
+                self.take(TimerHandle(((slot.generation as u64) << 32) | idx as u64));
+            }
+        }
+        self.purge();
     }
 
     /// Get the deadline of the most recent event.
@@ -118,12 +191,46 @@ impl<E: TimerEvent> TimerList<E> {
     ///
     /// Returns `None` if no event is expired.
     pub fn expire_one(&mut self, now: TimeValue) -> Option<(TimeValue, E)> {
-        if let Some(e) = self.events.peek() {
-            if e.deadline <= now {
-                return self.events.pop().map(|e| (e.deadline, e.event));
-            }
+        if self.events.peek()?.deadline > now {
+            return None;
+        }
+        let e = self.events.pop().unwrap();
+        let event = self.take(e.handle).unwrap();
+        self.purge();
+        Some((e.deadline, event))
+    }
+
+    /// Takes the event out of its slot and frees the slot, leaving a
+    /// tombstone in the heap.
+    fn take(&mut self, handle: TimerHandle) -> Option<E> {
+        let idx = handle.0 as u32;
+        let generation = (handle.0 >> 32) as u32;
+        let slot = self.slots.get_mut(idx as usize)?;
+        if slot.generation != generation {
+            return None;
+        }
+        let event = slot.event.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(idx);
+        self.len -= 1;
+        Some(event)
+    }
+
+    fn is_tombstone(&self, e: &TimerEventWrapper) -> bool {
+        self.slots[e.handle.0 as u32 as usize].generation != (e.handle.0 >> 32) as u32
+    }
+
+    /// Removes the tombstones, so that the top of the heap is always a
+    /// pending event.
+    fn purge(&mut self) {
+        if self.events.len() > 2 * self.len {
+            let mut events = core::mem::take(&mut self.events).into_vec();
+            events.retain(|e| !self.is_tombstone(e));
+            self.events = events.into();
+        }
+        while self.events.peek().is_some_and(|e| self.is_tombstone(e)) {
+            self.events.pop();
         }
-        None
     }
 }
 
@@ -208,6 +315,47 @@ mod tests {
         assert_eq!(COUNT.load(Ordering::SeqCst), 4);
     }
 
+    #[test]
+    fn test_cancel_handle() {
+        struct TestEvent(usize);
+
+        impl TimerEvent for TestEvent {
+            fn callback(self, _now: TimeValue) {}
+        }
+
+        let mut timer_list = TimerList::new();
+        let handles: Vec<_> = (0..100)
+            .map(|i| timer_list.set(Duration::from_millis(i as u64), TestEvent(i)))
+            .collect();
+        // cancel the earliest ones, the tombstones are popped off the top.
+        for &h in &handles[..10] {
+            assert!(timer_list.cancel_handle(h).is_some());
+            assert!(timer_list.cancel_handle(h).is_none());
+        }
+        assert_eq!(timer_list.next_deadline(), Some(Duration::from_millis(10)));
+        // cancel the later ones, the heap is rebuilt when the tombstones
+        // outnumber the pending events.
+        for &h in &handles[20..] {
+            assert!(timer_list.cancel_handle(h).is_some());
+        }
+        assert!(timer_list.events.len() <= 2 * 10);
+
+        // the slots are reused, but the stale handles do not match them.
+        let h = timer_list.set(Duration::from_millis(15), TestEvent(100));
+        for &h in handles[..10].iter().chain(&handles[20..]) {
+            assert!(timer_list.cancel_handle(h).is_none());
+        }
+        let mut expired = Vec::new();
+        while let Some((deadline, event)) = timer_list.expire_one(Duration::from_secs(1)) {
+            expired.push((deadline, event.0));
+        }
+        assert_eq!(expired.len(), 11);
+        assert!(expired.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(expired.contains(&(Duration::from_millis(15), 100)));
+        assert!(timer_list.cancel_handle(h).is_none());
+        assert!(timer_list.is_empty());
+    }
+
     #[test]
     fn test_timer_list_fn() {
         let mut timer_list = TimerList::new();
@@ -0,0 +1,533 @@
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::time::Duration;
+
+use crate::{TimeValue, TimerEvent, TimerHandle};
+
+/// Each level of the wheel has `1 << SLOT_BITS` slots.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+/// The wheel covers `SLOTS.pow(LEVELS)` ticks after the current tick, later
+/// events are kept in the overflow list.
+const LEVELS: usize = 4;
+
+const OVERFLOW_LIST: usize = LEVELS * SLOTS;
+const READY_LIST: usize = OVERFLOW_LIST + 1;
+const NUM_LISTS: usize = READY_LIST + 1;
+
+const NIL: u32 = u32::MAX;
+
+/// The default length of a tick.
+const DEFAULT_RESOLUTION: Duration = Duration::from_millis(1);
+
+struct Entry<E> {
+    deadline: TimeValue,
+    tick: u64,
+    /// `None` if the entry is free.
+    event: Option<E>,
+    /// Incremented when the entry is freed, so that the stale handles do not
+    /// match the reused entry.
+    generation: u32,
+    list: usize,
+    prev: u32,
+    next: u32,
+}
+
+/// A hierarchical [timing wheel][1] of timed events.
+///
+/// The time is divided into ticks of a fixed length (the resolution). Events
+/// are hashed into the slots of several levels by their deadline ticks, the
+/// slots of level `i` span `64^i` ticks each. When the wheel turns to the
+/// next slot of a level, the events in it are moved to the lower levels, so
+/// that each event is moved only a few times before it expires. The
+/// events in a slot are kept in an intrusive doubly linked list, so both
+/// [`set`](Self::set) and [`cancel_handle`](Self::cancel_handle) take O(1)
+/// time.
+///
+/// The events due at the current tick are kept in the ready list sorted by
+/// deadline, so [`expire_one`](Self::expire_one) just pops its head. The
+/// result of [`next_deadline`](Self::next_deadline) is cached, and only
+/// recomputed after the earliest event is removed.
+///
+/// The resolution only affects the efficiency, the events are still expired
+/// by their exact deadlines.
+///
+/// [1]: https://www.cs.columbia.edu/~nahum/w6998/papers/sosp87-timing-wheels.pdf
+pub struct TimerWheel<E: TimerEvent> {
+    resolution_nanos: u64,
+    entries: Vec<Entry<E>>,
+    free_head: u32,
+    heads: [u32; NUM_LISTS],
+    /// The last entry of the ready list.
+    ready_tail: u32,
+    /// Bitmaps of the non-empty slots of each level.
+    occupied: [u64; LEVELS],
+    /// The events due at or before the current tick are in the ready list,
+    /// the later ones are in the wheel.
+    current_tick: u64,
+    len: usize,
+    /// The cached result of [`next_deadline`](Self::next_deadline), `None`
+    /// if it needs to be recomputed.
+    cached_next: Cell<Option<Option<TimeValue>>>,
+}
+
+impl<E: TimerEvent> TimerWheel<E> {
+    /// Creates a new empty timer wheel, with a resolution of 1 millisecond.
+    pub fn new() -> Self {
+        Self::with_resolution(DEFAULT_RESOLUTION)
+    }
+
+    /// Creates a new empty timer wheel with the given length of ticks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resolution is zero.
+    pub fn with_resolution(resolution: Duration) -> Self {
+        let resolution_nanos = resolution.as_nanos() as u64;
+        assert!(resolution_nanos > 0, "zero timer wheel resolution");
+        Self {
+            resolution_nanos,
+            entries: Vec::new(),
+            free_head: NIL,
+            heads: [NIL; NUM_LISTS],
+            ready_tail: NIL,
+            occupied: [0; LEVELS],
+            current_tick: 0,
+            len: 0,
+            cached_next: Cell::new(Some(None)),
+        }
+    }
+
+    /// Whether there is no timed event.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Set a timed event that will be triggered at `deadline`.
+    ///
+    /// Returns a handle to cancel the event.
+    pub fn set(&mut self, deadline: TimeValue, event: E) -> TimerHandle {
+        let tick = self.tick_of(deadline);
+        let idx = if self.free_head != NIL {
+            let idx = self.free_head;
+            self.free_head = self.entries[idx as usize].next;
+            let entry = &mut self.entries[idx as usize];
+            entry.deadline = deadline;
+            entry.tick = tick;
+            entry.event = Some(event);
+            idx
+        } else {
+            assert!(self.entries.len() < NIL as usize, "too many timer events");
+            self.entries.push(Entry {
+                deadline,
+                tick,
+                event: Some(event),
+                generation: 0,
+                list: NUM_LISTS,
+                prev: NIL,
+                next: NIL,
+            });
+            (self.entries.len() - 1) as u32
+        };
+        self.len += 1;
+        if let Some(next) = self.cached_next.get() {
+            self.cached_next.set(Some(min_option(next, Some(deadline))));
+        }
+        self.place(idx);
+        let generation = self.entries[idx as usize].generation;
+        TimerHandle(((generation as u64) << 32) | idx as u64)
+    }
+
+    /// Cancel the event of the given handle.
+    ///
+    /// Returns the event if it has not expired or been cancelled.
+    pub fn cancel_handle(&mut self, handle: TimerHandle) -> Option<E> {
+        let idx = handle.0 as u32;
+        let generation = (handle.0 >> 32) as u32;
+        match self.entries.get(idx as usize) {
+            Some(e) if e.generation == generation && e.event.is_some() => {
+                self.unlink(idx);
+                Some(self.free(idx).1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Cancel all events that meet the condition.
+    ///
+    /// It visits all events, prefer [`cancel_handle`](Self::cancel_handle)
+    /// if possible.
+    pub fn cancel<F>(&mut self, condition: F)
+    where
+        F: Fn(&E) -> bool,
+    {
+        for idx in 0..self.entries.len() as u32 {
+            if self.entries[idx as usize]
+                .event
+                .as_ref()
+                .is_some_and(&condition)
+            {
+                self.unlink(idx);
+                self.free(idx);
+            }
+        }
+    }
+
+    /// Get the deadline of the most recent event.
+    ///
+    /// The result is cached. To recompute it, only the earliest non-empty
+    /// slot of each level and the overflow list are visited.
+    pub fn next_deadline(&self) -> Option<TimeValue> {
+        if let Some(next) = self.cached_next.get() {
+            return next;
+        }
+        let next = self.compute_next_deadline();
+        self.cached_next.set(Some(next));
+        next
+    }
+
+    /// Try to expire the earliest event that passed the deadline at the given
+    /// time.
+    ///
+    /// Returns `None` if no event is expired.
+    pub fn expire_one(&mut self, now: TimeValue) -> Option<(TimeValue, E)> {
+        self.advance(self.tick_of(now));
+        let head = self.heads[READY_LIST];
+        if head != NIL && self.entries[head as usize].deadline <= now {
+            self.unlink(head);
+            Some(self.free(head))
+        } else {
+            None
+        }
+    }
+
+    fn tick_of(&self, time: TimeValue) -> u64 {
+        (time.as_nanos() / self.resolution_nanos as u128) as u64
+    }
+
+    fn time_of(&self, tick: u64) -> TimeValue {
+        Duration::from_nanos(tick.saturating_mul(self.resolution_nanos))
+    }
+
+    fn compute_next_deadline(&self) -> Option<TimeValue> {
+        // The events in the wheel are due after the current tick, so they
+        // are all later than the ready ones.
+        let head = self.heads[READY_LIST];
+        if head != NIL {
+            return Some(self.entries[head as usize].deadline);
+        }
+        let mut next = None;
+        for level in 0..LEVELS {
+            if let Some((list, start)) = self.next_slot(level) {
+                if next.map_or(true, |next| next > self.time_of(start)) {
+                    next = min_option(next, self.min_deadline(list));
+                }
+            }
+        }
+        min_option(next, self.min_deadline(OVERFLOW_LIST))
+    }
+
+    fn min_deadline(&self, list: usize) -> Option<TimeValue> {
+        let mut min = None;
+        let mut idx = self.heads[list];
+        while idx != NIL {
+            let entry = &self.entries[idx as usize];
+            min = min_option(min, Some(entry.deadline));
+            idx = entry.next;
+        }
+        min
+    }
+
+    /// Puts the entry into the list determined by its deadline tick.
+    fn place(&mut self, idx: u32) {
+        let tick = self.entries[idx as usize].tick;
+        if tick <= self.current_tick {
+            self.insert_ready(idx);
+            return;
+        }
+        let delta = tick - self.current_tick;
+        let level = ((u64::BITS - delta.leading_zeros() - 1) / SLOT_BITS) as usize;
+        let list = if level < LEVELS {
+            let slot = (tick >> (level as u32 * SLOT_BITS)) & SLOT_MASK;
+            level * SLOTS + slot as usize
+        } else {
+            OVERFLOW_LIST
+        };
+        let head = self.heads[list];
+        let entry = &mut self.entries[idx as usize];
+        entry.list = list;
+        entry.prev = NIL;
+        entry.next = head;
+        if head != NIL {
+            self.entries[head as usize].prev = idx;
+        }
+        self.heads[list] = idx;
+        if list < OVERFLOW_LIST {
+            self.occupied[list / SLOTS] |= 1 << (list % SLOTS);
+        }
+    }
+
+    /// Inserts the entry into the ready list, which is sorted by deadline.
+    ///
+    /// The list is searched from the tail, as the events moved into it are
+    /// mostly later than the ones already there.
+    fn insert_ready(&mut self, idx: u32) {
+        let deadline = self.entries[idx as usize].deadline;
+        let mut prev = self.ready_tail;
+        while prev != NIL && self.entries[prev as usize].deadline > deadline {
+            prev = self.entries[prev as usize].prev;
+        }
+        let next = if prev != NIL {
+            self.entries[prev as usize].next
+        } else {
+            self.heads[READY_LIST]
+        };
+        let entry = &mut self.entries[idx as usize];
+        entry.list = READY_LIST;
+        entry.prev = prev;
+        entry.next = next;
+        if prev != NIL {
+            self.entries[prev as usize].next = idx;
+        } else {
+            self.heads[READY_LIST] = idx;
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = idx;
+        } else {
+            self.ready_tail = idx;
+        }
+    }
+
+    fn unlink(&mut self, idx: u32) {
+        let Entry {
+            list, prev, next, ..
+        } = self.entries[idx as usize];
+        if prev != NIL {
+            self.entries[prev as usize].next = next;
+        } else {
+            self.heads[list] = next;
+            if next == NIL && list < OVERFLOW_LIST {
+                self.occupied[list / SLOTS] &= !(1 << (list % SLOTS));
+            }
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = prev;
+        } else if list == READY_LIST {
+            self.ready_tail = prev;
+        }
+    }
+
+    /// Frees an unlinked entry, and returns its deadline and event.
+    fn free(&mut self, idx: u32) -> (TimeValue, E) {
+        let entry = &mut self.entries[idx as usize];
+        let event = entry.event.take().unwrap();
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.list = NUM_LISTS;
+        entry.next = self.free_head;
+        self.free_head = idx;
+        self.len -= 1;
+        if self.cached_next.get() == Some(Some(entry.deadline)) {
+            self.cached_next.set(None);
+        }
+        (entry.deadline, event)
+    }
+
+    /// Takes all entries out of the list, and places them again.
+    fn rehash(&mut self, list: usize) {
+        let mut idx = self.heads[list];
+        self.heads[list] = NIL;
+        if list < OVERFLOW_LIST {
+            self.occupied[list / SLOTS] &= !(1 << (list % SLOTS));
+        }
+        while idx != NIL {
+            let next = self.entries[idx as usize].next;
+            self.place(idx);
+            idx = next;
+        }
+    }
+
+    /// Returns the earliest non-empty slot of the level, and the tick when
+    /// the wheel turns to it.
+    fn next_slot(&self, level: usize) -> Option<(usize, u64)> {
+        let occupied = self.occupied[level];
+        if occupied == 0 {
+            return None;
+        }
+        // The slots after the current one come first, the current slot of a
+        // higher level holds the events one round later.
+        let shift = level as u32 * SLOT_BITS;
+        let index = (self.current_tick >> shift) & SLOT_MASK;
+        let distance = occupied
+            .rotate_right((index as u32 + 1) % SLOTS as u32)
+            .trailing_zeros() as u64
+            + 1;
+        let slot = ((index + distance) & SLOT_MASK) as usize;
+        let start = ((self.current_tick >> shift) + distance) << shift;
+        Some((level * SLOTS + slot, start))
+    }
+
+    /// Returns the next tick when some events need to be moved: the wheel
+    /// turns to a non-empty slot, or the top level turns while there are
+    /// overflowed events.
+    fn next_stop(&self) -> Option<u64> {
+        let mut next = (0..LEVELS)
+            .filter_map(|level| self.next_slot(level))
+            .map(|(_, start)| start)
+            .min();
+        if self.heads[OVERFLOW_LIST] != NIL {
+            let shift = (LEVELS - 1) as u32 * SLOT_BITS;
+            let turn = ((self.current_tick >> shift) + 1) << shift;
+            next = Some(next.map_or(turn, |next| next.min(turn)));
+        }
+        next
+    }
+
+    /// Turns the wheel to the `target` tick, and moves the events due by then
+    /// to the ready list.
+    ///
+    /// The empty slots are skipped, so it takes time proportional to the
+    /// number of the events moved, not the elapsed ticks.
+    fn advance(&mut self, target: u64) {
+        while self.current_tick < target {
+            let next = self.next_stop().map_or(target, |next| next.min(target));
+            self.current_tick = next;
+            if next & SLOT_MASK == 0 {
+                self.cascade();
+            }
+            self.rehash((next & SLOT_MASK) as usize);
+        }
+    }
+
+    /// Moves the events in the current slots of the higher levels to the
+    /// lower levels, when the current tick enters a new round of level 0.
+    fn cascade(&mut self) {
+        let tick = self.current_tick;
+        for level in 1..LEVELS {
+            let shift = level as u32 * SLOT_BITS;
+            let slot = ((tick >> shift) & SLOT_MASK) as usize;
+            self.rehash(level * SLOTS + slot);
+            if slot != 0 && level + 1 < LEVELS {
+                return;
+            }
+        }
+        // The top level turns to the next slot, some overflowed events may
+        // fit in the wheel now.
+        self.rehash(OVERFLOW_LIST);
+    }
+}
+
+impl<E: TimerEvent> Default for TimerWheel<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn min_option(a: Option<TimeValue>, b: Option<TimeValue>) -> Option<TimeValue> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimeValue, TimerEvent, TimerWheel};
+    use crate::TimerList;
+    use core::time::Duration;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[derive(Debug, PartialEq)]
+    struct TestEvent(usize);
+
+    impl TimerEvent for TestEvent {
+        fn callback(self, _now: TimeValue) {}
+    }
+
+    #[test]
+    fn test_expire_in_order() {
+        let mut wheel = TimerWheel::new();
+        let deadlines = [
+            Duration::from_secs(3),
+            Duration::from_micros(500),
+            Duration::from_secs(3600 * 24), // beyond the wheel
+            Duration::from_millis(65),
+            Duration::from_micros(1500),
+            Duration::from_micros(1200),
+        ];
+        for (i, &ddl) in deadlines.iter().enumerate() {
+            wheel.set(ddl, TestEvent(i));
+        }
+        assert_eq!(wheel.next_deadline(), Some(Duration::from_micros(500)));
+
+        let mut expired = Vec::new();
+        let mut now = Duration::ZERO;
+        while !wheel.is_empty() {
+            while let Some((deadline, event)) = wheel.expire_one(now) {
+                assert!(deadline <= now);
+                expired.push(event.0);
+            }
+            now += Duration::from_micros(700);
+            if now > Duration::from_secs(4) {
+                now = Duration::from_secs(3600 * 24);
+            }
+        }
+        assert_eq!(expired, [1, 5, 4, 3, 0, 2]);
+    }
+
+    #[test]
+    fn test_cancel_handle() {
+        let mut wheel = TimerWheel::new();
+        let h0 = wheel.set(Duration::from_millis(10), TestEvent(0));
+        let h1 = wheel.set(Duration::from_secs(100), TestEvent(1));
+        assert_eq!(wheel.cancel_handle(h1), Some(TestEvent(1)));
+        assert_eq!(wheel.cancel_handle(h1), None);
+        assert_eq!(wheel.next_deadline(), Some(Duration::from_millis(10)));
+
+        let (_, event) = wheel.expire_one(Duration::from_millis(10)).unwrap();
+        assert_eq!(event, TestEvent(0));
+        assert!(wheel.is_empty());
+        // the entry is reused, but the stale handles do not match it.
+        let h2 = wheel.set(Duration::from_millis(20), TestEvent(2));
+        assert_eq!(wheel.cancel_handle(h0), None);
+        assert_eq!(wheel.cancel_handle(h1), None);
+        assert_eq!(wheel.cancel_handle(h2), Some(TestEvent(2)));
+        assert_eq!(wheel.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_same_as_timer_list() {
+        let mut rng = SmallRng::seed_from_u64(0xdead_beef);
+        let mut wheel = TimerWheel::with_resolution(Duration::from_micros(100));
+        let mut list = TimerList::new();
+        let mut handles = Vec::new();
+        let mut now = Duration::ZERO;
+        for i in 0..20000 {
+            match rng.gen_range(0..10) {
+                0..=4 => {
+                    let range = [1_000, 1_000_000, 1_000_000_000][rng.gen_range(0..3)];
+                    let deadline = now + Duration::from_nanos(rng.gen_range(0..range));
+                    handles.push((
+                        wheel.set(deadline, TestEvent(i)),
+                        list.set(deadline, TestEvent(i)),
+                    ));
+                }
+                5..=6 if !handles.is_empty() => {
+                    let (hw, hl) = handles.swap_remove(rng.gen_range(0..handles.len()));
+                    assert_eq!(wheel.cancel_handle(hw), list.cancel_handle(hl));
+                }
+                _ => now += Duration::from_nanos(rng.gen_range(0..1_000_000)),
+            }
+            assert_eq!(wheel.next_deadline(), list.next_deadline());
+            loop {
+                let (wheel_event, list_event) = (wheel.expire_one(now), list.expire_one(now));
+                match (wheel_event, list_event) {
+                    (Some((dw, _)), Some((dl, _))) => assert_eq!(dw, dl),
+                    (None, None) => break,
+                    (w, l) => panic!("mismatch at {:?}: {:?} != {:?}", now, w, l),
+                }
+            }
+        }
+    }
+}
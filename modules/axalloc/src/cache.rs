@@ -0,0 +1,343 @@
+//! Per-CPU caches of small objects in front of the byte allocator.
+//!
+//! Each CPU keeps a magazine, i.e., a small stack of free objects, for each
+//! size class. Allocations and deallocations of small objects are served by
+//! the magazine of the current CPU without taking the lock of the byte
+//! allocator. An empty magazine is refilled with a batch of objects, and a
+//! full magazine is flushed by half, both under one acquisition of the lock.
+
+use core::fmt;
+
+/// Statistics of the per-CPU caches, summed over all CPUs.
+///
+/// They are all zeros if the `percpu_cache` feature is not enabled.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CacheStats {
+    /// The number of allocations served by the caches.
+    pub alloc_hits: u64,
+    /// The number of allocations that refilled an empty cache from the byte
+    /// allocator.
+    pub alloc_misses: u64,
+    /// The number of deallocations put into the caches.
+    pub free_hits: u64,
+    /// The number of deallocations that flushed a full cache to the byte
+    /// allocator.
+    pub free_misses: u64,
+    /// The number of allocations and deallocations that bypassed the caches,
+    /// for their large sizes or alignments.
+    pub bypassed: u64,
+    /// The number of bytes of the free objects in the caches.
+    pub cached_bytes: usize,
+}
+
+/// `part / total` in percentage, with one decimal place.
+struct Percent(u64, u64);
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let permille = if self.1 == 0 {
+            0
+        } else {
+            self.0 * 1000 / self.1
+        };
+        write!(f, "{}.{}%", permille / 10, permille % 10)
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let allocs = self.alloc_hits + self.alloc_misses;
+        let frees = self.free_hits + self.free_misses;
+        write!(
+            f,
+            "alloc hits: {}/{} ({}), free hits: {}/{} ({}), bypassed: {}, cached: {} bytes",
+            self.alloc_hits,
+            allocs,
+            Percent(self.alloc_hits, allocs),
+            self.free_hits,
+            frees,
+            Percent(self.free_hits, frees),
+            self.bypassed,
+            self.cached_bytes,
+        )
+    }
+}
+
+#[cfg(feature = "percpu_cache")]
+pub(crate) use self::imp::*;
+
+#[cfg(feature = "percpu_cache")]
+mod imp {
+    use allocator::{AllocResult, ByteAllocator};
+    use core::alloc::Layout;
+    use core::ptr::NonNull;
+    use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use kernel_guard::{NoPreempt, NoPreemptIrqSave};
+
+    use super::CacheStats;
+    use crate::GlobalAllocator;
+
+    /// The size of the smallest class is `2^MIN_CLASS_SHIFT` bytes.
+    const MIN_CLASS_SHIFT: usize = 4;
+    /// The number of size classes, from 16 bytes to 2 KB.
+    const NUM_CLASSES: usize = 8;
+    /// The largest size of the cached objects.
+    const MAX_CACHED_SIZE: usize = 1 << (MIN_CLASS_SHIFT + NUM_CLASSES - 1);
+    /// The largest alignment of the cached objects.
+    const MAX_CACHED_ALIGN: usize = 16;
+    /// The capacity of a magazine.
+    const MAGAZINE_SIZE: usize = 32;
+    /// The number of objects to refill or flush at a time.
+    const BATCH_SIZE: usize = MAGAZINE_SIZE / 2;
+
+    /// A stack of free objects of the same size class.
+    struct Magazine {
+        len: usize,
+        objs: [usize; MAGAZINE_SIZE],
+    }
+
+    struct CpuCache {
+        mags: [Magazine; NUM_CLASSES],
+        alloc_hits: AtomicU64,
+        alloc_misses: AtomicU64,
+        free_hits: AtomicU64,
+        free_misses: AtomicU64,
+        bypassed: AtomicU64,
+        cached_bytes: AtomicUsize,
+    }
+
+    /// The magazines are only accessed by the owner CPU with IRQs and
+    /// preemption disabled, while the counters may also be read by others.
+    #[percpu::def_percpu]
+    static CPU_CACHE: CpuCache = CpuCache::new();
+
+    impl CpuCache {
+        const fn new() -> Self {
+            const EMPTY: Magazine = Magazine {
+                len: 0,
+                objs: [0; MAGAZINE_SIZE],
+            };
+            Self {
+                mags: [EMPTY; NUM_CLASSES],
+                alloc_hits: AtomicU64::new(0),
+                alloc_misses: AtomicU64::new(0),
+                free_hits: AtomicU64::new(0),
+                free_misses: AtomicU64::new(0),
+                bypassed: AtomicU64::new(0),
+                cached_bytes: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    /// Increases a counter of the current CPU.
+    fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the size class of `layout`, or `None` if it's not cached.
+    pub(crate) fn size_class(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(1);
+        if size > MAX_CACHED_SIZE || layout.align() > MAX_CACHED_ALIGN {
+            return None;
+        }
+        let shift = size.next_power_of_two().trailing_zeros() as usize;
+        Some(shift.saturating_sub(MIN_CLASS_SHIFT))
+    }
+
+    /// Records an allocation or deallocation that bypassed the caches.
+    pub(crate) fn count_bypassed() {
+        let _guard = NoPreempt::new();
+        inc(&unsafe { CPU_CACHE.current_ref_raw() }.bypassed);
+    }
+
+    fn class_layout(class: usize) -> Layout {
+        Layout::from_size_align(1 << (class + MIN_CLASS_SHIFT), MAX_CACHED_ALIGN).unwrap()
+    }
+
+    impl GlobalAllocator {
+        /// Allocates an object of the size class from the cache of the current
+        /// CPU, and refills the cache if it's empty.
+        pub(crate) fn cache_alloc(&self, class: usize) -> AllocResult<NonNull<u8>> {
+            let layout = class_layout(class);
+            let _guard = NoPreemptIrqSave::new();
+            let cache = unsafe { CPU_CACHE.current_ref_mut_raw() };
+            let mag = &mut cache.mags[class];
+            if mag.len == 0 {
+                inc(&cache.alloc_misses);
+                let mut balloc = self.balloc.lock();
+                while mag.len < BATCH_SIZE {
+                    match self.alloc_locked(&mut balloc, layout) {
+                        Ok(ptr) => {
+                            mag.objs[mag.len] = ptr.as_ptr() as usize;
+                            mag.len += 1;
+                        }
+                        Err(_) if mag.len > 0 => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                cache
+                    .cached_bytes
+                    .fetch_add(mag.len * layout.size(), Ordering::Relaxed);
+            } else {
+                inc(&cache.alloc_hits);
+            }
+            mag.len -= 1;
+            cache
+                .cached_bytes
+                .fetch_sub(layout.size(), Ordering::Relaxed);
+            Ok(unsafe { NonNull::new_unchecked(mag.objs[mag.len] as *mut u8) })
+        }
+
+        /// Puts an object of the size class into the cache of the current CPU,
+        /// and flushes half of the cache if it's full.
+        pub(crate) fn cache_dealloc(&self, class: usize, pos: NonNull<u8>) {
+            let layout = class_layout(class);
+            let _guard = NoPreemptIrqSave::new();
+            let cache = unsafe { CPU_CACHE.current_ref_mut_raw() };
+            let mag = &mut cache.mags[class];
+            if mag.len == MAGAZINE_SIZE {
+                inc(&cache.free_misses);
+                // Flush the oldest objects, and keep the recently freed ones
+                // which are more likely to be in the CPU cache.
+                let mut balloc = self.balloc.lock();
+                for &obj in &mag.objs[..BATCH_SIZE] {
+                    balloc.dealloc(unsafe { NonNull::new_unchecked(obj as *mut u8) }, layout);
+                }
+                cache
+                    .cached_bytes
+                    .fetch_sub(BATCH_SIZE * layout.size(), Ordering::Relaxed);
+                drop(balloc);
+                mag.objs.copy_within(BATCH_SIZE.., 0);
+                mag.len -= BATCH_SIZE;
+            } else {
+                inc(&cache.free_hits);
+            }
+            mag.objs[mag.len] = pos.as_ptr() as usize;
+            mag.len += 1;
+            cache
+                .cached_bytes
+                .fetch_add(layout.size(), Ordering::Relaxed);
+        }
+    }
+
+    /// Sums up the statistics of all CPUs.
+    pub(crate) fn stats() -> CacheStats {
+        let mut stats = CacheStats::default();
+        for cpu in 0..axconfig::SMP {
+            // The counters are atomic, so they can be read remotely.
+            let cache = unsafe { CPU_CACHE.remote_ref_raw(cpu) };
+            stats.alloc_hits += cache.alloc_hits.load(Ordering::Relaxed);
+            stats.alloc_misses += cache.alloc_misses.load(Ordering::Relaxed);
+            stats.free_hits += cache.free_hits.load(Ordering::Relaxed);
+            stats.free_misses += cache.free_misses.load(Ordering::Relaxed);
+            stats.bypassed += cache.bypassed.load(Ordering::Relaxed);
+            stats.cached_bytes += cache.cached_bytes.load(Ordering::Relaxed);
+        }
+        stats
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const POOL_SIZE: usize = 1024 * 1024;
+
+        fn mag(class: usize) -> &'static Magazine {
+            unsafe { &CPU_CACHE.current_ref_raw().mags[class] }
+        }
+
+        #[test]
+        fn test_size_class() {
+            let class = |size, align| size_class(&Layout::from_size_align(size, align).unwrap());
+            assert_eq!(class(0, 1), Some(0));
+            assert_eq!(class(1, 1), Some(0));
+            assert_eq!(class(16, 8), Some(0));
+            assert_eq!(class(17, 8), Some(1));
+            assert_eq!(class(32, 16), Some(1));
+            assert_eq!(class(33, 1), Some(2));
+            assert_eq!(class(1024, 4), Some(NUM_CLASSES - 2));
+            assert_eq!(class(1025, 4), Some(NUM_CLASSES - 1));
+            assert_eq!(class(MAX_CACHED_SIZE, 16), Some(NUM_CLASSES - 1));
+            // too large or too aligned
+            assert_eq!(class(MAX_CACHED_SIZE + 1, 1), None);
+            assert_eq!(class(16, MAX_CACHED_ALIGN * 2), None);
+            assert_eq!(class(4096, 4096), None);
+            for c in 0..NUM_CLASSES {
+                assert_eq!(size_class(&class_layout(c)), Some(c));
+            }
+        }
+
+        #[test]
+        fn test_magazine_refill_flush() {
+            const CLASS: usize = 2;
+            const NUM_OBJS: usize = BATCH_SIZE * 2 + 4;
+            let size = class_layout(CLASS).size();
+            let _guard = crate::lock_global_for_test();
+
+            let layout = Layout::from_size_align(POOL_SIZE, 4096).unwrap();
+            let pool = unsafe { std::alloc::alloc(layout) };
+            let alloc = GlobalAllocator::new();
+            alloc.init(pool as usize, POOL_SIZE);
+            // Other tests may have used the caches with the global allocator,
+            // so forget the cached objects and count from here.
+            unsafe { CPU_CACHE.current_ref_mut_raw().mags[CLASS].len = 0 };
+            let base = super::stats();
+            let stats = || {
+                let s = super::stats();
+                CacheStats {
+                    alloc_hits: s.alloc_hits - base.alloc_hits,
+                    alloc_misses: s.alloc_misses - base.alloc_misses,
+                    free_hits: s.free_hits - base.free_hits,
+                    free_misses: s.free_misses - base.free_misses,
+                    bypassed: s.bypassed - base.bypassed,
+                    cached_bytes: s.cached_bytes - base.cached_bytes,
+                }
+            };
+
+            // An empty magazine is refilled with a batch each time.
+            let objs: Vec<_> = (0..NUM_OBJS)
+                .map(|_| alloc.cache_alloc(CLASS).unwrap().as_ptr() as usize)
+                .collect();
+            let s = stats();
+            assert_eq!(s.alloc_misses, 3);
+            assert_eq!(s.alloc_hits, NUM_OBJS as u64 - 3);
+            assert_eq!(mag(CLASS).len, BATCH_SIZE * 3 - NUM_OBJS);
+            assert_eq!(s.cached_bytes, mag(CLASS).len * size);
+            let mut sorted = objs.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), NUM_OBJS);
+            assert!(objs.iter().all(|&obj| obj % MAX_CACHED_ALIGN == 0));
+
+            // A full magazine flushes the oldest batch to the byte allocator.
+            let free = |obj| alloc.cache_dealloc(CLASS, NonNull::new(obj as *mut u8).unwrap());
+            let first_flush = MAGAZINE_SIZE - mag(CLASS).len;
+            for &obj in &objs[..first_flush] {
+                free(obj);
+            }
+            assert_eq!(mag(CLASS).len, MAGAZINE_SIZE);
+            assert_eq!(stats().free_misses, 0);
+            free(objs[first_flush]);
+            assert_eq!(stats().free_misses, 1);
+            assert_eq!(mag(CLASS).len, MAGAZINE_SIZE - BATCH_SIZE + 1);
+            for &obj in &objs[first_flush + 1..] {
+                free(obj);
+            }
+            let s = stats();
+            assert_eq!(s.free_hits, NUM_OBJS as u64 - 1);
+            assert_eq!(s.free_misses, 1);
+            assert_eq!(s.cached_bytes, MAGAZINE_SIZE * size);
+            // The most recently freed objects are kept.
+            assert_eq!(mag(CLASS).objs[..], objs[NUM_OBJS - MAGAZINE_SIZE..]);
+
+            // Last in, first out, and no refill until it's empty.
+            for &obj in objs.iter().rev().take(MAGAZINE_SIZE) {
+                assert_eq!(alloc.cache_alloc(CLASS).unwrap().as_ptr() as usize, obj);
+            }
+            assert_eq!(mag(CLASS).len, 0);
+            assert_eq!(stats().alloc_misses, 3);
+            assert_eq!(stats().cached_bytes, 0);
+            unsafe { std::alloc::dealloc(pool, layout) };
+        }
+    }
+}
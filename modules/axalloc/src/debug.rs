@@ -0,0 +1,487 @@
+//! Debug heap to detect heap corruption, with the `alloc-debug` feature.
+//!
+//! Each block of the global allocator is surrounded by red zones, which are
+//! filled with a pattern and checked when the block is freed, to detect
+//! buffer overflows and underflows. A freed block is filled with a poison
+//! pattern, and kept in a quarantine instead of being reused immediately.
+//! The poison is checked when the block leaves the quarantine, to detect
+//! writes after free.
+//!
+//! Corruption is reported by a panic, with the backtrace of the allocation
+//! site recorded by the `alloc-trace` feature. [`check_heap`] checks all the
+//! live and quarantined blocks at once, which can be called periodically to
+//! find out when the corruption happens.
+//!
+//! A block is laid out as follows, where the header is right before the
+//! front red zone, and the padding is for the alignment of the user data:
+//!
+//! ```text
+//! | padding | header | front red zone | user data | back red zone |
+//! ```
+//!
+//! It's independent of the byte allocator, as it only enlarges the requested
+//! layouts. The red zones and the quarantine are counted in
+//! [`GlobalAllocator::used_bytes`].
+
+use core::alloc::Layout;
+use core::fmt;
+use core::mem::size_of;
+use core::ptr::NonNull;
+
+use spinlock::SpinNoIrq;
+
+use crate::trace::{self, Frames};
+use crate::GlobalAllocator;
+
+/// The size of each red zone.
+const REDZONE_SIZE: usize = 16;
+/// The pattern of the red zones.
+const REDZONE_BYTE: u8 = 0xcc;
+/// The pattern of the freed blocks.
+const POISON_BYTE: u8 = 0x6b;
+/// The maximum total size of the blocks in the quarantine.
+const QUARANTINE_SIZE: usize = 0x10_0000; // 1 MB
+
+const LIVE_MAGIC: usize = 0x4845_4150_4c49_5645; // "HEAPLIVE"
+const FREED_MAGIC: usize = 0x4845_4150_4652_4545; // "HEAPFREE"
+
+const HEADER_SIZE: usize = size_of::<Header>();
+
+#[derive(Clone, Copy)]
+struct Header {
+    magic: usize,
+    size: usize,
+    align: usize,
+    /// The next block in the quarantine.
+    next: usize,
+    /// The backtrace of the allocation site.
+    backtrace: &'static [usize],
+}
+
+/// The freed blocks in FIFO order, linked by their headers.
+struct Quarantine {
+    head: usize,
+    tail: usize,
+    bytes: usize,
+}
+
+static QUARANTINE: SpinNoIrq<Quarantine> = SpinNoIrq::new(Quarantine {
+    head: 0,
+    tail: 0,
+    bytes: 0,
+});
+
+/// The kinds of heap corruption.
+#[derive(Clone, Copy)]
+enum Corruption {
+    /// The pointer to free was not allocated.
+    InvalidFree,
+    /// The header of the block is overwritten.
+    HeaderOverwritten,
+    DoubleFree,
+    /// The block is freed with a different layout.
+    LayoutMismatch(Layout),
+    /// A byte before the block is overwritten, at the offset from the block.
+    Underflow(usize),
+    /// A byte after the block is overwritten, at the offset from the end.
+    Overflow(usize),
+    /// A byte of the freed block is overwritten, at the offset from the block.
+    UseAfterFree(usize),
+}
+
+/// A report of heap corruption, with the header of the corrupted block.
+struct Report {
+    ptr: usize,
+    corruption: Corruption,
+    header: Option<Header>,
+}
+
+fn header_of(ptr: usize) -> *mut Header {
+    (ptr - REDZONE_SIZE - HEADER_SIZE) as *mut Header
+}
+
+/// Returns the layout of the whole block, and the offset of the user data.
+fn block_layout(size: usize, align: usize) -> (Layout, usize) {
+    let align = align.max(core::mem::align_of::<Header>());
+    let offset = (HEADER_SIZE + REDZONE_SIZE + align - 1) & !(align - 1);
+    let layout = Layout::from_size_align(offset + size + REDZONE_SIZE, align).unwrap();
+    (layout, offset)
+}
+
+/// Returns the address of the first byte in `[start, start + len)` that is
+/// not `byte`.
+fn find_mismatch(start: usize, len: usize, byte: u8) -> Option<usize> {
+    let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+    bytes.iter().position(|&b| b != byte).map(|i| start + i)
+}
+
+/// Checks the red zones of a live block.
+fn check_redzones(ptr: usize, header: &Header) -> Option<Corruption> {
+    let front = ptr - REDZONE_SIZE;
+    if let Some(addr) = find_mismatch(front, REDZONE_SIZE, REDZONE_BYTE) {
+        return Some(Corruption::Underflow(ptr - addr));
+    }
+    let back = ptr + header.size;
+    if let Some(addr) = find_mismatch(back, REDZONE_SIZE, REDZONE_BYTE) {
+        return Some(Corruption::Overflow(addr - back));
+    }
+    None
+}
+
+fn check_live(ptr: usize) -> Option<Report> {
+    let header = unsafe { *header_of(ptr) };
+    if header.magic != LIVE_MAGIC {
+        return Some(Report {
+            ptr,
+            corruption: Corruption::HeaderOverwritten,
+            header: None,
+        });
+    }
+    Some(Report {
+        ptr,
+        corruption: check_redzones(ptr, &header)?,
+        header: Some(header),
+    })
+}
+
+/// Checks the poison and the red zones of a block in the quarantine.
+fn check_freed(ptr: usize) -> Option<Report> {
+    let header = unsafe { *header_of(ptr) };
+    if header.magic != FREED_MAGIC {
+        return Some(Report {
+            ptr,
+            corruption: Corruption::HeaderOverwritten,
+            header: None,
+        });
+    }
+    let corruption = if let Some(addr) = find_mismatch(ptr, header.size, POISON_BYTE) {
+        Corruption::UseAfterFree(addr - ptr)
+    } else {
+        check_redzones(ptr, &header)?
+    };
+    Some(Report {
+        ptr,
+        corruption,
+        header: Some(header),
+    })
+}
+
+pub(crate) fn alloc(ga: &GlobalAllocator, layout: Layout) -> crate::AllocResult<NonNull<u8>> {
+    let (outer, offset) = block_layout(layout.size(), layout.align());
+    let block = ga.alloc_untraced(outer)?;
+    let ptr = unsafe { block.as_ptr().add(offset) };
+    unsafe {
+        ptr.sub(REDZONE_SIZE)
+            .write_bytes(REDZONE_BYTE, REDZONE_SIZE);
+        ptr.add(layout.size())
+            .write_bytes(REDZONE_BYTE, REDZONE_SIZE);
+    }
+    let ptr = unsafe { NonNull::new_unchecked(ptr) };
+    let backtrace = trace::record_alloc(ptr, layout.size());
+    let header = Header {
+        magic: LIVE_MAGIC,
+        size: layout.size(),
+        align: layout.align(),
+        next: 0,
+        backtrace,
+    };
+    unsafe { header_of(ptr.as_ptr() as usize).write(header) };
+    Ok(ptr)
+}
+
+pub(crate) fn dealloc(ga: &GlobalAllocator, pos: NonNull<u8>, layout: Layout) {
+    let ptr = pos.as_ptr() as usize;
+    let header = unsafe { &mut *header_of(ptr) };
+    let corruption = match header.magic {
+        LIVE_MAGIC if header.size != layout.size() || header.align != layout.align() => {
+            Some(Corruption::LayoutMismatch(layout))
+        }
+        LIVE_MAGIC => check_redzones(ptr, header),
+        FREED_MAGIC => Some(Corruption::DoubleFree),
+        _ => {
+            report(Report {
+                ptr,
+                corruption: Corruption::InvalidFree,
+                header: None,
+            });
+        }
+    };
+    if let Some(corruption) = corruption {
+        report(Report {
+            ptr,
+            corruption,
+            header: Some(*header),
+        });
+    }
+
+    trace::record_dealloc(pos);
+    header.magic = FREED_MAGIC;
+    header.next = 0;
+    unsafe { pos.as_ptr().write_bytes(POISON_BYTE, header.size) };
+
+    let (outer, _) = block_layout(header.size, header.align);
+    let mut quarantine = QUARANTINE.lock();
+    if quarantine.tail == 0 {
+        quarantine.head = ptr;
+    } else {
+        unsafe { (*header_of(quarantine.tail)).next = ptr };
+    }
+    quarantine.tail = ptr;
+    quarantine.bytes += outer.size();
+    drop(quarantine);
+
+    while let Some(ptr) = evict_one() {
+        if let Some(report) = check_freed(ptr) {
+            self::report(report);
+        }
+        let header = unsafe { &*header_of(ptr) };
+        let (outer, offset) = block_layout(header.size, header.align);
+        let block = unsafe { NonNull::new_unchecked((ptr - offset) as *mut u8) };
+        ga.dealloc_untraced(block, outer);
+    }
+}
+
+/// Removes the oldest block from the quarantine if it's full.
+fn evict_one() -> Option<usize> {
+    let mut quarantine = QUARANTINE.lock();
+    if quarantine.bytes <= QUARANTINE_SIZE {
+        return None;
+    }
+    let ptr = quarantine.head;
+    let header = unsafe { &*header_of(ptr) };
+    quarantine.head = header.next;
+    if quarantine.head == 0 {
+        quarantine.tail = 0;
+    }
+    quarantine.bytes -= block_layout(header.size, header.align).0.size();
+    Some(ptr)
+}
+
+/// Checks the red zones of all live blocks, and the poison of all blocks in
+/// the quarantine.
+///
+/// It panics with a report if any corruption is found.
+pub fn check_heap() {
+    if let Some(report) = trace::find_map_live(check_live) {
+        self::report(report);
+    }
+    let found = {
+        let quarantine = QUARANTINE.lock();
+        let mut ptr = quarantine.head;
+        let mut found = None;
+        while ptr != 0 && found.is_none() {
+            found = check_freed(ptr);
+            ptr = unsafe { (*header_of(ptr)).next };
+        }
+        found
+    };
+    if let Some(report) = found {
+        self::report(report);
+    }
+}
+
+#[cold]
+fn report(report: Report) -> ! {
+    panic!("{}", report)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "heap corruption: ")?;
+        match self.corruption {
+            Corruption::InvalidFree => {
+                return write!(
+                    f,
+                    "invalid free of {:#x}, or its header is overwritten",
+                    self.ptr
+                );
+            }
+            Corruption::HeaderOverwritten => write!(f, "header overwritten")?,
+            Corruption::DoubleFree => write!(f, "double free")?,
+            Corruption::LayoutMismatch(layout) => write!(
+                f,
+                "freed with size {} and align {}",
+                layout.size(),
+                layout.align()
+            )?,
+            Corruption::Underflow(off) => write!(f, "write to byte {} before the start", off)?,
+            Corruption::Overflow(off) => write!(f, "write to byte {} past the end", off)?,
+            Corruption::UseAfterFree(off) => write!(f, "write after free at offset {}", off)?,
+        }
+        write!(f, " of the block at {:#x}", self.ptr)?;
+        if let Some(header) = &self.header {
+            write!(
+                f,
+                " (size {}, align {}), allocated at:\n{}",
+                header.size,
+                header.align,
+                Frames(header.backtrace)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out a block in `buf` as [`alloc`] does, with the user data filled
+    /// with `fill`, and returns the pointer to the user data.
+    fn make_block(buf: &mut [usize], size: usize, magic: usize, fill: u8) -> usize {
+        let (layout, offset) = block_layout(size, 1);
+        assert!(core::mem::size_of_val(buf) >= layout.size());
+        let block = buf.as_mut_ptr() as usize;
+        let ptr = block + offset;
+        let header = Header {
+            magic,
+            size,
+            align: 1,
+            next: 0,
+            backtrace: &[],
+        };
+        unsafe {
+            (block as *mut u8).write_bytes(REDZONE_BYTE, layout.size());
+            (ptr as *mut u8).write_bytes(fill, size);
+            header_of(ptr).write(header);
+        }
+        ptr
+    }
+
+    fn poke(addr: usize, byte: u8) {
+        unsafe { (addr as *mut u8).write(byte) }
+    }
+
+    #[test]
+    fn test_block_layout() {
+        let min_offset = HEADER_SIZE + REDZONE_SIZE;
+        for (size, align) in [(0, 1), (10, 1), (24, 8), (100, 64), (4096, 4096)] {
+            let (layout, offset) = block_layout(size, align);
+            let align = align.max(core::mem::align_of::<Header>());
+            assert_eq!(layout.align(), align);
+            assert_eq!(layout.size(), offset + size + REDZONE_SIZE);
+            // The header and the front red zone fit before the aligned data.
+            assert!(offset >= min_offset && offset < min_offset + align);
+            assert_eq!(offset % align, 0);
+        }
+        assert_eq!(block_layout(10, 1).1, min_offset);
+        assert_eq!(block_layout(16, 4096).1, 4096);
+    }
+
+    #[test]
+    fn test_check_redzones() {
+        const SIZE: usize = 20;
+        let mut buf = [REDZONE_BYTE; REDZONE_SIZE * 2 + SIZE];
+        let ptr = buf.as_mut_ptr() as usize + REDZONE_SIZE;
+        let header = Header {
+            magic: LIVE_MAGIC,
+            size: SIZE,
+            align: 1,
+            next: 0,
+            backtrace: &[],
+        };
+        let check = || check_redzones(ptr, &header);
+        // The user data is not checked.
+        unsafe { (ptr as *mut u8).write_bytes(0, SIZE) };
+        assert!(check().is_none());
+
+        for (addr, expected) in [
+            (ptr - 1, 1),
+            (ptr - REDZONE_SIZE, REDZONE_SIZE),
+            (ptr + SIZE, 0),
+            (ptr + SIZE + REDZONE_SIZE - 1, REDZONE_SIZE - 1),
+        ] {
+            poke(addr, 0);
+            match check() {
+                Some(Corruption::Underflow(off)) if addr < ptr => assert_eq!(off, expected),
+                Some(Corruption::Overflow(off)) if addr > ptr => assert_eq!(off, expected),
+                _ => panic!("corruption at {:#x} not found", addr),
+            }
+            poke(addr, REDZONE_BYTE);
+            assert!(check().is_none());
+        }
+    }
+
+    #[test]
+    fn test_check_live() {
+        let mut buf = [0; 16];
+        let ptr = make_block(&mut buf, 32, LIVE_MAGIC, 0);
+        assert!(check_live(ptr).is_none());
+        poke(ptr + 32 + 3, 0);
+        let report = check_live(ptr).unwrap();
+        assert!(matches!(report.corruption, Corruption::Overflow(3)));
+        assert_eq!(report.header.unwrap().size, 32);
+        let msg = std::format!("{}", report);
+        assert!(msg.starts_with("heap corruption: write to byte 3 past the end of the block"));
+        assert!(msg.contains("(size 32, align 1)"));
+    }
+
+    #[test]
+    fn test_check_freed() {
+        let mut buf = [0; 16];
+        let ptr = make_block(&mut buf, 32, FREED_MAGIC, POISON_BYTE);
+        assert!(check_freed(ptr).is_none());
+
+        poke(ptr + 5, 0);
+        let report = check_freed(ptr).unwrap();
+        assert!(matches!(report.corruption, Corruption::UseAfterFree(5)));
+        poke(ptr + 5, POISON_BYTE);
+
+        poke(ptr - 2, 0);
+        let report = check_freed(ptr).unwrap();
+        assert!(matches!(report.corruption, Corruption::Underflow(2)));
+        poke(ptr - 2, REDZONE_BYTE);
+        assert!(check_freed(ptr).is_none());
+
+        // A live block is not expected in the quarantine.
+        let ptr = make_block(&mut buf, 32, LIVE_MAGIC, POISON_BYTE);
+        let report = check_freed(ptr).unwrap();
+        assert!(matches!(report.corruption, Corruption::HeaderOverwritten));
+        assert!(report.header.is_none());
+    }
+
+    #[test]
+    fn test_check_heap() {
+        let _guard = crate::lock_global_for_test();
+        let ga = crate::global_allocator();
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        let live = ga.alloc(layout).unwrap().as_ptr() as usize;
+        let freed = ga.alloc(layout).unwrap();
+        ga.dealloc(freed, layout);
+        let freed = freed.as_ptr() as usize;
+        check_heap();
+
+        let panic_msg = || {
+            let err = std::panic::catch_unwind(check_heap).unwrap_err();
+            *err.downcast::<std::string::String>().unwrap()
+        };
+        poke(live + 40, 0);
+        assert!(panic_msg().contains("write to byte 0 past the end"));
+        poke(live + 40, REDZONE_BYTE);
+        poke(freed + 39, 0);
+        assert!(panic_msg().contains("write after free at offset 39"));
+        poke(freed + 39, POISON_BYTE);
+        check_heap();
+        ga.dealloc(NonNull::new(live as *mut u8).unwrap(), layout);
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn test_double_free() {
+        let _guard = crate::lock_global_for_test();
+        let ga = crate::global_allocator();
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let ptr = ga.alloc(layout).unwrap();
+        ga.dealloc(ptr, layout);
+        ga.dealloc(ptr, layout);
+    }
+
+    #[test]
+    #[should_panic(expected = "freed with size 32 and align 8")]
+    fn test_layout_mismatch() {
+        let _guard = crate::lock_global_for_test();
+        let ga = crate::global_allocator();
+        let ptr = ga.alloc(Layout::from_size_align(24, 8).unwrap()).unwrap();
+        ga.dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+    }
+}
@@ -4,16 +4,46 @@
 //! [`core::alloc::GlobalAlloc`]. A static global variable of type
 //! [`GlobalAllocator`] is defined with the `#[global_allocator]` attribute, to
 //! be registered as the standard library’s default allocator.
+//!
+//! # Cargo Features
+//!
+//! - `tlsf`, `slab`, `buddy`: Use [`TlsfByteAllocator`], [`SlabByteAllocator`]
+//!   or [`BuddyByteAllocator`] as the byte allocator. `tlsf` is enabled by
+//!   default.
+//! - `buddy_page`: Use [`BuddyPageAllocator`] as the page allocator instead of
+//!   [`BitmapPageAllocator`]. It has no limit on the memory size, and supports
+//!   adding more memory regions. Large contiguous allocations are faster.
+//! - `percpu_cache`: Serve small allocations by per-CPU caches in front of
+//!   the byte allocator, to avoid contending its lock on multiple CPUs. It
+//!   reports hit rates by [`GlobalAllocator::cache_stats`].
+//! - `alloc-trace`: Record the live allocations of the global allocator, to
+//!   find memory leaks. See the [`trace`](mod@trace) module.
+//! - `alloc-debug`: Detect heap corruption by red zones, poisoning and
+//!   quarantine of freed blocks. It enables `alloc-trace` to report the
+//!   allocation sites. See the [`debug`](mod@debug) module.
+//!
+//! [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
+//! [`SlabByteAllocator`]: allocator::SlabByteAllocator
+//! [`BuddyByteAllocator`]: allocator::BuddyByteAllocator
+//! [`BuddyPageAllocator`]: allocator::BuddyPageAllocator
+//! [`BitmapPageAllocator`]: allocator::BitmapPageAllocator
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(feature = "alloc-trace", feature(allocator_api, btreemap_alloc))]
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
+mod cache;
 mod page;
 
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+#[cfg(feature = "alloc-debug")]
+pub mod debug;
+#[cfg(feature = "alloc-trace")]
+pub mod trace;
+
+use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
 use spinlock::SpinNoIrq;
@@ -21,6 +51,7 @@ use spinlock::SpinNoIrq;
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
+pub use cache::CacheStats;
 pub use page::GlobalPage;
 
 cfg_if::cfg_if! {
@@ -33,6 +64,14 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "buddy_page")] {
+        use allocator::BuddyPageAllocator as DefaultPageAllocator;
+    } else {
+        use allocator::BitmapPageAllocator as DefaultPageAllocator;
+    }
+}
+
 /// The global allocator used by ArceOS.
 ///
 /// It combines a [`ByteAllocator`] and a [`PageAllocator`] into a simple
@@ -40,13 +79,19 @@ cfg_if::cfg_if! {
 /// there is no memory, asks the page allocator for more memory and adds it to
 /// the byte allocator.
 ///
-/// Currently, [`TlsfByteAllocator`] is used as the byte allocator, while
-/// [`BitmapPageAllocator`] is used as the page allocator.
+/// By default, [`TlsfByteAllocator`] is used as the byte allocator, while
+/// [`BitmapPageAllocator`] is used as the page allocator. They can be changed
+/// by cargo features.
+///
+/// With the `percpu_cache` feature, small allocations are served by per-CPU
+/// caches in front of the byte allocator, which are refilled from and flushed
+/// to the byte allocator in batches.
 ///
 /// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
+/// [`BitmapPageAllocator`]: allocator::BitmapPageAllocator
 pub struct GlobalAllocator {
     balloc: SpinNoIrq<DefaultByteAllocator>,
-    palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    palloc: SpinNoIrq<DefaultPageAllocator<PAGE_SIZE>>,
 }
 
 impl GlobalAllocator {
@@ -54,7 +99,7 @@ impl GlobalAllocator {
     pub const fn new() -> Self {
         Self {
             balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
-            palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            palloc: SpinNoIrq::new(DefaultPageAllocator::new()),
         }
     }
 
@@ -88,8 +133,14 @@ impl GlobalAllocator {
 
     /// Add the given region to the allocator.
     ///
-    /// It will add the whole region to the byte allocator.
+    /// It will add the whole region to the page allocator if it's
+    /// [`BuddyPageAllocator`], otherwise to the byte allocator.
+    ///
+    /// [`BuddyPageAllocator`]: allocator::BuddyPageAllocator
     pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        #[cfg(feature = "buddy_page")]
+        return self.palloc.lock().add_memory(start_vaddr, size);
+        #[cfg(not(feature = "buddy_page"))]
         self.balloc.lock().add_memory(start_vaddr, size)
     }
 
@@ -103,8 +154,37 @@ impl GlobalAllocator {
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     ///  aligned to it.
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "alloc-debug")]
+        if self.is_global() {
+            return debug::alloc(self, layout);
+        }
+        let ptr = self.alloc_untraced(layout)?;
+        #[cfg(feature = "alloc-trace")]
+        if self.is_global() {
+            trace::record_alloc(ptr, layout.size());
+        }
+        Ok(ptr)
+    }
+
+    /// Allocates without recording it for the `alloc-trace` feature, or
+    /// adding red zones for the `alloc-debug` feature.
+    pub(crate) fn alloc_untraced(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "percpu_cache")]
+        if self.is_global() {
+            if let Some(class) = cache::size_class(&layout) {
+                return self.cache_alloc(class);
+            }
+            cache::count_bypassed();
+        }
+        self.alloc_locked(&mut self.balloc.lock(), layout)
+    }
+
+    fn alloc_locked(
+        &self,
+        balloc: &mut DefaultByteAllocator,
+        layout: Layout,
+    ) -> AllocResult<NonNull<u8>> {
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
-        let mut balloc = self.balloc.lock();
         loop {
             if let Ok(ptr) = balloc.alloc(layout) {
                 return Ok(ptr);
@@ -133,6 +213,28 @@ impl GlobalAllocator {
     ///
     /// [`alloc`]: GlobalAllocator::alloc
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "alloc-debug")]
+        if self.is_global() {
+            return debug::dealloc(self, pos, layout);
+        }
+        // Remove the record before the memory can be allocated again.
+        #[cfg(feature = "alloc-trace")]
+        if self.is_global() {
+            trace::record_dealloc(pos);
+        }
+        self.dealloc_untraced(pos, layout)
+    }
+
+    /// Deallocates without recording it for the `alloc-trace` feature, or
+    /// checking and quarantining it for the `alloc-debug` feature.
+    pub(crate) fn dealloc_untraced(&self, pos: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "percpu_cache")]
+        if self.is_global() {
+            if let Some(class) = cache::size_class(&layout) {
+                return self.cache_dealloc(class, pos);
+            }
+            cache::count_bypassed();
+        }
         self.balloc.lock().dealloc(pos, layout)
     }
 
@@ -158,13 +260,20 @@ impl GlobalAllocator {
     }
 
     /// Returns the number of allocated bytes in the byte allocator.
+    ///
+    /// The free objects in the per-CPU caches are not counted.
     pub fn used_bytes(&self) -> usize {
-        self.balloc.lock().used_bytes()
+        self.balloc
+            .lock()
+            .used_bytes()
+            .saturating_sub(self.cached_bytes())
     }
 
     /// Returns the number of available bytes in the byte allocator.
+    ///
+    /// The free objects in the per-CPU caches are counted.
     pub fn available_bytes(&self) -> usize {
-        self.balloc.lock().available_bytes()
+        self.balloc.lock().available_bytes() + self.cached_bytes()
     }
 
     /// Returns the number of allocated pages in the page allocator.
@@ -176,6 +285,27 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.palloc.lock().available_pages()
     }
+
+    /// Returns the statistics of the per-CPU caches (the `percpu_cache`
+    /// feature), or zeros if the feature is not enabled.
+    pub fn cache_stats(&self) -> CacheStats {
+        #[cfg(feature = "percpu_cache")]
+        if self.is_global() {
+            return cache::stats();
+        }
+        CacheStats::default()
+    }
+
+    fn cached_bytes(&self) -> usize {
+        self.cache_stats().cached_bytes
+    }
+
+    /// The per-CPU caches and the allocation records are only used by the
+    /// [`global_allocator`], as they are also global.
+    #[cfg(any(feature = "percpu_cache", feature = "alloc-trace"))]
+    fn is_global(&self) -> bool {
+        core::ptr::eq(self, &GLOBAL_ALLOCATOR)
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
@@ -203,9 +333,13 @@ pub fn global_allocator() -> &'static GlobalAllocator {
 /// Initializes the global allocator with the given memory region.
 ///
 /// Note that the memory region bounds are just numbers, and the allocator
-/// does not actually access the region. Users should ensure that the region
-/// is valid and not being used by others, so that the allocated memory is also
-/// valid.
+/// does not actually access the region, except that the [`BuddyPageAllocator`]
+/// (the `buddy_page` feature) keeps its metadata in the free memory, which is
+/// initialized from the low end of the region only as the pages are
+/// allocated. Users should ensure that the region is valid and not being used
+/// by others, so that the allocated memory is also valid.
+///
+/// [`BuddyPageAllocator`]: allocator::BuddyPageAllocator
 ///
 /// This function should be called only once, and before any allocation.
 pub fn global_init(start_vaddr: usize, size: usize) {
@@ -231,3 +365,20 @@ pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
     );
     GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
+
+/// Initializes the global allocator for the tests on the first call, and
+/// serializes the tests that use it, as the per-CPU caches and the allocation
+/// records are also global.
+#[cfg(all(test, any(feature = "percpu_cache", feature = "alloc-trace")))]
+fn lock_global_for_test() -> std::sync::MutexGuard<'static, ()> {
+    const POOL_SIZE: usize = 16 * 1024 * 1024;
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    static INIT: std::sync::Once = std::sync::Once::new();
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    INIT.call_once(|| {
+        let layout = Layout::from_size_align(POOL_SIZE, PAGE_SIZE).unwrap();
+        let pool = unsafe { std::alloc::alloc(layout) };
+        global_init(pool as usize, POOL_SIZE);
+    });
+    guard
+}
@@ -0,0 +1,650 @@
+//! Tracking of the live heap allocations, to find memory leaks.
+//!
+//! With the `alloc-trace` feature, every allocation of the global allocator
+//! is recorded with its size, the ID of the requesting task, the time, and the
+//! backtrace of the call site, until it's freed. [`snapshot`] copies the live
+//! allocations into a [`Snapshot`], which can be grouped by call sites, or
+//! compared with an earlier snapshot to find the call sites whose allocations
+//! keep growing.
+//!
+//! The OS must implement [`AllocTraceIf`] to provide the task IDs, the time
+//! and the backtraces.
+//!
+//! The records take extra heap memory, which is not recorded itself but is
+//! counted in [`GlobalAllocator::used_bytes`]. If there is no memory for the
+//! record of an allocation, the allocation is not recorded, but counted by
+//! [`Snapshot::dropped`]. At most [`MAX_BACKTRACES`] distinct backtraces are
+//! kept, the allocations from other call sites are recorded without
+//! backtraces. All allocations are serialized by the lock of the records, so
+//! the feature is only for debugging.
+//!
+//! [`GlobalAllocator::used_bytes`]: crate::GlobalAllocator::used_bytes
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::fmt;
+use core::ptr::NonNull;
+use core::time::Duration;
+
+use crate_interface::call_interface;
+use spinlock::SpinNoIrq;
+
+use crate::GLOBAL_ALLOCATOR;
+
+/// The maximum number of frames in the backtrace of an allocation.
+pub const MAX_FRAMES: usize = 16;
+
+/// The maximum number of distinct backtraces kept by the records.
+pub const MAX_BACKTRACES: usize = 4096;
+
+/// The OS functions used to record the allocations.
+#[crate_interface::def_interface]
+pub trait AllocTraceIf {
+    /// Returns the ID of the current task, or 0 if there is no task.
+    fn current_task_id() -> u64;
+
+    /// Returns the current time.
+    fn current_time() -> Duration;
+
+    /// Fills `frames` with the return addresses of the caller's call stack,
+    /// from the innermost frame, and returns the number of frames.
+    fn backtrace(frames: &mut [usize]) -> usize;
+}
+
+/// Allocates the records from the global allocator, without recording them.
+#[derive(Clone, Copy)]
+struct Untraced;
+
+unsafe impl Allocator for Untraced {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            let dangling = unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+            return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+        }
+        let ptr = GLOBAL_ALLOCATOR
+            .alloc_untraced(layout)
+            .map_err(|_| AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            GLOBAL_ALLOCATOR.dealloc_untraced(ptr, layout)
+        }
+    }
+}
+
+/// A live allocation.
+#[derive(Debug, Clone, Copy)]
+pub struct AllocRecord {
+    /// The start address of the allocated memory.
+    pub addr: usize,
+    /// The requested size in bytes.
+    pub size: usize,
+    /// The ID of the task that requested it, or 0 if there was no task.
+    pub task_id: u64,
+    /// The time when it was allocated.
+    pub time: Duration,
+    /// The return addresses of the call stack, from the innermost frame. The
+    /// first frames are in the allocator itself.
+    pub backtrace: &'static [usize],
+    /// The sequence number, which increases with each allocation.
+    seq: u64,
+}
+
+/// A hash table of the live allocations keyed by the address, with open
+/// addressing and linear probing.
+///
+/// Unlike the collections in `alloc`, it grows by fallible allocations, so
+/// that the records can be dropped when the memory runs out.
+struct LiveTable {
+    /// The number of slots is zero or a power of two.
+    slots: Vec<Option<AllocRecord>, Untraced>,
+    len: usize,
+}
+
+impl LiveTable {
+    const fn new() -> Self {
+        Self {
+            slots: Vec::new_in(Untraced),
+            len: 0,
+        }
+    }
+
+    fn home(&self, addr: usize) -> usize {
+        let hash = (addr as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        (hash >> 32) as usize & (self.slots.len() - 1)
+    }
+
+    /// Returns the slot of `addr`, or the empty slot where it should be
+    /// inserted.
+    fn find(&self, addr: usize) -> usize {
+        let mask = self.slots.len() - 1;
+        let mut i = self.home(addr);
+        while self.slots[i].is_some_and(|r| r.addr != addr) {
+            i = (i + 1) & mask;
+        }
+        i
+    }
+
+    /// Inserts or replaces the record of an address. Returns `Err` if the
+    /// table needs to grow but there is no memory.
+    fn try_insert(&mut self, record: AllocRecord) -> Result<(), AllocError> {
+        // Keep the load factor under 3/4.
+        if (self.len + 1) * 4 > self.slots.len() * 3 {
+            self.grow()?;
+        }
+        let i = self.find(record.addr);
+        if self.slots[i].replace(record).is_none() {
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    fn grow(&mut self) -> Result<(), AllocError> {
+        let cap = (self.slots.len() * 2).max(64);
+        let mut slots = Vec::new_in(Untraced);
+        slots.try_reserve_exact(cap).map_err(|_| AllocError)?;
+        slots.resize(cap, None);
+        let old = core::mem::replace(&mut self.slots, slots);
+        for record in old.into_iter().flatten() {
+            let i = self.find(record.addr);
+            self.slots[i] = Some(record);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, addr: usize) -> Option<AllocRecord> {
+        if self.len == 0 {
+            return None;
+        }
+        let mask = self.slots.len() - 1;
+        let mut hole = self.find(addr);
+        let removed = self.slots[hole].take()?;
+        self.len -= 1;
+        // Move the following records of the probe sequence back to fill the
+        // hole, unless they would be moved before their home slots.
+        let mut i = hole;
+        loop {
+            i = (i + 1) & mask;
+            let Some(record) = self.slots[i] else {
+                break;
+            };
+            let home = self.home(record.addr);
+            if (i.wrapping_sub(home) & mask) >= (i.wrapping_sub(hole) & mask) {
+                self.slots[hole] = self.slots[i].take();
+                hole = i;
+            }
+        }
+        Some(removed)
+    }
+
+    fn values(&self) -> impl Iterator<Item = &AllocRecord> {
+        self.slots.iter().flatten()
+    }
+}
+
+struct Records {
+    live: LiveTable,
+    /// The distinct backtraces in order, at most [`MAX_BACKTRACES`]. They are
+    /// never freed, as the snapshots may refer to them.
+    backtraces: Vec<&'static [usize], Untraced>,
+    next_seq: u64,
+    /// The number of allocations not recorded for lack of memory.
+    dropped: u64,
+}
+
+static RECORDS: SpinNoIrq<Records> = SpinNoIrq::new(Records::new());
+
+impl Records {
+    const fn new() -> Self {
+        Self {
+            live: LiveTable::new(),
+            backtraces: Vec::new_in(Untraced),
+            next_seq: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Returns the kept copy of the backtrace, or an empty one if the table
+    /// of backtraces is full or there is no memory.
+    fn intern_backtrace(&mut self, frames: &[usize]) -> &'static [usize] {
+        let idx = match self.backtraces.binary_search_by(|&bt| bt.cmp(frames)) {
+            Ok(idx) => return self.backtraces[idx],
+            Err(idx) => idx,
+        };
+        if self.backtraces.len() == MAX_BACKTRACES || self.backtraces.try_reserve(1).is_err() {
+            return &[];
+        }
+        let mut backtrace = Vec::new_in(Untraced);
+        if backtrace.try_reserve_exact(frames.len()).is_err() {
+            return &[];
+        }
+        backtrace.extend_from_slice(frames);
+        let backtrace: &'static [usize] = backtrace.leak();
+        self.backtraces.insert(idx, backtrace);
+        backtrace
+    }
+}
+
+/// Records an allocation of the global allocator, and returns the backtrace.
+#[inline(never)]
+pub(crate) fn record_alloc(ptr: NonNull<u8>, size: usize) -> &'static [usize] {
+    let mut buf = [0; MAX_FRAMES + 1];
+    let frames = buf.as_mut_slice();
+    let len = call_interface!(AllocTraceIf::backtrace, frames);
+    // Skip the frame of this function.
+    let frames = &buf[len.min(1)..len];
+    let task_id = call_interface!(AllocTraceIf::current_task_id);
+    let time = call_interface!(AllocTraceIf::current_time);
+
+    let mut records = RECORDS.lock();
+    let backtrace = records.intern_backtrace(frames);
+    let seq = records.next_seq;
+    records.next_seq += 1;
+    let addr = ptr.as_ptr() as usize;
+    let record = AllocRecord {
+        addr,
+        size,
+        task_id,
+        time,
+        backtrace,
+        seq,
+    };
+    if records.live.try_insert(record).is_err() {
+        records.dropped += 1;
+    }
+    backtrace
+}
+
+/// Removes the record of an allocation, before it's freed.
+pub(crate) fn record_dealloc(ptr: NonNull<u8>) {
+    RECORDS.lock().live.remove(ptr.as_ptr() as usize);
+}
+
+/// Calls `f` with the address of each live allocation, until it returns
+/// `Some`.
+///
+/// The records are locked during the calls, so `f` must not allocate.
+#[cfg(feature = "alloc-debug")]
+pub(crate) fn find_map_live<T>(mut f: impl FnMut(usize) -> Option<T>) -> Option<T> {
+    RECORDS.lock().live.values().find_map(|r| f(r.addr))
+}
+
+/// Takes a snapshot of the live allocations.
+pub fn snapshot() -> Snapshot {
+    let records = RECORDS.lock();
+    // The snapshot is not recorded, so it won't be reported as a leak.
+    let mut snapshot = Vec::with_capacity_in(records.live.len, Untraced);
+    snapshot.extend(records.live.values().copied());
+    snapshot.sort_unstable_by_key(|r| r.addr);
+    Snapshot {
+        records: snapshot,
+        next_seq: records.next_seq,
+        dropped: records.dropped,
+    }
+}
+
+/// The live allocations at some time, taken by [`snapshot`].
+///
+/// It's displayed as the allocations grouped by call sites.
+pub struct Snapshot {
+    /// Sorted by the address.
+    records: Vec<AllocRecord, Untraced>,
+    next_seq: u64,
+    dropped: u64,
+}
+
+/// The live allocations from the same call site.
+#[derive(Debug, Clone)]
+pub struct CallSite {
+    /// The backtrace of the call site.
+    pub backtrace: &'static [usize],
+    /// The number of allocations.
+    pub count: usize,
+    /// The total size of the allocations in bytes.
+    pub bytes: usize,
+    /// The time of the oldest allocation.
+    pub oldest: Duration,
+}
+
+/// The changes of the live allocations from a call site between two
+/// snapshots.
+#[derive(Debug, Clone)]
+pub struct CallSiteDiff {
+    /// The backtrace of the call site.
+    pub backtrace: &'static [usize],
+    /// The number of allocations made after the earlier snapshot, and still
+    /// live in the later one.
+    pub allocated: usize,
+    /// The total size of `allocated` in bytes.
+    pub allocated_bytes: usize,
+    /// The number of allocations live in the earlier snapshot, but freed in
+    /// the later one.
+    pub freed: usize,
+    /// The total size of `freed` in bytes.
+    pub freed_bytes: usize,
+}
+
+/// The changes of the live allocations between two snapshots, grouped by call
+/// sites, created by [`Snapshot::diff`].
+pub struct SnapshotDiff {
+    sites: Vec<CallSiteDiff>,
+}
+
+impl Snapshot {
+    /// Returns the live allocations, sorted by the address.
+    pub fn records(&self) -> &[AllocRecord] {
+        &self.records
+    }
+
+    /// Returns the number of allocations that were not recorded for lack of
+    /// memory, before the snapshot was taken.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns the total size of the live allocations in bytes.
+    pub fn total_bytes(&self) -> usize {
+        self.records.iter().map(|r| r.size).sum()
+    }
+
+    /// Groups the live allocations by call sites, sorted by the total size
+    /// in descending order.
+    pub fn call_sites(&self) -> Vec<CallSite> {
+        let mut sites = BTreeMap::new();
+        for r in self.records.iter() {
+            let site = sites
+                .entry(r.backtrace.as_ptr() as usize)
+                .or_insert(CallSite {
+                    backtrace: r.backtrace,
+                    count: 0,
+                    bytes: 0,
+                    oldest: r.time,
+                });
+            site.count += 1;
+            site.bytes += r.size;
+            site.oldest = site.oldest.min(r.time);
+        }
+        let mut sites: Vec<_> = sites.into_values().collect();
+        sites.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+        sites
+    }
+
+    /// Compares with an `earlier` snapshot, and groups the changes by call
+    /// sites.
+    ///
+    /// A call site that keeps allocating without freeing between snapshots
+    /// is likely leaking memory.
+    pub fn diff(&self, earlier: &Snapshot) -> SnapshotDiff {
+        let mut sites = BTreeMap::new();
+        for r in self.records.iter().filter(|r| r.seq >= earlier.next_seq) {
+            let site = site_diff(&mut sites, r);
+            site.allocated += 1;
+            site.allocated_bytes += r.size;
+        }
+        for r in earlier.records.iter().filter(|r| !self.contains(r)) {
+            let site = site_diff(&mut sites, r);
+            site.freed += 1;
+            site.freed_bytes += r.size;
+        }
+        let mut sites: Vec<_> = sites.into_values().collect();
+        sites.sort_by_key(|s| core::cmp::Reverse(s.net_bytes()));
+        SnapshotDiff { sites }
+    }
+
+    fn contains(&self, record: &AllocRecord) -> bool {
+        self.records
+            .binary_search_by_key(&record.addr, |r| r.addr)
+            .is_ok_and(|i| self.records[i].seq == record.seq)
+    }
+}
+
+fn site_diff<'a>(
+    sites: &'a mut BTreeMap<usize, CallSiteDiff>,
+    record: &AllocRecord,
+) -> &'a mut CallSiteDiff {
+    sites
+        .entry(record.backtrace.as_ptr() as usize)
+        .or_insert(CallSiteDiff {
+            backtrace: record.backtrace,
+            allocated: 0,
+            allocated_bytes: 0,
+            freed: 0,
+            freed_bytes: 0,
+        })
+}
+
+impl CallSiteDiff {
+    /// Returns the net change of the live bytes.
+    pub fn net_bytes(&self) -> isize {
+        self.allocated_bytes as isize - self.freed_bytes as isize
+    }
+}
+
+impl SnapshotDiff {
+    /// Returns the changes of the call sites, sorted by the net change of the
+    /// live bytes in descending order.
+    pub fn sites(&self) -> &[CallSiteDiff] {
+        &self.sites
+    }
+}
+
+/// Displays the return addresses of a backtrace, one per line.
+pub(crate) struct Frames<'a>(pub &'a [usize]);
+
+impl fmt::Display for Frames<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return writeln!(f, "  <no frames>");
+        }
+        for (i, ret) in self.0.iter().enumerate() {
+            writeln!(f, "  #{:<2} {:#018x}", i, ret)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sites = self.call_sites();
+        writeln!(
+            f,
+            "{} bytes in {} live allocations from {} call sites:",
+            self.total_bytes(),
+            self.records.len(),
+            sites.len()
+        )?;
+        if self.dropped > 0 {
+            writeln!(
+                f,
+                "{} allocations were not recorded for lack of memory",
+                self.dropped
+            )?;
+        }
+        for site in sites {
+            writeln!(
+                f,
+                "{} bytes in {} allocations, the oldest at {:?}, backtrace:",
+                site.bytes, site.count, site.oldest
+            )?;
+            write!(f, "{}", Frames(site.backtrace))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let net_bytes: isize = self.sites.iter().map(|s| s.net_bytes()).sum();
+        writeln!(
+            f,
+            "{:+} bytes from {} call sites:",
+            net_bytes,
+            self.sites.len()
+        )?;
+        for site in self.sites.iter() {
+            writeln!(
+                f,
+                "{:+} bytes, {} allocated ({} bytes), {} freed ({} bytes), backtrace:",
+                site.net_bytes(),
+                site.allocated,
+                site.allocated_bytes,
+                site.freed,
+                site.freed_bytes
+            )?;
+            write!(f, "{}", Frames(site.backtrace))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    std::thread_local! {
+        /// The fake return address of the allocations of the current thread.
+        pub(crate) static CALL_SITE: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct AllocTraceIfImpl;
+
+    #[crate_interface::impl_interface]
+    impl AllocTraceIf for AllocTraceIfImpl {
+        fn current_task_id() -> u64 {
+            1
+        }
+
+        fn current_time() -> Duration {
+            Duration::ZERO
+        }
+
+        fn backtrace(frames: &mut [usize]) -> usize {
+            // The first frame is `record_alloc` itself.
+            frames[0] = 0;
+            frames[1] = CALL_SITE.get();
+            2
+        }
+    }
+
+    fn record(addr: usize) -> AllocRecord {
+        AllocRecord {
+            addr,
+            size: 8,
+            task_id: 0,
+            time: Duration::ZERO,
+            backtrace: &[],
+            seq: addr as u64,
+        }
+    }
+
+    #[test]
+    fn test_live_table() {
+        const NUM: usize = 1000;
+        let _guard = crate::lock_global_for_test();
+        let mut table = LiveTable::new();
+        assert!(table.remove(0x1000).is_none());
+        for i in 0..NUM {
+            table.try_insert(record(0x1000 + i * 16)).unwrap();
+        }
+        assert_eq!(table.len, NUM);
+        assert!(table.slots.len() * 3 >= NUM * 4);
+
+        // Remove every other record, the rest are still found after the
+        // records are moved back.
+        for i in (0..NUM).step_by(2) {
+            let addr = 0x1000 + i * 16;
+            assert_eq!(table.remove(addr).unwrap().addr, addr);
+            assert!(table.remove(addr).is_none());
+        }
+        assert_eq!(table.len, NUM / 2);
+        for i in 0..NUM {
+            let addr = 0x1000 + i * 16;
+            let slot = table.slots[table.find(addr)];
+            assert_eq!(slot.is_some(), i % 2 == 1);
+        }
+        let mut addrs: Vec<_> = table.values().map(|r| r.addr).collect();
+        addrs.sort();
+        assert!(addrs
+            .iter()
+            .copied()
+            .eq((1..NUM).step_by(2).map(|i| 0x1000 + i * 16)));
+
+        // Replacing a record doesn't change the length.
+        table.try_insert(record(0x1010)).unwrap();
+        assert_eq!(table.len, NUM / 2);
+    }
+
+    #[test]
+    fn test_backtrace_limit() {
+        let _guard = crate::lock_global_for_test();
+        let mut records = Records::new();
+        let first = records.intern_backtrace(&[1, 2]);
+        assert_eq!(first, [1, 2]);
+        for i in 1..MAX_BACKTRACES {
+            records.intern_backtrace(&[i + 1]);
+        }
+        assert!(records.backtraces.windows(2).all(|w| w[0] < w[1]));
+        // The known backtraces are shared, and the new ones are dropped.
+        assert_eq!(records.intern_backtrace(&[1, 2]).as_ptr(), first.as_ptr());
+        assert_eq!(records.intern_backtrace(&[1, 3]), []);
+        assert_eq!(records.backtraces.len(), MAX_BACKTRACES);
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let _guard = crate::lock_global_for_test();
+        let alloc = crate::global_allocator();
+        let layout = Layout::from_size_align(48, 8).unwrap();
+        let alloc_at = |site| {
+            CALL_SITE.set(site);
+            alloc.alloc(layout).unwrap()
+        };
+        let mine =
+            |s: &Snapshot, site| s.records().iter().filter(|r| r.backtrace == [site]).count();
+
+        let kept = alloc_at(0x1111);
+        let freed = alloc_at(0x2222);
+        let earlier = snapshot();
+        assert!(earlier.records().windows(2).all(|w| w[0].addr < w[1].addr));
+        assert_eq!(mine(&earlier, 0x1111), 1);
+        assert_eq!(mine(&earlier, 0x2222), 1);
+        let r = earlier
+            .records()
+            .iter()
+            .find(|r| r.addr == kept.as_ptr() as usize);
+        assert_eq!(r.unwrap().size, 48);
+        assert_eq!(r.unwrap().task_id, 1);
+
+        alloc.dealloc(freed, layout);
+        let leaked: Vec<_> = (0..3).map(|_| alloc_at(0x1111)).collect();
+        let later = snapshot();
+        assert_eq!(mine(&later, 0x1111), 4);
+        assert_eq!(mine(&later, 0x2222), 0);
+        let site = |site| {
+            later
+                .call_sites()
+                .into_iter()
+                .find(|s| s.backtrace == [site])
+        };
+        assert_eq!(site(0x1111).unwrap().count, 4);
+        assert_eq!(site(0x1111).unwrap().bytes, 4 * 48);
+
+        let diff = later.diff(&earlier);
+        let site = |site| diff.sites().iter().find(|s| s.backtrace == [site]).cloned();
+        let grown = site(0x1111).unwrap();
+        assert_eq!((grown.allocated, grown.freed), (3, 0));
+        assert_eq!(grown.net_bytes(), 3 * 48);
+        let shrunk = site(0x2222).unwrap();
+        assert_eq!((shrunk.allocated, shrunk.freed), (0, 1));
+        assert_eq!(shrunk.net_bytes(), -48);
+
+        for ptr in leaked.into_iter().chain([kept]) {
+            alloc.dealloc(ptr, layout);
+        }
+        assert_eq!(mine(&snapshot(), 0x1111), 0);
+    }
+}
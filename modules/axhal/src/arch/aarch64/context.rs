@@ -90,6 +90,16 @@ impl TaskContext {
         self.fp_state.switch_to(&next_ctx.fp_state);
         unsafe { context_switch(self, next_ctx) }
     }
+
+    /// Returns the saved frame pointer (`X29`) of a task that is switched out.
+    ///
+    /// # Safety
+    ///
+    /// The context must be saved by [`switch_to`](Self::switch_to), and the
+    /// task must not be running, otherwise the value is stale or invalid.
+    pub unsafe fn frame_pointer(&self) -> usize {
+        self.r29 as usize
+    }
 }
 
 #[naked]
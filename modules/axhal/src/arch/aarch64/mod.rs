@@ -35,6 +35,18 @@ pub fn wait_for_irqs() {
     aarch64_cpu::asm::wfi();
 }
 
+/// Enables interrupts and waits for them atomically, i.e., an interrupt that
+/// becomes pending while interrupts are disabled also wakes up the CPU.
+///
+/// It should be called with interrupts disabled, after checking that there
+/// is nothing to do. Interrupts are enabled when it returns.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // `wfi` returns if any interrupt is pending, regardless of `DAIF.I`.
+    aarch64_cpu::asm::wfi();
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -135,3 +147,14 @@ pub fn read_thread_pointer() -> usize {
 pub unsafe fn write_thread_pointer(tpidr_el0: usize) {
     TPIDR_EL0.set(tpidr_el0 as _)
 }
+
+/// Reads the frame pointer register of the current CPU.
+///
+/// It is used to walk the stack by [`Backtrace`](crate::backtrace::Backtrace).
+/// The result is only meaningful if the code is compiled with frame pointers.
+#[inline(always)]
+pub fn read_frame_pointer() -> usize {
+    let fp;
+    unsafe { core::arch::asm!("mov {}, x29", out(reg) fp) };
+    fp
+}
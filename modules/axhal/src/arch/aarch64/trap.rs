@@ -50,23 +50,24 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
         Some(ESR_EL1::EC::Value::DataAbortLowerEL)
         | Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => {
             let iss = esr.read(ESR_EL1::ISS);
-            warn!(
-                "EL0 Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}",
-                tf.elr,
-                FAR_EL1.get(),
-                iss
-            );
+            let vaddr = FAR_EL1.get() as usize;
+            if !crate::trap::handle_page_fault_extern(vaddr.into(), true) {
+                warn!(
+                    "EL0 Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}",
+                    tf.elr, vaddr, iss
+                );
+            }
         }
         Some(ESR_EL1::EC::Value::DataAbortCurrentEL)
         | Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => {
             let iss = esr.read(ESR_EL1::ISS);
-            panic!(
-                "EL1 Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}:\n{:#x?}",
-                tf.elr,
-                FAR_EL1.get(),
-                iss,
-                tf,
-            );
+            let vaddr = FAR_EL1.get() as usize;
+            if !crate::trap::handle_page_fault_extern(vaddr.into(), false) {
+                panic!(
+                    "EL1 Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}:\n{:#x?}",
+                    tf.elr, vaddr, iss, tf,
+                );
+            }
         }
         _ => {
             panic!(
@@ -118,6 +118,16 @@ impl TaskContext {
             context_switch(self, next_ctx)
         }
     }
+
+    /// Returns the saved frame pointer (`s0`) of a task that is switched out.
+    ///
+    /// # Safety
+    ///
+    /// The context must be saved by [`switch_to`](Self::switch_to), and the
+    /// task must not be running, otherwise the value is stale or invalid.
+    pub unsafe fn frame_pointer(&self) -> usize {
+        self.s0
+    }
 }
 
 #[naked]
@@ -36,6 +36,18 @@ pub fn wait_for_irqs() {
     unsafe { riscv::asm::wfi() }
 }
 
+/// Enables interrupts and waits for them atomically, i.e., an interrupt that
+/// becomes pending while interrupts are disabled also wakes up the CPU.
+///
+/// It should be called with interrupts disabled, after checking that there
+/// is nothing to do. Interrupts are enabled when it returns.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // `wfi` returns if any interrupt is pending, regardless of `sstatus.SIE`.
+    unsafe { riscv::asm::wfi() };
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -107,3 +119,14 @@ pub fn read_thread_pointer() -> usize {
 pub unsafe fn write_thread_pointer(tp: usize) {
     core::arch::asm!("mv tp, {}", in(reg) tp)
 }
+
+/// Reads the frame pointer register of the current CPU.
+///
+/// It is used to walk the stack by [`Backtrace`](crate::backtrace::Backtrace).
+/// The result is only meaningful if the code is compiled with frame pointers.
+#[inline(always)]
+pub fn read_frame_pointer() -> usize {
+    let fp;
+    unsafe { core::arch::asm!("mv {}, s0", out(reg) fp) };
+    fp
+}
@@ -1,4 +1,5 @@
 use riscv::register::scause::{self, Exception as E, Trap};
+use riscv::register::stval;
 
 use super::TrapFrame;
 
@@ -14,11 +15,32 @@ fn handle_breakpoint(sepc: &mut usize) {
     *sepc += 2
 }
 
+fn handle_page_fault(tf: &TrapFrame, cause: Trap, from_user: bool) {
+    let vaddr = stval::read();
+    if crate::trap::handle_page_fault_extern(vaddr.into(), from_user) {
+        return;
+    }
+    if from_user {
+        warn!(
+            "User {:?} @ {:#x}, fault_vaddr={:#x}",
+            cause, tf.sepc, vaddr
+        );
+    } else {
+        panic!(
+            "Kernel {:?} @ {:#x}, fault_vaddr={:#x}:\n{:#x?}",
+            cause, tf.sepc, vaddr, tf
+        );
+    }
+}
+
 #[no_mangle]
-fn riscv_trap_handler(tf: &mut TrapFrame, _from_user: bool) {
+fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     let scause = scause::read();
     match scause.cause() {
         Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
+        cause @ Trap::Exception(E::LoadPageFault | E::StorePageFault | E::InstructionPageFault) => {
+            handle_page_fault(tf, cause, from_user)
+        }
         Trap::Interrupt(_) => crate::trap::handle_irq_extern(scause.bits()),
         _ => {
             panic!(
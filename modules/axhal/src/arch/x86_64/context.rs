@@ -194,6 +194,17 @@ impl TaskContext {
         }
         unsafe { context_switch(&mut self.rsp, &next_ctx.rsp) }
     }
+
+    /// Returns the saved frame pointer (`RBP`) of a task that is switched out.
+    ///
+    /// # Safety
+    ///
+    /// The context must be saved by [`switch_to`](Self::switch_to), and the
+    /// task must not be running, otherwise the value is stale or invalid.
+    pub unsafe fn frame_pointer(&self) -> usize {
+        // `RBP` is pushed first in `context_switch`, above 5 other registers.
+        unsafe { (self.rsp as *const u64).add(5).read() as usize }
+    }
 }
 
 #[naked]
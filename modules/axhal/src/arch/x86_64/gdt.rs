@@ -6,6 +6,10 @@ use x86_64::structures::gdt::{Descriptor, DescriptorFlags};
 use x86_64::structures::{tss::TaskStateSegment, DescriptorTablePointer};
 use x86_64::{addr::VirtAddr, PrivilegeLevel};
 
+/// The index of the Interrupt Stack Table (IST) entry in the TSS, that is
+/// used as the stack of the double fault handler.
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
 /// A wrapper of the Global Descriptor Table (GDT) with maximum 16 entries.
 #[repr(align(16))]
 pub struct GdtStruct {
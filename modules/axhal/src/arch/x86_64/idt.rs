@@ -32,7 +32,11 @@ impl IdtStruct {
             )
         };
         for i in 0..NUM_INT {
-            entries[i].set_handler_fn(unsafe { core::mem::transmute(ENTRIES[i]) });
+            let opts = entries[i].set_handler_fn(unsafe { core::mem::transmute(ENTRIES[i]) });
+            if i == x86::irq::DOUBLE_FAULT_VECTOR as usize {
+                // the current stack may be overflowed, use a separate one.
+                unsafe { opts.set_stack_index(super::gdt::DOUBLE_FAULT_IST_INDEX) };
+            }
         }
         idt
     }
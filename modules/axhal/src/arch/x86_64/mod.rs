@@ -12,7 +12,7 @@ use x86::{controlregs, msr, tlb};
 use x86_64::instructions::interrupts;
 
 pub use self::context::{ExtendedState, FxsaveArea, TaskContext, TrapFrame};
-pub use self::gdt::GdtStruct;
+pub use self::gdt::{GdtStruct, DOUBLE_FAULT_IST_INDEX};
 pub use self::idt::IdtStruct;
 pub use x86_64::structures::tss::TaskStateSegment;
 
@@ -48,6 +48,21 @@ pub fn wait_for_irqs() {
     }
 }
 
+/// Enables interrupts and waits for them atomically, i.e., an interrupt that
+/// becomes pending while interrupts are disabled also wakes up the CPU.
+///
+/// It should be called with interrupts disabled, after checking that there
+/// is nothing to do. Interrupts are enabled when it returns.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    if cfg!(target_os = "none") {
+        // `hlt` is in the interrupt shadow of `sti`.
+        unsafe { asm!("sti; hlt") }
+    } else {
+        core::hint::spin_loop()
+    }
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -108,3 +123,14 @@ pub fn read_thread_pointer() -> usize {
 pub unsafe fn write_thread_pointer(fs_base: usize) {
     unsafe { msr::wrmsr(msr::IA32_FS_BASE, fs_base as u64) }
 }
+
+/// Reads the frame pointer register of the current CPU.
+///
+/// It is used to walk the stack by [`Backtrace`](crate::backtrace::Backtrace).
+/// The result is only meaningful if the code is compiled with frame pointers.
+#[inline(always)]
+pub fn read_frame_pointer() -> usize {
+    let fp;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp) };
+    fp
+}
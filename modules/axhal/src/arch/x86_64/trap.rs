@@ -11,23 +11,30 @@ const IRQ_VECTOR_END: u8 = 0xff;
 fn x86_trap_handler(tf: &TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => {
+            let vaddr = unsafe { cr2() };
+            if crate::trap::handle_page_fault_extern(vaddr.into(), tf.is_user()) {
+                return;
+            }
             if tf.is_user() {
                 warn!(
                     "User #PF @ {:#x}, fault_vaddr={:#x}, error_code={:#x}",
-                    tf.rip,
-                    unsafe { cr2() },
-                    tf.error_code,
+                    tf.rip, vaddr, tf.error_code,
                 );
             } else {
                 panic!(
                     "Kernel #PF @ {:#x}, fault_vaddr={:#x}, error_code={:#x}:\n{:#x?}",
-                    tf.rip,
-                    unsafe { cr2() },
-                    tf.error_code,
-                    tf,
+                    tf.rip, vaddr, tf.error_code, tf,
                 );
             }
         }
+        DOUBLE_FAULT_VECTOR => {
+            // Most likely the #PF handler failed to push the trap frame as
+            // the stack overflowed into its guard page. We are running on a
+            // separate stack now, so check the faulting address again.
+            let vaddr = unsafe { cr2() };
+            crate::trap::handle_page_fault_extern(vaddr.into(), tf.is_user());
+            panic!("#DF @ {:#x}, fault_vaddr={:#x}:\n{:#x?}", tf.rip, vaddr, tf);
+        }
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
@@ -0,0 +1,100 @@
+//! Stack backtraces by walking the frame pointer chain.
+//!
+//! The kernel must be compiled with frame pointers (`-C force-frame-pointers`),
+//! otherwise the backtrace stops early or contains garbage frames.
+
+use core::fmt;
+use core::ops::Range;
+
+/// The maximum number of frames in a backtrace.
+pub const MAX_FRAMES: usize = 32;
+
+/// The offset from the frame pointer to the frame record, which consists of
+/// the caller's frame pointer and the return address.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const FRAME_RECORD_OFFSET: isize = -2;
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+const FRAME_RECORD_OFFSET: isize = 0;
+
+/// A captured backtrace, the return addresses from the innermost frame.
+#[derive(Clone)]
+pub struct Backtrace {
+    frames: [usize; MAX_FRAMES],
+    len: usize,
+}
+
+impl Backtrace {
+    /// Captures the backtrace of the caller, whose stack is in `stack`.
+    #[inline(always)]
+    pub fn capture(stack: Range<usize>) -> Self {
+        let fp = crate::arch::read_frame_pointer();
+        // Safety: the frame records are checked to be in the current stack.
+        unsafe { Self::from_frame_pointer(fp, stack) }
+    }
+
+    /// Walks the frame pointer chain from `fp`.
+    ///
+    /// It stops at the first frame record that is misaligned, out of `stack`,
+    /// or not above the previous one, so a corrupted chain is never followed
+    /// out of the stack.
+    ///
+    /// # Safety
+    ///
+    /// The memory in `stack` must be readable.
+    pub unsafe fn from_frame_pointer(mut fp: usize, stack: Range<usize>) -> Self {
+        const RECORD_SIZE: usize = 2 * core::mem::size_of::<usize>();
+        let mut bt = Self {
+            frames: [0; MAX_FRAMES],
+            len: 0,
+        };
+        let mut prev_record = 0;
+        while bt.len < MAX_FRAMES {
+            let record = (fp as *const usize).wrapping_offset(FRAME_RECORD_OFFSET) as usize;
+            if fp == 0
+                || record % core::mem::align_of::<usize>() != 0
+                || record <= prev_record
+                || record < stack.start
+                || record.saturating_add(RECORD_SIZE) > stack.end
+            {
+                break;
+            }
+            let (next_fp, ret) = unsafe {
+                let ptr = record as *const usize;
+                (ptr.read(), ptr.add(1).read())
+            };
+            if ret == 0 {
+                break;
+            }
+            bt.frames[bt.len] = ret;
+            bt.len += 1;
+            prev_record = record;
+            fp = next_fp;
+        }
+        bt
+    }
+
+    /// Returns the return addresses of the frames, from the innermost one.
+    pub fn frames(&self) -> &[usize] {
+        &self.frames[..self.len]
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.len == 0 {
+            return writeln!(f, "  <no frames>");
+        }
+        for (i, ret) in self.frames().iter().enumerate() {
+            writeln!(f, "  #{:<2} {:#018x}", i, ret)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.frames().iter().map(|ret| *ret as *const ()))
+            .finish()
+    }
+}
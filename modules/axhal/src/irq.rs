@@ -5,6 +5,7 @@ use handler_table::HandlerTable;
 use crate::platform::irq::MAX_IRQ_COUNT;
 
 pub use crate::platform::irq::{dispatch_irq, register_handler, set_enable};
+pub use crate::platform::irq::{send_ipi, IPI_IRQ_NUM};
 
 /// The type if an IRQ handler.
 pub type IrqHandler = handler_table::Handler;
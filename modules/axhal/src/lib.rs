@@ -37,6 +37,7 @@ extern crate log;
 mod platform;
 
 pub mod arch;
+pub mod backtrace;
 pub mod cpu;
 pub mod mem;
 pub mod time;
@@ -1,7 +1,9 @@
 //! Page table manipulation.
 
 use axalloc::global_allocator;
+use lazy_init::LazyInit;
 use page_table::PagingIf;
+use spinlock::SpinNoIrq;
 
 use crate::mem::{phys_to_virt, virt_to_phys, MemRegionFlags, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
@@ -64,3 +66,22 @@ cfg_if::cfg_if! {
         pub type PageTable = page_table::aarch64::A64PageTable<PagingIfImpl>;
     }
 }
+
+static KERNEL_PAGE_TABLE: LazyInit<SpinNoIrq<PageTable>> = LazyInit::new();
+
+/// Sets the kernel page table, which is shared by all CPUs.
+///
+/// It must be called only once, before any call to [`kernel_page_table`].
+pub fn init_kernel_page_table(page_table: PageTable) {
+    KERNEL_PAGE_TABLE.init_by(SpinNoIrq::new(page_table));
+}
+
+/// Returns the kernel page table.
+///
+/// # Panics
+///
+/// Panics if the kernel page table is not initialized by
+/// [`init_kernel_page_table`].
+pub fn kernel_page_table() -> &'static SpinNoIrq<PageTable> {
+    &KERNEL_PAGE_TABLE
+}
@@ -1,7 +1,11 @@
-//! Description tables (per-CPU GDT, per-CPU ISS, IDT)
+//! Description tables (per-CPU GDT, per-CPU ISS, IDT, per-CPU double fault
+//! stack)
 
-use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment};
+use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment, DOUBLE_FAULT_IST_INDEX};
 use lazy_init::LazyInit;
+use x86_64::VirtAddr;
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
 
 static IDT: LazyInit<IdtStruct> = LazyInit::new();
 
@@ -11,12 +15,19 @@ static TSS: LazyInit<TaskStateSegment> = LazyInit::new();
 #[percpu::def_percpu]
 static GDT: LazyInit<GdtStruct> = LazyInit::new();
 
+#[percpu::def_percpu]
+static DOUBLE_FAULT_STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+
 fn init_percpu() {
     unsafe {
         IDT.load();
         let tss = TSS.current_ref_mut_raw();
         let gdt = GDT.current_ref_mut_raw();
-        tss.init_by(TaskStateSegment::new());
+        let mut new_tss = TaskStateSegment::new();
+        let df_stack_top = DOUBLE_FAULT_STACK.current_ref_raw().as_ptr_range().end;
+        new_tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            VirtAddr::from_ptr(df_stack_top);
+        tss.init_by(new_tss);
         gdt.init_by(GdtStruct::new(tss));
         gdt.load();
         gdt.load_tss();
@@ -1,6 +1,7 @@
 //! Trap handling.
 
 use crate_interface::{call_interface, def_interface};
+use memory_addr::VirtAddr;
 
 /// Trap handler interface.
 ///
@@ -13,7 +14,12 @@ use crate_interface::{call_interface, def_interface};
 pub trait TrapHandler {
     /// Handles interrupt requests for the given IRQ number.
     fn handle_irq(irq_num: usize);
-    // more e.g.: handle_page_fault();
+    /// Handles the page fault at the given virtual address. `is_user`
+    /// indicates whether the fault is caused by user code.
+    ///
+    /// Returns `false` if the fault is not handled, then the trap handler
+    /// will report it as an unhandled page fault.
+    fn handle_page_fault(vaddr: VirtAddr, is_user: bool) -> bool;
 }
 
 /// Call the external IRQ handler.
@@ -21,3 +27,9 @@ pub trait TrapHandler {
 pub(crate) fn handle_irq_extern(irq_num: usize) {
     call_interface!(TrapHandler::handle_irq, irq_num);
 }
+
+/// Call the external page fault handler.
+#[cfg(target_os = "none")]
+pub(crate) fn handle_page_fault_extern(vaddr: VirtAddr, is_user: bool) -> bool {
+    call_interface!(TrapHandler::handle_page_fault, vaddr, is_user)
+}
@@ -6,8 +6,12 @@
 //! # Cargo Features
 //!
 //! - `alloc`: Enable global memory allocator.
-//! - `paging`: Enable page table manipulation support.
+//! - `paging`: Enable page table manipulation support. Task stacks are
+//!   protected by guard pages if `multitask` is also enabled, and overflows
+//!   into them are reported on x86_64.
 //! - `irq`: Enable interrupt handling support.
+//! - `tickless`: Stop the periodic timer tick when the CPU is idle.
+//! - `watchdog`: Report soft lockups and hung tasks with backtraces.
 //! - `multitask`: Enable multi-threading support.
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
 //! - `fs`: Enable filesystem support.
@@ -84,6 +88,44 @@ impl axlog::LogIf for LogIfImpl {
     }
 }
 
+#[cfg(feature = "alloc-trace")]
+struct AllocTraceIfImpl;
+
+#[cfg(feature = "alloc-trace")]
+#[crate_interface::impl_interface]
+impl axalloc::trace::AllocTraceIf for AllocTraceIfImpl {
+    fn current_task_id() -> u64 {
+        #[cfg(feature = "multitask")]
+        if let Some(curr) = axtask::current_may_uninit() {
+            return curr.id().as_u64();
+        }
+        0
+    }
+
+    fn current_time() -> core::time::Duration {
+        axhal::time::current_time()
+    }
+
+    fn backtrace(frames: &mut [usize]) -> usize {
+        use axhal::backtrace::Backtrace;
+
+        let fp = axhal::arch::read_frame_pointer();
+        // The range of the boot stack is not known, so the walk is bounded by
+        // its size.
+        let boot_stack = || fp..fp.saturating_add(axconfig::TASK_STACK_SIZE);
+        #[cfg(feature = "multitask")]
+        let stack = axtask::current_may_uninit()
+            .and_then(|curr| curr.kstack_range())
+            .unwrap_or_else(boot_stack);
+        #[cfg(not(feature = "multitask"))]
+        let stack = boot_stack();
+        let bt = unsafe { Backtrace::from_frame_pointer(fp, stack) };
+        let len = bt.frames().len().min(frames.len());
+        frames[..len].copy_from_slice(&bt.frames()[..len]);
+        len
+    }
+}
+
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 static INITED_CPUS: AtomicUsize = AtomicUsize::new(0);
@@ -232,10 +274,7 @@ fn init_allocator() {
 #[cfg(feature = "paging")]
 fn remap_kernel_memory() -> Result<(), axhal::paging::PagingError> {
     use axhal::mem::{memory_regions, phys_to_virt};
-    use axhal::paging::PageTable;
-    use lazy_init::LazyInit;
-
-    static KERNEL_PAGE_TABLE: LazyInit<PageTable> = LazyInit::new();
+    use axhal::paging::{kernel_page_table, PageTable};
 
     if axhal::cpu::this_cpu_is_bsp() {
         let mut kernel_page_table = PageTable::try_new()?;
@@ -248,10 +287,10 @@ fn remap_kernel_memory() -> Result<(), axhal::paging::PagingError> {
                 true,
             )?;
         }
-        KERNEL_PAGE_TABLE.init_by(kernel_page_table);
+        axhal::paging::init_kernel_page_table(kernel_page_table);
     }
 
-    unsafe { axhal::arch::write_page_table_root(KERNEL_PAGE_TABLE.root_paddr()) };
+    unsafe { axhal::arch::write_page_table_root(kernel_page_table().lock().root_paddr()) };
     Ok(())
 }
 
@@ -260,12 +299,15 @@ fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
 
     // Setup timer interrupt handler
+    #[cfg(not(feature = "tickless"))]
     const PERIODIC_INTERVAL_NANOS: u64 =
         axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
 
+    #[cfg(not(feature = "tickless"))]
     #[percpu::def_percpu]
     static NEXT_DEADLINE: u64 = 0;
 
+    #[cfg(not(feature = "tickless"))]
     fn update_timer() {
         let now_ns = axhal::time::current_time_nanos();
         // Safety: we have disabled preemption in IRQ handler.
@@ -278,11 +320,18 @@ fn init_interrupt() {
     }
 
     axhal::irq::register_handler(TIMER_IRQ_NUM, || {
+        // In tickless mode, the next timer is programmed by `axtask`.
+        #[cfg(not(feature = "tickless"))]
         update_timer();
         #[cfg(feature = "multitask")]
         axtask::on_timer_tick();
     });
 
+    // The reschedule IPI only needs to wake up the idle CPU, which then
+    // picks up the tasks sent to it.
+    #[cfg(feature = "smp")]
+    axhal::irq::register_handler(axhal::irq::IPI_IRQ_NUM, || {});
+
     // Enable IRQs before starting app
     axhal::arch::enable_irqs();
 }
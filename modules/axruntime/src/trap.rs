@@ -6,8 +6,34 @@ impl axhal::trap::TrapHandler for TrapHandlerImpl {
         #[cfg(feature = "irq")]
         {
             let guard = kernel_guard::NoPreempt::new();
+            #[cfg(feature = "multitask")]
+            {
+                // Interrupts only nest in softirq handlers, and the time of
+                // the nested ones is included in the outermost one.
+                let nested = axtask::in_softirq();
+                let start = axhal::time::current_time();
+                axtask::irq_enter();
+                axhal::irq::dispatch_irq(_irq_num);
+                axtask::irq_exit();
+                if !nested {
+                    axtask::account_irq_time(axhal::time::current_time() - start);
+                }
+            }
+            #[cfg(not(feature = "multitask"))]
             axhal::irq::dispatch_irq(_irq_num);
             drop(guard); // rescheduling may occur when preemption is re-enabled.
         }
     }
+
+    fn handle_page_fault(_vaddr: axhal::mem::VirtAddr, _is_user: bool) -> bool {
+        #[cfg(all(feature = "multitask", feature = "paging"))]
+        if !_is_user {
+            if let Some(curr) = axtask::current_may_uninit() {
+                if curr.in_stack_guard(_vaddr) {
+                    panic!("stack overflow in {}", curr.id_name());
+                }
+            }
+        }
+        false
+    }
 }
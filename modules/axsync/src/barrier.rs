@@ -0,0 +1,108 @@
+//! A barrier to synchronize a group of tasks.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axtask::WaitQueue;
+
+use crate::Mutex;
+
+/// A barrier enables multiple tasks to synchronize the beginning of some
+/// computation, similar to
+/// [`std::sync::Barrier`](https://doc.rust-lang.org/std/sync/struct.Barrier.html).
+pub struct Barrier {
+    /// The number of tasks arrived in the current generation.
+    count: Mutex<usize>,
+    generation: AtomicUsize,
+    num_tasks: usize,
+    wq: WaitQueue,
+}
+
+/// A `BarrierWaitResult` is returned by [`Barrier::wait()`] when all tasks in
+/// the [`Barrier`] have rendezvoused.
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// Returns `true` if this task is the "leader task" for the call to
+    /// [`Barrier::wait()`].
+    ///
+    /// Only one task will have `true` returned from their result, all other
+    /// tasks will have `false` returned.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// Creates a new barrier that can block a given number of tasks.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            count: Mutex::new(0),
+            generation: AtomicUsize::new(0),
+            num_tasks: n,
+            wq: WaitQueue::new(),
+        }
+    }
+
+    /// Blocks the current task until all tasks have rendezvoused here.
+    ///
+    /// Barriers are re-usable after all tasks have rendezvoused once.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut count = self.count.lock();
+        let generation = self.generation.load(Ordering::Relaxed);
+        *count += 1;
+        if *count < self.num_tasks {
+            drop(count);
+            // We have been counted, exiting here would break the barrier.
+            let cancel_enabled = axtask::set_cancel_enabled(false);
+            self.wq
+                .wait_until(|| self.generation.load(Ordering::Acquire) != generation);
+            axtask::set_cancel_enabled(cancel_enabled);
+            BarrierWaitResult(false)
+        } else {
+            *count = 0;
+            self.generation.fetch_add(1, Ordering::Release);
+            drop(count);
+            self.wq.notify_all(true);
+            BarrierWaitResult(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Barrier;
+    use axtask as thread;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn rendezvous() {
+        let _lock = crate::mutex::tests::SERIAL.lock();
+        crate::mutex::tests::init();
+
+        const NUM_TASKS: usize = 8;
+        const NUM_ROUNDS: usize = 10;
+        static BARRIER: Barrier = Barrier::new(NUM_TASKS);
+        static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+        static LEADERS: AtomicUsize = AtomicUsize::new(0);
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| {
+                for round in 0..NUM_ROUNDS {
+                    ARRIVED.fetch_add(1, Ordering::Relaxed);
+                    if BARRIER.wait().is_leader() {
+                        LEADERS.fetch_add(1, Ordering::Relaxed);
+                    }
+                    assert!(ARRIVED.load(Ordering::Relaxed) >= (round + 1) * NUM_TASKS);
+                    BARRIER.wait();
+                }
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        while FINISHED.load(Ordering::Relaxed) < NUM_TASKS {
+            thread::yield_now();
+        }
+        assert_eq!(LEADERS.load(Ordering::Relaxed), NUM_ROUNDS);
+    }
+}
@@ -3,26 +3,64 @@
 //! Currently supported primitives:
 //!
 //! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`Condvar`]: A condition variable.
+//! - [`RwLock`]: A reader-writer lock with writer preference.
+//! - [`Semaphore`]: A counting semaphore.
+//! - [`Barrier`]: A barrier to synchronize a group of tasks.
+//! - [`Once`]: A one-time initialization primitive.
+//! - [`RcuCell`]: A pointer protected by read-copy-update (RCU), for data
+//!   that is read on hot paths and rarely updated.
 //! - mod [`spin`](spinlock): spin-locks.
 //!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
 //!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
-//!   feature is enabled by default.
+//!   feature is enabled by default. The primitives other than [`Mutex`] are
+//!   only available with this feature.
+//! - `irq`: Enables the timeouts of [`Condvar::wait_timeout`] and
+//!   [`Semaphore::acquire_timeout`].
+//! - `lockdep`: Checks the acquisition order of the spin locks and the
+//!   [`Mutex`]es at runtime to detect potential deadlocks, and checks that
+//!   the [`Mutex`]es are never locked in atomic contexts.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
+extern crate alloc;
+
 pub use spinlock as spin;
 
+#[cfg(feature = "multitask")]
+mod barrier;
+#[cfg(feature = "multitask")]
+mod condvar;
 #[cfg(feature = "multitask")]
 mod mutex;
+#[cfg(feature = "multitask")]
+mod once;
+mod rcu;
+#[cfg(feature = "multitask")]
+mod rwlock;
+#[cfg(feature = "multitask")]
+mod semaphore;
 
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
 pub use self::mutex::{Mutex, MutexGuard};
 
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::{
+    barrier::{Barrier, BarrierWaitResult},
+    condvar::{Condvar, WaitTimeoutResult},
+    once::Once,
+    rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    semaphore::Semaphore,
+};
+
+pub use self::rcu::{RcuCell, RcuRef};
+
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
 pub use spinlock::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
@@ -1,11 +1,12 @@
-//! A naïve sleeping mutex.
+//! A naïve sleeping mutex with priority inheritance.
 
 use core::cell::UnsafeCell;
 use core::fmt;
 use core::ops::{Deref, DerefMut};
-use core::sync::atomic::{AtomicU64, Ordering};
 
-use axtask::{current, WaitQueue};
+use axtask::{current, PiState, WaitQueue};
+#[cfg(feature = "lockdep")]
+use {core::panic::Location, lockdep::LockClass, lockdep::LockKind};
 
 /// A mutual exclusion primitive useful for protecting shared data, similar to
 /// [`std::sync::Mutex`](https://doc.rust-lang.org/std/sync/struct.Mutex.html).
@@ -13,9 +14,18 @@ use axtask::{current, WaitQueue};
 /// When the mutex is locked, the current task will block and be put into the
 /// wait queue. When the mutex is unlocked, all tasks waiting on the queue
 /// will be woken up.
+///
+/// While tasks are blocked on the mutex, the owner inherits the highest
+/// priority among them, to avoid the priority inversion.
+///
+/// With the "lockdep" feature, it's checked to be never locked with IRQs
+/// disabled or spin locks held, and its acquisition order with other locks
+/// is checked as well.
 pub struct Mutex<T: ?Sized> {
     wq: WaitQueue,
-    owner_id: AtomicU64,
+    pi: PiState,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
     data: UnsafeCell<T>,
 }
 
@@ -23,7 +33,7 @@ pub struct Mutex<T: ?Sized> {
 ///
 /// When the guard falls out of scope it will release the lock.
 pub struct MutexGuard<'a, T: ?Sized + 'a> {
-    lock: &'a Mutex<T>,
+    pub(crate) lock: &'a Mutex<T>,
     data: *mut T,
 }
 
@@ -34,10 +44,13 @@ unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
 impl<T> Mutex<T> {
     /// Creates a new [`Mutex`] wrapping the supplied data.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(data: T) -> Self {
         Self {
             wq: WaitQueue::new(),
-            owner_id: AtomicU64::new(0),
+            pi: PiState::new(),
+            #[cfg(feature = "lockdep")]
+            class: LockClass::new(),
             data: UnsafeCell::new(data),
         }
     }
@@ -61,37 +74,37 @@ impl<T: ?Sized> Mutex<T> {
     /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
     #[inline(always)]
     pub fn is_locked(&self) -> bool {
-        self.owner_id.load(Ordering::Relaxed) != 0
+        self.pi.is_locked()
     }
 
     /// Locks the [`Mutex`] and returns a guard that permits access to the inner data.
     ///
     /// The returned value may be dereferenced for data access
     /// and the lock will be dropped when the guard falls out of scope.
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn lock(&self) -> MutexGuard<T> {
-        let current_id = current().id().as_u64();
-        loop {
-            // Can fail to lock even if the spinlock is not locked. May be more efficient than `try_lock`
-            // when called in a loop.
-            match self.owner_id.compare_exchange_weak(
-                0,
-                current_id,
-                Ordering::Acquire,
-                Ordering::Relaxed,
-            ) {
-                Ok(_) => break,
-                Err(owner_id) => {
-                    assert_ne!(
-                        owner_id,
-                        current_id,
-                        "{} tried to acquire mutex it already owns.",
-                        current().id_name()
-                    );
-                    // Wait until the lock looks unlocked before retrying
-                    self.wq.wait_until(|| !self.is_locked());
-                }
+        #[cfg(feature = "lockdep")]
+        self.class
+            .acquire(self.lock_addr(), LockKind::Sleep, Location::caller());
+        while !self.pi.try_lock() {
+            assert!(
+                !self.pi.is_owned_by_current(),
+                "{} tried to acquire mutex it already owns.",
+                current().id_name()
+            );
+            // Wait until the lock looks unlocked before retrying, the owner
+            // runs with our priority meanwhile. Locking is not a cancellation
+            // point, we must not exit as a waiter.
+            let cancel_enabled = axtask::set_cancel_enabled(false);
+            if self.pi.add_waiter() {
+                self.wq.wait_until(|| !self.is_locked());
+                self.pi.remove_waiter();
             }
+            axtask::set_cancel_enabled(cancel_enabled);
         }
+        #[cfg(feature = "lockdep")]
+        self.class
+            .acquired(self.lock_addr(), LockKind::Sleep, Location::caller());
         MutexGuard {
             lock: self,
             data: unsafe { &mut *self.data.get() },
@@ -100,15 +113,12 @@ impl<T: ?Sized> Mutex<T> {
 
     /// Try to lock this [`Mutex`], returning a lock guard if successful.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
-        let current_id = current().id().as_u64();
-        // The reason for using a strong compare_exchange is explained here:
-        // https://github.com/Amanieu/parking_lot/pull/207#issuecomment-575869107
-        if self
-            .owner_id
-            .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
-        {
+        if self.pi.try_lock() {
+            #[cfg(feature = "lockdep")]
+            self.class
+                .acquired(self.lock_addr(), LockKind::Sleep, Location::caller());
             Some(MutexGuard {
                 lock: self,
                 data: unsafe { &mut *self.data.get() },
@@ -118,6 +128,21 @@ impl<T: ?Sized> Mutex<T> {
         }
     }
 
+    /// Creates a guard for the [`Mutex`] locked by the current task, without
+    /// locking it again.
+    ///
+    /// # Safety
+    ///
+    /// The mutex must be locked by the current task, usually with the guard
+    /// forgotten by [`core::mem::forget`]. It's useful to expose the lock to
+    /// FFI.
+    pub unsafe fn make_guard_unchecked(&self) -> MutexGuard<T> {
+        MutexGuard {
+            lock: self,
+            data: &mut *self.data.get(),
+        }
+    }
+
     /// Force unlock the [`Mutex`].
     ///
     /// # Safety
@@ -126,16 +151,25 @@ impl<T: ?Sized> Mutex<T> {
     /// thread. However, this can be useful in some instances for exposing
     /// the lock to FFI that doesn’t know how to deal with RAII.
     pub unsafe fn force_unlock(&self) {
-        let owner_id = self.owner_id.swap(0, Ordering::Release);
-        assert_eq!(
-            owner_id,
-            current().id().as_u64(),
+        assert!(
+            self.pi.is_owned_by_current(),
             "{} tried to release mutex it doesn't own",
             current().id_name()
         );
+        // Release it before waking up the waiters, which take other locks.
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock_addr());
+        self.pi.unlock();
         self.wq.notify_one(true);
     }
 
+    /// Returns the address that identifies this lock in [`lockdep`].
+    #[cfg(feature = "lockdep")]
+    #[inline(always)]
+    fn lock_addr(&self) -> *const () {
+        self as *const Self as *const ()
+    }
+
     /// Returns a mutable reference to the underlying data.
     ///
     /// Since this call borrows the [`Mutex`] mutably, and a mutable reference is guaranteed to be exclusive in
@@ -151,6 +185,7 @@ impl<T: ?Sized> Mutex<T> {
 
 impl<T: ?Sized + Default> Default for Mutex<T> {
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn default() -> Self {
         Self::new(Default::default())
     }
@@ -198,12 +233,19 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::Mutex;
     use axtask as thread;
-    use std::sync::Once;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Mutex as StdMutex, Once};
 
     static INIT: Once = Once::new();
+    /// Serializes the tests, as they share the global scheduler.
+    pub(crate) static SERIAL: StdMutex<()> = StdMutex::new(());
+
+    pub(crate) fn init() {
+        INIT.call_once(thread::init_scheduler);
+    }
 
     fn may_interrupt() {
         // simulate interrupts
@@ -214,6 +256,7 @@ mod tests {
 
     #[test]
     fn lots_and_lots() {
+        let _lock = SERIAL.lock();
         INIT.call_once(thread::init_scheduler);
 
         const NUM_TASKS: u32 = 10;
@@ -249,4 +292,81 @@ mod tests {
         assert_eq!(*M.lock(), NUM_ITERS * NUM_TASKS * 3);
         println!("Mutex test OK");
     }
+    #[test]
+    fn priority_inheritance() {
+        let _lock = SERIAL.lock();
+        INIT.call_once(thread::init_scheduler);
+
+        static M1: Mutex<()> = Mutex::new(());
+        static M2: Mutex<()> = Mutex::new(());
+        static STAGE: AtomicUsize = AtomicUsize::new(0);
+        static BOOSTED_PRIO: AtomicUsize = AtomicUsize::new(0);
+        static RESTORED_PRIO: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+        fn wait_stage(stage: usize) {
+            while STAGE.load(Ordering::Acquire) < stage {
+                thread::yield_now();
+            }
+        }
+
+        // `low` holds M1, `mid` holds M2 and waits for M1, and the main task
+        // with a real-time priority waits for M2.
+        let low = thread::spawn(|| {
+            let guard = M1.lock();
+            STAGE.store(1, Ordering::Release);
+            wait_stage(3);
+            BOOSTED_PRIO.store(thread::current().sched_priority(), Ordering::Release);
+            drop(guard);
+            RESTORED_PRIO.store(thread::current().sched_priority(), Ordering::Release);
+        });
+        wait_stage(1);
+        let mid = thread::spawn(|| {
+            let _guard2 = M2.lock();
+            STAGE.store(2, Ordering::Release);
+            let _guard1 = M1.lock();
+        });
+        wait_stage(2);
+        thread::yield_now(); // let `mid` block on M1
+
+        let curr = thread::current();
+        assert!(thread::set_sched_policy(
+            curr.as_task_ref(),
+            thread::SchedPolicy::Fifo,
+            10
+        ));
+        STAGE.store(3, Ordering::Release);
+        drop(M2.lock());
+        assert!(thread::set_sched_policy(
+            curr.as_task_ref(),
+            thread::SchedPolicy::Normal,
+            0
+        ));
+
+        low.join();
+        mid.join();
+        assert_eq!(BOOSTED_PRIO.load(Ordering::Acquire), 10);
+        assert_eq!(RESTORED_PRIO.load(Ordering::Acquire), 0);
+        println!("Mutex priority inheritance test OK");
+    }
+
+    #[test]
+    fn forget_guard() {
+        let _lock = SERIAL.lock();
+        INIT.call_once(thread::init_scheduler);
+
+        let curr = thread::current();
+        let refs = std::sync::Arc::strong_count(curr.as_task_ref());
+        let m = Mutex::new(0);
+        core::mem::forget(m.lock());
+        assert!(m.is_locked());
+        assert!(m.try_lock().is_none());
+        // The lock holds a reference to its owner, released on drop.
+        drop(m);
+        assert_eq!(std::sync::Arc::strong_count(curr.as_task_ref()), refs);
+
+        // The owner does not refer to the dropped lock.
+        let m = Mutex::new(0);
+        *m.lock() += 1;
+        assert_eq!(*m.lock(), 1);
+    }
 }
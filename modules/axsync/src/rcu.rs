@@ -0,0 +1,169 @@
+//! A pointer protected by read-copy-update (RCU).
+
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use axtask::{rcu_read_lock, RcuReadGuard};
+
+/// A pointer to a value protected by RCU, like the `__rcu` pointers in Linux.
+///
+/// Readers get the current value by [`read`](Self::read) without locking,
+/// and writers publish a new value by [`replace`](Self::replace), then the
+/// old value is dropped after all its readers are done.
+///
+/// Writers are not serialized with each other. If the new value is computed
+/// from the current one, the writers must hold a lock, such as a
+/// [`Mutex`](crate::Mutex), otherwise concurrent updates may be lost.
+pub struct RcuCell<T> {
+    ptr: AtomicPtr<T>,
+    _marker: PhantomData<Box<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for RcuCell<T> {}
+unsafe impl<T: Send + Sync> Sync for RcuCell<T> {}
+
+/// A reference to the value of an [`RcuCell`], in an RCU read-side critical
+/// section.
+///
+/// The value can not be dropped until the reference is dropped. The task
+/// must not block while holding it.
+pub struct RcuRef<'a, T> {
+    data: &'a T,
+    _guard: RcuReadGuard,
+}
+
+impl<T> RcuCell<T> {
+    /// Creates a new [`RcuCell`] holding the given value.
+    pub fn new(data: T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(data))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the current value.
+    pub fn read(&self) -> RcuRef<'_, T> {
+        let guard = rcu_read_lock();
+        // Safety: the value is not dropped until the read-side critical
+        // section ends.
+        let data = unsafe { &*self.ptr.load(Ordering::Acquire) };
+        RcuRef {
+            data,
+            _guard: guard,
+        }
+    }
+
+    /// Returns a mutable reference to the current value.
+    ///
+    /// There are no readers since it borrows the cell mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ptr.load(Ordering::Relaxed) }
+    }
+
+    /// Publishes a new value, and drops the old value after a grace period.
+    ///
+    /// It blocks until the readers of the old value are done, so it must not
+    /// be called in an RCU read-side critical section.
+    pub fn replace(&self, data: T) {
+        let old = self.swap(data);
+        axtask::synchronize_rcu();
+        drop(old);
+    }
+
+    /// Publishes a new value, and drops the old value in the background after
+    /// a grace period, without blocking.
+    pub fn replace_deferred(&self, data: T)
+    where
+        T: Send + 'static,
+    {
+        let old = self.swap(data);
+        axtask::call_rcu(move || drop(old));
+    }
+
+    fn swap(&self, data: T) -> Box<T> {
+        let new = Box::into_raw(Box::new(data));
+        unsafe { Box::from_raw(self.ptr.swap(new, Ordering::AcqRel)) }
+    }
+}
+
+impl<T> Drop for RcuCell<T> {
+    fn drop(&mut self) {
+        // There are no readers since the cell is not borrowed.
+        drop(unsafe { Box::from_raw(*self.ptr.get_mut()) });
+    }
+}
+
+impl<T> Deref for RcuRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Mutex, RcuCell};
+    use axtask as thread;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Version(usize);
+
+    impl Drop for Version {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn readers_and_writers() {
+        let _lock = crate::mutex::tests::SERIAL.lock();
+        crate::mutex::tests::init();
+
+        const NUM_READERS: usize = 4;
+        const NUM_WRITERS: usize = 2;
+        const NUM_UPDATES: usize = 100;
+        static WRITE_LOCK: Mutex<()> = Mutex::new(());
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        let cell: &'static RcuCell<Version> = Box::leak(Box::new(RcuCell::new(Version(0))));
+        for _ in 0..NUM_READERS {
+            thread::spawn(move || {
+                let mut last = 0;
+                while last < NUM_WRITERS * NUM_UPDATES {
+                    let version = cell.read();
+                    // Versions are published in order.
+                    assert!(version.0 >= last);
+                    last = version.0;
+                    drop(version);
+                    thread::yield_now();
+                }
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        for i in 0..NUM_WRITERS {
+            thread::spawn(move || {
+                for _ in 0..NUM_UPDATES {
+                    let _guard = WRITE_LOCK.lock();
+                    let next = Version(cell.read().0 + 1);
+                    if i % 2 == 0 {
+                        cell.replace(next);
+                    } else {
+                        cell.replace_deferred(next);
+                    }
+                }
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        while FINISHED.load(Ordering::Relaxed) < NUM_READERS + NUM_WRITERS {
+            thread::yield_now();
+        }
+        thread::rcu_barrier();
+        assert_eq!(cell.read().0, NUM_WRITERS * NUM_UPDATES);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), NUM_WRITERS * NUM_UPDATES);
+    }
+}
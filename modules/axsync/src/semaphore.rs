@@ -0,0 +1,144 @@
+//! A counting semaphore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+/// A counting semaphore.
+///
+/// It maintains a count of available permits. [`acquire`](Self::acquire)
+/// blocks the current task until a permit is available and takes it, and
+/// [`release`](Self::release) gives a permit back.
+pub struct Semaphore {
+    count: AtomicUsize,
+    wq: WaitQueue,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    /// Returns the number of available permits.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Acquires a permit, blocking the current task until one is available.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            self.wq.wait_until(|| self.count() > 0);
+        }
+    }
+
+    /// Tries to acquire a permit without blocking.
+    ///
+    /// Returns `false` if no permit is available.
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Acquires a permit, blocking the current task until one is available or
+    /// the given duration has elapsed.
+    ///
+    /// Returns `false` if it timed out. Without the `irq` feature, the timeout
+    /// is ignored.
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        #[cfg(feature = "irq")]
+        {
+            let deadline = axhal::time::current_time() + dur;
+            while !self.try_acquire() {
+                let now = axhal::time::current_time();
+                if now >= deadline
+                    || self
+                        .wq
+                        .wait_timeout_until(deadline - now, || self.count() > 0)
+                {
+                    return self.try_acquire();
+                }
+            }
+            true
+        }
+        #[cfg(not(feature = "irq"))]
+        {
+            let _ = dur;
+            self.acquire();
+            true
+        }
+    }
+
+    /// Releases a permit, and wakes up a task waiting for it.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Semaphore;
+    use axtask as thread;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn bounded_concurrency() {
+        let _lock = crate::mutex::tests::SERIAL.lock();
+        crate::mutex::tests::init();
+
+        const NUM_TASKS: usize = 10;
+        const NUM_PERMITS: usize = 3;
+        static SEM: Semaphore = Semaphore::new(NUM_PERMITS);
+        static INSIDE: AtomicUsize = AtomicUsize::new(0);
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| {
+                for _ in 0..100 {
+                    SEM.acquire();
+                    let inside = INSIDE.fetch_add(1, Ordering::Relaxed) + 1;
+                    assert!(inside <= NUM_PERMITS);
+                    thread::yield_now();
+                    INSIDE.fetch_sub(1, Ordering::Relaxed);
+                    SEM.release();
+                }
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        while FINISHED.load(Ordering::Relaxed) < NUM_TASKS {
+            thread::yield_now();
+        }
+        assert_eq!(SEM.count(), NUM_PERMITS);
+    }
+
+    #[test]
+    fn cancel_waiter() {
+        let _lock = crate::mutex::tests::SERIAL.lock();
+        crate::mutex::tests::init();
+
+        static SEM: Semaphore = Semaphore::new(0);
+        static ACQUIRED: AtomicUsize = AtomicUsize::new(0);
+
+        let waiter = thread::spawn(|| {
+            SEM.acquire();
+            ACQUIRED.fetch_add(1, Ordering::Relaxed);
+        });
+        thread::yield_now(); // let the waiter block
+        assert!(thread::cancel(&waiter));
+        assert!(!thread::cancel(&waiter));
+        assert_eq!(waiter.join(), Some(thread::EXIT_CANCELED));
+        assert_eq!(ACQUIRED.load(Ordering::Relaxed), 0);
+
+        // the canceled waiter has left the queue, the permit is not taken.
+        SEM.release();
+        assert!(SEM.try_acquire());
+    }
+}
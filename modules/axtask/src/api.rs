@@ -2,31 +2,80 @@
 
 use alloc::{string::String, sync::Arc};
 
-pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
+pub(crate) use crate::run_queue::{current_run_queue, AxRunQueue};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::futex::{futex_requeue, futex_wait, futex_wake, FutexError};
+#[cfg(feature = "irq")]
+#[doc(cfg(all(feature = "multitask", feature = "irq")))]
+pub use crate::hrtimer::{HrTimer, HrTimerMode};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::pi::PiState;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::rcu::{call_rcu, rcu_barrier, rcu_read_lock, synchronize_rcu, RcuReadGuard};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::sched_class::SchedPolicy;
+#[cfg(feature = "irq")]
+#[doc(cfg(all(feature = "multitask", feature = "irq")))]
+pub use crate::softirq::{
+    do_softirq, in_softirq, irq_enter, irq_exit, raise_softirq, register_softirq,
+    unregister_softirq, SoftIrqHandler, BLOCK_SOFTIRQ, HI_SOFTIRQ, NET_RX_SOFTIRQ, NET_TX_SOFTIRQ,
+    NR_SOFTIRQS,
+};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::stat::{account_irq_time, total_stat, TaskStat};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{CurrentTask, SpawnError, TaskId, TaskInner};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::wait_queue::WaitQueue;
+#[cfg(feature = "watchdog")]
+#[doc(cfg(feature = "watchdog"))]
+pub use crate::watchdog::{
+    set_hung_task_check, set_hung_task_timeout, set_soft_lockup_threshold, set_watchdog_panic,
+};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::workqueue::{Work, WorkQueue};
+#[doc(cfg(feature = "multitask"))]
+pub use scheduler::{RT_PRIO_MAX, RT_PRIO_MIN};
+
+/// The exit code of the tasks that exit because they are canceled by
+/// [`cancel`].
+pub const EXIT_CANCELED: i32 = i32::MIN;
 
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
 
+/// The CPU affinity mask of a task. Each bit indicates whether the task is
+/// allowed to run on the CPU with the corresponding ID.
+pub type AxCpuMask = bitmaps::Bitmap<{ axconfig::SMP }>;
+
 cfg_if::cfg_if! {
-    if #[cfg(feature = "sched_rr")] {
+    if #[cfg(feature = "sched_edf")] {
+        pub(crate) type AxTask = scheduler::EDFTask<TaskInner>;
+        pub(crate) type NormalScheduler = scheduler::EDFScheduler<TaskInner>;
+    } else if #[cfg(feature = "sched_rr")] {
         const MAX_TIME_SLICE: usize = 5;
         pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
-        pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
+        pub(crate) type NormalScheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
     } else if #[cfg(feature = "sched_cfs")] {
         pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
+        pub(crate) type NormalScheduler = scheduler::CFScheduler<TaskInner>;
+    } else if #[cfg(feature = "sched_mlfq")] {
+        const MLFQ_LEVELS: usize = 8;
+        const MLFQ_BASE_SLICE: usize = 2;
+        const MLFQ_BOOST_PERIOD: usize = 500;
+        pub(crate) type AxTask = scheduler::MLFQTask<TaskInner>;
+        pub(crate) type NormalScheduler =
+            scheduler::MLFQScheduler<TaskInner, MLFQ_LEVELS, MLFQ_BASE_SLICE, MLFQ_BOOST_PERIOD>;
     } else {
         // If no scheduler features are set, use FIFO as the default.
         pub(crate) type AxTask = scheduler::FifoTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::FifoScheduler<TaskInner>;
+        pub(crate) type NormalScheduler = scheduler::FifoScheduler<TaskInner>;
     }
 }
 
+pub(crate) type Scheduler = crate::sched_class::ClassScheduler;
+
 #[cfg(feature = "preempt")]
 struct KernelGuardIfImpl;
 
@@ -46,6 +95,21 @@ impl kernel_guard::KernelGuardIf for KernelGuardIfImpl {
     }
 }
 
+#[cfg(feature = "lockdep")]
+struct LockdepIfImpl;
+
+#[cfg(feature = "lockdep")]
+#[crate_interface::impl_interface]
+impl lockdep::LockdepIf for LockdepIfImpl {
+    fn current_held_locks() -> *mut lockdep::HeldLocks {
+        current_may_uninit().map_or(core::ptr::null_mut(), |curr| curr.held_locks_ptr())
+    }
+
+    fn irqs_enabled() -> bool {
+        axhal::arch::irqs_enabled()
+    }
+}
+
 /// Gets the current task, or returns [`None`] if the current task is not
 /// initialized.
 pub fn current_may_uninit() -> Option<CurrentTask> {
@@ -66,8 +130,13 @@ pub fn init_scheduler() {
     info!("Initialize scheduling...");
 
     crate::run_queue::init();
+    crate::rcu::init_percpu();
+    crate::rcu::init();
     #[cfg(feature = "irq")]
-    crate::timers::init();
+    {
+        crate::timers::init();
+        crate::softirq::init_percpu();
+    }
 
     info!("  use {} scheduler.", Scheduler::scheduler_name());
 }
@@ -75,6 +144,12 @@ pub fn init_scheduler() {
 /// Initializes the task scheduler for secondary CPUs.
 pub fn init_scheduler_secondary() {
     crate::run_queue::init_secondary();
+    crate::rcu::init_percpu();
+    #[cfg(feature = "irq")]
+    {
+        crate::timers::init();
+        crate::softirq::init_percpu();
+    }
 }
 
 /// Handles periodic timer ticks for the task manager.
@@ -84,19 +159,73 @@ pub fn init_scheduler_secondary() {
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
     crate::timers::check_events();
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    crate::rcu::timer_tick();
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::check();
+    #[cfg(feature = "tickless")]
+    if !crate::timers::update_tick() {
+        crate::timers::reprogram();
+        return;
+    }
+    current_run_queue().scheduler_timer_tick();
+    #[cfg(feature = "tickless")]
+    crate::timers::reprogram();
 }
 
 /// Spawns a new task with the given parameters.
 ///
 /// Returns the task reference.
+///
+/// # Panics
+///
+/// Panics if there are already [`axconfig::MAX_TASKS`] tasks. Use
+/// [`try_spawn_raw`] to handle it.
 pub fn spawn_raw<F>(f: F, name: String, stack_size: usize) -> AxTaskRef
 where
     F: FnOnce() + Send + 'static,
 {
-    let task = TaskInner::new(f, name, stack_size);
-    RUN_QUEUE.lock().add_task(task.clone());
-    task
+    match try_spawn_raw(f, name, stack_size) {
+        Ok(task) => task,
+        Err(_) => panic!("too many tasks, at most {}", axconfig::MAX_TASKS),
+    }
+}
+
+/// Spawns a new task with the given parameters.
+///
+/// Returns the task reference, or [`SpawnError::TooManyTasks`] if there are
+/// already [`axconfig::MAX_TASKS`] tasks.
+pub fn try_spawn_raw<F>(f: F, name: String, stack_size: usize) -> Result<AxTaskRef, SpawnError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let task = TaskInner::new(f, name, stack_size)?;
+    crate::run_queue::spawn_task(task.clone());
+    Ok(task)
+}
+
+/// Spawns a new task with the given parameters, which is only allowed to run
+/// on the CPUs in `cpumask`.
+///
+/// The mask is set before the task is put into a run queue, so it never runs
+/// on other CPUs. Returns [`SpawnError::EmptyCpuMask`] if the mask is empty,
+/// or [`SpawnError::TooManyTasks`] if there are already
+/// [`axconfig::MAX_TASKS`] tasks.
+pub fn spawn_raw_with_affinity<F>(
+    f: F,
+    name: String,
+    stack_size: usize,
+    cpumask: AxCpuMask,
+) -> Result<AxTaskRef, SpawnError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    if cpumask.is_empty() {
+        return Err(SpawnError::EmptyCpuMask);
+    }
+    let task = TaskInner::new(f, name, stack_size)?;
+    task.set_cpumask(cpumask);
+    crate::run_queue::spawn_task(task.clone());
+    Ok(task)
 }
 
 /// Spawns a new task with the default parameters.
@@ -105,6 +234,11 @@ where
 /// [`axconfig::TASK_STACK_SIZE`].
 ///
 /// Returns the task reference.
+///
+/// # Panics
+///
+/// Panics if there are already [`axconfig::MAX_TASKS`] tasks. Use
+/// [`try_spawn`] to handle it.
 pub fn spawn<F>(f: F) -> AxTaskRef
 where
     F: FnOnce() + Send + 'static,
@@ -112,6 +246,17 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
+/// Spawns a new task with the default parameters, like [`spawn`].
+///
+/// Returns the task reference, or [`SpawnError::TooManyTasks`] if there are
+/// already [`axconfig::MAX_TASKS`] tasks.
+pub fn try_spawn<F>(f: F) -> Result<AxTaskRef, SpawnError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    try_spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
+}
+
 /// Set the priority for current task.
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
@@ -122,13 +267,158 @@ where
 ///
 /// [CFS]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
 pub fn set_priority(prio: isize) -> bool {
-    RUN_QUEUE.lock().set_current_priority(prio)
+    crate::pi::set_current_priority(prio)
+}
+
+/// Reserves `runtime` of CPU time in every `period` for the current task,
+/// which should be finished within `deadline` after each period starts.
+///
+/// The current task becomes a real-time task scheduled by the EDF scheduler.
+/// The durations are rounded up to timer ticks.
+///
+/// The bandwidth is reserved on the first CPU allowed by the task's affinity
+/// mask that is not overloaded by it, and the task only runs on that CPU until
+/// [`clear_deadline_params`] is called.
+///
+/// Returns `false` if the parameters do not satisfy
+/// `0 < runtime <= deadline <= period`, or the reservation is rejected by the
+/// admission control since all allowed CPUs would be overloaded.
+#[cfg(feature = "sched_edf")]
+pub fn set_deadline_params(
+    runtime: core::time::Duration,
+    deadline: core::time::Duration,
+    period: core::time::Duration,
+) -> bool {
+    fn to_ticks(dur: core::time::Duration) -> u64 {
+        let nanos_per_tick = axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+        (dur.as_nanos() as u64).div_ceil(nanos_per_tick)
+    }
+    let params = scheduler::EDFParams::new(to_ticks(runtime), to_ticks(deadline), to_ticks(period));
+    current_run_queue().set_current_deadline_params(Some(params))
+}
+
+/// Releases the CPU bandwidth reserved by [`set_deadline_params`], and turns
+/// the current task back into a best-effort task.
+#[cfg(feature = "sched_edf")]
+pub fn clear_deadline_params() {
+    current_run_queue().set_current_deadline_params(None);
+}
+
+/// Sets the scheduling policy and the priority of the given task.
+///
+/// For the real-time policies ([`SchedPolicy::Fifo`] and
+/// [`SchedPolicy::RoundRobin`]), the priority ranges from [`RT_PRIO_MIN`] to
+/// [`RT_PRIO_MAX`], higher values mean higher priority. For other policies,
+/// the priority must be 0.
+///
+/// If the task is ready, it's moved to the scheduling class of the new policy
+/// immediately. The running task may be preempted at the next timer tick.
+///
+/// Returns `false` if the priority is invalid.
+pub fn set_sched_policy(task: &AxTaskRef, policy: SchedPolicy, prio: usize) -> bool {
+    let valid = if policy.is_realtime() {
+        (RT_PRIO_MIN..=RT_PRIO_MAX).contains(&prio)
+    } else {
+        prio == 0
+    };
+    if !valid || task.is_idle() {
+        return false;
+    }
+    crate::pi::set_base_policy(task, policy, prio);
+    true
+}
+
+/// Sets the CPU affinity mask of the given task.
+///
+/// If the task is the current task and the current CPU is not in the new mask,
+/// it will be migrated to an allowed CPU immediately. Otherwise, the new mask
+/// takes effect the next time the task is scheduled.
+///
+/// Returns `false` if the mask is empty, or it excludes the CPU whose EDF
+/// bandwidth is reserved by the task.
+pub fn set_affinity(task: &AxTaskRef, cpumask: AxCpuMask) -> bool {
+    if cpumask.is_empty() {
+        return false;
+    }
+    #[cfg(feature = "sched_edf")]
+    if task.edf_cpu().is_some_and(|cpu_id| !cpumask.get(cpu_id)) {
+        return false;
+    }
+    task.set_cpumask(cpumask);
+    if current().ptr_eq(task) {
+        current_run_queue().migrate_current();
+    }
+    true
+}
+
+/// Requests the given task to exit.
+///
+/// The cancellation is deferred: the task exits with [`EXIT_CANCELED`] when
+/// it reaches a cancellation point, i.e., when it waits on a [`WaitQueue`]
+/// or a futex, sleeps, or calls [`test_cancel`]. If it's blocked at a
+/// cancellation point, it's woken up immediately. Its joiners are woken up
+/// with the exit code as usual.
+///
+/// The task exits without returning from its functions, so the destructors
+/// of the objects on its stack are not run. To not leave any lock locked
+/// forever, the cancellation is deferred while the task holds sleeping locks
+/// (see [`sleeping_lock_acquired`]), until they are all released.
+///
+/// Returns `false` if the task is an idle task, or it has already been
+/// canceled.
+pub fn cancel(task: &AxTaskRef) -> bool {
+    if task.is_idle() || !task.set_cancel_pending() {
+        return false;
+    }
+    debug!("task cancel: {}", task.id_name());
+    // Pairs with the fence after the task is marked as blocked, see
+    // `AxRunQueue::block_current()`.
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    if task.is_canceled() {
+        current_run_queue().unblock_task(task.clone(), true);
+    }
+    true
+}
+
+/// Exits the current task with [`EXIT_CANCELED`] if it has been canceled by
+/// [`cancel`] and the cancellation is enabled.
+pub fn test_cancel() {
+    if current().is_canceled() {
+        exit(EXIT_CANCELED);
+    }
+}
+
+/// Records that the current task has acquired a sleeping lock, such as a
+/// mutex.
+///
+/// The task is not canceled by [`cancel`] until it releases all the sleeping
+/// locks, by calling [`sleeping_lock_released`] for each of them. Locks
+/// based on [`PiState`] are counted by it already.
+pub fn sleeping_lock_acquired() {
+    current().count_sleeping_lock(true);
+}
+
+/// Records that the current task has released a sleeping lock, see
+/// [`sleeping_lock_acquired`].
+pub fn sleeping_lock_released() {
+    current().count_sleeping_lock(false);
+}
+
+/// Enables or disables the cancellation of the current task, and returns
+/// whether it was enabled.
+///
+/// A disabled task is not woken up or exited by [`cancel`], and the request
+/// is deferred until it's enabled again and reaches a cancellation point.
+/// It's useful to protect the waits that must not be interrupted, such as
+/// acquiring a lock.
+pub fn set_cancel_enabled(enabled: bool) -> bool {
+    current().set_cancel_enabled(enabled)
 }
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
-    RUN_QUEUE.lock().yield_current();
+    current_run_queue().yield_current();
 }
 
 /// Current task is going to sleep for the given duration.
@@ -140,17 +430,29 @@ pub fn sleep(dur: core::time::Duration) {
 
 /// Current task is going to sleep, it will be woken up at the given deadline.
 ///
-/// If the feature `irq` is not enabled, it uses busy-wait instead.
+/// It's a cancellation point, see [`cancel`]. If the feature `irq` is not
+/// enabled, it uses busy-wait instead.
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
     #[cfg(feature = "irq")]
-    RUN_QUEUE.lock().sleep_until(deadline);
+    loop {
+        current_run_queue().sleep_until(deadline);
+        let curr = current();
+        if !curr.in_timer_list() {
+            break;
+        }
+        // Woken up before the deadline by `cancel()`. It's spurious if the
+        // cancellation has just been disabled, then sleep again.
+        crate::timers::cancel_alarm(curr.as_task_ref());
+        test_cancel();
+    }
     #[cfg(not(feature = "irq"))]
     axhal::time::busy_wait_until(deadline);
+    test_cancel();
 }
 
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
-    RUN_QUEUE.lock().exit_current(exit_code)
+    current_run_queue().exit_current(exit_code)
 }
 
 /// The idle task routine.
@@ -161,6 +463,15 @@ pub fn run_idle() -> ! {
         yield_now();
         debug!("idle task: waiting for IRQs...");
         #[cfg(feature = "irq")]
-        axhal::arch::wait_for_irqs();
+        {
+            // Check for new tasks with IRQs disabled, so that the IRQ which
+            // wakes up a task or the reschedule IPI always wakes up the CPU.
+            axhal::arch::disable_irqs();
+            if crate::run_queue::has_pending_tasks() {
+                axhal::arch::enable_irqs();
+            } else {
+                axhal::arch::enable_irqs_and_wait();
+            }
+        }
     }
 }
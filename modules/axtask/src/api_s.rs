@@ -20,3 +20,34 @@ pub fn sleep(dur: core::time::Duration) {
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
     axhal::time::busy_wait_until(deadline);
 }
+
+/// A guard of an RCU read-side critical section.
+///
+/// For single-task situation, there is nothing to do.
+pub struct RcuReadGuard {
+    _not_send: core::marker::PhantomData<*const ()>,
+}
+
+/// Starts an RCU read-side critical section, which ends when the returned
+/// guard is dropped.
+pub fn rcu_read_lock() -> RcuReadGuard {
+    RcuReadGuard {
+        _not_send: core::marker::PhantomData,
+    }
+}
+
+/// For single-task situation, no read-side critical section can run
+/// concurrently, so the grace period has elapsed already.
+pub fn synchronize_rcu() {}
+
+/// For single-task situation, we just call `f` after the grace period, i.e.,
+/// immediately.
+pub fn call_rcu<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    f()
+}
+
+/// For single-task situation, all callbacks have run already.
+pub fn rcu_barrier() {}
@@ -0,0 +1,138 @@
+//! Fast userspace mutexes (futexes) keyed by address.
+//!
+//! Tasks waiting on a futex are put into one of a fixed number of wait
+//! queues, chosen by hashing the address of the futex word. Each waiting
+//! task records the address it's waiting on, so that tasks waiting on
+//! different futexes in the same bucket can be told apart.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use crate::WaitQueue;
+
+/// The number of wait queue buckets.
+const FUTEX_BUCKETS: usize = 64;
+
+static FUTEX_QUEUES: [WaitQueue; FUTEX_BUCKETS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: WaitQueue = WaitQueue::new();
+    [EMPTY; FUTEX_BUCKETS]
+};
+
+/// The error type of [`futex_wait`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The futex word does not contain the expected value.
+    WouldBlock,
+    /// The timeout expired before the task was woken up.
+    TimedOut,
+}
+
+fn futex_addr(futex: &AtomicU32) -> usize {
+    futex as *const AtomicU32 as usize
+}
+
+fn futex_queue(addr: usize) -> &'static WaitQueue {
+    // futex words are 4-byte aligned, ignore the low bits.
+    &FUTEX_QUEUES[(addr >> 2) % FUTEX_BUCKETS]
+}
+
+/// Blocks the current task on the futex if it still contains the `expected`
+/// value, until it's woken up by [`futex_wake`], or the `timeout` has elapsed.
+///
+/// The value is checked atomically with respect to the wakers, so a wake up
+/// after the futex is changed will not be missed. Without the `irq` feature,
+/// the timeout is ignored.
+pub fn futex_wait(
+    futex: &AtomicU32,
+    expected: u32,
+    timeout: Option<Duration>,
+) -> Result<(), FutexError> {
+    let curr = crate::current();
+    let addr = futex_addr(futex);
+    let deadline = timeout.map(|dur| axhal::time::current_time() + dur);
+    debug!(
+        "task futex_wait: {} addr={:#x} deadline={:?}",
+        curr.id_name(),
+        addr,
+        deadline
+    );
+
+    curr.set_futex_addr(addr);
+    let blocked =
+        futex_queue(addr).block_current_if(|| futex.load(Ordering::SeqCst) == expected, deadline);
+    if !blocked {
+        curr.set_futex_addr(0);
+        return Err(FutexError::WouldBlock);
+    }
+
+    // Still in the wait queue, must have timed out. The task may have been
+    // requeued to another futex, so look it up by the current address, which
+    // is checked again with the queue locked.
+    let mut timed_out = false;
+    while curr.in_wait_queue() {
+        let addr = curr.futex_addr();
+        if let Some(removed) =
+            futex_queue(addr).remove_task_if(curr.as_task_ref(), || curr.futex_addr() == addr)
+        {
+            timed_out = removed;
+            break;
+        }
+    }
+    #[cfg(feature = "irq")]
+    if curr.in_timer_list() {
+        // timeout was set but not triggered (woken up by `futex_wake()`)
+        crate::timers::cancel_alarm(curr.as_task_ref());
+    }
+    curr.set_futex_addr(0);
+
+    // Waiting on a futex is a cancellation point. Pass on the wake up to
+    // another waiter if the task has been woken up by `futex_wake()`.
+    if curr.is_canceled() {
+        if !timed_out {
+            futex_wake(futex, 1);
+        }
+        crate::exit(crate::EXIT_CANCELED);
+    }
+
+    if timed_out {
+        Err(FutexError::TimedOut)
+    } else {
+        Ok(())
+    }
+}
+
+/// Wakes up at most `count` tasks waiting on the futex.
+///
+/// Returns the number of tasks woken up.
+pub fn futex_wake(futex: &AtomicU32, count: usize) -> usize {
+    let addr = futex_addr(futex);
+    futex_queue(addr).notify_matching(count, true, |task| task.futex_addr() == addr)
+}
+
+/// Wakes up at most `wake_count` tasks waiting on the futex, and moves at
+/// most `requeue_count` of the remaining waiters to wait on the `target`
+/// futex instead.
+///
+/// Returns the number of tasks woken up and the number of tasks requeued.
+pub fn futex_requeue(
+    futex: &AtomicU32,
+    wake_count: usize,
+    target: &AtomicU32,
+    requeue_count: usize,
+) -> (usize, usize) {
+    let addr = futex_addr(futex);
+    let target_addr = futex_addr(target);
+    let woken = futex_wake(futex, wake_count);
+    let mut requeued = 0;
+    futex_queue(addr).requeue(futex_queue(target_addr), |task| {
+        if requeued < requeue_count && task.futex_addr() == addr {
+            task.set_futex_addr(target_addr);
+            requeued += 1;
+            true
+        } else {
+            false
+        }
+    });
+    (woken, requeued)
+}
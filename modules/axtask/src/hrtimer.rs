@@ -0,0 +1,232 @@
+//! High-resolution timers.
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axhal::time::TimeValue;
+use spinlock::SpinNoIrq;
+
+use crate::timers::{cancel_timer, set_timer, AxTimerEvent, TimerRef};
+use crate::WaitQueue;
+
+/// Timers whose callbacks are waiting to run in the timer task.
+static DEFERRED_TIMERS: SpinNoIrq<VecDeque<Arc<HrTimerInner>>> = SpinNoIrq::new(VecDeque::new());
+
+static TIMER_TASK_WQ: WaitQueue = WaitQueue::new();
+
+static TIMER_TASK_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Where the callback of a [`HrTimer`] runs.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum HrTimerMode {
+    /// In the timer interrupt handler with IRQs disabled, right after the
+    /// timer expires. The callback must not block.
+    Irq,
+    /// In a dedicated task shared by all timers of this mode. The callback
+    /// can block, but it delays the callbacks of other timers.
+    Task,
+}
+
+struct HrTimerState {
+    /// Increased each time the timer is started or canceled, to recognize
+    /// the stale events in the timer lists.
+    seq: u64,
+    timer: Option<TimerRef>,
+    expires: Option<TimeValue>,
+    period: Option<Duration>,
+    /// Whether the callback is waiting to run in the timer task.
+    pending: bool,
+    /// The expirations missed since the callback runs last time.
+    missed: usize,
+    /// The expirations missed before the callback runs last time.
+    overrun: usize,
+}
+
+pub(crate) struct HrTimerInner {
+    mode: HrTimerMode,
+    callback: Box<dyn Fn() + Send + Sync>,
+    state: SpinNoIrq<HrTimerState>,
+}
+
+impl HrTimerInner {
+    fn disarm(&self, state: &mut HrTimerState) {
+        state.seq += 1;
+        if let Some(timer) = state.timer.take() {
+            cancel_timer(timer);
+        }
+        state.expires = None;
+        state.pending = false;
+    }
+
+    /// Handles an expired event of the timer set when the sequence number is
+    /// `seq`, in the timer interrupt handler.
+    pub(crate) fn expire(self: Arc<Self>, seq: u64, now: TimeValue) {
+        let mut state = self.state.lock();
+        if state.seq != seq {
+            // Canceled or restarted after the event expired.
+            return;
+        }
+        if let (Some(period), Some(expires)) = (state.period, state.expires) {
+            let mut next = expires + period;
+            if next <= now {
+                let missed = (now - next).as_nanos() / period.as_nanos() + 1;
+                next += Duration::from_nanos((period.as_nanos() * missed) as u64);
+                state.missed += missed as usize;
+            }
+            state.expires = Some(next);
+            state.timer = Some(set_timer(next, AxTimerEvent::HrTimer(self.clone(), seq)));
+        } else {
+            state.expires = None;
+            state.timer = None;
+        }
+
+        match self.mode {
+            HrTimerMode::Irq => {
+                state.overrun = core::mem::take(&mut state.missed);
+                drop(state);
+                (self.callback)();
+            }
+            HrTimerMode::Task => {
+                if state.pending {
+                    state.missed += 1;
+                } else {
+                    state.pending = true;
+                    DEFERRED_TIMERS.lock().push_back(self.clone());
+                    drop(state);
+                    TIMER_TASK_WQ.notify_one(false);
+                }
+            }
+        }
+    }
+
+    /// Runs the deferred callback in the timer task, unless the timer has
+    /// been canceled or restarted since then.
+    fn run_deferred(&self) {
+        let mut state = self.state.lock();
+        if !state.pending {
+            return;
+        }
+        state.pending = false;
+        state.overrun = core::mem::take(&mut state.missed);
+        drop(state);
+        (self.callback)();
+    }
+}
+
+/// A high-resolution timer, which calls a callback at a deadline with the
+/// nanosecond precision, and optionally periodically after that.
+///
+/// The timer is added to the timer list of the CPU where it's started, and
+/// it keeps firing on that CPU if it's periodic. With the `tickless` feature,
+/// the timer interrupt is programmed to the exact deadline. Otherwise, the
+/// timers are checked at each scheduler tick, so the callback may be delayed
+/// up to a tick.
+///
+/// The timer is canceled when it's dropped.
+pub struct HrTimer {
+    pub(crate) inner: Arc<HrTimerInner>,
+}
+
+impl HrTimer {
+    /// Creates a new timer that is not started. `callback` runs in the context
+    /// specified by `mode` each time the timer expires.
+    ///
+    /// The timer task is spawned the first time a timer with
+    /// [`HrTimerMode::Task`] is created, so it must not be called in IRQ
+    /// context.
+    pub fn new<F>(mode: HrTimerMode, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        if mode == HrTimerMode::Task && !TIMER_TASK_STARTED.swap(true, Ordering::AcqRel) {
+            let _task = crate::spawn_raw(
+                timer_task_entry,
+                "hrtimer".into(),
+                axconfig::TASK_STACK_SIZE,
+            );
+            #[cfg(feature = "watchdog")]
+            crate::watchdog::set_hung_task_check(&_task, false);
+        }
+        Self {
+            inner: Arc::new(HrTimerInner {
+                mode,
+                callback: Box::new(callback),
+                state: SpinNoIrq::new(HrTimerState {
+                    seq: 0,
+                    timer: None,
+                    expires: None,
+                    period: None,
+                    pending: false,
+                    missed: 0,
+                    overrun: 0,
+                }),
+            }),
+        }
+    }
+
+    /// Starts the timer to expire at `deadline`, then every `period` after
+    /// that if it's not [`None`] or zero.
+    ///
+    /// If the timer is already started, it's restarted with the new settings.
+    /// The deadline may have passed, then the timer expires immediately.
+    pub fn start(&self, deadline: TimeValue, period: Option<Duration>) {
+        let mut state = self.inner.state.lock();
+        self.inner.disarm(&mut state);
+        state.expires = Some(deadline);
+        state.period = period.filter(|p| !p.is_zero());
+        state.missed = 0;
+        state.overrun = 0;
+        let event = AxTimerEvent::HrTimer(self.inner.clone(), state.seq);
+        state.timer = Some(set_timer(deadline, event));
+    }
+
+    /// Cancels the timer, and the callback deferred to the timer task if it
+    /// has not run yet.
+    ///
+    /// It does not wait for the callback that is running. Returns `false` if
+    /// the timer is not started or has expired (for one-shot timers).
+    pub fn cancel(&self) -> bool {
+        let mut state = self.inner.state.lock();
+        let active = state.expires.is_some();
+        self.inner.disarm(&mut state);
+        active
+    }
+
+    /// Returns the time of the next expiration, or [`None`] if the timer is
+    /// not active.
+    pub fn expires(&self) -> Option<TimeValue> {
+        self.inner.state.lock().expires
+    }
+
+    /// Returns the period of the timer, or [`None`] if it's one-shot.
+    pub fn period(&self) -> Option<Duration> {
+        self.inner.state.lock().period
+    }
+
+    /// Returns the number of expirations missed before the last time the
+    /// callback runs, because the timer interrupt or the timer task was
+    /// delayed by more than a period.
+    pub fn overrun(&self) -> usize {
+        self.inner.state.lock().overrun
+    }
+}
+
+impl Drop for HrTimer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn timer_task_entry() {
+    loop {
+        TIMER_TASK_WQ.wait_until(|| !DEFERRED_TIMERS.lock().is_empty());
+        loop {
+            let timer = DEFERRED_TIMERS.lock().pop_front();
+            match timer {
+                Some(timer) => timer.run_deferred(),
+                None => break,
+            }
+        }
+    }
+}
@@ -4,6 +4,49 @@
 //! creation, scheduling, sleeping, termination, etc. The scheduler algorithm
 //! is configurable by cargo features.
 //!
+//! # Scheduling Classes
+//!
+//! Tasks are scheduled by their scheduling policies (`SchedPolicy`), set by
+//! `set_sched_policy`. Real-time tasks (`SCHED_FIFO` and `SCHED_RR`) always
+//! run before normal tasks, and idle class tasks (`SCHED_IDLE`) only run when
+//! there is no other ready task. Normal tasks (`SCHED_OTHER` and
+//! `SCHED_BATCH`) are scheduled by the scheduler selected by the `sched_*`
+//! features below.
+//!
+//! Sleeping locks can avoid priority inversion with [`PiState`]: the owner of
+//! a lock inherits the priorities of the tasks waiting for it, including
+//! those waiting for locks owned by the waiters, until it releases the lock.
+//!
+//! Tasks can also wait on futexes keyed by address ([`futex_wait`]), which are
+//! the building blocks of synchronization primitives in userspace libraries.
+//!
+//! A task can be requested to exit by another task with [`cancel`]. The
+//! request takes effect when the task waits or sleeps without holding any
+//! sleeping locks, and its joiners get the exit code [`EXIT_CANCELED`].
+//!
+//! Each CPU has its own timer list for sleeping tasks and high-resolution
+//! timers ([`HrTimer`]), whose callbacks run in IRQ context or in a task.
+//!
+//! Heavy work in interrupt handlers can be deferred to softirqs
+//! ([`raise_softirq`]), which run right after the handlers with IRQs enabled
+//! (or in the per-CPU `ksoftirqd` tasks if they are raised in tasks, or keep
+//! being raised), or to worker tasks of a [`WorkQueue`], which can block.
+//!
+//! Data that is read on hot paths and rarely updated can be protected by
+//! RCU ([`rcu_read_lock`] and [`synchronize_rcu`]), whose grace periods are
+//! detected from the quiescent states of each CPU in the scheduler.
+//!
+//! The CPU time, context switches and waiting time of each task are recorded
+//! at context switches, and can be queried by [`TaskInner::stat`].
+//!
+//! With the `watchdog` feature, CPUs that do not schedule for a long time
+//! with preemption disabled (soft lockups), and tasks blocked in wait queues
+//! for a long time (hung tasks) are reported with backtraces.
+//!
+//! With the `lockdep` feature, the locks held by each task are recorded for
+//! the [`lockdep`] validator. The run queue lock, which is held across
+//! context switches, is handed over to the next task.
+//!
 //! # Cargo Features
 //!
 //! - `multitask`: Enable multi-task support. If it's enabled, complex task
@@ -12,7 +55,25 @@
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
 //!    APIs can be used, such as [`sleep`], [`sleep_until`], and
 //!    [`WaitQueue::wait_timeout`].
+//! - `smp`: Enable SMP (symmetric multiprocessing) support. Each CPU has its
+//!    own run queue, and tasks are migrated between CPUs by load balancing.
+//!    Idle CPUs are woken up by reschedule IPIs when tasks are sent to them.
 //! - `preempt`: Enable preemptive scheduling.
+//! - `paging`: Protect task stacks with unmapped guard pages, so that stack
+//!    overflows are caught by page faults. The overflows are only reported
+//!    as such on x86_64, where page faults can be handled on a separate
+//!    stack; on riscv64 and aarch64, the trap entry faults again while saving
+//!    the trap frame on the overflowed stack. Without `paging`, a canary at
+//!    the bottom of each stack is checked on context switches.
+//! - `tickless`: Stop the periodic scheduler tick when the CPU is idle, and
+//!    program one-shot timers to fire exactly at the deadlines of timed
+//!    events. It also enables the `irq` feature.
+//! - `watchdog`: Detect soft lockups and hung tasks in the timer interrupt
+//!    handler, see [`set_soft_lockup_threshold`] and [`set_hung_task_timeout`].
+//!    It also enables the `multitask` and `irq` features.
+//! - `lockdep`: Record the locks held by each task, to check the lock
+//!    acquisition order with the [`lockdep`] validator. It also enables the
+//!    `multitask` feature.
 //! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
 //!   `multitask` feature if it is enabled. This feature is enabled by default,
 //!   and it can be overriden by other scheduler features.
@@ -20,10 +81,19 @@
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_edf`: Use the [Earliest Deadline First real-time scheduler][4].
+//!   Tasks can reserve CPU bandwidth by `set_deadline_params`. It also
+//!   enables the `multitask` and `preempt` features if it is enabled.
+//! - `sched_mlfq`: Use the [Multi-level feedback queue scheduler][5], which
+//!   favors interactive tasks over CPU-bound ones. The priority set by
+//!   `set_priority` is the highest queue level of the task. It also enables
+//!   the `multitask` and `preempt` features if it is enabled.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
 //! [3]: scheduler::CFScheduler
+//! [4]: scheduler::EDFScheduler
+//! [5]: scheduler::MLFQScheduler
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -35,13 +105,28 @@ cfg_if::cfg_if! {
         extern crate log;
         extern crate alloc;
 
+        mod pi;
+        mod rcu;
         mod run_queue;
+        mod sched_class;
+        mod stat;
         mod task;
         mod api;
+        mod futex;
         mod wait_queue;
+        mod workqueue;
 
+        #[cfg(feature = "irq")]
+        mod hrtimer;
+        #[cfg(feature = "irq")]
+        mod softirq;
         #[cfg(feature = "irq")]
         mod timers;
+        #[cfg(feature = "watchdog")]
+        mod watchdog;
+
+        #[cfg(test)]
+        mod tests;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
@@ -49,5 +134,6 @@ cfg_if::cfg_if! {
     } else {
         mod api_s;
         pub use self::api_s::{sleep, sleep_until, yield_now};
+        pub use self::api_s::{call_rcu, rcu_barrier, rcu_read_lock, synchronize_rcu, RcuReadGuard};
     }
 }
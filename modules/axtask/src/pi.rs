@@ -0,0 +1,344 @@
+//! Priority inheritance for sleeping locks.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spinlock::SpinNoIrq;
+
+use crate::sched_class::SchedParams;
+use crate::{current, current_run_queue, AxTask, AxTaskRef, SchedPolicy, RT_PRIO_MAX, RT_PRIO_MIN};
+
+/// The maximum length of lock chains to propagate priorities along. It also
+/// stops the propagation on deadlocks.
+const MAX_CHAIN_DEPTH: usize = 16;
+
+/// The bit in [`PiState::state`] set when the lock may have waiters. The owner
+/// must lock the waiters to release the lock while it's set.
+const HAS_WAITERS: usize = 1;
+
+/// The priority inheritance state of a task.
+///
+/// Lock order: the waiters of a [`PiState`] are locked before the states of
+/// the tasks, which are locked before the run queues.
+pub(crate) struct TaskPiState {
+    /// The scheduling parameters set by the user, without inheritance.
+    base: SchedParams,
+    /// The lock that the task is waiting for.
+    ///
+    /// It stays valid while it's set, since the task can not stop waiting
+    /// without clearing it with this state locked.
+    blocked_on: *const PiState,
+    /// The highest parameters among the waiters of each lock owned by the
+    /// task, keyed by the address of the lock. Only locks with waiters are
+    /// recorded.
+    inherited: BTreeMap<usize, SchedParams>,
+}
+
+impl TaskPiState {
+    pub const fn new(base: SchedParams) -> Self {
+        Self {
+            base,
+            blocked_on: ptr::null(),
+            inherited: BTreeMap::new(),
+        }
+    }
+
+    pub const fn base(&self) -> SchedParams {
+        self.base
+    }
+
+    /// The scheduling parameters with the highest priority among the base
+    /// parameters and the waiters of the owned locks.
+    fn effective_params(&self) -> SchedParams {
+        self.inherited
+            .values()
+            .copied()
+            .fold(self.base, SchedParams::max)
+    }
+}
+
+/// The ownership and the priority inheritance state of a sleeping lock.
+///
+/// It records the owner of the lock, and the waiters once there is
+/// contention. When a task waits for the lock, the owner inherits the
+/// priority of the task if it's higher, and so does the owner of the lock that
+/// the owner is waiting for, and so on. The owner gets its own priority back
+/// when it releases the lock.
+///
+/// Locking and unlocking without contention only update the owner atomically.
+pub struct PiState {
+    /// The owner task (as a pointer from [`Arc::into_raw`], whose reference
+    /// is held by the lock), ORed with [`HAS_WAITERS`].
+    state: AtomicUsize,
+    waiters: SpinNoIrq<BTreeMap<u64, AxTaskRef>>,
+}
+
+impl PiState {
+    /// Creates a new state for an unlocked lock.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            waiters: SpinNoIrq::new(BTreeMap::new()),
+        }
+    }
+
+    /// Whether the lock is owned by any task.
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & !HAS_WAITERS != 0
+    }
+
+    /// Whether the lock is owned by the current task.
+    #[inline]
+    pub fn is_owned_by_current(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & !HAS_WAITERS == current_ptr()
+    }
+
+    /// Tries to make the current task the owner of the lock. Returns `false`
+    /// if it's owned by another task.
+    ///
+    /// The current task inherits the priorities of the tasks already waiting
+    /// for the lock.
+    pub fn try_lock(&self) -> bool {
+        let curr = current_ptr();
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & !HAS_WAITERS != 0 {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state | curr,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(s) => state = s,
+            }
+        }
+        // The reference held by the lock, the task is alive as it's running.
+        unsafe { Arc::increment_strong_count(curr as *const AxTask) };
+        let curr = current();
+        curr.count_sleeping_lock(true);
+        if state & HAS_WAITERS != 0 {
+            let waiters = self.waiters.lock();
+            self.update_inherited(curr.as_task_ref(), &waiters);
+            drop(waiters);
+            update_chain(curr.as_task_ref());
+        }
+        true
+    }
+
+    /// Releases the lock owned by the current task, and drops the priorities
+    /// inherited from the waiters.
+    pub fn unlock(&self) {
+        let curr = current_ptr();
+        if self
+            .state
+            .compare_exchange(curr, 0, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            let curr = current();
+            let waiters = self.waiters.lock();
+            curr.pi_state().inherited.remove(&self.key());
+            // Keep the flag for the remaining waiters, the next owner will
+            // inherit their priorities.
+            let state = if waiters.is_empty() { 0 } else { HAS_WAITERS };
+            self.state.store(state, Ordering::Release);
+            drop(waiters);
+            update_chain(curr.as_task_ref());
+        }
+        current().count_sleeping_lock(false);
+        unsafe { Arc::decrement_strong_count(curr as *const AxTask) };
+    }
+
+    /// Adds the current task to the waiters before it blocks on the lock, the
+    /// owner inherits its priority if it's higher.
+    ///
+    /// Returns `false` if the lock is not owned any more, then the caller
+    /// should retry locking instead of blocking.
+    pub fn add_waiter(&self) -> bool {
+        let curr = current();
+        let mut waiters = self.waiters.lock();
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & HAS_WAITERS == 0 {
+            if state == 0 {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state | HAS_WAITERS,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(s) => state = s,
+            }
+        }
+        let Some(owner) = self.owner() else {
+            return false;
+        };
+        waiters.insert(curr.id().as_u64(), curr.clone());
+        curr.pi_state().blocked_on = self;
+        self.update_inherited(&owner, &waiters);
+        drop(waiters);
+        update_chain(&owner);
+        true
+    }
+
+    /// Removes the current task from the waiters after it's woken up.
+    pub fn remove_waiter(&self) {
+        let curr = current();
+        let mut waiters = self.waiters.lock();
+        waiters.remove(&curr.id().as_u64());
+        curr.pi_state().blocked_on = ptr::null();
+        if waiters.is_empty() {
+            let _ =
+                self.state
+                    .compare_exchange(HAS_WAITERS, 0, Ordering::Relaxed, Ordering::Relaxed);
+        }
+        let owner = self.owner();
+        if let Some(owner) = &owner {
+            self.update_inherited(owner, &waiters);
+        }
+        drop(waiters);
+        if let Some(owner) = owner {
+            update_chain(&owner);
+        }
+    }
+
+    /// The key of the lock in [`TaskPiState::inherited`].
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Returns the owner of the lock.
+    ///
+    /// The waiters must be locked with [`HAS_WAITERS`] set, so that the owner
+    /// can not release the lock meanwhile.
+    fn owner(&self) -> Option<AxTaskRef> {
+        let owner = (self.state.load(Ordering::Acquire) & !HAS_WAITERS) as *const AxTask;
+        (!owner.is_null()).then(|| unsafe {
+            Arc::increment_strong_count(owner);
+            Arc::from_raw(owner)
+        })
+    }
+
+    /// Records the highest parameters among the waiters in the state of the
+    /// owner.
+    fn update_inherited(&self, owner: &AxTaskRef, waiters: &BTreeMap<u64, AxTaskRef>) {
+        let top = waiters
+            .values()
+            .map(|waiter| waiter.sched_params())
+            .reduce(SchedParams::max);
+        let mut pi = owner.pi_state();
+        match top {
+            Some(params) => pi.inherited.insert(self.key(), params),
+            None => pi.inherited.remove(&self.key()),
+        };
+    }
+}
+
+impl Default for PiState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for PiState {
+    fn drop(&mut self) {
+        // The lock may be dropped without unlocking, e.g., the guard is
+        // forgotten.
+        let owner = *self.state.get_mut() & !HAS_WAITERS;
+        if owner != 0 {
+            unsafe { Arc::decrement_strong_count(owner as *const AxTask) };
+        }
+    }
+}
+
+/// The value of the current task in [`PiState::state`].
+fn current_ptr() -> usize {
+    Arc::as_ptr(current().as_task_ref()) as usize
+}
+
+/// Recomputes the effective scheduling parameters of the task, and then of
+/// the owners along the chain of locks that it's waiting for.
+fn update_chain(task: &AxTaskRef) {
+    let mut task = task.clone();
+    let mut depth = 0;
+    while depth < MAX_CHAIN_DEPTH {
+        let pi = task.pi_state();
+        let blocked_on = unsafe { pi.blocked_on.as_ref() };
+        // The waiters are locked before the task, so only try to lock them
+        // here and retry on failure to avoid deadlocks.
+        let waiters = match blocked_on.map(|lock| lock.waiters.try_lock()) {
+            Some(None) => {
+                drop(pi);
+                core::hint::spin_loop();
+                continue;
+            }
+            Some(Some(waiters)) => Some(waiters),
+            None => None,
+        };
+        let params = pi.effective_params();
+        if params == task.sched_params() {
+            break;
+        }
+        crate::run_queue::set_sched_params(&task, params);
+        drop(pi);
+        // The lock can not be freed while the task is in its waiters.
+        let (Some(lock), Some(waiters)) = (blocked_on, waiters) else {
+            break;
+        };
+        let Some(owner) = lock.owner() else {
+            break;
+        };
+        lock.update_inherited(&owner, &waiters);
+        drop(waiters);
+        task = owner;
+        depth += 1;
+    }
+}
+
+/// Changes the base scheduling policy of the task, the inherited priority is
+/// kept if it's higher.
+pub(crate) fn set_base_policy(task: &AxTaskRef, policy: SchedPolicy, rt_prio: usize) {
+    {
+        let mut pi = task.pi_state();
+        pi.base.policy = policy;
+        pi.base.rt_prio = rt_prio;
+    }
+    // The owner of the lock that the task is waiting for may need to inherit
+    // the new priority, or give it up.
+    update_chain(task);
+}
+
+/// Changes the base priority of the current task in its scheduling class, the
+/// inherited priority is kept if it's higher.
+pub(crate) fn set_current_priority(prio: isize) -> bool {
+    let curr = current();
+    let mut pi = curr.pi_state();
+    let mut base = pi.base;
+    match base.policy {
+        SchedPolicy::Normal | SchedPolicy::Batch => {
+            // The range of the priority depends on the normal scheduler.
+            if !current_run_queue().set_current_priority(prio) {
+                return false;
+            }
+            base.prio = prio;
+        }
+        SchedPolicy::Fifo | SchedPolicy::RoundRobin => {
+            if !(RT_PRIO_MIN as isize..=RT_PRIO_MAX as isize).contains(&prio) {
+                return false;
+            }
+            base.rt_prio = prio as usize;
+        }
+        SchedPolicy::Idle => return false,
+    }
+    pi.base = base;
+    let params = pi.effective_params();
+    // Apply it unconditionally, as the normal scheduler has been updated.
+    crate::run_queue::set_sched_params(curr.as_task_ref(), params);
+    true
+}
@@ -0,0 +1,161 @@
+//! Read-copy-update (RCU), for data that is read on hot paths and rarely
+//! updated.
+//!
+//! Readers access the data in read-side critical sections started by
+//! [`rcu_read_lock`], which only disable preemption. Writers publish a new
+//! version of the data, and release the old version after a grace period,
+//! when all the critical sections that may see the old version have ended.
+//!
+//! Since a task can not be switched out in a critical section, a context
+//! switch on a CPU is a quiescent state: no critical section that started
+//! before it is still running on that CPU. The scheduler counts the
+//! quiescent states of each CPU, and a grace period ends when every other
+//! CPU has passed one:
+//!
+//! - Every call to the scheduler is a quiescent state, including those of
+//!   the idle task, which runs the scheduler whenever it's woken up by an
+//!   interrupt.
+//! - With the `preempt` feature, a timer tick that interrupts a task with
+//!   preemption enabled is also a quiescent state.
+//!
+//! IRQ handlers and softirqs run with preemption disabled, so they are
+//! read-side critical sections without calling [`rcu_read_lock`].
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use kernel_guard::NoPreempt;
+use lazy_init::LazyInit;
+use spinlock::SpinNoIrq;
+
+use crate::{Work, WorkQueue};
+
+/// The number of quiescent states that each CPU has passed, plus 1. It's 0
+/// if the scheduler of the CPU is not initialized.
+#[percpu::def_percpu]
+static QS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The callbacks queued by [`call_rcu`], waiting for the next grace period.
+static PENDING_CALLBACKS: SpinNoIrq<Vec<Box<dyn FnOnce() + Send>>> = SpinNoIrq::new(Vec::new());
+
+/// The work queue to run the callbacks, and the work that waits for a grace
+/// period and then runs a batch of callbacks.
+static RCU_WQ: LazyInit<WorkQueue> = LazyInit::new();
+static RCU_WORK: LazyInit<Arc<Work>> = LazyInit::new();
+
+/// A guard of an RCU read-side critical section, created by
+/// [`rcu_read_lock`]. The critical section ends when it's dropped.
+///
+/// The task must not block in the critical section.
+pub struct RcuReadGuard {
+    _guard: NoPreempt,
+}
+
+/// Starts an RCU read-side critical section, which ends when the returned
+/// guard is dropped.
+///
+/// The data released by the writers after [`synchronize_rcu`] or by
+/// [`call_rcu`] remains valid until the critical section ends. Critical
+/// sections can be nested.
+pub fn rcu_read_lock() -> RcuReadGuard {
+    RcuReadGuard {
+        _guard: NoPreempt::new(),
+    }
+}
+
+/// Waits until a grace period has elapsed, i.e., all the RCU read-side
+/// critical sections that started before this call have ended.
+///
+/// It blocks, so it must not be called in a read-side critical section or
+/// in IRQ context.
+pub fn synchronize_rcu() {
+    #[cfg(feature = "preempt")]
+    assert!(
+        crate::current().can_preempt(0),
+        "synchronize_rcu() called with preemption disabled"
+    );
+    // The current CPU is not in any critical section now.
+    let this_cpu = axhal::cpu::this_cpu_id();
+
+    // Order the removal of the old data before taking the snapshot.
+    fence(Ordering::SeqCst);
+    let snapshot: [usize; axconfig::SMP] =
+        core::array::from_fn(|cpu| unsafe { QS_COUNT.remote_ref_raw(cpu) }.load(Ordering::Acquire));
+    for (cpu, &count) in snapshot.iter().enumerate() {
+        if cpu == this_cpu || count == 0 {
+            continue;
+        }
+        while unsafe { QS_COUNT.remote_ref_raw(cpu) }.load(Ordering::Acquire) == count {
+            wait_a_moment();
+        }
+    }
+    fence(Ordering::SeqCst);
+}
+
+/// Queues `f` to run in a worker task after a grace period.
+///
+/// Unlike [`synchronize_rcu`], it does not block, and it can be called in
+/// any context, including read-side critical sections and IRQ handlers.
+pub fn call_rcu<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    PENDING_CALLBACKS.lock().push(Box::new(f));
+    RCU_WQ.queue(&RCU_WORK);
+}
+
+/// Waits until all the callbacks queued by [`call_rcu`] so far have run.
+///
+/// It must not be called in the callbacks.
+pub fn rcu_barrier() {
+    RCU_WQ.flush();
+}
+
+/// Records a quiescent state of the current CPU, in the scheduler with IRQs
+/// disabled.
+pub(crate) fn report_qs() {
+    unsafe { QS_COUNT.current_ref_raw() }.fetch_add(1, Ordering::Release);
+}
+
+/// Records a quiescent state in the timer interrupt handler, if the
+/// interrupted task is not in a read-side critical section.
+#[cfg(feature = "irq")]
+pub(crate) fn timer_tick() {
+    #[cfg(feature = "preempt")]
+    if let Some(curr) = crate::current_may_uninit() {
+        // The preemption is disabled once by the IRQ handler.
+        if curr.can_preempt(1) {
+            report_qs();
+        }
+    }
+}
+
+/// Starts counting the quiescent states of the current CPU.
+pub(crate) fn init_percpu() {
+    unsafe { QS_COUNT.current_ref_raw() }.store(1, Ordering::Release);
+}
+
+/// Starts the worker task to run the callbacks of [`call_rcu`].
+pub(crate) fn init() {
+    RCU_WQ.init_by(WorkQueue::new("rcu", 1));
+    RCU_WORK.init_by(Work::new(run_callbacks));
+}
+
+fn run_callbacks() {
+    // The callbacks queued later are left to the next run of the work.
+    let callbacks = core::mem::take(&mut *PENDING_CALLBACKS.lock());
+    if callbacks.is_empty() {
+        return;
+    }
+    synchronize_rcu();
+    for f in callbacks {
+        f();
+    }
+}
+
+fn wait_a_moment() {
+    #[cfg(feature = "irq")]
+    crate::sleep(core::time::Duration::from_millis(1));
+    #[cfg(not(feature = "irq"))]
+    crate::yield_now();
+}
@@ -1,48 +1,423 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+#[cfg(any(feature = "smp", test))]
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "smp")]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kernel_guard::NoPreemptIrqSave;
 use lazy_init::LazyInit;
 use scheduler::BaseScheduler;
-use spinlock::SpinNoIrq;
+use spinlock::{SpinNoIrq, SpinRaw, TicketLock};
 
+use crate::sched_class::SchedParams;
 use crate::task::{CurrentTask, TaskState};
 use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
 
-// TODO: per-CPU
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+/// The run queue of each CPU.
+///
+/// It must be locked with both IRQs and preemption disabled. Use
+/// [`current_run_queue`] to lock the run queue of the current CPU.
+///
+/// It's a fair ticket lock, since remote CPUs lock it to wake up or migrate
+/// tasks, and the owner CPU would starve with an unfair lock.
+#[percpu::def_percpu]
+static RUN_QUEUE: LazyInit<SpinRaw<AxRunQueue, TicketLock>> = LazyInit::new();
+
+/// The number of ready tasks in the run queue of each CPU.
+///
+/// It can be read without locking the run queue, used as a hint for load
+/// balancing.
+#[percpu::def_percpu]
+static RUN_QUEUE_LOAD: AtomicUsize = AtomicUsize::new(0);
+
+/// Tasks sent to each CPU by other CPUs, waiting to be put into its run queue.
+///
+/// It's used when the target run queue can not be locked directly, and it
+/// must be locked after the run queue locks.
+#[cfg(feature = "smp")]
+#[percpu::def_percpu]
+static WAKE_LIST: SpinRaw<VecDeque<AxTaskRef>> = SpinRaw::new(VecDeque::new());
+
+/// Whether each CPU is running its idle task, which may be waiting for IRQs
+/// and needs a reschedule IPI to notice the tasks sent to it.
+#[cfg(feature = "smp")]
+#[percpu::def_percpu]
+static CPU_IDLE: AtomicBool = AtomicBool::new(false);
 
 // TODO: per-CPU
 static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::new());
 
 static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 
+/// The CPU bandwidth reserved by the real-time tasks on each CPU.
+#[cfg(feature = "sched_edf")]
+#[percpu::def_percpu]
+static EDF_BANDWIDTH: scheduler::EDFBandwidth = scheduler::EDFBandwidth::new(1);
+
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// The task that was switched out on this CPU most recently. It is released
+/// by the next task after the context switch is completed.
+#[percpu::def_percpu]
+static PREV_TASK: Option<AxTaskRef> = None;
+
+/// The interval (in timer ticks) of the periodic load balancing.
+#[cfg(all(feature = "smp", feature = "irq"))]
+const LOAD_BALANCE_INTERVAL: usize = 10;
+
 pub(crate) struct AxRunQueue {
+    cpu_id: usize,
     scheduler: Scheduler,
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    ticks: usize,
+}
+
+/// A locked reference to the run queue of the current CPU.
+///
+/// A task holding it may be switched out, stolen by another CPU, and resumed
+/// there. So it always releases the run queue of the CPU it is dropped on,
+/// instead of the one it was locked on, and it must not be dereferenced after
+/// a reschedule.
+pub(crate) struct AxRunQueueRef {
+    rq: *mut AxRunQueue,
+    _guard: NoPreemptIrqSave,
+}
+
+/// Locks the run queue of the current CPU.
+pub(crate) fn current_run_queue() -> AxRunQueueRef {
+    let guard = NoPreemptIrqSave::new();
+    // IRQs and preemption are disabled, we will stay on this CPU.
+    let rq = unsafe { RUN_QUEUE.current_ref_raw().get_unchecked() };
+    // The lock is released manually in `AxRunQueueRef::drop()`.
+    let mut locked = core::mem::ManuallyDrop::new(rq.lock());
+    AxRunQueueRef {
+        rq: &mut **locked,
+        _guard: guard,
+    }
+}
+
+/// Locks the run queue of the given CPU, and calls `f` on it.
+///
+/// The current CPU must not hold its own run queue lock.
+fn with_run_queue<F, T>(cpu_id: usize, f: F) -> T
+where
+    F: FnOnce(&mut AxRunQueue) -> T,
+{
+    let _guard = NoPreemptIrqSave::new();
+    let mut rq = unsafe { RUN_QUEUE.remote_ref_raw(cpu_id).get_unchecked() }.lock();
+    f(&mut rq)
+}
+
+/// Returns the number of ready tasks on the given CPU, or [`None`] if the
+/// scheduler on that CPU is not initialized.
+#[cfg(feature = "smp")]
+fn run_queue_load(cpu_id: usize) -> Option<usize> {
+    unsafe {
+        if RUN_QUEUE.remote_ref_raw(cpu_id).is_init() {
+            Some(
+                RUN_QUEUE_LOAD
+                    .remote_ref_raw(cpu_id)
+                    .load(Ordering::Acquire),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+/// Selects a CPU allowed by the task's affinity mask to run it, which has the
+/// fewest ready tasks. `preferred` is selected if there is a tie.
+#[cfg(feature = "smp")]
+fn select_cpu(task: &AxTaskRef, preferred: usize) -> usize {
+    // Real-time tasks stay on the CPU that their bandwidth is reserved on.
+    #[cfg(feature = "sched_edf")]
+    if let Some(cpu_id) = task.edf_cpu() {
+        return cpu_id;
+    }
+    let cpumask = task.cpumask();
+    // Idle CPUs with the tick stopped only notice new tasks periodically, so
+    // avoid them if possible.
+    #[cfg(feature = "tickless")]
+    {
+        let this_cpu = axhal::cpu::this_cpu_id();
+        let ticking = |&cpu_id: &usize| cpu_id == this_cpu || !crate::timers::tick_stopped(cpu_id);
+        let cpus = cpumask.into_iter().filter(ticking);
+        if let Some(cpu_id) = least_loaded_cpu(cpus, preferred, run_queue_load) {
+            return cpu_id;
+        }
+    }
+    least_loaded_cpu(&cpumask, preferred, run_queue_load).unwrap_or(preferred)
+}
+
+/// Finds the CPU with the fewest ready tasks in `cpus`, where `load` returns
+/// the number of ready tasks on a CPU, or [`None`] if it can't be selected.
+/// `preferred` is selected if there is a tie.
+#[cfg(any(feature = "smp", test))]
+pub(crate) fn least_loaded_cpu<I, F>(cpus: I, preferred: usize, load: F) -> Option<usize>
+where
+    I: IntoIterator<Item = usize>,
+    F: Fn(usize) -> Option<usize>,
+{
+    let mut selected = None;
+    let mut min_load = usize::MAX;
+    for cpu_id in cpus {
+        match load(cpu_id) {
+            Some(load) if load < min_load || (load == min_load && cpu_id == preferred) => {
+                selected = Some(cpu_id);
+                min_load = load;
+            }
+            _ => {}
+        }
+    }
+    selected
+}
+
+/// Takes at most `nr` tasks which satisfy `filter` out of `scheduler`, in the
+/// order they would be picked. The other tasks keep their order.
+#[cfg(any(feature = "smp", test))]
+pub(crate) fn take_tasks<F>(scheduler: &mut Scheduler, nr: usize, filter: F) -> Vec<AxTaskRef>
+where
+    F: Fn(&AxTaskRef) -> bool,
+{
+    let mut taken = Vec::new();
+    let mut skipped = Vec::new();
+    while taken.len() < nr {
+        let Some(task) = scheduler.pick_next_task() else {
+            break;
+        };
+        if filter(&task) {
+            taken.push(task);
+        } else {
+            skipped.push(task);
+        }
+    }
+    // Tasks can only be put back to the end of the queue, so the rest of them
+    // are taken out as well, and all of them are put back in order.
+    if !skipped.is_empty() {
+        while let Some(task) = scheduler.pick_next_task() {
+            skipped.push(task);
+        }
+        for task in skipped {
+            scheduler.put_prev_task(task, false);
+        }
+    }
+    taken
+}
+
+#[cfg(not(feature = "smp"))]
+fn select_cpu(_task: &AxTaskRef, preferred: usize) -> usize {
+    preferred
+}
+
+/// Sends a ready task to the run queue of another CPU.
+///
+/// The current CPU may hold its own run queue lock, so the target run queue
+/// is only try-locked. If it fails, the task is put into the target's wake
+/// list instead.
+#[cfg(feature = "smp")]
+fn send_task(cpu_id: usize, task: AxTaskRef) {
+    debug!("task migrate: {} to CPU {}", task.id_name(), cpu_id);
+    let rq = unsafe { RUN_QUEUE.remote_ref_raw(cpu_id).get_unchecked() };
+    if let Some(mut rq) = rq.try_lock() {
+        rq.enqueue(task, None);
+    } else {
+        unsafe { WAKE_LIST.remote_ref_raw(cpu_id) }
+            .lock()
+            .push_back(task);
+    }
+    kick_cpu(cpu_id);
+}
+
+/// Sends a reschedule IPI to the given CPU if it's idle, so that it runs the
+/// tasks just queued to it without waiting for the next timer interrupt.
+#[cfg(all(feature = "smp", feature = "irq"))]
+fn kick_cpu(cpu_id: usize) {
+    // Pairs with the fence in `has_pending_tasks`: either the idle CPU sees
+    // the new tasks before it waits for IRQs, or we see it idle here.
+    core::sync::atomic::fence(Ordering::SeqCst);
+    if cpu_id != axhal::cpu::this_cpu_id()
+        && unsafe { CPU_IDLE.remote_ref_raw(cpu_id) }.load(Ordering::Relaxed)
+    {
+        axhal::irq::send_ipi(cpu_id);
+    }
+}
+
+/// The idle task never waits for IRQs without the `irq` feature.
+#[cfg(all(feature = "smp", not(feature = "irq")))]
+fn kick_cpu(_cpu_id: usize) {}
+
+/// Whether there are tasks to run on the current CPU, checked by the idle
+/// task with IRQs disabled before it waits for IRQs.
+#[cfg(feature = "irq")]
+pub(crate) fn has_pending_tasks() -> bool {
+    core::sync::atomic::fence(Ordering::SeqCst);
+    #[cfg(feature = "smp")]
+    if !unsafe { WAKE_LIST.current_ref_raw() }.lock().is_empty() {
+        return true;
+    }
+    unsafe { RUN_QUEUE_LOAD.current_ref_raw() }.load(Ordering::Acquire) > 0
+}
+
+/// Changes the effective scheduling parameters of the task. If the task is
+/// ready, it's moved to the scheduling class of the new policy.
+///
+/// The current CPU must not hold its own run queue lock.
+pub(crate) fn set_sched_params(task: &AxTaskRef, params: SchedParams) {
+    loop {
+        // A ready task can only be in the run queue of the CPU it belongs to,
+        // and it can not be moved to another CPU while that run queue is
+        // locked.
+        let cpu_id = task.cpu_id();
+        let done = with_run_queue(cpu_id, |rq| {
+            if task.cpu_id() != cpu_id {
+                return false;
+            }
+            let queued = rq.scheduler.remove_task(task);
+            task.set_sched_params(params);
+            if params.policy.is_normal() {
+                rq.scheduler.set_priority(task, params.prio);
+            }
+            if let Some(task) = queued {
+                rq.scheduler.add_task(task);
+            }
+            true
+        });
+        if done {
+            break;
+        }
+    }
+}
+
+/// Adds a newly spawned task to the run queue of the least loaded CPU.
+pub(crate) fn spawn_task(task: AxTaskRef) {
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::register_task(&task);
+    let cpu_id = select_cpu(&task, axhal::cpu::this_cpu_id());
+    with_run_queue(cpu_id, |rq| rq.add_task(task));
+    #[cfg(feature = "smp")]
+    kick_cpu(cpu_id);
+}
+
+impl Deref for AxRunQueueRef {
+    type Target = AxRunQueue;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rq }
+    }
+}
+
+impl DerefMut for AxRunQueueRef {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.rq }
+    }
+}
+
+impl Drop for AxRunQueueRef {
+    fn drop(&mut self) {
+        // IRQs are still disabled by `self._guard`, which is dropped later.
+        unsafe { RUN_QUEUE.current_ref_raw().get_unchecked().force_unlock() };
+    }
 }
 
 impl AxRunQueue {
-    pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE);
+    fn new(cpu_id: usize) -> SpinRaw<Self, TicketLock> {
         let mut scheduler = Scheduler::new();
-        scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+        scheduler.init();
+        SpinRaw::new(Self {
+            cpu_id,
+            scheduler,
+            #[cfg(all(feature = "smp", feature = "irq"))]
+            ticks: 0,
+        })
+    }
+
+    fn load(&self) -> &AtomicUsize {
+        unsafe { RUN_QUEUE_LOAD.remote_ref_raw(self.cpu_id) }
+    }
+
+    /// Puts a ready task into the scheduler of this run queue.
+    fn enqueue(&mut self, task: AxTaskRef, put_prev: Option<bool>) {
+        task.set_cpu_id(self.cpu_id);
+        task.accounting().enqueue(axhal::time::current_time_nanos());
+        match put_prev {
+            Some(preempt) => self.scheduler.put_prev_task(task, preempt),
+            None => self.scheduler.add_task(task),
+        }
+        self.load().fetch_add(1, Ordering::Release);
+    }
+
+    /// Takes the next task to run out of the scheduler of this run queue.
+    ///
+    /// Tasks which are not allowed to run on this CPU any more are sent to
+    /// other CPUs.
+    fn dequeue(&mut self) -> Option<AxTaskRef> {
+        let task = self.scheduler.pick_next_task()?;
+        self.load().fetch_sub(1, Ordering::Release);
+        #[cfg(feature = "smp")]
+        if !task.can_run_on(self.cpu_id) {
+            let cpu_id = select_cpu(&task, self.cpu_id);
+            if cpu_id != self.cpu_id {
+                send_task(cpu_id, task);
+                return self.dequeue();
+            }
+        }
+        Some(task)
+    }
+
+    /// Puts a ready task into this run queue if it's allowed to run on this
+    /// CPU, otherwise sends it to another CPU allowed by its affinity mask.
+    fn activate_task(&mut self, task: AxTaskRef, put_prev: Option<bool>) {
+        #[cfg(feature = "smp")]
+        if !task.can_run_on(self.cpu_id) {
+            let cpu_id = select_cpu(&task, self.cpu_id);
+            if cpu_id != self.cpu_id {
+                send_task(cpu_id, task);
+                return;
+            }
+        }
+        self.enqueue(task, put_prev);
+    }
+
+    /// Puts the tasks sent by other CPUs into this run queue.
+    #[cfg(feature = "smp")]
+    fn drain_wake_list(&mut self) {
+        let wake_list = unsafe { WAKE_LIST.remote_ref_raw(self.cpu_id) };
+        let tasks = core::mem::take(&mut *wake_list.lock());
+        for task in tasks {
+            self.activate_task(task, None);
+        }
     }
 
     pub fn add_task(&mut self, task: AxTaskRef) {
-        debug!("task spawn: {}", task.id_name());
+        debug!("task spawn: {} on CPU {}", task.id_name(), self.cpu_id);
         assert!(task.is_ready());
-        self.scheduler.add_task(task);
+        self.enqueue(task, None);
     }
 
     #[cfg(feature = "irq")]
     pub fn scheduler_timer_tick(&mut self) {
         let curr = crate::current();
+        #[cfg(feature = "sched_edf")]
+        if curr.is_idle() {
+            // Keep the deadline clock going while the CPU is idle.
+            self.scheduler.normal.clock_tick();
+        }
         if !curr.is_idle() && self.scheduler.task_tick(curr.as_task_ref()) {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
+
+        #[cfg(feature = "smp")]
+        {
+            self.drain_wake_list();
+            self.ticks += 1;
+            if self.ticks % LOAD_BALANCE_INTERVAL == 0 {
+                self.load_balance();
+            }
+        }
     }
 
     pub fn yield_current(&mut self) {
@@ -52,18 +427,74 @@ impl AxRunQueue {
         self.resched(false);
     }
 
+    /// Migrates the current task to another CPU, if it's not allowed to run
+    /// on this CPU any more.
+    pub fn migrate_current(&mut self) {
+        let curr = crate::current();
+        if !curr.can_run_on(self.cpu_id) {
+            self.resched(false);
+        }
+    }
+
+    /// Sets the priority of the current task in the normal scheduler.
     pub fn set_current_priority(&mut self, prio: isize) -> bool {
         self.scheduler
+            .normal
             .set_priority(crate::current().as_task_ref(), prio)
     }
 
+    /// Sets the real-time parameters of the current task.
+    ///
+    /// The reservation is admitted by the first CPU allowed by the task's
+    /// affinity mask that has enough bandwidth left, and the task is migrated
+    /// to that CPU if necessary.
+    #[cfg(feature = "sched_edf")]
+    pub fn set_current_deadline_params(&mut self, params: Option<scheduler::EDFParams>) -> bool {
+        let curr = crate::current();
+        // Only normal tasks are scheduled by the EDF scheduler.
+        if params.is_some() && !curr.sched_policy().is_normal() {
+            return false;
+        }
+        let old = curr.as_task_ref().params();
+        let old_cpu = curr.edf_cpu();
+        let bandwidth = |cpu_id| unsafe { EDF_BANDWIDTH.remote_ref_raw(cpu_id) };
+        let new_cpu = if params.is_some() {
+            let admit = |cpu_id| {
+                let old = old.filter(|_| old_cpu == Some(cpu_id));
+                bandwidth(cpu_id).reserve(old.as_ref(), params.as_ref())
+            };
+            let initialized =
+                |&cpu_id: &usize| unsafe { RUN_QUEUE.remote_ref_raw(cpu_id).is_init() };
+            let Some(cpu_id) = curr
+                .cpumask()
+                .into_iter()
+                .filter(initialized)
+                .find(|&id| admit(id))
+            else {
+                return false;
+            };
+            Some(cpu_id)
+        } else {
+            None
+        };
+        if let Some(old_cpu) = old_cpu.filter(|&id| new_cpu != Some(id)) {
+            bandwidth(old_cpu).reserve(old.as_ref(), None);
+        }
+        self.scheduler.normal.set_params(curr.as_task_ref(), params);
+        curr.set_edf_cpu(new_cpu);
+        if new_cpu.is_some_and(|id| id != self.cpu_id) {
+            self.resched(false);
+        }
+        true
+    }
+
     #[cfg(feature = "preempt")]
     pub fn preempt_resched(&mut self) {
         let curr = crate::current();
         assert!(curr.is_running());
 
         // When we get the mutable reference of the run queue, we must
-        // have held the `SpinNoIrq` lock with both IRQs and preemption
+        // have held the run queue lock with both IRQs and preemption
         // disabled. So we need to set `current_disable_count` to 1 in
         // `can_preempt()` to obtain the preemption permission before
         //  locking the run queue.
@@ -90,7 +521,16 @@ impl AxRunQueue {
             EXITED_TASKS.lock().clear();
             axhal::misc::terminate();
         } else {
+            if curr.sleeping_locks() != 0 {
+                warn!(
+                    "task {} exits with {} sleeping locks held",
+                    curr.id_name(),
+                    curr.sleeping_locks()
+                );
+            }
             curr.set_state(TaskState::Exited);
+            #[cfg(feature = "sched_edf")]
+            self.set_current_deadline_params(None);
             curr.notify_exit(exit_code, self);
             EXITED_TASKS.lock().push_back(curr.clone());
             WAIT_FOR_EXIT.notify_one_locked(false, self);
@@ -112,16 +552,23 @@ impl AxRunQueue {
         #[cfg(feature = "preempt")]
         assert!(curr.can_preempt(1));
 
+        #[cfg(feature = "watchdog")]
+        curr.watchdog().block(axhal::time::current_time_nanos());
         curr.set_state(TaskState::Blocked);
         wait_queue_push(curr.clone());
-        self.resched(false);
+        if !Self::cancel_blocking(&curr) {
+            self.resched(false);
+        }
     }
 
     pub fn unblock_task(&mut self, task: AxTaskRef, resched: bool) {
         debug!("task unblock: {}", task.id_name());
-        if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(task); // TODO: priority
+        // The task may be woken up by several CPUs at the same time, only
+        // one of them can make the transition.
+        if task.transition_state(TaskState::Blocked, TaskState::Ready) {
+            #[cfg(feature = "sched_mlfq")]
+            task.set_woken();
+            self.activate_task(task, None); // TODO: priority
             if resched {
                 #[cfg(feature = "preempt")]
                 crate::current().set_preempt_pending(true);
@@ -138,32 +585,80 @@ impl AxRunQueue {
 
         let now = axhal::time::current_time();
         if now < deadline {
-            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+            // The timer may fire on another CPU right after it's set, so mark
+            // the task as blocked first.
             curr.set_state(TaskState::Blocked);
-            self.resched(false);
+            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+            if !Self::cancel_blocking(&curr) {
+                self.resched(false);
+            }
         }
     }
 }
 
 impl AxRunQueue {
+    /// Checks whether the current task which has just been marked as blocked
+    /// is canceled, and makes it running again if so.
+    ///
+    /// The canceler sets the request before it tries to wake up the blocked
+    /// task, and we mark the task as blocked before checking the request, so
+    /// at least one of them will see the other. Returns `true` if the task
+    /// should not sleep, and the caller is responsible for exiting it.
+    fn cancel_blocking(curr: &CurrentTask) -> bool {
+        core::sync::atomic::fence(Ordering::SeqCst);
+        // If the transition fails, the canceler has woken it up already.
+        curr.is_canceled() && curr.transition_state(TaskState::Blocked, TaskState::Running)
+    }
+
     /// Common reschedule subroutine. If `preempt`, keep current task's time
     /// slice, otherwise reset it.
     fn resched(&mut self, preempt: bool) {
+        #[cfg(feature = "watchdog")]
+        crate::watchdog::touch();
+        crate::rcu::report_qs();
         let prev = crate::current();
+        // The task gives up the CPU voluntarily if it's blocked or exited.
+        let voluntary = !prev.is_running();
         if prev.is_running() {
             prev.set_state(TaskState::Ready);
             if !prev.is_idle() {
-                self.scheduler.put_prev_task(prev.clone(), preempt);
+                self.activate_task(prev.clone(), Some(preempt));
+            }
+        }
+        #[cfg(feature = "smp")]
+        self.drain_wake_list();
+        let next = self
+            .dequeue()
+            .or_else(|| self.idle_balance())
+            .unwrap_or_else(|| unsafe {
+                // Safety: IRQs must be disabled at this time.
+                IDLE_TASK.current_ref_raw().get_unchecked().clone()
+            });
+        #[cfg(feature = "smp")]
+        unsafe { CPU_IDLE.current_ref_raw() }.store(next.is_idle(), Ordering::SeqCst);
+        #[cfg(feature = "tickless")]
+        self.update_tick(&next);
+        self.switch_to(prev, next, voluntary);
+    }
+
+    /// Stops the scheduler tick if the CPU is going to be idle, or restarts it
+    /// otherwise.
+    #[cfg(feature = "tickless")]
+    fn update_tick(&mut self, next: &AxTaskRef) {
+        if next.is_idle() {
+            crate::timers::stop_tick();
+        } else {
+            #[cfg(not(feature = "sched_edf"))]
+            crate::timers::start_tick();
+            #[cfg(feature = "sched_edf")]
+            for _ in 0..crate::timers::start_tick() {
+                // Catch up with the deadline clock.
+                self.scheduler.normal.clock_tick();
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
-            // Safety: IRQs must be disabled at this time.
-            IDLE_TASK.current_ref_raw().get_unchecked().clone()
-        });
-        self.switch_to(prev, next);
     }
 
-    fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
+    fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef, voluntary: bool) {
         trace!(
             "context switch: {} -> {}",
             prev_task.id_name(),
@@ -175,6 +670,19 @@ impl AxRunQueue {
         if prev_task.ptr_eq(&next_task) {
             return;
         }
+        #[cfg(not(feature = "paging"))]
+        prev_task.check_stack_canary();
+        #[cfg(all(feature = "paging", feature = "smp"))]
+        crate::task::sync_stack_guards();
+
+        // The next task may have been switched out on another CPU just now,
+        // wait until its context is saved.
+        while next_task.on_cpu() {
+            core::hint::spin_loop();
+        }
+        next_task.set_on_cpu(true);
+        next_task.set_cpu_id(self.cpu_id);
+        crate::stat::account_task_switch(prev_task.as_task_ref(), &next_task, voluntary);
 
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
@@ -185,12 +693,116 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            // The run queue lock is released by the next task.
+            #[cfg(feature = "lockdep")]
+            (*prev_task.held_locks_ptr()).transfer(
+                RUN_QUEUE.current_ref_raw().get_unchecked().lock_addr(),
+                &mut *next_task.held_locks_ptr(),
+            );
+
+            *PREV_TASK.current_ref_mut_raw() = Some(prev_task.clone());
             CurrentTask::set_current(prev_task, next_task);
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
+            finish_task_switch();
+        }
+    }
+
+    /// Tries to pull a task from the busiest CPU, when this CPU is going to
+    /// be idle.
+    fn idle_balance(&mut self) -> Option<AxTaskRef> {
+        #[cfg(feature = "smp")]
+        if self.steal_tasks(|busiest_load| busiest_load.min(1)) > 0 {
+            return self.dequeue();
         }
+        None
+    }
+
+    /// Periodically pulls tasks from the busiest CPU to even out the number
+    /// of ready tasks between the two CPUs.
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    fn load_balance(&mut self) {
+        let this_load = self.load().load(Ordering::Acquire);
+        self.steal_tasks(|busiest_load| busiest_load.saturating_sub(this_load) / 2);
+    }
+
+    /// Finds the CPU with the most ready tasks, and moves some of them into
+    /// this run queue. `nr_to_steal` computes the number of tasks to move
+    /// from the load of the busiest CPU.
+    ///
+    /// We already hold the lock of this run queue, so the victim run queue
+    /// is only try-locked to avoid deadlocks. Returns the number of tasks
+    /// actually moved.
+    #[cfg(feature = "smp")]
+    fn steal_tasks<F>(&mut self, nr_to_steal: F) -> usize
+    where
+        F: FnOnce(usize) -> usize,
+    {
+        let mut busiest = None;
+        let mut busiest_load = 0;
+        for cpu_id in (0..axconfig::SMP).filter(|&id| id != self.cpu_id) {
+            match run_queue_load(cpu_id) {
+                Some(load) if load > busiest_load => {
+                    busiest = Some(cpu_id);
+                    busiest_load = load;
+                }
+                _ => {}
+            }
+        }
+
+        let Some(busiest) = busiest else {
+            return 0;
+        };
+        let nr_to_steal = nr_to_steal(busiest_load);
+        if nr_to_steal == 0 {
+            return 0;
+        }
+
+        let victim = unsafe { RUN_QUEUE.remote_ref_raw(busiest).get_unchecked() };
+        let Some(mut victim) = victim.try_lock() else {
+            return 0;
+        };
+
+        // Tasks not allowed to run on this CPU are left in the victim run queue.
+        let tasks = take_tasks(&mut victim.scheduler, nr_to_steal, |task| {
+            task.can_run_on(self.cpu_id)
+        });
+        let nr_stolen = tasks.len();
+        for task in tasks {
+            debug!(
+                "task migrate: {} from CPU {} to CPU {}",
+                task.id_name(),
+                busiest,
+                self.cpu_id
+            );
+            victim.load().fetch_sub(1, Ordering::Release);
+            self.enqueue(task, None);
+        }
+        nr_stolen
     }
 }
 
+/// Completes the context switch on the current CPU, after the previous task's
+/// context is saved.
+///
+/// # Safety
+///
+/// IRQs must be disabled at this time.
+pub(crate) unsafe fn finish_task_switch() {
+    if let Some(prev_task) = PREV_TASK.current_ref_mut_raw().take() {
+        prev_task.set_on_cpu(false);
+    }
+}
+
+/// Releases the run queue lock which was implicitly held across the context
+/// switch, for a newly started task.
+///
+/// # Safety
+///
+/// It must be called at the entry of a new task, with IRQs disabled.
+pub(crate) unsafe fn force_unlock_current() {
+    RUN_QUEUE.current_ref_raw().get_unchecked().force_unlock();
+}
+
 fn gc_entry() {
     loop {
         // Drop all exited tasks and recycle resources.
@@ -215,19 +827,36 @@ fn gc_entry() {
 
 pub(crate) fn init() {
     const IDLE_TASK_STACK_SIZE: usize = 4096;
-    let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE);
+    let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE)
+        .expect("failed to create the idle task");
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
 
     let main_task = TaskInner::new_init("main".into());
     main_task.set_state(TaskState::Running);
+    main_task.set_on_cpu(true);
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::register_task(&main_task);
 
-    RUN_QUEUE.init_by(AxRunQueue::new());
+    let cpu_id = axhal::cpu::this_cpu_id();
+    RUN_QUEUE.with_current(|rq| rq.init_by(AxRunQueue::new(cpu_id)));
+    let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE)
+        .expect("failed to create the gc task");
+    #[cfg(feature = "watchdog")]
+    {
+        crate::watchdog::register_task(&gc_task);
+        crate::watchdog::set_hung_task_check(&gc_task, false);
+    }
+    with_run_queue(cpu_id, |rq| rq.add_task(gc_task));
     unsafe { CurrentTask::init_current(main_task) }
 }
 
 pub(crate) fn init_secondary() {
     let idle_task = TaskInner::new_init("idle".into());
     idle_task.set_state(TaskState::Running);
+    idle_task.set_on_cpu(true);
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
+
+    let cpu_id = axhal::cpu::this_cpu_id();
+    RUN_QUEUE.with_current(|rq| rq.init_by(AxRunQueue::new(cpu_id)));
     unsafe { CurrentTask::init_current(idle_task) }
 }
@@ -0,0 +1,247 @@
+//! Stackable scheduling classes.
+
+use scheduler::{BaseScheduler, IdleScheduler, RTScheduler};
+
+use crate::{AxTask, AxTaskRef, NormalScheduler};
+
+/// The time slice (in timer ticks) of `SCHED_RR` tasks.
+const RT_TIME_SLICE: usize = 10;
+/// The time slice (in timer ticks) of `SCHED_IDLE` tasks.
+const IDLE_TIME_SLICE: usize = 5;
+
+/// The scheduling policy of a task.
+///
+/// The values are the same as the `SCHED_*` constants in POSIX (and Linux).
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SchedPolicy {
+    /// The default time-sharing policy (`SCHED_OTHER`). Tasks are scheduled by
+    /// the scheduler selected by the `sched_*` features.
+    Normal = 0,
+    /// Fixed-priority real-time policy, first-in-first-out among tasks of the
+    /// same priority (`SCHED_FIFO`).
+    Fifo = 1,
+    /// Fixed-priority real-time policy, round-robin among tasks of the same
+    /// priority (`SCHED_RR`).
+    RoundRobin = 2,
+    /// Time-sharing policy for non-interactive tasks (`SCHED_BATCH`). Tasks
+    /// are scheduled by the same scheduler as [`SchedPolicy::Normal`].
+    Batch = 3,
+    /// Very low priority background policy (`SCHED_IDLE`). Tasks only run
+    /// when there is no other ready task.
+    Idle = 5,
+}
+
+impl SchedPolicy {
+    /// Whether it's a real-time policy (`SCHED_FIFO` or `SCHED_RR`).
+    pub const fn is_realtime(self) -> bool {
+        matches!(self, Self::Fifo | Self::RoundRobin)
+    }
+
+    /// Whether it's a time-sharing policy (`SCHED_OTHER` or `SCHED_BATCH`),
+    /// which is scheduled by the normal scheduler.
+    pub const fn is_normal(self) -> bool {
+        matches!(self, Self::Normal | Self::Batch)
+    }
+}
+
+impl TryFrom<u8> for SchedPolicy {
+    /// The unknown policy value.
+    type Error = u8;
+
+    #[inline]
+    fn try_from(policy: u8) -> Result<Self, u8> {
+        match policy {
+            0 => Ok(Self::Normal),
+            1 => Ok(Self::Fifo),
+            2 => Ok(Self::RoundRobin),
+            3 => Ok(Self::Batch),
+            5 => Ok(Self::Idle),
+            _ => Err(policy),
+        }
+    }
+}
+
+/// The scheduling parameters of a task.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct SchedParams {
+    pub policy: SchedPolicy,
+    /// The real-time priority, only for the real-time policies.
+    pub rt_prio: usize,
+    /// The priority in the normal scheduler (e.g., the nice value in CFS),
+    /// lower values mean higher priority.
+    pub prio: isize,
+}
+
+impl SchedParams {
+    pub const fn new(policy: SchedPolicy, rt_prio: usize, prio: isize) -> Self {
+        Self {
+            policy,
+            rt_prio,
+            prio,
+        }
+    }
+
+    /// A rank to compare parameters across scheduling classes, higher values
+    /// mean higher priority.
+    fn rank(&self) -> isize {
+        match self.policy {
+            SchedPolicy::Idle => 0,
+            SchedPolicy::Normal | SchedPolicy::Batch => 100 - self.prio,
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => 200 + self.rt_prio as isize,
+        }
+    }
+
+    /// Returns the parameters with the higher priority, or `self` if they
+    /// have the same priority.
+    pub fn max(self, other: Self) -> Self {
+        if other.rank() > self.rank() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+impl Default for SchedParams {
+    fn default() -> Self {
+        Self::new(SchedPolicy::Normal, 0, 0)
+    }
+}
+
+/// Scheduling classes in the order of their priorities.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+enum SchedClass {
+    RealTime = 0,
+    Normal = 1,
+    Idle = 2,
+}
+
+impl SchedClass {
+    const ALL: [Self; 3] = [Self::RealTime, Self::Normal, Self::Idle];
+
+    fn of(task: &AxTaskRef) -> Self {
+        match task.sched_policy() {
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => Self::RealTime,
+            SchedPolicy::Normal | SchedPolicy::Batch => Self::Normal,
+            SchedPolicy::Idle => Self::Idle,
+        }
+    }
+}
+
+/// A scheduler composed of the scheduling classes, each of which is a
+/// [`BaseScheduler`].
+///
+/// The ready task of the highest class is always picked first: real-time
+/// tasks, then normal tasks, then idle class tasks. The running task is
+/// preempted at the next timer tick if a task of a higher class is ready.
+pub(crate) struct ClassScheduler {
+    rt: RTScheduler<AxTask, RT_TIME_SLICE>,
+    pub(crate) normal: NormalScheduler,
+    idle: IdleScheduler<AxTask, IDLE_TIME_SLICE>,
+    nr_ready: [usize; 3],
+}
+
+impl ClassScheduler {
+    pub const fn new() -> Self {
+        Self {
+            rt: RTScheduler::new(),
+            normal: NormalScheduler::new(),
+            idle: IdleScheduler::new(),
+            nr_ready: [0; 3],
+        }
+    }
+
+    pub fn scheduler_name() -> &'static str {
+        NormalScheduler::scheduler_name()
+    }
+
+    fn highest_ready_class(&self) -> Option<SchedClass> {
+        SchedClass::ALL
+            .into_iter()
+            .find(|&class| self.nr_ready[class as usize] > 0)
+    }
+}
+
+impl BaseScheduler for ClassScheduler {
+    type SchedItem = AxTaskRef;
+
+    fn init(&mut self) {
+        self.rt.init();
+        self.normal.init();
+        self.idle.init();
+    }
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        let class = SchedClass::of(&task);
+        task.set_in_run_queue(true);
+        match class {
+            SchedClass::RealTime => self.rt.add_task(task),
+            SchedClass::Normal => self.normal.add_task(task),
+            SchedClass::Idle => self.idle.add_task(task),
+        }
+        self.nr_ready[class as usize] += 1;
+    }
+
+    /// Unlike other schedulers, it's safe to call it with a task that is not
+    /// in the scheduler, in which case [`None`] is returned.
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        if !task.in_run_queue() {
+            return None;
+        }
+        let class = SchedClass::of(task);
+        let task = match class {
+            SchedClass::RealTime => self.rt.remove_task(task),
+            SchedClass::Normal => self.normal.remove_task(task),
+            SchedClass::Idle => self.idle.remove_task(task),
+        }?;
+        task.set_in_run_queue(false);
+        self.nr_ready[class as usize] -= 1;
+        Some(task)
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        let class = self.highest_ready_class()?;
+        let task = match class {
+            SchedClass::RealTime => self.rt.pick_next_task(),
+            SchedClass::Normal => self.normal.pick_next_task(),
+            SchedClass::Idle => self.idle.pick_next_task(),
+        }?;
+        task.set_in_run_queue(false);
+        self.nr_ready[class as usize] -= 1;
+        Some(task)
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        let class = SchedClass::of(&prev);
+        prev.set_in_run_queue(true);
+        match class {
+            SchedClass::RealTime => self.rt.put_prev_task(prev, preempt),
+            SchedClass::Normal => self.normal.put_prev_task(prev, preempt),
+            SchedClass::Idle => self.idle.put_prev_task(prev, preempt),
+        }
+        self.nr_ready[class as usize] += 1;
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        let class = SchedClass::of(current);
+        let resched = match class {
+            SchedClass::RealTime => self.rt.task_tick(current),
+            SchedClass::Normal => self.normal.task_tick(current),
+            SchedClass::Idle => self.idle.task_tick(current),
+        };
+        #[cfg(feature = "sched_edf")]
+        if class != SchedClass::Normal {
+            self.normal.clock_tick();
+        }
+        resched || self.highest_ready_class().is_some_and(|c| c < class)
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        match SchedClass::of(task) {
+            SchedClass::RealTime => self.rt.set_priority(task, prio),
+            SchedClass::Normal => self.normal.set_priority(task, prio),
+            SchedClass::Idle => self.idle.set_priority(task, prio),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Softirqs, the bottom halves of interrupt handlers.
+
+use alloc::format;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use handler_table::HandlerTable;
+use kernel_guard::{NoPreempt, NoPreemptIrqSave};
+
+use crate::{AxCpuMask, WaitQueue};
+
+/// The number of softirq vectors.
+pub const NR_SOFTIRQS: usize = 16;
+
+/// The softirq vector for high-priority works, which runs first.
+pub const HI_SOFTIRQ: usize = 0;
+/// The softirq vector for transmitting network packets.
+pub const NET_TX_SOFTIRQ: usize = 1;
+/// The softirq vector for receiving network packets.
+pub const NET_RX_SOFTIRQ: usize = 2;
+/// The softirq vector for completing block device requests.
+pub const BLOCK_SOFTIRQ: usize = 3;
+
+/// The maximum times to restart processing softirqs raised while they are
+/// being processed, then the rest are left to `ksoftirqd`.
+const MAX_SOFTIRQ_RESTART: usize = 10;
+
+/// The type of a softirq handler.
+pub type SoftIrqHandler = handler_table::Handler;
+
+static SOFTIRQ_HANDLERS: HandlerTable<NR_SOFTIRQS> = HandlerTable::new();
+
+/// The bitmap of the raised softirq vectors on each CPU.
+#[percpu::def_percpu]
+static SOFTIRQ_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the current CPU is processing softirqs, to avoid reentrance from
+/// nested interrupts.
+#[percpu::def_percpu]
+static IN_SOFTIRQ: bool = false;
+
+/// The nesting depth of IRQ handlers on each CPU.
+#[percpu::def_percpu]
+static HARDIRQ_COUNT: usize = 0;
+
+/// The `ksoftirqd` task of each CPU waits here for the softirqs that can not
+/// be processed at the end of IRQ handlers.
+#[percpu::def_percpu]
+static KSOFTIRQD_WQ: WaitQueue = WaitQueue::new();
+
+/// Registers a handler for the given softirq vector.
+///
+/// Returns `false` if the vector is out of range or already has a handler.
+pub fn register_softirq(vec: usize, handler: SoftIrqHandler) -> bool {
+    if vec < NR_SOFTIRQS && SOFTIRQ_HANDLERS.register_handler(vec, handler) {
+        return true;
+    }
+    warn!("register handler for softirq {} failed", vec);
+    false
+}
+
+/// Unregisters the handler of the given softirq vector.
+///
+/// The handler may be running on other CPUs, so it waits for a grace period
+/// of RCU until the running handlers return. Returns `false` if the vector is
+/// out of range or has no handler.
+pub fn unregister_softirq(vec: usize) -> bool {
+    if vec < NR_SOFTIRQS && SOFTIRQ_HANDLERS.unregister_handler(vec).is_some() {
+        crate::synchronize_rcu();
+        return true;
+    }
+    false
+}
+
+/// Marks the given softirq vector as pending on the current CPU.
+///
+/// It's usually called in IRQ handlers, then the softirq handler runs when
+/// the IRQ handler returns, with IRQs enabled. If it's called in a task, the
+/// `ksoftirqd` task of the current CPU is woken up to run the handler.
+///
+/// # Panics
+///
+/// Panics if the vector is out of range.
+pub fn raise_softirq(vec: usize) {
+    assert!(vec < NR_SOFTIRQS, "invalid softirq vector {}", vec);
+    let _guard = NoPreempt::new();
+    unsafe { SOFTIRQ_PENDING.current_ref_raw() }.fetch_or(1 << vec, Ordering::Release);
+    // Safety: preemption is disabled.
+    let in_irq = unsafe { HARDIRQ_COUNT.read_current_raw() > 0 || IN_SOFTIRQ.read_current_raw() };
+    if !in_irq {
+        wakeup_ksoftirqd();
+    }
+}
+
+/// Whether the current CPU is processing softirqs, i.e., it's called by a
+/// softirq handler or an interrupt handler nested in it.
+pub fn in_softirq() -> bool {
+    let _guard = NoPreempt::new();
+    unsafe { IN_SOFTIRQ.read_current_raw() }
+}
+
+/// Marks the start of an IRQ handler on the current CPU.
+///
+/// It must be paired with [`irq_exit`], with IRQs and preemption disabled.
+pub fn irq_enter() {
+    unsafe { HARDIRQ_COUNT.write_current_raw(HARDIRQ_COUNT.read_current_raw() + 1) };
+}
+
+/// Marks the end of an IRQ handler on the current CPU, and runs the pending
+/// softirqs.
+///
+/// It's called with IRQs and preemption disabled.
+pub fn irq_exit() {
+    unsafe { HARDIRQ_COUNT.write_current_raw(HARDIRQ_COUNT.read_current_raw() - 1) };
+    do_softirq();
+}
+
+/// Runs the pending softirqs of the current CPU, in the order of their
+/// vectors. Each vector runs at most once for each raise.
+///
+/// It's called at the end of IRQ handlers (by [`irq_exit`]) and in the
+/// `ksoftirqd` tasks, with IRQs and preemption disabled. IRQs are enabled
+/// while the softirq handlers run, and it returns immediately in the nested
+/// interrupts. If softirqs are still raised after
+/// `MAX_SOFTIRQ_RESTART` rounds, they are left to `ksoftirqd`.
+pub fn do_softirq() {
+    // Safety: preemption is disabled.
+    if unsafe { IN_SOFTIRQ.read_current_raw() } {
+        return;
+    }
+    let pending = unsafe { SOFTIRQ_PENDING.current_ref_raw() };
+    if pending.load(Ordering::Acquire) == 0 {
+        return;
+    }
+
+    unsafe { IN_SOFTIRQ.write_current_raw(true) };
+    for _ in 0..MAX_SOFTIRQ_RESTART {
+        let mut vecs = pending.swap(0, Ordering::AcqRel);
+        if vecs == 0 {
+            break;
+        }
+        axhal::arch::enable_irqs();
+        while vecs != 0 {
+            let vec = vecs.trailing_zeros() as usize;
+            vecs &= vecs - 1;
+            if !SOFTIRQ_HANDLERS.handle(vec) {
+                warn!("Unhandled softirq {}", vec);
+            }
+        }
+        axhal::arch::disable_irqs();
+    }
+    unsafe { IN_SOFTIRQ.write_current_raw(false) };
+    if pending.load(Ordering::Acquire) != 0 {
+        wakeup_ksoftirqd();
+    }
+}
+
+/// Spawns the `ksoftirqd` task of the current CPU.
+pub(crate) fn init_percpu() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let mut cpumask = AxCpuMask::new();
+    cpumask.set(cpu_id, true);
+    let _task = crate::spawn_raw_with_affinity(
+        ksoftirqd_entry,
+        format!("ksoftirqd/{}", cpu_id),
+        axconfig::TASK_STACK_SIZE,
+        cpumask,
+    )
+    .expect("failed to spawn ksoftirqd");
+    // It may wait for softirqs for a long time.
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::set_hung_task_check(&_task, false);
+}
+
+fn wakeup_ksoftirqd() {
+    // Safety: preemption is disabled by the callers.
+    unsafe { KSOFTIRQD_WQ.current_ref_raw() }.notify_one(false);
+}
+
+fn ksoftirqd_entry() {
+    // Safety: the task only runs on this CPU.
+    let wq = unsafe { KSOFTIRQD_WQ.current_ref_raw() };
+    let pending = unsafe { SOFTIRQ_PENDING.current_ref_raw() };
+    loop {
+        wq.wait_until(|| pending.load(Ordering::Acquire) != 0);
+        let _guard = NoPreemptIrqSave::new();
+        do_softirq();
+    }
+}
@@ -0,0 +1,162 @@
+//! Per-task CPU accounting.
+//!
+//! The running time of a task is measured at context switches. The time spent
+//! in interrupt handlers, including the softirqs processed on return, is
+//! charged to the interrupted task as its system time, and the rest of its running time is the user time. Tasks never leave
+//! the kernel in ArceOS, so the user time is the time spent in the task
+//! itself, including the API calls it makes.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::time::current_time_nanos;
+
+use crate::AxTaskRef;
+
+/// The statistics of all non-idle tasks, including the exited ones.
+static TOTAL: TaskAccounting = TaskAccounting::new();
+
+/// CPU usage statistics of a task.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct TaskStat {
+    /// CPU time spent in the task itself.
+    pub user_time: Duration,
+    /// CPU time spent in interrupt handlers while the task is running.
+    pub system_time: Duration,
+    /// Number of context switches because the task blocked or exited.
+    pub voluntary_switches: u64,
+    /// Number of context switches while the task is still ready to run,
+    /// because it's preempted or it yields the CPU.
+    pub involuntary_switches: u64,
+    /// Time spent in the ready queue, waiting for a CPU.
+    pub wait_time: Duration,
+    /// The CPU that the task last ran on.
+    pub last_cpu: usize,
+}
+
+/// Accounting counters of a task.
+pub(crate) struct TaskAccounting {
+    /// The running time in nanoseconds, including `system_ns`.
+    run_ns: AtomicU64,
+    system_ns: AtomicU64,
+    nvcsw: AtomicU64,
+    nivcsw: AtomicU64,
+    wait_ns: AtomicU64,
+    /// When the task was switched in last time.
+    run_start: AtomicU64,
+    /// When the task was put into the run queue last time.
+    ready_since: AtomicU64,
+}
+
+impl TaskAccounting {
+    pub const fn new() -> Self {
+        Self {
+            run_ns: AtomicU64::new(0),
+            system_ns: AtomicU64::new(0),
+            nvcsw: AtomicU64::new(0),
+            nivcsw: AtomicU64::new(0),
+            wait_ns: AtomicU64::new(0),
+            run_start: AtomicU64::new(0),
+            ready_since: AtomicU64::new(0),
+        }
+    }
+
+    /// Records that the task is put into the run queue at `now`.
+    pub fn enqueue(&self, now: u64) {
+        self.ready_since.store(now, Ordering::Relaxed);
+    }
+
+    /// Records that the task is switched in at `now`, returns the time it
+    /// waited in the run queue.
+    pub fn switch_in(&self, now: u64) -> u64 {
+        self.run_start.store(now, Ordering::Relaxed);
+        let wait = now.saturating_sub(self.ready_since.load(Ordering::Relaxed));
+        self.wait_ns.fetch_add(wait, Ordering::Relaxed);
+        wait
+    }
+
+    /// Records that the task is switched out at `now`, returns the time it
+    /// ran since it was switched in.
+    pub fn switch_out(&self, now: u64, voluntary: bool) -> u64 {
+        let run = now.saturating_sub(self.run_start.load(Ordering::Relaxed));
+        self.run_ns.fetch_add(run, Ordering::Relaxed);
+        if voluntary {
+            self.nvcsw.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.nivcsw.fetch_add(1, Ordering::Relaxed);
+        }
+        run
+    }
+
+    /// Charges the time spent in interrupt handlers as the system time.
+    pub fn add_system_time(&self, nanos: u64) {
+        self.system_ns.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Returns the statistics, `running` indicates whether the current
+    /// running period should be counted.
+    pub fn stat(&self, running: bool, last_cpu: usize) -> TaskStat {
+        let mut run_ns = self.run_ns.load(Ordering::Relaxed);
+        if running {
+            run_ns += current_time_nanos().saturating_sub(self.run_start.load(Ordering::Relaxed));
+        }
+        let system_ns = self.system_ns.load(Ordering::Relaxed);
+        TaskStat {
+            user_time: Duration::from_nanos(run_ns.saturating_sub(system_ns)),
+            system_time: Duration::from_nanos(system_ns),
+            voluntary_switches: self.nvcsw.load(Ordering::Relaxed),
+            involuntary_switches: self.nivcsw.load(Ordering::Relaxed),
+            wait_time: Duration::from_nanos(self.wait_ns.load(Ordering::Relaxed)),
+            last_cpu,
+        }
+    }
+}
+
+/// Updates the statistics of both tasks when switching from `prev` to `next`.
+pub(crate) fn account_task_switch(prev: &AxTaskRef, next: &AxTaskRef, voluntary: bool) {
+    let now = current_time_nanos();
+    let run = prev.accounting().switch_out(now, voluntary);
+    if !prev.is_idle() {
+        TOTAL.run_ns.fetch_add(run, Ordering::Relaxed);
+        let counter = if voluntary {
+            &TOTAL.nvcsw
+        } else {
+            &TOTAL.nivcsw
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    let wait = next.accounting().switch_in(now);
+    if !next.is_idle() {
+        TOTAL.wait_ns.fetch_add(wait, Ordering::Relaxed);
+    }
+}
+
+/// Charges the time spent in an interrupt handler to the current task as its
+/// system time.
+pub fn account_irq_time(dur: Duration) {
+    if let Some(curr) = crate::current_may_uninit() {
+        let nanos = dur.as_nanos() as u64;
+        curr.accounting().add_system_time(nanos);
+        if !curr.is_idle() {
+            TOTAL.add_system_time(nanos);
+        }
+    }
+}
+
+/// Returns the accumulated CPU usage statistics of all tasks except the idle
+/// tasks, including the exited ones.
+///
+/// The current running period of the current task is included, but those of
+/// the tasks running on other CPUs are not.
+pub fn total_stat() -> TaskStat {
+    let mut stat = TOTAL.stat(false, axhal::cpu::this_cpu_id());
+    if let Some(curr) = crate::current_may_uninit() {
+        if !curr.is_idle() {
+            let acct = curr.accounting();
+            let running =
+                current_time_nanos().saturating_sub(acct.run_start.load(Ordering::Relaxed));
+            stat.user_time += Duration::from_nanos(running);
+        }
+    }
+    stat
+}
@@ -1,20 +1,29 @@
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicIsize, AtomicU8, AtomicUsize, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
+use allocator::{BaseAllocator, BitmapIdAllocator, IdAllocator};
 use axhal::arch::TaskContext;
 use memory_addr::{align_up_4k, VirtAddr};
+use spinlock::{SpinNoIrq, SpinNoIrqGuard};
 
-use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+use crate::pi::TaskPiState;
+use crate::sched_class::SchedParams;
+use crate::stat::{TaskAccounting, TaskStat};
+#[cfg(feature = "irq")]
+use crate::timers::TimerRef;
+#[cfg(feature = "watchdog")]
+use crate::watchdog::TaskWatchdog;
+use crate::{AxCpuMask, AxRunQueue, AxTask, AxTaskRef, SchedPolicy, WaitQueue};
 
 /// A unique identifier for a thread.
+///
+/// It's unique among the live tasks, and may be reused after the task is
+/// dropped.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct TaskId(u64);
 
@@ -38,9 +47,49 @@ pub struct TaskInner {
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
 
+    /// The CPU that the task is queued on, or last ran on.
+    cpu_id: AtomicUsize,
+    /// Whether the task is running on a CPU, or its context is being saved.
+    on_cpu: AtomicBool,
+    /// The CPUs that the task is allowed to run on.
+    cpumask: SpinNoIrq<AxCpuMask>,
+    /// The CPU whose EDF bandwidth is reserved by the task, or `usize::MAX`
+    /// for best-effort tasks. Real-time tasks only run on this CPU.
+    #[cfg(feature = "sched_edf")]
+    edf_cpu: AtomicUsize,
+    /// Whether the task is in the scheduler of a run queue.
+    in_run_queue: AtomicBool,
+
+    /// The effective scheduling parameters, which may be inherited from
+    /// the waiters of the locks owned by the task.
+    sched_policy: AtomicU8,
+    rt_priority: AtomicUsize,
+    normal_prio: AtomicIsize,
+    pi: SpinNoIrq<TaskPiState>,
+
     in_wait_queue: AtomicBool,
     #[cfg(feature = "irq")]
     in_timer_list: AtomicBool,
+    /// The alarm in the timer list of some CPU, to cancel it.
+    #[cfg(feature = "irq")]
+    timer_handle: SpinNoIrq<Option<TimerRef>>,
+    /// The address of the futex that the task is waiting on, or 0.
+    futex_addr: AtomicUsize,
+    /// Whether another task has requested to cancel this task.
+    cancel_pending: AtomicBool,
+    /// Whether the task exits at cancellation points if it's canceled.
+    cancel_enabled: AtomicBool,
+    /// The number of sleeping locks held by the task, it's not canceled
+    /// until they are all released.
+    sleeping_locks: AtomicUsize,
+
+    accounting: TaskAccounting,
+    #[cfg(feature = "watchdog")]
+    watchdog: TaskWatchdog,
+    /// The locks held by the task, only accessed by the task itself or by
+    /// the context switch with IRQs disabled.
+    #[cfg(feature = "lockdep")]
+    held_locks: UnsafeCell<lockdep::HeldLocks>,
 
     #[cfg(feature = "preempt")]
     need_resched: AtomicBool,
@@ -57,10 +106,34 @@ pub struct TaskInner {
     tls: TlsArea,
 }
 
+/// The number of bitmap words for task IDs, from 1 to [`axconfig::MAX_TASKS`].
+const TASK_ID_WORDS: usize = axconfig::MAX_TASKS / 64 + 1;
+
+/// The allocator of task IDs. IDs of dropped tasks are reused, and the lowest
+/// free ID is always allocated first to keep the IDs small.
+pub(crate) static TASK_IDS: SpinNoIrq<BitmapIdAllocator<TASK_ID_WORDS>> =
+    SpinNoIrq::new(BitmapIdAllocator::new());
+
+/// The error type of spawning a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// The CPU affinity mask is empty.
+    EmptyCpuMask,
+    /// There are already [`axconfig::MAX_TASKS`] tasks.
+    TooManyTasks,
+}
+
 impl TaskId {
-    fn new() -> Self {
-        static ID_COUNTER: AtomicU64 = AtomicU64::new(1);
-        Self(ID_COUNTER.fetch_add(1, Ordering::Relaxed))
+    fn new() -> Result<Self, SpawnError> {
+        let mut ids = TASK_IDS.lock();
+        if ids.size() == 0 {
+            // ID 0 is never used, which means the current task in some APIs.
+            ids.init(1, axconfig::MAX_TASKS);
+        }
+        match ids.alloc_id(1, 1) {
+            Ok(id) => Ok(Self(id as u64)),
+            Err(_) => Err(SpawnError::TooManyTasks),
+        }
     }
 
     /// Convert the task ID to a `u64`.
@@ -101,6 +174,45 @@ impl TaskInner {
         alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
     }
 
+    /// Gets the CPU affinity mask of the task.
+    pub fn cpumask(&self) -> AxCpuMask {
+        *self.cpumask.lock()
+    }
+
+    /// Gets the scheduling policy of the task.
+    pub fn sched_policy(&self) -> SchedPolicy {
+        // Only valid policies are stored by `set_sched_params`.
+        SchedPolicy::try_from(self.sched_policy.load(Ordering::Acquire))
+            .unwrap_or(SchedPolicy::Normal)
+    }
+
+    /// Gets the real-time priority of the task, or 0 if it's not a real-time
+    /// task.
+    pub fn sched_priority(&self) -> usize {
+        if self.sched_policy().is_realtime() {
+            self.rt_priority.load(Ordering::Acquire)
+        } else {
+            0
+        }
+    }
+
+    /// Gets the CPU usage statistics of the task.
+    pub fn stat(&self) -> TaskStat {
+        self.accounting.stat(self.is_running(), self.cpu_id())
+    }
+
+    /// Whether the task has been requested to be canceled by [`cancel`].
+    ///
+    /// [`cancel`]: crate::cancel
+    pub fn is_cancel_pending(&self) -> bool {
+        self.cancel_pending.load(Ordering::SeqCst)
+    }
+
+    /// Whether the task has exited, it may not be joined or dropped yet.
+    pub fn is_exited(&self) -> bool {
+        self.state() == TaskState::Exited
+    }
+
     /// Wait for the task to exit, and return the exit code.
     ///
     /// It will return immediately if the task has already exited (but not dropped).
@@ -121,9 +233,30 @@ impl TaskInner {
             is_init: false,
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            cpu_id: AtomicUsize::new(0),
+            on_cpu: AtomicBool::new(false),
+            cpumask: SpinNoIrq::new(AxCpuMask::mask(axconfig::SMP)),
+            #[cfg(feature = "sched_edf")]
+            edf_cpu: AtomicUsize::new(usize::MAX),
+            in_run_queue: AtomicBool::new(false),
+            sched_policy: AtomicU8::new(SchedPolicy::Normal as u8),
+            rt_priority: AtomicUsize::new(0),
+            normal_prio: AtomicIsize::new(0),
+            pi: SpinNoIrq::new(TaskPiState::new(SchedParams::default())),
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            #[cfg(feature = "irq")]
+            timer_handle: SpinNoIrq::new(None),
+            futex_addr: AtomicUsize::new(0),
+            cancel_pending: AtomicBool::new(false),
+            cancel_enabled: AtomicBool::new(true),
+            sleeping_locks: AtomicUsize::new(0),
+            accounting: TaskAccounting::new(),
+            #[cfg(feature = "watchdog")]
+            watchdog: TaskWatchdog::new(),
+            #[cfg(feature = "lockdep")]
+            held_locks: UnsafeCell::new(lockdep::HeldLocks::new()),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
@@ -138,11 +271,13 @@ impl TaskInner {
     }
 
     /// Create a new task with the given entry function and stack size.
-    pub(crate) fn new<F>(entry: F, name: String, stack_size: usize) -> AxTaskRef
+    ///
+    /// Returns [`SpawnError::TooManyTasks`] if there is no free task ID.
+    pub(crate) fn new<F>(entry: F, name: String, stack_size: usize) -> Result<AxTaskRef, SpawnError>
     where
         F: FnOnce() + Send + 'static,
     {
-        let mut t = Self::new_common(TaskId::new(), name);
+        let mut t = Self::new_common(TaskId::new()?, name);
         debug!("new task: {}", t.id_name());
         let kstack = TaskStack::alloc(align_up_4k(stack_size));
 
@@ -151,13 +286,23 @@ impl TaskInner {
         #[cfg(not(feature = "tls"))]
         let tls = VirtAddr::from(0);
 
+        // Inherit the scheduling policy from the creator, but not the
+        // priority inherited from its lock waiters.
+        if let Some(curr) = crate::current_may_uninit() {
+            let params = SchedParams {
+                prio: 0,
+                ..curr.pi_state().base()
+            };
+            t.set_sched_params(params);
+            *t.pi.get_mut() = TaskPiState::new(params);
+        }
         t.entry = Some(Box::into_raw(Box::new(entry)));
         t.ctx.get_mut().init(task_entry as usize, kstack.top(), tls);
         t.kstack = Some(kstack);
         if t.name == "idle" {
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        Ok(Arc::new(AxTask::new(t)))
     }
 
     /// Creates an "init task" using the current CPU states, to use as the
@@ -169,7 +314,10 @@ impl TaskInner {
     /// And there is no need to set the `entry`, `kstack` or `tls` fields, as
     /// they will be filled automatically when the task is switches out.
     pub(crate) fn new_init(name: String) -> AxTaskRef {
-        let mut t = Self::new_common(TaskId::new(), name);
+        let Ok(id) = TaskId::new() else {
+            panic!("too many tasks, at most {}", axconfig::MAX_TASKS);
+        };
+        let mut t = Self::new_common(id, name);
         t.is_init = true;
         if t.name == "idle" {
             t.is_idle = true;
@@ -187,6 +335,20 @@ impl TaskInner {
         self.state.store(state as u8, Ordering::Release)
     }
 
+    /// Transitions the task state from `current` to `new` atomically. Returns
+    /// `false` if the task is not in the `current` state.
+    #[inline]
+    pub(crate) fn transition_state(&self, current: TaskState, new: TaskState) -> bool {
+        self.state
+            .compare_exchange(
+                current as u8,
+                new as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+    }
+
     #[inline]
     pub(crate) fn is_running(&self) -> bool {
         matches!(self.state(), TaskState::Running)
@@ -197,11 +359,6 @@ impl TaskInner {
         matches!(self.state(), TaskState::Ready)
     }
 
-    #[inline]
-    pub(crate) fn is_blocked(&self) -> bool {
-        matches!(self.state(), TaskState::Blocked)
-    }
-
     #[inline]
     pub(crate) const fn is_init(&self) -> bool {
         self.is_init
@@ -212,6 +369,92 @@ impl TaskInner {
         self.is_idle
     }
 
+    #[inline]
+    pub(crate) fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_cpumask(&self, cpumask: AxCpuMask) {
+        *self.cpumask.lock() = cpumask;
+    }
+
+    /// Whether the task is allowed to run on the given CPU.
+    #[inline]
+    pub(crate) fn can_run_on(&self, cpu_id: usize) -> bool {
+        #[cfg(feature = "sched_edf")]
+        if let Some(edf_cpu) = self.edf_cpu() {
+            return cpu_id == edf_cpu;
+        }
+        cpu_id < axconfig::SMP && self.cpumask.lock().get(cpu_id)
+    }
+
+    /// Returns the CPU whose EDF bandwidth is reserved by the task, or
+    /// [`None`] if it's a best-effort task.
+    #[cfg(feature = "sched_edf")]
+    #[inline]
+    pub(crate) fn edf_cpu(&self) -> Option<usize> {
+        let cpu_id = self.edf_cpu.load(Ordering::Acquire);
+        (cpu_id != usize::MAX).then_some(cpu_id)
+    }
+
+    #[cfg(feature = "sched_edf")]
+    #[inline]
+    pub(crate) fn set_edf_cpu(&self, cpu_id: Option<usize>) {
+        self.edf_cpu
+            .store(cpu_id.unwrap_or(usize::MAX), Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn in_run_queue(&self) -> bool {
+        self.in_run_queue.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_in_run_queue(&self, in_run_queue: bool) {
+        self.in_run_queue.store(in_run_queue, Ordering::Release);
+    }
+
+    /// Gets the effective scheduling parameters.
+    #[inline]
+    pub(crate) fn sched_params(&self) -> SchedParams {
+        SchedParams::new(
+            self.sched_policy(),
+            self.rt_priority.load(Ordering::Acquire),
+            self.normal_prio.load(Ordering::Acquire),
+        )
+    }
+
+    /// Sets the effective scheduling parameters. The task must not be in any
+    /// run queue.
+    #[inline]
+    pub(crate) fn set_sched_params(&self, params: SchedParams) {
+        self.sched_policy
+            .store(params.policy as u8, Ordering::Release);
+        self.rt_priority.store(params.rt_prio, Ordering::Release);
+        self.normal_prio.store(params.prio, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn pi_state(&self) -> SpinNoIrqGuard<TaskPiState> {
+        self.pi.lock()
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -234,6 +477,109 @@ impl TaskInner {
         self.in_timer_list.store(in_timer_list, Ordering::Release);
     }
 
+    #[inline]
+    #[cfg(feature = "irq")]
+    pub(crate) fn timer_handle(&self) -> SpinNoIrqGuard<Option<TimerRef>> {
+        self.timer_handle.lock()
+    }
+
+    /// Whether the given address is in the guard page of the task's kernel
+    /// stack, that is, accessing it means the stack has overflowed.
+    ///
+    /// Only the x86_64 page fault handler can reliably call it on overflows,
+    /// as the trap frame is pushed onto a separate double fault stack. On
+    /// riscv64 and aarch64, the trap frame is pushed onto the overflowed stack
+    /// itself, which faults again in the guard page before the handler runs.
+    #[cfg(feature = "paging")]
+    pub fn in_stack_guard(&self, vaddr: VirtAddr) -> bool {
+        self.kstack
+            .as_ref()
+            .is_some_and(|kstack| kstack.in_guard_page(vaddr))
+    }
+
+    /// Panics if the canary at the bottom of the task's kernel stack is
+    /// overwritten.
+    #[cfg(not(feature = "paging"))]
+    pub(crate) fn check_stack_canary(&self) {
+        if self.kstack.as_ref().is_some_and(TaskStack::canary_broken) {
+            panic!("stack overflow in {}", self.id_name());
+        }
+    }
+
+    #[inline]
+    pub(crate) fn futex_addr(&self) -> usize {
+        self.futex_addr.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_futex_addr(&self, addr: usize) {
+        self.futex_addr.store(addr, Ordering::Release);
+    }
+
+    /// Marks the task as canceled. Returns `false` if it's already canceled.
+    #[inline]
+    pub(crate) fn set_cancel_pending(&self) -> bool {
+        !self.cancel_pending.swap(true, Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn cancel_enabled(&self) -> bool {
+        self.cancel_enabled.load(Ordering::Acquire)
+    }
+
+    /// Sets whether the task can be canceled, and returns the old value.
+    #[inline]
+    pub(crate) fn set_cancel_enabled(&self, enabled: bool) -> bool {
+        self.cancel_enabled.swap(enabled, Ordering::AcqRel)
+    }
+
+    /// Whether the task should exit at a cancellation point.
+    #[inline]
+    pub(crate) fn is_canceled(&self) -> bool {
+        self.cancel_enabled() && self.is_cancel_pending() && self.sleeping_locks() == 0
+    }
+
+    /// The number of sleeping locks held by the task.
+    #[inline]
+    pub(crate) fn sleeping_locks(&self) -> usize {
+        self.sleeping_locks.load(Ordering::SeqCst)
+    }
+
+    /// Counts a sleeping lock acquired by the task, or released if `acquired`
+    /// is `false`.
+    #[inline]
+    pub(crate) fn count_sleeping_lock(&self, acquired: bool) {
+        if acquired {
+            self.sleeping_locks.fetch_add(1, Ordering::SeqCst);
+        } else {
+            let old = self.sleeping_locks.fetch_sub(1, Ordering::SeqCst);
+            debug_assert!(old > 0, "{} released an unlocked lock", self.id_name());
+        }
+    }
+
+    #[inline]
+    pub(crate) fn accounting(&self) -> &TaskAccounting {
+        &self.accounting
+    }
+
+    #[inline]
+    #[cfg(feature = "watchdog")]
+    pub(crate) fn watchdog(&self) -> &TaskWatchdog {
+        &self.watchdog
+    }
+
+    #[cfg(feature = "lockdep")]
+    #[inline]
+    pub(crate) const fn held_locks_ptr(&self) -> *mut lockdep::HeldLocks {
+        self.held_locks.get()
+    }
+
+    /// Returns the address range of the kernel stack, or [`None`] if the task
+    /// runs on a stack not allocated by itself, such as the boot stack.
+    pub fn kstack_range(&self) -> Option<core::ops::Range<usize>> {
+        self.kstack.as_ref().map(|kstack| kstack.range())
+    }
+
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn set_preempt_pending(&self, pending: bool) {
@@ -265,7 +611,7 @@ impl TaskInner {
     fn current_check_preempt_pending() {
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
-            let mut rq = crate::RUN_QUEUE.lock();
+            let mut rq = crate::current_run_queue();
             if curr.need_resched.load(Ordering::Acquire) {
                 rq.preempt_resched();
             }
@@ -289,37 +635,179 @@ impl fmt::Debug for TaskInner {
             .field("id", &self.id)
             .field("name", &self.name)
             .field("state", &self.state())
+            .field("cpu_id", &self.cpu_id())
+            .field("sched_policy", &self.sched_policy())
             .finish()
     }
 }
 
+impl scheduler::RTSchedInfo for TaskInner {
+    fn rt_priority(&self) -> usize {
+        self.rt_priority.load(Ordering::Acquire)
+    }
+
+    fn set_rt_priority(&self, prio: usize) {
+        self.rt_priority.store(prio, Ordering::Release);
+    }
+
+    fn is_round_robin(&self) -> bool {
+        self.sched_policy() == SchedPolicy::RoundRobin
+    }
+}
+
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        // Before the ID is reused by another task.
+        #[cfg(feature = "watchdog")]
+        crate::watchdog::unregister_task(self);
+        TASK_IDS.lock().dealloc_id(self.id.0 as usize, 1);
+    }
+}
+
+/// The size of the unmapped guard page below each task stack.
+#[cfg(feature = "paging")]
+const STACK_GUARD_SIZE: usize = memory_addr::PAGE_SIZE_4K;
+
+/// Incremented each time a stack guard page is unmapped.
+#[cfg(all(feature = "paging", feature = "smp"))]
+static STACK_GUARD_GEN: AtomicUsize = AtomicUsize::new(0);
+
+/// The value of [`STACK_GUARD_GEN`] when the TLB of the CPU was last flushed.
+#[cfg(all(feature = "paging", feature = "smp"))]
+#[percpu::def_percpu]
+static FLUSHED_GUARD_GEN: usize = 0;
+
+/// Flushes the TLB of the current CPU if any stack guard page has been
+/// unmapped since the last flush.
+///
+/// The guard page is unmapped in the shared kernel page table, but other CPUs
+/// may have cached its translation from an earlier use of the memory. The
+/// stack is not used by anyone until its task runs, so it's enough for each
+/// CPU to flush before switching to the next task. It must be called with
+/// IRQs disabled.
+#[cfg(all(feature = "paging", feature = "smp"))]
+pub(crate) fn sync_stack_guards() {
+    let gen = STACK_GUARD_GEN.load(Ordering::Acquire);
+    let flushed = unsafe { FLUSHED_GUARD_GEN.current_ref_mut_raw() };
+    if *flushed != gen {
+        axhal::arch::flush_tlb(None);
+        *flushed = gen;
     }
 }
 
+/// The magic number at the bottom of each task stack, which is checked on
+/// context switches to detect stack overflows.
+#[cfg(not(feature = "paging"))]
+const STACK_CANARY: u64 = 0x5354_4143_4b5f_4341; // "STACK_CA"
+
 struct TaskStack {
     ptr: NonNull<u8>,
     layout: Layout,
+    #[cfg(feature = "paging")]
+    guarded: bool,
 }
 
 impl TaskStack {
+    #[cfg(feature = "paging")]
     pub fn alloc(size: usize) -> Self {
-        let layout = Layout::from_size_align(size, 16).unwrap();
-        Self {
+        let layout = Layout::from_size_align(size + STACK_GUARD_SIZE, STACK_GUARD_SIZE).unwrap();
+        let mut stack = Self {
             ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
             layout,
+            guarded: false,
+        };
+        match stack.set_guard_page(true) {
+            Ok(()) => stack.guarded = true,
+            Err(e) => {
+                static WARNED: AtomicBool = AtomicBool::new(false);
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    warn!("failed to set the stack guard page: {:?}", e);
+                }
+            }
         }
+        stack
+    }
+
+    #[cfg(not(feature = "paging"))]
+    pub fn alloc(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, 16).unwrap();
+        let stack = Self {
+            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
+            layout,
+        };
+        unsafe { stack.ptr.cast::<u64>().as_ptr().write(STACK_CANARY) };
+        stack
     }
 
     pub const fn top(&self) -> VirtAddr {
         unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.layout.size())) }
     }
+
+    /// The accessible address range of the stack, excluding the guard page.
+    pub fn range(&self) -> core::ops::Range<usize> {
+        let start = self.ptr.as_ptr() as usize;
+        #[cfg(feature = "paging")]
+        let start = if self.guarded {
+            start + STACK_GUARD_SIZE
+        } else {
+            start
+        };
+        start..self.top().as_usize()
+    }
+
+    /// Whether the given address is in the guard page of the stack.
+    #[cfg(feature = "paging")]
+    pub fn in_guard_page(&self, vaddr: VirtAddr) -> bool {
+        let guard_start = self.ptr.as_ptr() as usize;
+        self.guarded && (guard_start..guard_start + STACK_GUARD_SIZE).contains(&vaddr.as_usize())
+    }
+
+    /// Unmaps (`guard` is true) or re-maps the lowest page of the stack in
+    /// the kernel page table.
+    ///
+    /// The stack memory is in the linear mapping. If the page is in a huge
+    /// page, the huge page is split into 4K pages first.
+    #[cfg(feature = "paging")]
+    fn set_guard_page(&self, guard: bool) -> axhal::paging::PagingResult {
+        use axhal::paging::{kernel_page_table, MappingFlags, PageSize};
+
+        let vaddr = VirtAddr::from(self.ptr.as_ptr() as usize);
+        let mut page_table = kernel_page_table().lock();
+        if guard {
+            while page_table.split_huge_page(vaddr)? != PageSize::Size4K {}
+            page_table.unmap(vaddr)?;
+        } else {
+            let paddr = axhal::mem::virt_to_phys(vaddr);
+            let flags = MappingFlags::READ | MappingFlags::WRITE;
+            page_table.map(vaddr, paddr, PageSize::Size4K, flags)?;
+        }
+        axhal::arch::flush_tlb(Some(vaddr));
+        // Other CPUs flush their stale TLB entries of the guard page before
+        // they can run the task, see `sync_stack_guards`.
+        #[cfg(feature = "smp")]
+        if guard {
+            STACK_GUARD_GEN.fetch_add(1, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Whether the canary at the bottom of the stack is overwritten.
+    #[cfg(not(feature = "paging"))]
+    pub fn canary_broken(&self) -> bool {
+        unsafe { self.ptr.cast::<u64>().as_ptr().read_volatile() != STACK_CANARY }
+    }
 }
 
 impl Drop for TaskStack {
     fn drop(&mut self) {
+        // The guard page must be accessible again before it's returned to the
+        // allocator.
+        #[cfg(feature = "paging")]
+        if self.guarded {
+            self.set_guard_page(false)
+                .expect("failed to restore the stack guard page");
+        }
         unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
     }
 }
@@ -380,7 +868,10 @@ impl Deref for CurrentTask {
 
 extern "C" fn task_entry() -> ! {
     // release the lock that was implicitly held across the reschedule
-    unsafe { crate::RUN_QUEUE.force_unlock() };
+    unsafe {
+        crate::run_queue::finish_task_switch();
+        crate::run_queue::force_unlock_current();
+    }
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
     let task = crate::current();
@@ -389,3 +880,32 @@ extern "C" fn task_entry() -> ! {
     }
     crate::exit(0);
 }
+
+#[cfg(all(test, feature = "paging"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_stack_guard() {
+        // There is no kernel page table in tests, so the guard page is only
+        // marked as unmapped.
+        let layout = Layout::from_size_align(0x4000 + STACK_GUARD_SIZE, STACK_GUARD_SIZE).unwrap();
+        let stack = TaskStack {
+            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
+            layout,
+            guarded: true,
+        };
+        let bottom = stack.ptr.as_ptr() as usize;
+        let mut t = TaskInner::new_common(TaskId::new().unwrap(), "guarded".into());
+        t.kstack = Some(stack);
+
+        assert!(t.in_stack_guard(bottom.into()));
+        assert!(t.in_stack_guard((bottom + STACK_GUARD_SIZE - 1).into()));
+        assert!(!t.in_stack_guard((bottom + STACK_GUARD_SIZE).into()));
+        assert!(!t.in_stack_guard((bottom - 1).into()));
+        assert_eq!(t.kstack_range().unwrap().start, bottom + STACK_GUARD_SIZE);
+
+        // Don't map the guard page again on drop.
+        t.kstack.as_mut().unwrap().guarded = false;
+    }
+}
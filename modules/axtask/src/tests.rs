@@ -1,7 +1,9 @@
-use core::sync::atomic::{AtomicUsize, Ordering};
+#![allow(clippy::approx_constant, clippy::needless_range_loop)]
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 use std::sync::{Mutex, Once};
 
-use crate::{self as axtask, current, WaitQueue};
+use crate::{self as axtask, current, FutexError, WaitQueue};
 
 static INIT: Once = Once::new();
 static SERIAL: Mutex<()> = Mutex::new(());
@@ -128,3 +130,849 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_spawn_errors() {
+    use allocator::IdAllocator;
+    use axtask::SpawnError;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let res = axtask::spawn_raw_with_affinity(|| {}, "T".into(), 0x1000, axtask::AxCpuMask::new());
+    assert!(matches!(res, Err(SpawnError::EmptyCpuMask)));
+
+    // Use up the task IDs, and then no task can be spawned.
+    let mut ids = Vec::new();
+    while let Ok(id) = crate::task::TASK_IDS.lock().alloc_id(1, 1) {
+        ids.push(id);
+    }
+    assert!(matches!(
+        axtask::try_spawn(|| {}),
+        Err(SpawnError::TooManyTasks)
+    ));
+    let mut cpumask = axtask::AxCpuMask::new();
+    cpumask.set(0, true);
+    let res = axtask::spawn_raw_with_affinity(|| {}, "T".into(), 0x1000, cpumask);
+    assert!(matches!(res, Err(SpawnError::TooManyTasks)));
+
+    for id in ids {
+        crate::task::TASK_IDS.lock().dealloc_id(id, 1);
+    }
+    let task = axtask::try_spawn(|| axtask::exit(7)).unwrap();
+    assert_eq!(task.join(), Some(7));
+}
+
+#[test]
+#[cfg(not(feature = "paging"))]
+fn test_stack_canary() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // The task is not spawned, so its stack is not used.
+    let task = crate::task::TaskInner::new(|| {}, "canary".into(), 0x1000).unwrap();
+    task.check_stack_canary();
+
+    let bottom = task.kstack_range().unwrap().start as *mut u64;
+    let canary = unsafe { bottom.read() };
+    unsafe { bottom.write(0) };
+    let check = std::panic::AssertUnwindSafe(|| task.check_stack_canary());
+    let err = std::panic::catch_unwind(check).unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(msg.starts_with("stack overflow in Task("), "{}", msg);
+    assert!(msg.contains("canary"));
+    unsafe { bottom.write(canary) };
+    task.check_stack_canary();
+}
+
+#[test]
+fn test_futex_wait_mismatch() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let futex = AtomicU32::new(1);
+    assert_eq!(
+        axtask::futex_wait(&futex, 0, None),
+        Err(FutexError::WouldBlock)
+    );
+    assert!(!current().in_wait_queue());
+    assert_eq!(axtask::futex_wake(&futex, 1), 0);
+}
+
+/// Spawns `n` tasks waiting on `futex` until it's no longer 0, and waits until
+/// all of them are blocked. Returns the counter of the woken tasks.
+fn spawn_futex_waiters(futex: &'static AtomicU32, n: usize) -> &'static AtomicUsize {
+    let started: &'static AtomicUsize = Box::leak(Box::new(AtomicUsize::new(0)));
+    let woken: &'static AtomicUsize = Box::leak(Box::new(AtomicUsize::new(0)));
+    for _ in 0..n {
+        axtask::spawn(move || {
+            started.fetch_add(1, Ordering::Relaxed);
+            assert_eq!(axtask::futex_wait(futex, 0, None), Ok(()));
+            woken.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    // Tasks run on this CPU in order, and block without yielding.
+    while started.load(Ordering::Relaxed) < n {
+        axtask::yield_now();
+    }
+    woken
+}
+
+#[test]
+fn test_futex_wake_count() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static FUTEX: AtomicU32 = AtomicU32::new(0);
+    let woken = spawn_futex_waiters(&FUTEX, 5);
+    assert_eq!(woken.load(Ordering::Relaxed), 0);
+
+    assert_eq!(axtask::futex_wake(&FUTEX, 2), 2);
+    while woken.load(Ordering::Relaxed) < 2 {
+        axtask::yield_now();
+    }
+    axtask::yield_now();
+    assert_eq!(woken.load(Ordering::Relaxed), 2);
+
+    assert_eq!(axtask::futex_wake(&FUTEX, usize::MAX), 3);
+    while woken.load(Ordering::Relaxed) < 5 {
+        axtask::yield_now();
+    }
+    assert_eq!(axtask::futex_wake(&FUTEX, usize::MAX), 0);
+}
+
+#[test]
+fn test_futex_requeue() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static FUTEX: AtomicU32 = AtomicU32::new(0);
+    static TARGET: AtomicU32 = AtomicU32::new(0);
+    let woken = spawn_futex_waiters(&FUTEX, 4);
+
+    assert_eq!(axtask::futex_requeue(&FUTEX, 1, &TARGET, 2), (1, 2));
+    while woken.load(Ordering::Relaxed) < 1 {
+        axtask::yield_now();
+    }
+    // One task is left on the original futex, and two are moved to the target.
+    assert_eq!(axtask::futex_wake(&TARGET, usize::MAX), 2);
+    assert_eq!(axtask::futex_wake(&FUTEX, usize::MAX), 1);
+    while woken.load(Ordering::Relaxed) < 4 {
+        axtask::yield_now();
+    }
+    assert_eq!(axtask::futex_requeue(&FUTEX, 1, &TARGET, 1), (0, 0));
+}
+
+#[cfg(feature = "irq")]
+#[test]
+fn test_futex_timeout() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static FUTEX: AtomicU32 = AtomicU32::new(0);
+    let waiter = axtask::spawn(|| {
+        let res = axtask::futex_wait(&FUTEX, 0, Some(core::time::Duration::ZERO));
+        assert_eq!(res, Err(FutexError::TimedOut));
+        assert!(!current().in_wait_queue());
+    });
+    while !waiter.in_wait_queue() {
+        axtask::yield_now();
+    }
+    // The clock does not advance on the host, expire the alarm by hand.
+    crate::timers::check_events();
+    assert_eq!(waiter.join(), Some(0));
+    assert_eq!(axtask::futex_wake(&FUTEX, 1), 0);
+}
+
+#[test]
+fn test_task_accounting() {
+    use crate::stat::TaskAccounting;
+    use core::time::Duration;
+
+    let acct = TaskAccounting::new();
+    acct.enqueue(100);
+    assert_eq!(acct.switch_in(150), 50);
+    acct.add_system_time(30);
+    assert_eq!(acct.switch_out(400, false), 250);
+    acct.enqueue(400);
+    assert_eq!(acct.switch_in(420), 20);
+    assert_eq!(acct.switch_out(500, true), 80);
+
+    let stat = acct.stat(false, 1);
+    assert_eq!(stat.user_time, Duration::from_nanos(300));
+    assert_eq!(stat.system_time, Duration::from_nanos(30));
+    assert_eq!(stat.wait_time, Duration::from_nanos(70));
+    assert_eq!(stat.involuntary_switches, 1);
+    assert_eq!(stat.voluntary_switches, 1);
+    assert_eq!(stat.last_cpu, 1);
+
+    // the system time is never more than the running time
+    acct.add_system_time(1000);
+    assert_eq!(acct.stat(false, 1).user_time, Duration::ZERO);
+}
+
+#[test]
+fn test_task_stat() {
+    use core::sync::atomic::AtomicBool;
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+    static WOKEN: AtomicBool = AtomicBool::new(false);
+
+    let total_before = axtask::total_stat();
+    let task = axtask::spawn(|| {
+        axtask::yield_now();
+        axtask::yield_now();
+        WQ.wait_until(|| WOKEN.load(Ordering::Acquire));
+        axtask::account_irq_time(Duration::from_micros(5));
+
+        let stat = current().stat();
+        assert_eq!(stat.involuntary_switches, 2);
+        assert_eq!(stat.voluntary_switches, 1);
+        assert_eq!(stat.system_time, Duration::from_micros(5));
+    });
+    while !task.in_wait_queue() {
+        axtask::yield_now();
+    }
+    WOKEN.store(true, Ordering::Release);
+    WQ.notify_one(true);
+    task.join();
+
+    // the exited task is still counted
+    let stat = task.stat();
+    assert_eq!(stat.voluntary_switches, 2);
+    let total = axtask::total_stat();
+    assert!(total.system_time >= total_before.system_time + Duration::from_micros(5));
+    assert!(total.voluntary_switches >= total_before.voluntary_switches + 2);
+}
+
+#[test]
+fn test_cancel_blocked() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+    static RETURNED: AtomicUsize = AtomicUsize::new(0);
+
+    let task = axtask::spawn(|| {
+        WQ.wait();
+        RETURNED.fetch_add(1, Ordering::Relaxed);
+    });
+    while !task.in_wait_queue() {
+        axtask::yield_now();
+    }
+    assert!(axtask::cancel(&task));
+    assert!(!axtask::cancel(&task));
+    assert_eq!(task.join(), Some(axtask::EXIT_CANCELED));
+    assert_eq!(RETURNED.load(Ordering::Relaxed), 0);
+    assert!(!task.in_wait_queue());
+    // the canceled task has left the queue
+    assert!(!WQ.notify_one(false));
+}
+
+#[test]
+fn test_cancel_holding_lock() {
+    use core::sync::atomic::AtomicBool;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static LOCK: axtask::PiState = axtask::PiState::new();
+    static WQ: WaitQueue = WaitQueue::new();
+    static WOKEN: AtomicBool = AtomicBool::new(false);
+    static UNLOCKED: AtomicBool = AtomicBool::new(false);
+    static RETURNED: AtomicBool = AtomicBool::new(false);
+
+    let task = axtask::spawn(|| {
+        assert!(LOCK.try_lock());
+        // not canceled at the cancellation point while holding the lock
+        WQ.wait_until(|| WOKEN.load(Ordering::Acquire));
+        axtask::test_cancel();
+        LOCK.unlock();
+        UNLOCKED.store(true, Ordering::Release);
+        axtask::test_cancel();
+        RETURNED.store(true, Ordering::Release);
+    });
+    while !task.in_wait_queue() {
+        axtask::yield_now();
+    }
+    assert!(axtask::cancel(&task));
+    // it's not woken up by the cancellation
+    axtask::yield_now();
+    assert!(task.in_wait_queue());
+    assert!(LOCK.is_locked());
+
+    WOKEN.store(true, Ordering::Release);
+    WQ.notify_one(true);
+    assert_eq!(task.join(), Some(axtask::EXIT_CANCELED));
+    assert!(UNLOCKED.load(Ordering::Acquire));
+    assert!(!RETURNED.load(Ordering::Acquire));
+    assert!(!LOCK.is_locked());
+}
+
+/// A sleeping lock with priority inheritance, as `axsync::Mutex` does.
+struct PiLock {
+    pi: axtask::PiState,
+    wq: WaitQueue,
+}
+
+impl PiLock {
+    const fn new() -> Self {
+        Self {
+            pi: axtask::PiState::new(),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    fn lock(&self) {
+        while !self.pi.try_lock() {
+            if self.pi.add_waiter() {
+                self.wq.wait_until(|| !self.pi.is_locked());
+                self.pi.remove_waiter();
+            }
+        }
+    }
+
+    fn unlock(&self) {
+        self.pi.unlock();
+        self.wq.notify_one(true);
+    }
+}
+
+#[test]
+fn test_pi_chain() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static L1: PiLock = PiLock::new();
+    static L2: PiLock = PiLock::new();
+    static STAGE: AtomicUsize = AtomicUsize::new(0);
+    static LOW_PRIO: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+    static MID_PRIO: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+
+    fn prio() -> usize {
+        current().sched_priority()
+    }
+
+    // `low` holds L1, `mid` holds L2 and waits for L1, and `high` with a
+    // real-time priority waits for L2.
+    let low = axtask::spawn(|| {
+        L1.lock();
+        STAGE.store(1, Ordering::Release);
+        // boosted through `mid`
+        while prio() == 0 {
+            axtask::yield_now();
+        }
+        LOW_PRIO[0].store(prio(), Ordering::Release);
+        L1.unlock();
+        LOW_PRIO[1].store(prio(), Ordering::Release);
+    });
+    while STAGE.load(Ordering::Acquire) < 1 {
+        axtask::yield_now();
+    }
+    let mid = axtask::spawn(|| {
+        L2.lock();
+        STAGE.store(2, Ordering::Release);
+        L1.lock();
+        MID_PRIO[0].store(prio(), Ordering::Release);
+        L1.unlock();
+        L2.unlock();
+        MID_PRIO[1].store(prio(), Ordering::Release);
+    });
+    while !mid.in_wait_queue() {
+        axtask::yield_now();
+    }
+    assert_eq!(STAGE.load(Ordering::Acquire), 2);
+
+    let high = axtask::spawn(|| {
+        L2.lock();
+        L2.unlock();
+    });
+    assert!(axtask::set_sched_policy(
+        &high,
+        axtask::SchedPolicy::Fifo,
+        10
+    ));
+
+    high.join();
+    mid.join();
+    low.join();
+    assert_eq!(LOW_PRIO[0].load(Ordering::Acquire), 10);
+    assert_eq!(LOW_PRIO[1].load(Ordering::Acquire), 0);
+    // `mid` keeps the priority until it releases L2
+    assert_eq!(MID_PRIO[0].load(Ordering::Acquire), 10);
+    assert_eq!(MID_PRIO[1].load(Ordering::Acquire), 0);
+}
+
+#[test]
+fn test_pi_release_one_of_locks() {
+    use core::sync::atomic::AtomicBool;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static L1: PiLock = PiLock::new();
+    static L2: PiLock = PiLock::new();
+    static WQ: WaitQueue = WaitQueue::new();
+    static LOCKED: AtomicBool = AtomicBool::new(false);
+    static GO: AtomicBool = AtomicBool::new(false);
+    static PRIO: [AtomicUsize; 3] = [
+        AtomicUsize::new(0),
+        AtomicUsize::new(0),
+        AtomicUsize::new(0),
+    ];
+
+    // `owner` holds both locks, waited for by tasks with different priorities
+    let owner = axtask::spawn(|| {
+        L1.lock();
+        L2.lock();
+        LOCKED.store(true, Ordering::Release);
+        WQ.wait_until(|| GO.load(Ordering::Acquire));
+        PRIO[0].store(current().sched_priority(), Ordering::Release);
+        // it still inherits the priority of the waiter of L1
+        L2.unlock();
+        PRIO[1].store(current().sched_priority(), Ordering::Release);
+        L1.unlock();
+        PRIO[2].store(current().sched_priority(), Ordering::Release);
+    });
+    while !LOCKED.load(Ordering::Acquire) {
+        axtask::yield_now();
+    }
+
+    let waiter1 = axtask::spawn(|| {
+        L1.lock();
+        L1.unlock();
+    });
+    assert!(axtask::set_sched_policy(
+        &waiter1,
+        axtask::SchedPolicy::Fifo,
+        10
+    ));
+    let waiter2 = axtask::spawn(|| {
+        L2.lock();
+        L2.unlock();
+    });
+    assert!(axtask::set_sched_policy(
+        &waiter2,
+        axtask::SchedPolicy::Fifo,
+        20
+    ));
+    while !(waiter1.in_wait_queue() && waiter2.in_wait_queue()) {
+        axtask::yield_now();
+    }
+    GO.store(true, Ordering::Release);
+    WQ.notify_one(true);
+
+    owner.join();
+    waiter1.join();
+    waiter2.join();
+    assert_eq!(PRIO[0].load(Ordering::Acquire), 20);
+    assert_eq!(PRIO[1].load(Ordering::Acquire), 10);
+    assert_eq!(PRIO[2].load(Ordering::Acquire), 0);
+}
+
+#[cfg(feature = "irq")]
+#[test]
+fn test_timer_ref() {
+    use crate::hrtimer::HrTimer;
+    use crate::timers::{cancel_timer, check_events, set_timer, AxTimerEvent};
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // An event with a stale sequence number does nothing when it expires.
+    let timer = HrTimer::new(axtask::HrTimerMode::Irq, || panic!("stale event fired"));
+    let event = || AxTimerEvent::HrTimer(timer.inner.clone(), u64::MAX);
+
+    // The event is added to the timer list of the current CPU.
+    let r = set_timer(Duration::from_secs(1), event());
+    assert_eq!(r.cpu_id, axhal::cpu::this_cpu_id());
+    assert!(!crate::timers::timer_list(r.cpu_id).lock().is_empty());
+    assert!(cancel_timer(r));
+    assert!(!cancel_timer(r));
+    assert!(crate::timers::timer_list(r.cpu_id).lock().is_empty());
+
+    // The handle of an expired event matches nothing.
+    let r = set_timer(Duration::ZERO, event());
+    check_events();
+    assert!(!cancel_timer(r));
+    // even if its entry is reused
+    let r2 = set_timer(Duration::from_secs(1), event());
+    assert!(!cancel_timer(r));
+    assert!(cancel_timer(r2));
+}
+
+#[cfg(feature = "irq")]
+#[test]
+fn test_hrtimer_irq() {
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+    let timer = axtask::HrTimer::new(axtask::HrTimerMode::Irq, || {
+        FIRED.fetch_add(1, Ordering::Relaxed);
+    });
+    assert_eq!(timer.expires(), None);
+    assert!(!timer.cancel());
+
+    // The clock does not advance on the host, expire the events by hand.
+    timer.start(Duration::ZERO, None);
+    assert_eq!(timer.expires(), Some(Duration::ZERO));
+    crate::timers::check_events();
+    assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+    // a one-shot timer is inactive after it expires
+    assert_eq!(timer.expires(), None);
+    assert!(!timer.cancel());
+
+    // restarting replaces the previous deadline
+    timer.start(Duration::from_secs(1), None);
+    timer.start(Duration::ZERO, Some(Duration::from_millis(1)));
+    assert_eq!(timer.period(), Some(Duration::from_millis(1)));
+    crate::timers::check_events();
+    assert_eq!(FIRED.load(Ordering::Relaxed), 2);
+    // a periodic timer is rearmed for the next period
+    assert_eq!(timer.expires(), Some(Duration::from_millis(1)));
+    assert_eq!(timer.overrun(), 0);
+    crate::timers::check_events();
+    assert_eq!(FIRED.load(Ordering::Relaxed), 2);
+
+    assert!(timer.cancel());
+    assert_eq!(timer.expires(), None);
+    // a zero period means one-shot
+    timer.start(Duration::from_secs(1), Some(Duration::ZERO));
+    assert_eq!(timer.period(), None);
+    // dropping the timer cancels it
+    drop(timer);
+    let this_cpu = axhal::cpu::this_cpu_id();
+    assert!(crate::timers::timer_list(this_cpu).lock().is_empty());
+}
+
+#[cfg(feature = "irq")]
+#[test]
+fn test_hrtimer_task() {
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+    let main_task = current().clone();
+    let timer = axtask::HrTimer::new(axtask::HrTimerMode::Task, move || {
+        // runs in the timer task, not in the task that expires it
+        assert_ne!(current().id(), main_task.id());
+        FIRED.fetch_add(1, Ordering::Relaxed);
+    });
+
+    timer.start(Duration::ZERO, None);
+    crate::timers::check_events();
+    while FIRED.load(Ordering::Relaxed) < 1 {
+        axtask::yield_now();
+    }
+
+    // the deferred callback does not run if the timer is canceled first
+    timer.start(Duration::ZERO, None);
+    crate::timers::check_events();
+    assert!(!timer.cancel());
+    for _ in 0..10 {
+        axtask::yield_now();
+    }
+    assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_workqueue() {
+    use std::sync::Mutex as StdMutex;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static ORDER: StdMutex<Vec<usize>> = StdMutex::new(Vec::new());
+    static WQ: WaitQueue = WaitQueue::new();
+    static BLOCKED: AtomicUsize = AtomicUsize::new(1);
+
+    let queue = axtask::WorkQueue::new("test_wq", 1);
+    // The first work blocks the only worker, so the others stay pending.
+    let blocker = axtask::Work::new(|| WQ.wait_until(|| BLOCKED.load(Ordering::Acquire) == 0));
+    let works: Vec<_> = (0..3)
+        .map(|i| axtask::Work::new(move || ORDER.lock().unwrap().push(i)))
+        .collect();
+    assert!(queue.queue(&blocker));
+    for work in &works {
+        assert!(queue.queue(work));
+    }
+    // a pending work is not queued twice
+    assert!(works[0].is_pending());
+    assert!(!queue.queue(&works[0]));
+
+    BLOCKED.store(0, Ordering::Release);
+    WQ.notify_all(false);
+    queue.flush();
+    assert!(works.iter().all(|w| !w.is_pending()));
+    assert_eq!(*ORDER.lock().unwrap(), [0, 1, 2]);
+
+    // a done work can be queued again
+    assert!(queue.queue(&works[1]));
+    queue.flush();
+    assert_eq!(*ORDER.lock().unwrap(), [0, 1, 2, 1]);
+}
+
+#[test]
+fn test_workqueue_workers() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_WORKS: usize = 20;
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+
+    let queue = axtask::WorkQueue::new("test_wq", 4);
+    let works: Vec<_> = (0..NUM_WORKS)
+        .map(|_| {
+            axtask::Work::new(|| {
+                // the works can block
+                axtask::yield_now();
+                DONE.fetch_add(1, Ordering::Relaxed);
+            })
+        })
+        .collect();
+    for work in &works {
+        assert!(queue.queue(work));
+    }
+    // dropping the queue waits for the queued works
+    drop(queue);
+    assert_eq!(DONE.load(Ordering::Relaxed), NUM_WORKS);
+}
+
+#[cfg(feature = "irq")]
+#[test]
+fn test_softirq() {
+    use core::sync::atomic::AtomicBool;
+    use kernel_guard::NoPreemptIrqSave;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const VEC: usize = 8;
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    static IN_SOFTIRQ: AtomicBool = AtomicBool::new(false);
+
+    fn handler() {
+        IN_SOFTIRQ.store(axtask::in_softirq(), Ordering::Relaxed);
+        COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    assert!(axtask::register_softirq(VEC, handler));
+    assert!(!axtask::register_softirq(VEC, handler));
+    assert!(!axtask::register_softirq(axtask::NR_SOFTIRQS, handler));
+
+    // raised in an IRQ handler, runs when the handler returns
+    {
+        let _guard = NoPreemptIrqSave::new();
+        axtask::irq_enter();
+        axtask::raise_softirq(VEC);
+        axtask::raise_softirq(VEC);
+        assert_eq!(COUNT.load(Ordering::Relaxed), 0);
+        axtask::irq_exit();
+    }
+    assert_eq!(COUNT.load(Ordering::Relaxed), 1);
+    assert!(IN_SOFTIRQ.load(Ordering::Relaxed));
+    assert!(!axtask::in_softirq());
+
+    // raised in a task, runs in ksoftirqd
+    axtask::raise_softirq(VEC);
+    while COUNT.load(Ordering::Relaxed) < 2 {
+        axtask::yield_now();
+    }
+    assert!(IN_SOFTIRQ.load(Ordering::Relaxed));
+
+    assert!(axtask::unregister_softirq(VEC));
+    assert!(!axtask::unregister_softirq(VEC));
+}
+
+#[cfg(feature = "irq")]
+#[test]
+fn test_softirq_restart() {
+    use kernel_guard::NoPreemptIrqSave;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const VEC: usize = 9;
+    const TIMES: usize = 25;
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    // keeps raising itself
+    fn handler() {
+        if COUNT.fetch_add(1, Ordering::Relaxed) + 1 < TIMES {
+            axtask::raise_softirq(VEC);
+        }
+    }
+    assert!(axtask::register_softirq(VEC, handler));
+    {
+        let _guard = NoPreemptIrqSave::new();
+        axtask::irq_enter();
+        axtask::raise_softirq(VEC);
+        axtask::irq_exit();
+    }
+    // only a limited number of rounds run at the end of the IRQ handler
+    let count = COUNT.load(Ordering::Relaxed);
+    assert!(count > 0 && count < TIMES);
+
+    // the rest are left to ksoftirqd
+    while COUNT.load(Ordering::Relaxed) < TIMES {
+        axtask::yield_now();
+    }
+    assert!(axtask::unregister_softirq(VEC));
+}
+
+#[cfg(feature = "watchdog")]
+#[test]
+fn test_soft_lockup() {
+    use crate::watchdog::soft_lockup_stuck;
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // The clock does not advance on the host, pass the time by hand.
+    const SEC: u64 = 1_000_000_000;
+    axtask::yield_now();
+    let curr = current();
+    axtask::set_soft_lockup_threshold(Duration::from_secs(1));
+    assert_eq!(soft_lockup_stuck(&curr, SEC), None);
+
+    // A CPU stalled for longer than the threshold is reported once.
+    assert_eq!(soft_lockup_stuck(&curr, 2 * SEC), Some(2 * SEC));
+    assert_eq!(soft_lockup_stuck(&curr, 3 * SEC), None);
+
+    // Scheduling touches the watchdog, and the next stall is reported again.
+    axtask::yield_now();
+    assert_eq!(soft_lockup_stuck(&curr, SEC), None);
+    assert_eq!(soft_lockup_stuck(&curr, 3 * SEC), Some(3 * SEC));
+
+    // a zero threshold disables the check
+    axtask::yield_now();
+    axtask::set_soft_lockup_threshold(Duration::ZERO);
+    assert_eq!(soft_lockup_stuck(&curr, 3 * SEC), None);
+    axtask::set_soft_lockup_threshold(Duration::from_secs(20));
+}
+
+#[cfg(feature = "sched_edf")]
+#[test]
+fn test_edf_affinity() {
+    use core::time::Duration;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let curr = current().clone();
+    let ms = Duration::from_millis;
+    assert!(axtask::set_deadline_params(ms(10), ms(50), ms(100)));
+    let edf_cpu = curr.edf_cpu().unwrap();
+
+    let mut all = axtask::AxCpuMask::new();
+    let mut others = axtask::AxCpuMask::new();
+    let mut only = axtask::AxCpuMask::new();
+    for cpu in 0..axconfig::SMP {
+        all.set(cpu, true);
+        others.set(cpu, cpu != edf_cpu);
+    }
+    only.set(edf_cpu, true);
+
+    // the task can't leave the CPU whose bandwidth it reserved
+    assert!(!axtask::set_affinity(&curr, others));
+    assert!(axtask::set_affinity(&curr, only));
+
+    axtask::clear_deadline_params();
+    assert!(curr.edf_cpu().is_none());
+    assert!(axtask::set_affinity(&curr, all));
+}
+
+#[test]
+fn test_least_loaded_cpu() {
+    use crate::run_queue::least_loaded_cpu;
+
+    let loads = [3, 1, 1, 0];
+    let load = |cpu_id: usize| loads.get(cpu_id).copied();
+    assert_eq!(least_loaded_cpu(0..4, 0, load), Some(3));
+    // only the CPUs in the affinity mask are considered
+    assert_eq!(least_loaded_cpu([0, 1, 2], 0, load), Some(1));
+    assert_eq!(least_loaded_cpu([0], 3, load), Some(0));
+    // the preferred CPU wins a tie
+    assert_eq!(least_loaded_cpu([1, 2], 2, load), Some(2));
+    // CPUs without a run queue are skipped
+    assert_eq!(least_loaded_cpu([4, 5], 4, load), None);
+}
+
+#[test]
+fn test_steal_tasks() {
+    use crate::run_queue::take_tasks;
+    use scheduler::BaseScheduler;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    fn names(tasks: &[axtask::AxTaskRef]) -> Vec<&str> {
+        tasks.iter().map(|task| task.name()).collect()
+    }
+
+    // even tasks are pinned to other CPUs, and can't be stolen by CPU 0
+    let mut victim = crate::Scheduler::new();
+    victim.init();
+    let tasks: Vec<_> = (0..6)
+        .map(|i| crate::task::TaskInner::new(|| {}, format!("T{}", i), 0x1000).unwrap())
+        .collect();
+    for (i, task) in tasks.iter().enumerate() {
+        if i % 2 == 0 {
+            task.set_cpumask(axtask::AxCpuMask::new());
+        }
+        victim.add_task(task.clone());
+    }
+
+    let stolen = take_tasks(&mut victim, 2, |task| task.can_run_on(0));
+    assert_eq!(names(&stolen), ["T1", "T3"]);
+
+    // the skipped tasks keep their places in the victim run queue
+    let rest = take_tasks(&mut victim, usize::MAX, |_| true);
+    assert_eq!(names(&rest), ["T0", "T2", "T4", "T5"]);
+}
+
+#[cfg(feature = "tickless")]
+#[test]
+fn test_tickless() {
+    use crate::timers::{next_event_deadline, MAX_IDLE_NANOS, TICK_NANOS};
+    use kernel_guard::NoPreemptIrqSave;
+    use timer_list::TimeValue;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // the earliest of the next tick and the next timed event
+    let now = 1_000_000_000;
+    let tick = now + TICK_NANOS;
+    let event = |ns| Some(TimeValue::from_nanos(ns));
+    assert_eq!(next_event_deadline(now, Some(tick), None), tick);
+    assert_eq!(
+        next_event_deadline(now, Some(tick), event(now + 1)),
+        now + 1
+    );
+    assert_eq!(next_event_deadline(now, Some(tick), event(tick + 1)), tick);
+
+    // no periodic tick when the tick is stopped
+    assert_eq!(next_event_deadline(now, None, None), now + MAX_IDLE_NANOS);
+    let later = now + 3 * TICK_NANOS;
+    assert_eq!(next_event_deadline(now, None, event(later)), later);
+
+    // an idle CPU stops its tick, and timer interrupts are not ticks then
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let _guard = NoPreemptIrqSave::new();
+    crate::timers::stop_tick();
+    assert!(crate::timers::tick_stopped(cpu_id));
+    assert!(!crate::timers::update_tick());
+    crate::timers::start_tick();
+    assert!(!crate::timers::tick_stopped(cpu_id));
+}
@@ -1,40 +1,127 @@
 use alloc::sync::Arc;
 use axhal::time::current_time;
+#[cfg(feature = "tickless")]
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use kernel_guard::NoPreemptIrqSave;
 use lazy_init::LazyInit;
 use spinlock::SpinNoIrq;
-use timer_list::{TimeValue, TimerEvent, TimerList};
+use timer_list::{TimeValue, TimerEvent, TimerHandle, TimerWheel};
 
-use crate::{AxTaskRef, RUN_QUEUE};
+use crate::hrtimer::HrTimerInner;
+use crate::{current_run_queue, AxTaskRef};
 
-// TODO: per-CPU
-static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TaskWakeupEvent>>> = LazyInit::new();
+/// The timer list of each CPU.
+///
+/// Events are always added to the list of the current CPU, and expired by the
+/// timer interrupt of that CPU, but they can be canceled on any CPU.
+#[percpu::def_percpu]
+static TIMER_LIST: LazyInit<SpinNoIrq<TimerWheel<AxTimerEvent>>> = LazyInit::new();
 
-struct TaskWakeupEvent(AxTaskRef);
+/// The interval of scheduler ticks, in nanoseconds.
+#[cfg(feature = "tickless")]
+pub(crate) const TICK_NANOS: u64 = axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
 
-impl TimerEvent for TaskWakeupEvent {
-    fn callback(self, _now: TimeValue) {
-        let mut rq = RUN_QUEUE.lock();
-        self.0.set_in_timer_list(false);
-        rq.unblock_task(self.0, true);
+/// The maximum time that an idle CPU sleeps without any interrupt.
+///
+/// There is no way for other CPUs to wake up a sleeping CPU, so it must check
+/// its run queue periodically under SMP.
+#[cfg(all(feature = "tickless", feature = "smp"))]
+pub(crate) const MAX_IDLE_NANOS: u64 = TICK_NANOS * 10;
+#[cfg(all(feature = "tickless", not(feature = "smp")))]
+pub(crate) const MAX_IDLE_NANOS: u64 = axhal::time::NANOS_PER_SEC;
+
+/// The deadline of the next scheduler tick on each CPU, in nanoseconds.
+#[cfg(feature = "tickless")]
+#[percpu::def_percpu]
+static NEXT_TICK: u64 = 0;
+
+/// The deadline of the one-shot timer programmed on each CPU, in nanoseconds.
+#[cfg(feature = "tickless")]
+#[percpu::def_percpu]
+static NEXT_EVENT: u64 = 0;
+
+/// Whether the scheduler tick is stopped on each CPU (i.e., the CPU is idle).
+#[cfg(feature = "tickless")]
+#[percpu::def_percpu]
+static TICK_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// An event in the timer lists.
+pub(crate) enum AxTimerEvent {
+    /// Wakes up a task sleeping with a timeout.
+    TaskWakeup(AxTaskRef),
+    /// Fires a high-resolution timer, with the sequence number of the timer
+    /// when the event is set.
+    HrTimer(Arc<HrTimerInner>, u64),
+}
+
+impl TimerEvent for AxTimerEvent {
+    fn callback(self, now: TimeValue) {
+        match self {
+            Self::TaskWakeup(task) => {
+                let mut rq = current_run_queue();
+                task.set_in_timer_list(false);
+                rq.unblock_task(task, true);
+            }
+            Self::HrTimer(timer, seq) => timer.expire(seq, now),
+        }
+    }
+}
+
+/// The location of an event in the per-CPU timer lists, to cancel it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TimerRef {
+    pub(crate) cpu_id: usize,
+    handle: TimerHandle,
+}
+
+pub(crate) fn timer_list(cpu_id: usize) -> &'static SpinNoIrq<TimerWheel<AxTimerEvent>> {
+    unsafe { TIMER_LIST.remote_ref_raw(cpu_id) }
+}
+
+/// Adds an event to the timer list of the current CPU.
+pub(crate) fn set_timer(deadline: TimeValue, event: AxTimerEvent) -> TimerRef {
+    let _guard = NoPreemptIrqSave::new();
+    let cpu_id = axhal::cpu::this_cpu_id();
+    let mut timers = timer_list(cpu_id).lock();
+    let handle = timers.set(deadline, event);
+    #[cfg(feature = "tickless")]
+    if (deadline.as_nanos() as u64) < unsafe { NEXT_EVENT.read_current_raw() } {
+        program_next_event(&timers);
     }
+    TimerRef { cpu_id, handle }
+}
+
+/// Removes an event from the timer list it was added to. Returns `false` if
+/// the event has expired.
+pub(crate) fn cancel_timer(timer: TimerRef) -> bool {
+    timer_list(timer.cpu_id)
+        .lock()
+        .cancel_handle(timer.handle)
+        .is_some()
 }
 
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
-    let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(true);
-    timers.set(deadline, TaskWakeupEvent(task));
+    let timer = set_timer(deadline, AxTimerEvent::TaskWakeup(task.clone()));
+    *task.timer_handle() = Some(timer);
 }
 
 pub fn cancel_alarm(task: &AxTaskRef) {
-    let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(false);
-    timers.cancel(|t| Arc::ptr_eq(&t.0, task));
+    // The alarm may have expired, then the stale handle matches nothing.
+    if let Some(timer) = task.timer_handle().take() {
+        cancel_timer(timer);
+    }
 }
 
 pub fn check_events() {
     loop {
         let now = current_time();
-        let event = TIMER_LIST.lock().expire_one(now);
+        // Safety: IRQs are disabled in the timer interrupt handler.
+        let event = unsafe { TIMER_LIST.current_ref_raw() }
+            .lock()
+            .expire_one(now);
         if let Some((_deadline, event)) = event {
             event.callback(now);
         } else {
@@ -43,6 +130,112 @@ pub fn check_events() {
     }
 }
 
+/// Reprograms the one-shot timer of the current CPU, after the events and the
+/// tick are handled in the timer interrupt.
+#[cfg(feature = "tickless")]
+pub fn reprogram() {
+    program_next_event(&unsafe { TIMER_LIST.current_ref_raw() }.lock());
+}
+
+/// Checks whether a scheduler tick is due on the current CPU, and advances
+/// the deadline of the next tick if so.
+///
+/// In tickless mode, the timer interrupt is also raised for timed events, so
+/// not every interrupt is a tick.
+#[cfg(feature = "tickless")]
+pub fn update_tick() -> bool {
+    if tick_stopped(axhal::cpu::this_cpu_id()) {
+        return false;
+    }
+    let now_ns = axhal::time::current_time_nanos();
+    // Safety: IRQs are disabled in the timer interrupt handler.
+    let next_tick = unsafe { NEXT_TICK.read_current_raw() };
+    if now_ns < next_tick {
+        return false;
+    }
+    let next_tick = if now_ns >= next_tick + TICK_NANOS {
+        now_ns + TICK_NANOS
+    } else {
+        next_tick + TICK_NANOS
+    };
+    unsafe { NEXT_TICK.write_current_raw(next_tick) };
+    true
+}
+
+/// Stops the scheduler tick of the current CPU when it becomes idle. The
+/// timer is then only programmed for timed events.
+///
+/// IRQs must be disabled.
+#[cfg(feature = "tickless")]
+pub fn stop_tick() {
+    let stopped = unsafe { TICK_STOPPED.current_ref_raw() };
+    if !stopped.swap(true, Ordering::AcqRel) {
+        trace!("tick stopped on CPU {}", axhal::cpu::this_cpu_id());
+        program_next_event(&unsafe { TIMER_LIST.current_ref_raw() }.lock());
+    }
+}
+
+/// Restarts the scheduler tick of the current CPU when it leaves idle.
+///
+/// Returns the number of ticks skipped while the tick is stopped.
+///
+/// IRQs must be disabled.
+#[cfg(feature = "tickless")]
+pub fn start_tick() -> u64 {
+    let stopped = unsafe { TICK_STOPPED.current_ref_raw() };
+    if !stopped.swap(false, Ordering::AcqRel) {
+        return 0;
+    }
+    trace!("tick restarted on CPU {}", axhal::cpu::this_cpu_id());
+    let now_ns = axhal::time::current_time_nanos();
+    let next_tick = unsafe { NEXT_TICK.read_current_raw() };
+    let skipped = now_ns.saturating_sub(next_tick) / TICK_NANOS;
+    unsafe { NEXT_TICK.write_current_raw(now_ns + TICK_NANOS) };
+    program_next_event(&unsafe { TIMER_LIST.current_ref_raw() }.lock());
+    skipped
+}
+
+/// Whether the scheduler tick of the given CPU is stopped.
+#[cfg(feature = "tickless")]
+pub fn tick_stopped(cpu_id: usize) -> bool {
+    unsafe { TICK_STOPPED.remote_ref_raw(cpu_id) }.load(Ordering::Acquire)
+}
+
+/// Programs the one-shot timer of the current CPU to the earliest of the next
+/// tick and the next timed event.
+#[cfg(feature = "tickless")]
+fn program_next_event(timers: &TimerWheel<AxTimerEvent>) {
+    let now_ns = axhal::time::current_time_nanos();
+    let next_tick = if tick_stopped(axhal::cpu::this_cpu_id()) {
+        None
+    } else {
+        Some(unsafe { NEXT_TICK.read_current_raw() })
+    };
+    let deadline = next_event_deadline(now_ns, next_tick, timers.next_deadline());
+    unsafe { NEXT_EVENT.write_current_raw(deadline) };
+    axhal::time::set_oneshot_timer(deadline);
+}
+
+/// Returns the earliest of the next tick and the next timed event, in
+/// nanoseconds. If the tick is stopped (`next_tick` is [`None`]), the CPU
+/// sleeps for at most [`MAX_IDLE_NANOS`].
+#[cfg(feature = "tickless")]
+pub(crate) fn next_event_deadline(
+    now_ns: u64,
+    next_tick: Option<u64>,
+    next_event: Option<TimeValue>,
+) -> u64 {
+    let deadline = next_tick.unwrap_or(now_ns + MAX_IDLE_NANOS);
+    match next_event {
+        Some(next) => deadline.min(next.as_nanos() as u64),
+        None => deadline,
+    }
+}
+
+/// Initializes the timer list of the current CPU.
 pub fn init() {
-    TIMER_LIST.init_by(SpinNoIrq::new(TimerList::new()));
+    let resolution =
+        Duration::from_nanos(axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64);
+    unsafe { TIMER_LIST.current_ref_raw() }
+        .init_by(SpinNoIrq::new(TimerWheel::with_resolution(resolution)));
 }
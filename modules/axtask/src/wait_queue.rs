@@ -1,8 +1,9 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use axhal::time::TimeValue;
 use spinlock::SpinRaw;
 
-use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
+use crate::{current_run_queue, AxRunQueue, AxTaskRef, CurrentTask};
 
 /// A queue to store sleeping tasks.
 ///
@@ -27,7 +28,7 @@ use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
 /// assert_eq!(VALUE.load(Ordering::Relaxed), 1);
 /// ```
 pub struct WaitQueue {
-    queue: SpinRaw<VecDeque<AxTaskRef>>, // we already disabled IRQs when lock the `RUN_QUEUE`
+    queue: SpinRaw<VecDeque<AxTaskRef>>, // we already disabled IRQs when lock the run queue
 }
 
 impl WaitQueue {
@@ -45,12 +46,12 @@ impl WaitQueue {
         }
     }
 
-    fn cancel_events(&self, curr: CurrentTask) {
+    fn cancel_events(&self, curr: &CurrentTask) {
         // A task can be wake up only one events (timer or `notify()`), remove
         // the event from another queue.
         if curr.in_wait_queue() {
             // wake up by timer (timeout).
-            // `RUN_QUEUE` is not locked here, so disable IRQs.
+            // the run queue is not locked here, so disable IRQs.
             let _guard = kernel_guard::IrqSave::new();
             self.queue.lock().retain(|t| !curr.ptr_eq(t));
             curr.set_in_wait_queue(false);
@@ -62,14 +63,35 @@ impl WaitQueue {
         }
     }
 
+    /// Exits the current task if it's canceled while waiting in this queue,
+    /// after removing it from the queue and the timer list.
+    ///
+    /// If the task has been notified as well, the notification is passed on
+    /// to another waiter, so that it's not lost.
+    fn exit_if_canceled(&self, curr: &CurrentTask) {
+        if curr.is_canceled() {
+            let notified = !curr.in_wait_queue();
+            self.cancel_events(curr);
+            if notified {
+                self.notify_one(false);
+            }
+            crate::exit(crate::EXIT_CANCELED);
+        }
+    }
+
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
+    ///
+    /// It's a cancellation point: the task exits if it's canceled by
+    /// [`cancel`](crate::cancel), and so do other wait methods.
     pub fn wait(&self) {
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
-        self.cancel_events(crate::current());
+        let curr = crate::current();
+        self.exit_if_canceled(&curr);
+        self.cancel_events(&curr);
     }
 
     /// Blocks the current task and put it into the wait queue, until the given
@@ -81,17 +103,24 @@ impl WaitQueue {
     where
         F: Fn() -> bool,
     {
+        let curr = crate::current();
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let mut rq = current_run_queue();
+            // Check the condition with the wait queue locked, so that the
+            // notifier on another CPU can not slip in before we are queued.
+            let mut wq = self.queue.lock();
             if condition() {
                 break;
             }
             rq.block_current(|task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task);
+                drop(wq);
             });
+            drop(rq);
+            self.exit_if_canceled(&curr);
         }
-        self.cancel_events(crate::current());
+        self.cancel_events(&curr);
     }
 
     /// Blocks the current task and put it into the wait queue, until other tasks
@@ -105,14 +134,16 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
-
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
-            self.queue.lock().push_back(task)
+            self.queue.lock().push_back(task.clone());
+            // The timer may fire on another CPU right after it's set, so set
+            // it after the task is marked as blocked.
+            crate::timers::set_alarm_wakeup(deadline, task);
         });
+        self.exit_if_canceled(&curr);
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
-        self.cancel_events(curr);
+        self.cancel_events(&curr);
         timeout
     }
 
@@ -133,21 +164,26 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
-
         let mut timeout = true;
         while axhal::time::current_time() < deadline {
-            let mut rq = RUN_QUEUE.lock();
+            let mut rq = current_run_queue();
+            let mut wq = self.queue.lock();
             if condition() {
                 timeout = false;
                 break;
             }
             rq.block_current(|task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task.clone());
+                drop(wq);
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task);
+                }
             });
+            drop(rq);
+            self.exit_if_canceled(&curr);
         }
-        self.cancel_events(curr);
+        self.cancel_events(&curr);
         timeout
     }
 
@@ -156,7 +192,7 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
-        let mut rq = RUN_QUEUE.lock();
+        let mut rq = current_run_queue();
         if !self.queue.lock().is_empty() {
             self.notify_one_locked(resched, &mut rq)
         } else {
@@ -170,14 +206,14 @@ impl WaitQueue {
     /// preemption is enabled.
     pub fn notify_all(&self, resched: bool) {
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let mut rq = current_run_queue();
             if let Some(task) = self.queue.lock().pop_front() {
                 task.set_in_wait_queue(false);
                 rq.unblock_task(task, resched);
             } else {
                 break;
             }
-            drop(rq); // we must unlock `RUN_QUEUE` after unlocking `self.queue`.
+            drop(rq); // we must unlock the run queue after unlocking `self.queue`.
         }
     }
 
@@ -186,7 +222,7 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_task(&mut self, resched: bool, task: &AxTaskRef) -> bool {
-        let mut rq = RUN_QUEUE.lock();
+        let mut rq = current_run_queue();
         let mut wq = self.queue.lock();
         if let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) {
             task.set_in_wait_queue(false);
@@ -213,4 +249,117 @@ impl WaitQueue {
             rq.unblock_task(task, resched);
         }
     }
+
+    /// Blocks the current task and put it into the wait queue if the given
+    /// `condition` is true, which is checked with the wait queue locked. The
+    /// task is woken up by other tasks, or at the `deadline` if the `irq`
+    /// feature is enabled.
+    ///
+    /// Returns `false` if the task is not blocked. Unlike other wait methods,
+    /// the caller is responsible for removing the task from the queue (which
+    /// may be another one) and cancelling the timer after it's woken up.
+    pub(crate) fn block_current_if<F>(&self, condition: F, deadline: Option<TimeValue>) -> bool
+    where
+        F: FnOnce() -> bool,
+    {
+        let mut rq = current_run_queue();
+        let mut wq = self.queue.lock();
+        if !condition() {
+            return false;
+        }
+        #[cfg(not(feature = "irq"))]
+        let _ = deadline;
+        rq.block_current(|task| {
+            task.set_in_wait_queue(true);
+            wq.push_back(task.clone());
+            drop(wq);
+            #[cfg(feature = "irq")]
+            if let Some(deadline) = deadline {
+                crate::timers::set_alarm_wakeup(deadline, task);
+            }
+        });
+        true
+    }
+
+    /// Removes the given task from the wait queue, if `condition` is true
+    /// when the wait queue is locked.
+    ///
+    /// Returns `None` if the condition is false, otherwise whether the task
+    /// was in the queue.
+    pub(crate) fn remove_task_if<F>(&self, task: &AxTaskRef, condition: F) -> Option<bool>
+    where
+        F: FnOnce() -> bool,
+    {
+        // the run queue is not locked here, so disable IRQs.
+        let _guard = kernel_guard::IrqSave::new();
+        let mut wq = self.queue.lock();
+        if !condition() {
+            return None;
+        }
+        Some(match wq.iter().position(|t| Arc::ptr_eq(t, task)) {
+            Some(index) => {
+                wq.remove(index);
+                task.set_in_wait_queue(false);
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Wakes up at most `count` tasks in the wait queue for which `filter`
+    /// returns true, in the queue order.
+    ///
+    /// Returns the number of tasks woken up.
+    pub(crate) fn notify_matching<F>(&self, count: usize, resched: bool, filter: F) -> usize
+    where
+        F: Fn(&AxTaskRef) -> bool,
+    {
+        let mut rq = current_run_queue();
+        let mut wq = self.queue.lock();
+        let mut woken = 0;
+        while woken < count {
+            let Some(index) = wq.iter().position(&filter) else {
+                break;
+            };
+            let task = wq.remove(index).unwrap();
+            task.set_in_wait_queue(false);
+            rq.unblock_task(task, resched);
+            woken += 1;
+        }
+        woken
+    }
+
+    /// Moves the tasks for which `f` returns true to the `target` wait queue,
+    /// in the queue order. `f` is called with both queues locked.
+    ///
+    /// If `target` is the same queue, `f` is still called but no task is
+    /// moved. Returns the number of tasks for which `f` returns true.
+    pub(crate) fn requeue<F>(&self, target: &WaitQueue, mut f: F) -> usize
+    where
+        F: FnMut(&AxTaskRef) -> bool,
+    {
+        let _guard = kernel_guard::IrqSave::new();
+        if core::ptr::eq(self, target) {
+            return self.queue.lock().iter().filter(|t| f(t)).count();
+        }
+        // Lock the queues in the order of their addresses to avoid deadlocks.
+        let (mut wq, mut target_wq) = if (self as *const Self) < (target as *const Self) {
+            let wq = self.queue.lock();
+            (wq, target.queue.lock())
+        } else {
+            let target_wq = target.queue.lock();
+            (self.queue.lock(), target_wq)
+        };
+        let mut moved = 0;
+        let mut index = 0;
+        while index < wq.len() {
+            if f(&wq[index]) {
+                target_wq.push_back(wq.remove(index).unwrap());
+                moved += 1;
+            } else {
+                index += 1;
+            }
+        }
+        moved
+    }
 }
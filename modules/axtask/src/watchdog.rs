@@ -0,0 +1,252 @@
+//! Soft-lockup and hung-task detection.
+//!
+//! Both checks run in the timer interrupt handler:
+//!
+//! - A soft lockup is reported if the current CPU has not scheduled for
+//!   longer than the threshold, and the running task can not be preempted,
+//!   either because preemption is disabled or because the `preempt` feature
+//!   is not enabled. Lockups with IRQs disabled are not detected, since the
+//!   timer interrupt is masked as well.
+//! - A hung task is reported if it has been blocked in a wait queue without
+//!   a timeout for longer than the timeout.
+//!
+//! The reports contain the task, its state and a backtrace of the raw return
+//! addresses, which can be resolved with `addr2line` on the kernel ELF file.
+
+use alloc::{collections::BTreeMap, sync::Arc, sync::Weak, vec::Vec};
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::backtrace::Backtrace;
+use axhal::time::{current_time_nanos, NANOS_PER_SEC};
+use spinlock::SpinNoIrq;
+
+use crate::task::TaskState;
+use crate::{AxTask, AxTaskRef, TaskInner};
+
+/// The default threshold of soft lockups.
+const DEFAULT_SOFT_LOCKUP_THRESH: Duration = Duration::from_secs(20);
+/// The default timeout of hung tasks.
+const DEFAULT_HUNG_TASK_TIMEOUT: Duration = Duration::from_secs(120);
+/// The interval to scan the tasks for hung ones.
+const HUNG_TASK_CHECK_INTERVAL_NANOS: u64 = NANOS_PER_SEC;
+
+static SOFT_LOCKUP_THRESH_NANOS: AtomicU64 =
+    AtomicU64::new(DEFAULT_SOFT_LOCKUP_THRESH.as_nanos() as u64);
+static HUNG_TASK_TIMEOUT_NANOS: AtomicU64 =
+    AtomicU64::new(DEFAULT_HUNG_TASK_TIMEOUT.as_nanos() as u64);
+static PANIC_ON_REPORT: AtomicBool = AtomicBool::new(false);
+static NEXT_HUNG_TASK_CHECK: AtomicU64 = AtomicU64::new(0);
+
+/// All tasks that can hang, i.e., all tasks except the idle tasks.
+static TASKS: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// When the current CPU scheduled or could be preempted last time.
+#[percpu::def_percpu]
+static LAST_SCHED: AtomicU64 = AtomicU64::new(0);
+
+/// Whether the soft lockup of the current CPU has been reported, it's reset
+/// when the CPU schedules again.
+#[percpu::def_percpu]
+static SOFT_LOCKUP_REPORTED: bool = false;
+
+/// Watchdog states of a task.
+pub(crate) struct TaskWatchdog {
+    /// When the task was blocked last time.
+    blocked_since: AtomicU64,
+    /// Whether the task has been reported as hung since it was blocked.
+    reported: AtomicBool,
+    /// Whether the task is checked for being hung.
+    hung_check: AtomicBool,
+}
+
+impl TaskWatchdog {
+    pub const fn new() -> Self {
+        Self {
+            blocked_since: AtomicU64::new(0),
+            reported: AtomicBool::new(false),
+            hung_check: AtomicBool::new(true),
+        }
+    }
+
+    /// Records that the task is blocked at `now`.
+    pub fn block(&self, now: u64) {
+        self.blocked_since.store(now, Ordering::Relaxed);
+        self.reported.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Sets the time that a CPU can run without scheduling before it's reported
+/// as a soft lockup. Zero disables the check.
+pub fn set_soft_lockup_threshold(thresh: Duration) {
+    SOFT_LOCKUP_THRESH_NANOS.store(thresh.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Sets the time that a task can be blocked before it's reported as hung.
+/// Zero disables the check.
+pub fn set_hung_task_timeout(timeout: Duration) {
+    HUNG_TASK_TIMEOUT_NANOS.store(timeout.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Sets whether to panic after a soft lockup or a hung task is reported.
+pub fn set_watchdog_panic(panic: bool) {
+    PANIC_ON_REPORT.store(panic, Ordering::Relaxed);
+}
+
+/// Enables or disables the hung task check of the given task.
+///
+/// It's enabled by default. Disable it for the tasks that wait for long
+/// periods by design, such as the daemons waiting for requests.
+pub fn set_hung_task_check(task: &AxTaskRef, enabled: bool) {
+    task.watchdog().hung_check.store(enabled, Ordering::Relaxed);
+}
+
+/// Adds a new task to be checked for being hung.
+pub(crate) fn register_task(task: &AxTaskRef) {
+    if !task.is_idle() {
+        TASKS
+            .lock()
+            .insert(task.id().as_u64(), Arc::downgrade(task));
+    }
+}
+
+/// Removes a task when it's dropped.
+pub(crate) fn unregister_task(task: &TaskInner) {
+    TASKS.lock().remove(&task.id().as_u64());
+}
+
+/// Records that the current CPU schedules, it's called with IRQs disabled.
+pub(crate) fn touch() {
+    unsafe {
+        LAST_SCHED
+            .current_ref_raw()
+            .store(current_time_nanos(), Ordering::Relaxed);
+        SOFT_LOCKUP_REPORTED.write_current_raw(false);
+    }
+}
+
+/// Checks for soft lockups on the current CPU and hung tasks, in the timer
+/// interrupt handler.
+pub(crate) fn check() {
+    let now = current_time_nanos();
+    check_soft_lockup(now);
+    let next = NEXT_HUNG_TASK_CHECK.load(Ordering::Relaxed);
+    if now >= next
+        && NEXT_HUNG_TASK_CHECK
+            .compare_exchange(
+                next,
+                now + HUNG_TASK_CHECK_INTERVAL_NANOS,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    {
+        check_hung_tasks(now);
+    }
+}
+
+fn check_soft_lockup(now: u64) {
+    let Some(curr) = crate::current_may_uninit() else {
+        return;
+    };
+    let Some(stuck) = soft_lockup_stuck(&curr, now) else {
+        return;
+    };
+
+    let fp = axhal::arch::read_frame_pointer();
+    let bt = unsafe { Backtrace::from_frame_pointer(fp, stack_range(&curr, fp)) };
+    error!(
+        "soft lockup: CPU {} stuck for {:?} in {}, state={:?}, backtrace:\n{}",
+        axhal::cpu::this_cpu_id(),
+        Duration::from_nanos(stuck),
+        curr.id_name(),
+        curr.state(),
+        bt
+    );
+    report_done();
+}
+
+/// Returns how long the current CPU has not scheduled if it's a soft lockup
+/// not reported yet, and marks it as reported. It's called with IRQs
+/// disabled.
+pub(crate) fn soft_lockup_stuck(curr: &TaskInner, now: u64) -> Option<u64> {
+    let thresh = SOFT_LOCKUP_THRESH_NANOS.load(Ordering::Relaxed);
+    // The preemption is disabled once by the IRQ handler.
+    #[cfg(feature = "preempt")]
+    let preemptible = curr.can_preempt(1);
+    #[cfg(not(feature = "preempt"))]
+    let preemptible = false;
+    if curr.is_idle() || preemptible {
+        touch();
+        return None;
+    }
+    // Safety: IRQs are disabled.
+    let last = unsafe { LAST_SCHED.current_ref_raw() }.load(Ordering::Relaxed);
+    let stuck = now.saturating_sub(last);
+    if thresh == 0 || stuck <= thresh || unsafe { SOFT_LOCKUP_REPORTED.read_current_raw() } {
+        return None;
+    }
+    unsafe { SOFT_LOCKUP_REPORTED.write_current_raw(true) };
+    Some(stuck)
+}
+
+fn check_hung_tasks(now: u64) {
+    let timeout = HUNG_TASK_TIMEOUT_NANOS.load(Ordering::Relaxed);
+    if timeout == 0 {
+        return;
+    }
+    // The reports are slow, do not print them with the lock held. Neither
+    // upgrade the tasks with it held, since dropping the last reference of a
+    // task removes it from the map.
+    let tasks: Vec<Weak<AxTask>> = TASKS.lock().values().cloned().collect();
+    let hung: Vec<AxTaskRef> = tasks
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|task| {
+            let wd = task.watchdog();
+            task.state() == TaskState::Blocked
+                && !task.on_cpu()
+                && task.in_wait_queue()
+                && !task.in_timer_list()
+                && wd.hung_check.load(Ordering::Relaxed)
+                && !wd.reported.load(Ordering::Relaxed)
+                && now.saturating_sub(wd.blocked_since.load(Ordering::Relaxed)) > timeout
+        })
+        .collect();
+
+    for task in hung {
+        let wd = task.watchdog();
+        wd.reported.store(true, Ordering::Relaxed);
+        let blocked = now.saturating_sub(wd.blocked_since.load(Ordering::Relaxed));
+        // The task may have been woken up and running again, then the
+        // backtrace is garbage, but it's still bounded in the task stack.
+        let bt = unsafe {
+            let fp = (*task.ctx_mut_ptr()).frame_pointer();
+            Backtrace::from_frame_pointer(fp, stack_range(&task, fp))
+        };
+        error!(
+            "hung task: {} blocked for {:?}, state={:?}, backtrace:\n{}",
+            task.id_name(),
+            Duration::from_nanos(blocked),
+            task.state(),
+            bt
+        );
+        report_done();
+    }
+}
+
+/// Returns the range of the kernel stack of the task that contains `fp`.
+///
+/// The tasks created from the boot stacks do not record their stacks, and
+/// we assume the stack is at most [`axconfig::TASK_STACK_SIZE`] above `fp`.
+fn stack_range(task: &TaskInner, fp: usize) -> Range<usize> {
+    task.kstack_range()
+        .unwrap_or_else(|| fp..fp.saturating_add(axconfig::TASK_STACK_SIZE))
+}
+
+fn report_done() {
+    if PANIC_ON_REPORT.load(Ordering::Relaxed) {
+        panic!("watchdog detected a stuck CPU or task");
+    }
+}
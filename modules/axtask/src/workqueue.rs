@@ -0,0 +1,140 @@
+//! Work queues, to run deferred works in worker tasks.
+
+use alloc::{boxed::Box, collections::VecDeque, format, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use spinlock::SpinNoIrq;
+
+use crate::{AxTaskRef, WaitQueue};
+
+/// A work that can be queued to a [`WorkQueue`] repeatedly.
+pub struct Work {
+    func: Box<dyn Fn() + Send + Sync>,
+    pending: AtomicBool,
+}
+
+impl Work {
+    /// Creates a new work that calls `func` each time it runs.
+    pub fn new<F>(func: F) -> Arc<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Arc::new(Self {
+            func: Box::new(func),
+            pending: AtomicBool::new(false),
+        })
+    }
+
+    /// Whether the work is queued and has not started running.
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+}
+
+struct WorkQueueInner {
+    works: SpinNoIrq<VecDeque<Arc<Work>>>,
+    /// The number of works that are queued or running.
+    inflight: AtomicUsize,
+    stopped: AtomicBool,
+    /// Worker tasks wait here for works.
+    work_wq: WaitQueue,
+    /// Tasks wait here in [`WorkQueue::flush`].
+    flush_wq: WaitQueue,
+}
+
+/// A queue of works that run in its own worker tasks.
+///
+/// Unlike softirqs, the works can block. Works can be queued in IRQ
+/// handlers, and run in the queue order unless there are multiple workers.
+///
+/// The worker tasks exit when the queue is dropped, after all queued works
+/// are done.
+pub struct WorkQueue {
+    inner: Arc<WorkQueueInner>,
+    workers: Vec<AxTaskRef>,
+}
+
+impl WorkQueue {
+    /// Creates a new work queue, and spawns `num_workers` worker tasks named
+    /// after `name`.
+    pub fn new(name: &str, num_workers: usize) -> Self {
+        let inner = Arc::new(WorkQueueInner {
+            works: SpinNoIrq::new(VecDeque::new()),
+            inflight: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            work_wq: WaitQueue::new(),
+            flush_wq: WaitQueue::new(),
+        });
+        let workers: Vec<_> = (0..num_workers.max(1))
+            .map(|i| {
+                let inner = inner.clone();
+                crate::spawn_raw(
+                    move || worker_entry(inner),
+                    format!("{}/{}", name, i),
+                    axconfig::TASK_STACK_SIZE,
+                )
+            })
+            .collect();
+        // Workers may wait for works for a long time.
+        #[cfg(feature = "watchdog")]
+        for worker in &workers {
+            crate::watchdog::set_hung_task_check(worker, false);
+        }
+        Self { inner, workers }
+    }
+
+    /// Queues the work to run in a worker task.
+    ///
+    /// Returns `false` if the work is already pending in some queue.
+    pub fn queue(&self, work: &Arc<Work>) -> bool {
+        if work.pending.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.inner.inflight.fetch_add(1, Ordering::AcqRel);
+        self.inner.works.lock().push_back(work.clone());
+        self.inner.work_wq.notify_one(false);
+        true
+    }
+
+    /// Waits until all queued works are done, including the works queued
+    /// while waiting.
+    ///
+    /// It must not be called in the works of this queue.
+    pub fn flush(&self) {
+        let inner = &self.inner;
+        inner
+            .flush_wq
+            .wait_until(|| inner.inflight.load(Ordering::Acquire) == 0);
+    }
+}
+
+impl Drop for WorkQueue {
+    fn drop(&mut self) {
+        self.inner.stopped.store(true, Ordering::Release);
+        self.inner.work_wq.notify_all(false);
+        for worker in self.workers.drain(..) {
+            worker.join();
+        }
+    }
+}
+
+fn worker_entry(inner: Arc<WorkQueueInner>) {
+    loop {
+        inner
+            .work_wq
+            .wait_until(|| inner.stopped.load(Ordering::Acquire) || !inner.works.lock().is_empty());
+        let work = inner.works.lock().pop_front();
+        let Some(work) = work else {
+            if inner.stopped.load(Ordering::Acquire) {
+                return;
+            }
+            continue; // taken by another worker
+        };
+        // Clear it before running, so that the work can queue itself again.
+        work.pending.store(false, Ordering::Release);
+        (work.func)();
+        if inner.inflight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            inner.flush_wq.notify_all(true);
+        }
+    }
+}
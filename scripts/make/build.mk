@@ -1,7 +1,7 @@
 # Main building script
 
-include scripts/make/cargo.mk
 include scripts/make/features.mk
+include scripts/make/cargo.mk
 
 ifeq ($(APP_TYPE), c)
   include scripts/make/build_c.mk
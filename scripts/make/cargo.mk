@@ -17,6 +17,10 @@ build_args := \
   $(verbose)
 
 RUSTFLAGS := -C link-arg=-T$(LD_SCRIPT) -C link-arg=-no-pie
+# Keep frame pointers for the backtraces in `axhal::backtrace`
+ifneq ($(filter watchdog alloc-trace alloc-debug,$(FEATURES)),)
+  RUSTFLAGS += -C force-frame-pointers=yes
+endif
 RUSTDOCFLAGS := --enable-index-page -Zunstable-options -D rustdoc::broken_intra_doc_links
 
 ifeq ($(ARCH), x86_64)
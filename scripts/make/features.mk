@@ -55,6 +55,12 @@ endif
 ax_feat += $(filter-out $(lib_features),$(FEATURES))
 lib_feat += $(filter $(lib_features),$(FEATURES))
 
+ifeq ($(APP_TYPE), c)
+  ifneq ($(filter irq,$(FEATURES)),)    # POSIX timers need interrupts
+    lib_feat += irq
+  endif
+endif
+
 AX_FEAT := $(strip $(addprefix $(ax_feat_prefix),$(ax_feat)))
 LIB_FEAT := $(strip $(addprefix $(lib_feat_prefix),$(lib_feat)))
 APP_FEAT := $(strip $(shell echo $(APP_FEATURES) | tr ',' ' '))
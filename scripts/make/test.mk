@@ -3,6 +3,9 @@
 define unit_test
   $(call run_cmd,cargo test,-p percpu $(1) -- --nocapture)
   $(call run_cmd,cargo test,-p axfs $(1) --features "myfs" -- --nocapture)
+  $(call run_cmd,cargo test,-p axtask $(1) --features "multitask irq" -- --nocapture)
+  $(call run_cmd,cargo test,-p axtask $(1) --features "multitask irq tickless" -- --nocapture)
+  $(call run_cmd,cargo test,-p axtask $(1) --features "multitask irq watchdog" -- --nocapture)
   $(call run_cmd,cargo test,--workspace --exclude "arceos-*" $(1) -- --nocapture)
 endef
 
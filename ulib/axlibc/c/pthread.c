@@ -39,40 +39,41 @@ int pthread_mutex_trylock(pthread_mutex_t *m)
     return 0;
 }
 
-// TODO
-int pthread_setname_np(pthread_t thread, const char *name)
+int pthread_mutexattr_init(pthread_mutexattr_t *a)
 {
-    unimplemented();
+    *a = (pthread_mutexattr_t){0};
     return 0;
 }
 
-int pthread_cond_init(pthread_cond_t *restrict c, const pthread_condattr_t *restrict a)
+int pthread_mutexattr_destroy(pthread_mutexattr_t *a)
 {
-    *c = (pthread_cond_t){0};
-    if (a) {
-        c->_c_clock = a->__attr & 0x7fffffff;
-        if (a->__attr >> 31)
-            c->_c_shared = (void *)-1;
-    }
     return 0;
 }
 
-// TODO
-int pthread_cond_signal(pthread_cond_t *__cond)
+int pthread_mutexattr_getprotocol(const pthread_mutexattr_t *restrict a, int *restrict protocol)
 {
-    unimplemented();
+    *protocol = a->__attr & 8 ? PTHREAD_PRIO_INHERIT : PTHREAD_PRIO_NONE;
     return 0;
 }
 
-// TODO
-int pthread_cond_wait(pthread_cond_t *__restrict__ __cond, pthread_mutex_t *__restrict__ __mutex)
+int pthread_mutexattr_setprotocol(pthread_mutexattr_t *a, int protocol)
 {
-    unimplemented();
-    return 0;
+    switch (protocol) {
+    case PTHREAD_PRIO_NONE:
+        a->__attr &= ~8;
+        return 0;
+    case PTHREAD_PRIO_INHERIT:
+        a->__attr |= 8;
+        return 0;
+    case PTHREAD_PRIO_PROTECT:
+        return ENOTSUP;
+    default:
+        return EINVAL;
+    }
 }
 
 // TODO
-int pthread_cond_broadcast(pthread_cond_t *c)
+int pthread_setname_np(pthread_t thread, const char *name)
 {
     unimplemented();
     return 0;
@@ -1,3 +1,5 @@
+#ifndef AX_CONFIG_MULTITASK
+
 #include <sched.h>
 #include <stdio.h>
 
@@ -7,3 +9,47 @@ int sched_setaffinity(pid_t __pid, size_t __cpusetsize, const cpu_set_t *__cpuse
     unimplemented();
     return 0;
 }
+
+// TODO
+int sched_getaffinity(pid_t __pid, size_t __cpusetsize, cpu_set_t *__cpuset)
+{
+    unimplemented();
+    return 0;
+}
+
+// TODO
+int sched_setscheduler(pid_t __pid, int __policy, const struct sched_param *__param)
+{
+    unimplemented();
+    return 0;
+}
+
+// TODO
+int sched_getscheduler(pid_t __pid)
+{
+    unimplemented();
+    return 0;
+}
+
+// TODO
+int sched_getparam(pid_t __pid, struct sched_param *__param)
+{
+    unimplemented();
+    return 0;
+}
+
+// TODO
+int sched_get_priority_max(int __policy)
+{
+    unimplemented();
+    return 0;
+}
+
+// TODO
+int sched_get_priority_min(int __policy)
+{
+    unimplemented();
+    return 0;
+}
+
+#endif // AX_CONFIG_MULTITASK
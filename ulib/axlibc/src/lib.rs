@@ -63,10 +63,16 @@ mod net;
 mod pipe;
 #[cfg(feature = "multitask")]
 mod pthread;
+#[cfg(feature = "multitask")]
+mod sched;
+#[cfg(feature = "multitask")]
+mod semaphore;
 #[cfg(feature = "alloc")]
 mod strftime;
 #[cfg(feature = "fp_simd")]
 mod strtod;
+#[cfg(all(feature = "multitask", feature = "irq"))]
+mod timer;
 
 mod errno;
 mod io;
@@ -85,10 +91,10 @@ pub use self::io::{read, writev};
 pub use self::errno::strerror;
 pub use self::mktime::mktime;
 pub use self::rand::{rand, random, srand};
-pub use self::resource::{getrlimit, setrlimit};
+pub use self::resource::{getrlimit, getrusage, setrlimit};
 pub use self::setjmp::{longjmp, setjmp};
 pub use self::sys::sysconf;
-pub use self::time::{clock_gettime, nanosleep};
+pub use self::time::{clock_gettime, nanosleep, times};
 pub use self::unistd::{abort, exit, getpid};
 
 #[cfg(feature = "alloc")]
@@ -109,9 +115,35 @@ pub use self::net::{
 };
 
 #[cfg(feature = "multitask")]
-pub use self::pthread::{pthread_create, pthread_exit, pthread_join, pthread_self};
+pub use self::pthread::{pthread_barrier_destroy, pthread_barrier_init, pthread_barrier_wait};
+#[cfg(feature = "multitask")]
+pub use self::pthread::{
+    pthread_cancel, pthread_create, pthread_exit, pthread_join, pthread_self,
+    pthread_setaffinity_np, pthread_setcancelstate, pthread_testcancel,
+};
+#[cfg(feature = "multitask")]
+pub use self::pthread::{
+    pthread_cond_broadcast, pthread_cond_destroy, pthread_cond_init, pthread_cond_signal,
+    pthread_cond_timedwait, pthread_cond_wait,
+};
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_mutex_init, pthread_mutex_lock, pthread_mutex_unlock};
+#[cfg(feature = "multitask")]
+pub use self::pthread::{
+    pthread_rwlock_destroy, pthread_rwlock_init, pthread_rwlock_rdlock, pthread_rwlock_tryrdlock,
+    pthread_rwlock_trywrlock, pthread_rwlock_unlock, pthread_rwlock_wrlock,
+};
+#[cfg(feature = "multitask")]
+pub use self::sched::{
+    sched_get_priority_max, sched_get_priority_min, sched_getaffinity, sched_getparam,
+    sched_getscheduler, sched_setaffinity, sched_setscheduler,
+};
+#[cfg(feature = "multitask")]
+pub use self::semaphore::{
+    sem_destroy, sem_getvalue, sem_init, sem_post, sem_timedwait, sem_trywait, sem_wait,
+};
+#[cfg(all(feature = "multitask", feature = "irq"))]
+pub use self::timer::{timer_create, timer_delete, timer_getoverrun, timer_gettime, timer_settime};
 
 #[cfg(feature = "pipe")]
 pub use self::pipe::pipe;
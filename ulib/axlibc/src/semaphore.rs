@@ -0,0 +1,53 @@
+use crate::{ctypes, utils::e};
+use arceos_posix_api::{
+    sys_sem_destroy, sys_sem_getvalue, sys_sem_init, sys_sem_post, sys_sem_timedwait,
+    sys_sem_trywait, sys_sem_wait,
+};
+use core::ffi::{c_int, c_uint};
+
+/// Initialize an unnamed semaphore with the given value.
+#[no_mangle]
+pub unsafe extern "C" fn sem_init(sem: *mut ctypes::sem_t, pshared: c_int, value: c_uint) -> c_int {
+    e(sys_sem_init(sem, pshared, value))
+}
+
+/// Destroy an unnamed semaphore.
+#[no_mangle]
+pub unsafe extern "C" fn sem_destroy(sem: *mut ctypes::sem_t) -> c_int {
+    e(sys_sem_destroy(sem))
+}
+
+/// Decrement the semaphore, blocking until its value is positive.
+#[no_mangle]
+pub unsafe extern "C" fn sem_wait(sem: *mut ctypes::sem_t) -> c_int {
+    e(sys_sem_wait(sem))
+}
+
+/// Decrement the semaphore if its value is positive, otherwise fail with
+/// `EAGAIN`.
+#[no_mangle]
+pub unsafe extern "C" fn sem_trywait(sem: *mut ctypes::sem_t) -> c_int {
+    e(sys_sem_trywait(sem))
+}
+
+/// Decrement the semaphore, blocking until its value is positive or the
+/// absolute time `abstime` has passed.
+#[no_mangle]
+pub unsafe extern "C" fn sem_timedwait(
+    sem: *mut ctypes::sem_t,
+    abstime: *const ctypes::timespec,
+) -> c_int {
+    e(sys_sem_timedwait(sem, abstime))
+}
+
+/// Increment the semaphore.
+#[no_mangle]
+pub unsafe extern "C" fn sem_post(sem: *mut ctypes::sem_t) -> c_int {
+    e(sys_sem_post(sem))
+}
+
+/// Get the value of the semaphore.
+#[no_mangle]
+pub unsafe extern "C" fn sem_getvalue(sem: *mut ctypes::sem_t, sval: *mut c_int) -> c_int {
+    e(sys_sem_getvalue(sem, sval))
+}
@@ -0,0 +1,47 @@
+use crate::{ctypes, utils::e};
+use arceos_posix_api::{
+    sys_timer_create, sys_timer_delete, sys_timer_getoverrun, sys_timer_gettime, sys_timer_settime,
+};
+use core::ffi::c_int;
+
+/// Create a per-process timer.
+#[no_mangle]
+pub unsafe extern "C" fn timer_create(
+    clockid: ctypes::clockid_t,
+    sevp: *mut ctypes::sigevent,
+    timerid: *mut ctypes::timer_t,
+) -> c_int {
+    e(sys_timer_create(clockid, sevp, timerid))
+}
+
+/// Delete a per-process timer.
+#[no_mangle]
+pub unsafe extern "C" fn timer_delete(timerid: ctypes::timer_t) -> c_int {
+    e(sys_timer_delete(timerid))
+}
+
+/// Arm or disarm a per-process timer.
+#[no_mangle]
+pub unsafe extern "C" fn timer_settime(
+    timerid: ctypes::timer_t,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    e(sys_timer_settime(timerid, flags, new_value, old_value))
+}
+
+/// Get the remaining time and the interval of a per-process timer.
+#[no_mangle]
+pub unsafe extern "C" fn timer_gettime(
+    timerid: ctypes::timer_t,
+    curr_value: *mut ctypes::itimerspec,
+) -> c_int {
+    e(sys_timer_gettime(timerid, curr_value))
+}
+
+/// Get the overrun count of a per-process timer.
+#[no_mangle]
+pub unsafe extern "C" fn timer_getoverrun(timerid: ctypes::timer_t) -> c_int {
+    e(sys_timer_getoverrun(timerid))
+}
@@ -0,0 +1,145 @@
+//! A simple executor for asynchronous tasks (futures).
+//!
+//! Tasks spawned by [`spawn`] are polled by the threads running [`block_on`],
+//! so thousands of concurrent tasks only need the stacks of these threads.
+//! When a task is woken up by its [`Waker`](core::task::Waker), it's put into
+//! the ready queue, and an idle executor thread is woken up by a wait queue
+//! notification.
+//!
+//! The asynchronous I/O objects (e.g., `net::AsyncTcpStream`) register their
+//! wakers to the reactor when the operation would block. When there is no
+//! other task to run, the executor threads sleep for a short interval, then
+//! the reactor polls the network interfaces and wakes them up. Without the
+//! `irq` feature, the threads yield instead of sleeping.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use axstd::executor;
+//!
+//! let handle = executor::spawn(async { 1 + 1 });
+//! let value = executor::block_on(async move { handle.await * 2 });
+//! assert_eq!(value, 4);
+//! ```
+
+mod reactor;
+mod task;
+
+use alloc::{collections::VecDeque, sync::Arc, task::Wake};
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use arceos_api::task::{self as api, AxWaitQueueHandle};
+use spinlock::SpinNoIrq;
+
+use self::task::Task;
+
+pub use self::task::JoinHandle;
+
+#[cfg(feature = "net")]
+pub(crate) use self::reactor::register_io_waker;
+
+/// Tasks that are ready to be polled.
+static READY_QUEUE: SpinNoIrq<VecDeque<Arc<Task>>> = SpinNoIrq::new(VecDeque::new());
+
+/// Executor threads wait here when there is no task to run.
+static IDLE_WQ: AxWaitQueueHandle = AxWaitQueueHandle::new();
+
+fn schedule(task: Arc<Task>) {
+    READY_QUEUE.lock().push_back(task);
+    api::ax_wait_queue_wake(&IDLE_WQ, 1);
+}
+
+/// The waker of the future passed to [`block_on`].
+struct BlockOnWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for BlockOnWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        // There may be several threads running `block_on`, wake up all of
+        // them to make sure the right one is woken up.
+        api::ax_wait_queue_wake(&IDLE_WQ, u32::MAX);
+    }
+}
+
+/// Spawns a new asynchronous task, returning a [`JoinHandle`] for it.
+///
+/// The task is run by any thread running [`block_on`]. Dropping the handle
+/// detaches the task, it still runs to completion.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (task, handle) = Task::new(future);
+    schedule(task);
+    handle
+}
+
+/// Runs the given future to completion on the current thread, and runs
+/// other spawned tasks when the future is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let main_waker = Arc::new(BlockOnWaker {
+        woken: AtomicBool::new(true),
+    });
+    let waker = Waker::from(main_waker.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if main_waker.woken.swap(false, Ordering::AcqRel) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+        let task = READY_QUEUE.lock().pop_front();
+        if let Some(task) = task {
+            task.run();
+        } else if reactor::has_waiters() {
+            // The I/O objects can not notify their readiness, so wait for a
+            // while before polling them again, unless other tasks become
+            // ready meanwhile.
+            #[cfg(feature = "irq")]
+            wait_idle(&main_waker, Some(reactor::POLL_INTERVAL));
+            #[cfg(not(feature = "irq"))]
+            api::ax_yield_now();
+            reactor::poll();
+        } else {
+            wait_idle(&main_waker, None);
+        }
+    }
+}
+
+/// Blocks the current thread until the future of [`block_on`] is woken up or
+/// there are tasks ready, or the `timeout` expires.
+fn wait_idle(main_waker: &BlockOnWaker, timeout: Option<Duration>) {
+    api::ax_wait_queue_wait(
+        &IDLE_WQ,
+        || main_waker.woken.load(Ordering::Acquire) || !READY_QUEUE.lock().is_empty(),
+        timeout,
+    );
+}
+
+/// Yields the current task, letting other ready tasks run.
+pub async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
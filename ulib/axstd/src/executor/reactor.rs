@@ -0,0 +1,44 @@
+//! The reactor of asynchronous I/O objects.
+//!
+//! The network stack does not notify the readiness of sockets, it's driven by
+//! polling the network interfaces. So the I/O tasks are simply woken up to
+//! retry after the interfaces are polled, which is done every
+//! [`POLL_INTERVAL`] while the executor is idle.
+
+use alloc::vec::Vec;
+use core::mem;
+use core::task::Waker;
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use spinlock::SpinNoIrq;
+
+/// The interval to poll the I/O sources while there are I/O tasks waiting.
+#[cfg(feature = "irq")]
+pub(super) const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+static IO_WAKERS: SpinNoIrq<Vec<Waker>> = SpinNoIrq::new(Vec::new());
+
+/// Registers the waker of a task whose I/O operation would block.
+#[cfg(feature = "net")]
+pub(crate) fn register_io_waker(waker: &Waker) {
+    IO_WAKERS.lock().push(waker.clone());
+}
+
+/// Whether there are I/O tasks waiting.
+pub(super) fn has_waiters() -> bool {
+    !IO_WAKERS.lock().is_empty()
+}
+
+/// Polls the I/O sources and wakes up all the waiting I/O tasks.
+pub(super) fn poll() {
+    let wakers = mem::take(&mut *IO_WAKERS.lock());
+    if wakers.is_empty() {
+        return;
+    }
+    #[cfg(feature = "net")]
+    arceos_api::net::ax_poll_interfaces().ok();
+    for waker in wakers {
+        waker.wake();
+    }
+}
@@ -0,0 +1,124 @@
+use alloc::{boxed::Box, sync::Arc, task::Wake};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use spinlock::SpinNoIrq;
+
+use crate::sync::Mutex;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// A spawned task.
+pub(super) struct Task {
+    /// The future of the task, or `None` if it has completed.
+    future: Mutex<Option<BoxFuture>>,
+    /// Whether the task is in the ready queue.
+    queued: AtomicBool,
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+    /// Whether the output has been taken by the [`JoinHandle`].
+    taken: bool,
+}
+
+/// An owned permission to await the output of a spawned task.
+///
+/// It's returned by [`spawn`](super::spawn), and it's a future that resolves
+/// to the output of the task.
+///
+/// # Panics
+///
+/// Polling the handle again after it has resolved panics.
+pub struct JoinHandle<T> {
+    state: Arc<SpinNoIrq<JoinState<T>>>,
+}
+
+impl Task {
+    pub fn new<F>(future: F) -> (Arc<Self>, JoinHandle<F::Output>)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let state = Arc::new(SpinNoIrq::new(JoinState {
+            output: None,
+            waker: None,
+            taken: false,
+        }));
+        let task_state = state.clone();
+        let future = async move {
+            let output = future.await;
+            let waker = {
+                let mut state = task_state.lock();
+                state.output = Some(output);
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        };
+        let task = Arc::new(Self {
+            future: Mutex::new(Some(Box::pin(future))),
+            queued: AtomicBool::new(true),
+        });
+        (task, JoinHandle { state })
+    }
+
+    /// Polls the future of the task once.
+    pub fn run(self: Arc<Self>) {
+        // Clear the flag before polling, so that the task can be woken up
+        // again during polling.
+        self.queued.store(false, Ordering::Release);
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = self.future.lock();
+        if let Some(fut) = future.as_mut() {
+            if fut.as_mut().poll(&mut cx).is_ready() {
+                *future = None;
+            }
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            super::schedule(self);
+        }
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            super::schedule(self.clone());
+        }
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// Checks if the task has finished and its output is not taken yet.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().output.is_some()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock();
+        match state.output.take() {
+            Some(output) => {
+                state.taken = true;
+                Poll::Ready(output)
+            }
+            None if state.taken => panic!("`JoinHandle` polled after completion"),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
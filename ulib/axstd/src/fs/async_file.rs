@@ -0,0 +1,92 @@
+use crate::io::{prelude::*, Result, SeekFrom};
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::{File, Metadata};
+use crate::executor::yield_now;
+
+/// An asynchronous version of [`File`].
+///
+/// The filesystem operations complete synchronously, as the block devices
+/// are polled. Each operation yields to the [executor](crate::executor)
+/// afterwards, so that a long sequence of file I/O does not starve other
+/// tasks.
+pub struct AsyncFile(File);
+
+impl AsyncFile {
+    /// Attempts to open a file in read-only mode.
+    pub async fn open(path: &str) -> Result<Self> {
+        let file = File::open(path)?;
+        yield_now().await;
+        Ok(Self(file))
+    }
+
+    /// Opens a file in write-only mode.
+    pub async fn create(path: &str) -> Result<Self> {
+        let file = File::create(path)?;
+        yield_now().await;
+        Ok(Self(file))
+    }
+
+    /// Queries metadata about the underlying file.
+    pub fn metadata(&self) -> Result<Metadata> {
+        self.0.metadata()
+    }
+
+    /// Pulls some bytes from this file into the specified buffer, returning
+    /// how many bytes were read.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.0.read(buf)?;
+        yield_now().await;
+        Ok(n)
+    }
+
+    /// Reads all bytes until EOF, appending them to `buf`.
+    #[cfg(feature = "alloc")]
+    pub async fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let n = self.0.read_to_end(buf)?;
+        yield_now().await;
+        Ok(n)
+    }
+
+    /// Writes a buffer into this file, returning how many bytes were written.
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.0.write(buf)?;
+        yield_now().await;
+        Ok(n)
+    }
+
+    /// Attempts to write an entire buffer into this file.
+    pub async fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.0.write_all(buf)?;
+        yield_now().await;
+        Ok(())
+    }
+
+    /// Flushes the file, ensuring that all buffered contents reach the
+    /// underlying device.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.0.flush()?;
+        yield_now().await;
+        Ok(())
+    }
+
+    /// Seeks to an offset, in bytes, in this file.
+    pub async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let off = self.0.seek(pos)?;
+        yield_now().await;
+        Ok(off)
+    }
+
+    /// Converts this asynchronous file back into a [`File`].
+    pub fn into_std(self) -> File {
+        self.0
+    }
+}
+
+impl From<File> for AsyncFile {
+    fn from(file: File) -> Self {
+        Self(file)
+    }
+}
@@ -1,5 +1,7 @@
 //! Filesystem manipulation operations.
 
+#[cfg(feature = "async")]
+mod async_file;
 mod dir;
 mod file;
 
@@ -11,6 +13,9 @@ use alloc::{string::String, vec::Vec};
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 
+#[cfg(feature = "async")]
+pub use self::async_file::AsyncFile;
+
 /// Read the entire contents of a file into a bytes vector.
 #[cfg(feature = "alloc")]
 pub fn read(path: &str) -> io::Result<Vec<u8>> {
@@ -15,11 +15,15 @@
 //!     - `fp_simd`: Enable floating point and SIMD support.
 //! - Interrupts:
 //!     - `irq`: Enable interrupt handling support.
+//!     - `tickless`: Stop the periodic timer tick when the CPU is idle.
 //! - Memory
 //!     - `alloc`: Enable dynamic memory allocation.
 //!     - `alloc-tlsf`: Use the TLSF allocator.
 //!     - `alloc-slab`: Use the slab allocator.
 //!     - `alloc-buddy`: Use the buddy system allocator.
+//!     - `alloc-buddy-page`: Use the buddy page allocator, for large memory.
+//!     - `alloc-trace`: Record the live allocations to find memory leaks.
+//!     - `alloc-debug`: Detect heap corruption by red zones and poisoning.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
 //! - Task management
@@ -27,6 +31,9 @@
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
 //!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_edf`: Use the Earliest Deadline First (EDF) real-time scheduler.
+//!     - `sched_mlfq`: Use the Multi-level feedback queue (MLFQ) preemptive scheduler.
+//!     - `async`: Enable the async executor and asynchronous I/O adapters.
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
@@ -71,6 +78,8 @@ pub mod sync;
 pub mod thread;
 pub mod time;
 
+#[cfg(feature = "async")]
+pub mod executor;
 #[cfg(feature = "fs")]
 pub mod fs;
 #[cfg(feature = "net")]
@@ -0,0 +1,127 @@
+use super::{async_io, SocketAddr, ToSocketAddrs};
+use crate::io;
+
+use arceos_api::net::{self as api, AxTcpSocketHandle};
+use axerrno::{ax_err, ax_err_type, AxError};
+
+/// An asynchronous TCP stream between a local and a remote socket.
+///
+/// It's the asynchronous version of [`TcpStream`](super::TcpStream), whose
+/// operations are run by the [executor](crate::executor) without blocking the
+/// current thread.
+pub struct AsyncTcpStream(AxTcpSocketHandle);
+
+/// An asynchronous TCP socket server, listening for connections.
+pub struct AsyncTcpListener(AxTcpSocketHandle);
+
+impl AsyncTcpStream {
+    fn new(socket: AxTcpSocketHandle) -> io::Result<Self> {
+        api::ax_tcp_set_nonblocking(&socket, true)?;
+        Ok(Self(socket))
+    }
+
+    /// Opens a TCP connection to a remote host.
+    ///
+    /// If `addr` yields multiple addresses, `connect` will be attempted with
+    /// each of the addresses until a connection is successful.
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<AsyncTcpStream> {
+        let mut last_err = None;
+        for addr in addr.to_socket_addrs()? {
+            match Self::connect_addr(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err
+            .unwrap_or_else(|| ax_err_type!(InvalidInput, "could not resolve to any addresses")))
+    }
+
+    async fn connect_addr(addr: SocketAddr) -> io::Result<AsyncTcpStream> {
+        let stream = Self::new(api::ax_tcp_socket())?;
+        match api::ax_tcp_connect(&stream.0, addr) {
+            Err(AxError::WouldBlock) => {}
+            res => return res.map(|_| stream),
+        }
+        async_io(|| {
+            if api::ax_tcp_poll(&stream.0)?.writable {
+                Ok(())
+            } else {
+                Err(AxError::WouldBlock)
+            }
+        })
+        .await?;
+        // the connection is established if the peer address is available.
+        match api::ax_tcp_peer_addr(&stream.0) {
+            Ok(_) => Ok(stream),
+            Err(_) => ax_err!(ConnectionRefused, "socket connect() failed"),
+        }
+    }
+
+    /// Returns the socket address of the local half of this TCP connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        api::ax_tcp_socket_addr(&self.0)
+    }
+
+    /// Returns the socket address of the remote peer of this TCP connection.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        api::ax_tcp_peer_addr(&self.0)
+    }
+
+    /// Shuts down the connection.
+    pub fn shutdown(&self) -> io::Result<()> {
+        api::ax_tcp_shutdown(&self.0)
+    }
+
+    /// Receives data from the stream, returns the number of bytes read.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        async_io(|| api::ax_tcp_recv(&self.0, buf)).await
+    }
+
+    /// Sends data to the stream, returns the number of bytes written.
+    pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        async_io(|| api::ax_tcp_send(&self.0, buf)).await
+    }
+
+    /// Sends the entire buffer to the stream.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return ax_err!(WriteZero, "failed to write whole buffer"),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsyncTcpListener {
+    /// Creates a new `AsyncTcpListener` which will be bound to the specified
+    /// address.
+    ///
+    /// See [`TcpListener::bind`](super::TcpListener::bind) for details.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<AsyncTcpListener> {
+        super::each_addr(addr, |addr: io::Result<&SocketAddr>| {
+            let addr = addr?;
+            let backlog = 128;
+            let socket = api::ax_tcp_socket();
+            api::ax_tcp_set_nonblocking(&socket, true)?;
+            api::ax_tcp_bind(&socket, *addr)?;
+            api::ax_tcp_listen(&socket, backlog)?;
+            Ok(AsyncTcpListener(socket))
+        })
+    }
+
+    /// Returns the local socket address of this listener.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        api::ax_tcp_socket_addr(&self.0)
+    }
+
+    /// Accepts a new incoming connection from this listener.
+    ///
+    /// It resolves to the corresponding [`AsyncTcpStream`] and the remote
+    /// peer's address when a new connection is established.
+    pub async fn accept(&self) -> io::Result<(AsyncTcpStream, SocketAddr)> {
+        let (socket, addr) = async_io(|| api::ax_tcp_accept(&self.0)).await?;
+        Ok((AsyncTcpStream::new(socket)?, addr))
+    }
+}
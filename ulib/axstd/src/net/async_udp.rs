@@ -0,0 +1,74 @@
+use super::{async_io, SocketAddr, ToSocketAddrs};
+use crate::io;
+
+use arceos_api::net::{self as api, AxUdpSocketHandle};
+
+/// An asynchronous UDP socket.
+///
+/// It's the asynchronous version of [`UdpSocket`](super::UdpSocket), whose
+/// operations are run by the [executor](crate::executor) without blocking the
+/// current thread.
+pub struct AsyncUdpSocket(AxUdpSocketHandle);
+
+impl AsyncUdpSocket {
+    /// Creates a UDP socket from the given address.
+    ///
+    /// See [`UdpSocket::bind`](super::UdpSocket::bind) for details.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<AsyncUdpSocket> {
+        super::each_addr(addr, |addr: io::Result<&SocketAddr>| {
+            let addr = addr?;
+            let socket = api::ax_udp_socket();
+            api::ax_udp_set_nonblocking(&socket, true)?;
+            api::ax_udp_bind(&socket, *addr)?;
+            Ok(AsyncUdpSocket(socket))
+        })
+    }
+
+    /// Returns the socket address that this socket was created from.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        api::ax_udp_socket_addr(&self.0)
+    }
+
+    /// Returns the socket address of the remote peer this socket was connected to.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        api::ax_udp_peer_addr(&self.0)
+    }
+
+    /// Connects this UDP socket to a remote address, allowing the `send` and
+    /// `recv` methods to be used to send data and also applies filters to only
+    /// receive data from the specified address.
+    pub fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        api::ax_udp_connect(&self.0, addr)
+    }
+
+    /// Receives a single datagram message on the socket. On success, returns
+    /// the number of bytes read and the origin.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        async_io(|| api::ax_udp_recv_from(&self.0, buf)).await
+    }
+
+    /// Sends data on the socket to the given address. On success, returns the
+    /// number of bytes written.
+    ///
+    /// It is possible for `addr` to yield multiple addresses, but `send_to`
+    /// will only send data to the first address yielded by `addr`.
+    pub async fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], addr: A) -> io::Result<usize> {
+        match addr.to_socket_addrs()?.next() {
+            Some(addr) => async_io(|| api::ax_udp_send_to(&self.0, buf, addr)).await,
+            None => axerrno::ax_err!(InvalidInput, "no addresses to send data to"),
+        }
+    }
+
+    /// Receives a single datagram message on the socket from the remote
+    /// address to which it is connected. On success, returns the number of
+    /// bytes read.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        async_io(|| api::ax_udp_recv(&self.0, buf)).await
+    }
+
+    /// Sends data on the socket to the remote address to which it is
+    /// connected. On success, returns the number of bytes written.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        async_io(|| api::ax_udp_send(&self.0, buf)).await
+    }
+}
@@ -13,6 +13,14 @@
 //!   and [`SocketAddrV6`] are respectively IPv4 and IPv6 socket addresses
 //! * [`ToSocketAddrs`] is a trait that is used for generic address resolution when interacting
 //!   with networking objects like [`TcpListener`], [`TcpStream`] or [`UdpSocket`]
+//! * [`AsyncTcpListener`], [`AsyncTcpStream`] and [`AsyncUdpSocket`] are their
+//!   asynchronous versions, run by the [executor](crate::executor) (requires the
+//!   `async` feature)
+
+#[cfg(feature = "async")]
+mod async_tcp;
+#[cfg(feature = "async")]
+mod async_udp;
 
 mod socket_addr;
 mod tcp;
@@ -23,6 +31,11 @@ pub use self::socket_addr::{SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddr
 pub use self::tcp::{TcpListener, TcpStream};
 pub use self::udp::UdpSocket;
 
+#[cfg(feature = "async")]
+pub use self::async_tcp::{AsyncTcpListener, AsyncTcpStream};
+#[cfg(feature = "async")]
+pub use self::async_udp::AsyncUdpSocket;
+
 use crate::io;
 
 fn each_addr<A: ToSocketAddrs, F, T>(addr: A, mut f: F) -> io::Result<T>
@@ -44,3 +57,26 @@ where
         axerrno::ax_err_type!(InvalidInput, "could not resolve to any addresses")
     }))
 }
+
+/// Repeats the nonblocking operation `f` until it does not fail with
+/// `WouldBlock`, yielding to the executor in between.
+///
+/// The network interfaces are polled before each attempt, and the task is
+/// woken up to retry on the next poll of the executor's reactor.
+#[cfg(feature = "async")]
+async fn async_io<T, F>(mut f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    core::future::poll_fn(|cx| {
+        arceos_api::net::ax_poll_interfaces()?;
+        match f() {
+            Err(axerrno::AxError::WouldBlock) => {
+                crate::executor::register_io_waker(cx.waker());
+                core::task::Poll::Pending
+            }
+            res => core::task::Poll::Ready(res),
+        }
+    })
+    .await
+}
@@ -0,0 +1,73 @@
+//! A barrier to synchronize a group of tasks.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use arceos_api::task::{self as api, AxWaitQueueHandle};
+
+use super::Mutex;
+
+/// A barrier enables multiple tasks to synchronize the beginning of some
+/// computation, similar to
+/// [`std::sync::Barrier`](https://doc.rust-lang.org/std/sync/struct.Barrier.html).
+pub struct Barrier {
+    /// The number of tasks arrived in the current generation.
+    count: Mutex<usize>,
+    generation: AtomicUsize,
+    num_tasks: usize,
+    wq: AxWaitQueueHandle,
+}
+
+/// A `BarrierWaitResult` is returned by [`Barrier::wait()`] when all tasks in
+/// the [`Barrier`] have rendezvoused.
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// Returns `true` if this task is the "leader task" for the call to
+    /// [`Barrier::wait()`].
+    ///
+    /// Only one task will have `true` returned from their result, all other
+    /// tasks will have `false` returned.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// Creates a new barrier that can block a given number of tasks.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            count: Mutex::new(0),
+            generation: AtomicUsize::new(0),
+            num_tasks: n,
+            wq: AxWaitQueueHandle::new(),
+        }
+    }
+
+    /// Blocks the current task until all tasks have rendezvoused here.
+    ///
+    /// Barriers are re-usable after all tasks have rendezvoused once.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut count = self.count.lock();
+        let generation = self.generation.load(Ordering::Relaxed);
+        *count += 1;
+        if *count < self.num_tasks {
+            drop(count);
+            // We have been counted, exiting here would break the barrier.
+            let cancel_enabled = api::ax_set_cancel_enabled(false);
+            api::ax_wait_queue_wait(
+                &self.wq,
+                || self.generation.load(Ordering::Acquire) != generation,
+                None,
+            );
+            api::ax_set_cancel_enabled(cancel_enabled);
+            BarrierWaitResult(false)
+        } else {
+            *count = 0;
+            self.generation.fetch_add(1, Ordering::Release);
+            drop(count);
+            api::ax_wait_queue_wake(&self.wq, u32::MAX);
+            BarrierWaitResult(true)
+        }
+    }
+}
@@ -23,7 +23,7 @@ pub struct Mutex<T: ?Sized> {
 ///
 /// When the guard falls out of scope it will release the lock.
 pub struct MutexGuard<'a, T: ?Sized + 'a> {
-    lock: &'a Mutex<T>,
+    pub(super) lock: &'a Mutex<T>,
     data: *mut T,
 }
 
@@ -79,7 +79,10 @@ impl<T: ?Sized> Mutex<T> {
                 Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
-                Ok(_) => break,
+                Ok(_) => {
+                    api::ax_sleeping_lock_acquired();
+                    break;
+                }
                 Err(owner_id) => {
                     assert_ne!(
                         owner_id, current_id,
@@ -108,6 +111,7 @@ impl<T: ?Sized> Mutex<T> {
             .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
+            api::ax_sleeping_lock_acquired();
             Some(MutexGuard {
                 lock: self,
                 data: unsafe { &mut *self.data.get() },
@@ -132,6 +136,7 @@ impl<T: ?Sized> Mutex<T> {
             "Thread({}) tried to release mutex it doesn't own",
             current_id,
         );
+        api::ax_sleeping_lock_released();
         // wake up one waiting thread.
         api::ax_wait_queue_wake(&self.wq, 1);
     }
@@ -0,0 +1,65 @@
+//! A one-time initialization primitive.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use arceos_api::task::{self as api, AxWaitQueueHandle};
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+
+/// A synchronization primitive which can be used to run a one-time global
+/// initialization, similar to
+/// [`std::sync::Once`](https://doc.rust-lang.org/std/sync/struct.Once.html).
+///
+/// Tasks calling [`call_once`](Self::call_once) while the initialization is
+/// running are blocked until it completes.
+pub struct Once {
+    state: AtomicU8,
+    wq: AxWaitQueueHandle,
+}
+
+impl Once {
+    /// Creates a new [`Once`] value.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+            wq: AxWaitQueueHandle::new(),
+        }
+    }
+
+    /// Returns `true` if some [`call_once`](Self::call_once) call has
+    /// completed successfully.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Performs an initialization routine once and only once.
+    ///
+    /// If the routine panics, the [`Once`] is left in the running state, and
+    /// other tasks calling this function will block forever.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+        match self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                f();
+                self.state.store(COMPLETE, Ordering::Release);
+                api::ax_wait_queue_wake(&self.wq, u32::MAX);
+            }
+            Err(_) => {
+                api::ax_wait_queue_wait(&self.wq, || self.is_completed(), None);
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
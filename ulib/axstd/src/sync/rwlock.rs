@@ -0,0 +1,274 @@
+//! A sleeping reader-writer lock with writer preference.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use arceos_api::task::{self as api, AxWaitQueueHandle};
+
+/// The lock state when it's held by a writer.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A reader-writer lock, similar to
+/// [`std::sync::RwLock`](https://doc.rust-lang.org/std/sync/struct.RwLock.html).
+///
+/// It allows many readers or at most one writer at any point in time. Writers
+/// are preferred: new readers are blocked once a writer is waiting, so that
+/// writers are not starved by a stream of readers.
+pub struct RwLock<T: ?Sized> {
+    /// The number of readers, or [`WRITER`] if it's held by a writer.
+    state: AtomicUsize,
+    /// The number of writers waiting for the lock.
+    waiting_writers: AtomicUsize,
+    read_wq: AxWaitQueueHandle,
+    write_wq: AxWaitQueueHandle,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will release the shared access.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *const T,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the exclusive access.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *mut T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// Creates a new [`RwLock`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            waiting_writers: AtomicUsize::new(0),
+            read_wq: AxWaitQueueHandle::new(),
+            write_wq: AxWaitQueueHandle::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`RwLock`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        let RwLock { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Returns `true` if the lock is currently held by a writer.
+    ///
+    /// Like [`Mutex::is_locked`](super::Mutex::is_locked), it should only be
+    /// used as a heuristic.
+    #[inline(always)]
+    pub fn is_locked_exclusive(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == WRITER
+    }
+
+    fn can_read(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER == 0
+            && self.waiting_writers.load(Ordering::Relaxed) == 0
+    }
+
+    /// Locks this [`RwLock`] with shared read access, blocking the current
+    /// task until it can be acquired.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            api::ax_wait_queue_wait(&self.read_wq, || self.can_read(), None);
+        }
+    }
+
+    /// Attempts to acquire this [`RwLock`] with shared read access.
+    ///
+    /// It fails if the lock is held by a writer, or a writer is waiting.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        while self.can_read() {
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    api::ax_sleeping_lock_acquired();
+                    return Some(RwLockReadGuard {
+                        lock: self,
+                        data: self.data.get(),
+                    });
+                }
+                Err(s) => state = s,
+            }
+        }
+        None
+    }
+
+    /// Locks this [`RwLock`] with exclusive write access, blocking the current
+    /// task until it can be acquired.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        if let Some(guard) = self.try_write() {
+            return guard;
+        }
+        // Locking is not a cancellation point, we must not exit while we are
+        // counted as a waiting writer.
+        let cancel_enabled = api::ax_set_cancel_enabled(false);
+        self.waiting_writers.fetch_add(1, Ordering::Relaxed);
+        let guard = loop {
+            if let Some(guard) = self.try_write() {
+                break guard;
+            }
+            api::ax_wait_queue_wait(
+                &self.write_wq,
+                || self.state.load(Ordering::Relaxed) == 0,
+                None,
+            );
+        };
+        self.waiting_writers.fetch_sub(1, Ordering::Relaxed);
+        api::ax_set_cancel_enabled(cancel_enabled);
+        guard
+    }
+
+    /// Attempts to lock this [`RwLock`] with exclusive write access.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if self
+            .state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            api::ax_sleeping_lock_acquired();
+            Some(RwLockWriteGuard {
+                lock: self,
+                data: self.data.get(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Force unlock the shared read access of the [`RwLock`].
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held with shared read access, and the guard must be
+    /// forgotten. It's useful to expose the lock to FFI.
+    pub unsafe fn force_unlock_read(&self) {
+        let state = self.state.fetch_sub(1, Ordering::Release);
+        assert!(
+            state & WRITER == 0 && state > 0,
+            "RwLock is not read-locked"
+        );
+        api::ax_sleeping_lock_released();
+        if state == 1 {
+            api::ax_wait_queue_wake(&self.write_wq, 1);
+        }
+    }
+
+    /// Force unlock the exclusive write access of the [`RwLock`].
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held with exclusive write access, and the guard must
+    /// be forgotten. It's useful to expose the lock to FFI.
+    pub unsafe fn force_unlock_write(&self) {
+        let state = self.state.swap(0, Ordering::Release);
+        assert_eq!(state, WRITER, "RwLock is not write-locked");
+        api::ax_sleeping_lock_released();
+        if self.waiting_writers.load(Ordering::Relaxed) > 0 {
+            api::ax_wait_queue_wake(&self.write_wq, 1);
+        } else {
+            api::ax_wait_queue_wake(&self.read_wq, u32::MAX);
+        }
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`RwLock`] mutably, no actual locking needs
+    /// to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<T: ?Sized + Default> Default for RwLock<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "RwLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    /// The dropping of the [`RwLockReadGuard`] will release the shared access.
+    fn drop(&mut self) {
+        unsafe { self.lock.force_unlock_read() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    /// The dropping of the [`RwLockWriteGuard`] will release the exclusive
+    /// access.
+    fn drop(&mut self) {
+        unsafe { self.lock.force_unlock_write() }
+    }
+}
@@ -0,0 +1,74 @@
+//! A counting semaphore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use arceos_api::task::{self as api, AxWaitQueueHandle};
+
+use crate::time::{Duration, Instant};
+
+/// A counting semaphore.
+///
+/// It maintains a count of available permits. [`acquire`](Self::acquire)
+/// blocks the current task until a permit is available and takes it, and
+/// [`release`](Self::release) gives a permit back.
+pub struct Semaphore {
+    count: AtomicUsize,
+    wq: AxWaitQueueHandle,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+            wq: AxWaitQueueHandle::new(),
+        }
+    }
+
+    /// Returns the number of available permits.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Acquires a permit, blocking the current task until one is available.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            api::ax_wait_queue_wait(&self.wq, || self.count() > 0, None);
+        }
+    }
+
+    /// Tries to acquire a permit without blocking.
+    ///
+    /// Returns `false` if no permit is available.
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Acquires a permit, blocking the current task until one is available or
+    /// the given duration has elapsed.
+    ///
+    /// Returns `false` if it timed out. Without the `irq` feature, the timeout
+    /// is ignored.
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        let start = Instant::now();
+        while !self.try_acquire() {
+            let elapsed = start.elapsed();
+            if elapsed >= dur
+                || api::ax_wait_queue_wait(&self.wq, || self.count() > 0, Some(dur - elapsed))
+            {
+                return self.try_acquire();
+            }
+        }
+        true
+    }
+
+    /// Releases a permit, and wakes up a task waiting for it.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        api::ax_wait_queue_wake(&self.wq, 1);
+    }
+}
@@ -6,7 +6,7 @@ use crate::io;
 use alloc::{string::String, sync::Arc};
 use core::{cell::UnsafeCell, num::NonZeroU64};
 
-use arceos_api::task::{self as api, AxTaskHandle};
+use arceos_api::task::{self as api, AxCpuMask, AxTaskHandle};
 use axerrno::ax_err_type;
 
 /// A unique identifier for a running thread.
@@ -49,6 +49,8 @@ pub struct Builder {
     name: Option<String>,
     // The size of the stack for the spawned thread in bytes
     stack_size: Option<usize>,
+    // The CPUs that the spawned thread is allowed to run on
+    affinity: Option<AxCpuMask>,
 }
 
 impl Builder {
@@ -58,6 +60,7 @@ impl Builder {
         Builder {
             name: None,
             stack_size: None,
+            affinity: None,
         }
     }
 
@@ -73,6 +76,13 @@ impl Builder {
         self
     }
 
+    /// Sets the CPU affinity mask for the new thread, which restricts the CPUs
+    /// that it is allowed to run on.
+    pub fn affinity(mut self, cpumask: AxCpuMask) -> Builder {
+        self.affinity = Some(cpumask);
+        self
+    }
+
     /// Spawns a new thread by taking ownership of the `Builder`, and returns an
     /// [`io::Result`] to its [`JoinHandle`].
     ///
@@ -115,7 +125,7 @@ impl Builder {
             drop(their_packet);
         };
 
-        let task = api::ax_spawn(main, name, stack_size);
+        let task = api::ax_spawn(main, name, stack_size, self.affinity)?;
         Ok(JoinHandle {
             thread: Thread::from_id(task.id()),
             native: task,
@@ -173,17 +183,27 @@ impl<T> JoinHandle<T> {
         &self.thread
     }
 
+    /// Aborts the associated thread.
+    ///
+    /// The thread exits the next time it blocks on a synchronization primitive
+    /// or sleeps, or immediately if it's blocked. It doesn't return from its
+    /// closure, so the destructors are not run. But it's not aborted while it
+    /// holds any [`Mutex`](crate::sync::Mutex) or
+    /// [`RwLock`](crate::sync::RwLock), so that they are not left locked. A
+    /// [`join`](Self::join) on an aborted thread returns an error.
+    pub fn abort(&self) {
+        api::ax_cancel_task(&self.native);
+    }
+
     /// Waits for the associated thread to finish.
     ///
     /// This function will return immediately if the associated thread has
     /// already finished.
-    pub fn join(mut self) -> io::Result<T> {
+    pub fn join(self) -> io::Result<T> {
         api::ax_wait_for_exit(self.native).ok_or_else(|| ax_err_type!(BadState))?;
-        Arc::get_mut(&mut self.packet)
-            .unwrap()
-            .result
-            .get_mut()
-            .take()
-            .ok_or_else(|| ax_err_type!(BadState))
+        // SAFETY: the thread has exited and will not access the packet, even
+        // if its reference is never dropped because it's aborted.
+        unsafe { (*self.packet.result.get()).take() }
+            .ok_or_else(|| ax_err_type!(BadState, "the thread was aborted"))
     }
 }